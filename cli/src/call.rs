@@ -0,0 +1,81 @@
+//! `mcplease call`: build and start a project's server, call one tool, and print the result
+
+use crate::dev;
+use anyhow::{Result, anyhow};
+use mcplease::client::Client;
+use serde_json::{Value, json};
+use std::{path::Path, process::Command};
+
+/// Call `tool` on the server in `project_path` (or the current directory), with arguments from
+/// `key=value` pairs or a JSON object
+///
+/// Only the result is printed to stdout, so it can be piped elsewhere. A result with `isError` is
+/// an error, so scripts can check the exit status.
+pub fn run(
+    project_path: Option<&Path>,
+    tool: &str,
+    pairs: &[String],
+    json: Option<&str>,
+    raw: bool,
+) -> Result<()> {
+    let project_path = project_path.unwrap_or(Path::new("."));
+    if !project_path.join("Cargo.toml").exists() {
+        return Err(anyhow!(
+            "No Cargo.toml found at {}. Run this command from the root of an mcplease project.",
+            project_path.display()
+        ));
+    }
+
+    let mut client = Client::spawn(
+        Command::new("cargo")
+            .args(["run", "--quiet", "--", "serve"])
+            .current_dir(project_path),
+    )?;
+    client.initialize()?;
+    let tools = client.request("tools/list", json!({}))?["tools"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+
+    let Some(tool) = dev::find_tool(&tools, tool) else {
+        let names = tools
+            .iter()
+            .filter_map(|tool| tool["name"].as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(anyhow!(
+            "No tool named {tool}. The server's tools are: {names}"
+        ));
+    };
+    let name = tool["name"].as_str().unwrap_or_default();
+
+    let arguments = match json {
+        Some(json) => serde_json::from_str(json).map_err(|e| anyhow!("Invalid JSON: {e}"))?,
+        None => dev::parse_arguments(tool, pairs.iter().map(String::as_str))?,
+    };
+
+    let result = client.request(
+        "tools/call",
+        json!({ "name": name, "arguments": arguments }),
+    )?;
+
+    // notifications go to stderr, to keep stdout for the result
+    for notification in client.take_notifications() {
+        eprintln!(
+            "🔔 {} {}",
+            notification.method,
+            notification.params.unwrap_or(Value::Null)
+        );
+    }
+
+    if raw {
+        println!("{}", serde_json::to_string_pretty(&result)?);
+    } else {
+        dev::print_result(&result);
+    }
+
+    if result["isError"] == true {
+        return Err(anyhow!("{name} reported an error"));
+    }
+    Ok(())
+}
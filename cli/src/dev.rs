@@ -0,0 +1,237 @@
+//! `mcplease dev`: build and start a project's server, then call its tools from a REPL
+
+use anyhow::{Result, anyhow};
+use mcplease::client::Client;
+use serde_json::{Map, Value, json};
+use std::{
+    io::{self, BufRead, Write},
+    path::Path,
+    process::Command,
+};
+
+const HELP: &str = "\
+Commands:
+  <tool> [key=value ...]   call a tool, with string or JSON values
+  <tool> {\"key\": ...}      call a tool with a JSON object of arguments
+  tools                    list the server's tools and their parameters
+  restart                  rebuild and restart the server, after editing the project
+  help                     show this message
+  quit                     stop the server and exit";
+
+/// Start the server in `project_path` (or the current directory) and run the REPL until the user
+/// quits or stdin closes
+pub fn run(project_path: Option<&Path>, raw: bool) -> Result<()> {
+    let project_path = project_path.unwrap_or(Path::new("."));
+    if !project_path.join("Cargo.toml").exists() {
+        return Err(anyhow!(
+            "No Cargo.toml found at {}. Run this command from the root of an mcplease project.",
+            project_path.display()
+        ));
+    }
+
+    let mut session = Session::start(project_path)?;
+    println!("Type `help` for commands, or a tool name to call it.");
+
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        print!("mcp> ");
+        io::stdout().flush()?;
+        line.clear();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            println!();
+            return Ok(());
+        }
+
+        let line = line.trim();
+        match line {
+            "" => continue,
+            "quit" | "exit" => return Ok(()),
+            "help" => println!("{HELP}"),
+            "tools" => print_tools(&session.tools),
+            "restart" => {
+                // the old server is stopped first, so that cargo can replace its binary
+                drop(session);
+                session = Session::start(project_path)?;
+            }
+            line => match parse_call(line, &session.tools) {
+                Ok((name, arguments)) => session.call(&name, arguments, raw),
+                Err(e) => println!("❌ {e}"),
+            },
+        }
+    }
+}
+
+struct Session {
+    client: Client,
+    tools: Vec<Value>,
+}
+
+impl Session {
+    fn start(project_path: &Path) -> Result<Self> {
+        println!("🔨 Building and starting the server...");
+        let mut client = Client::spawn(
+            Command::new("cargo")
+                .args(["run", "--quiet", "--", "serve"])
+                .current_dir(project_path),
+        )?;
+
+        let initialize = client.initialize()?;
+        let tools = client.request("tools/list", json!({}))?["tools"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+
+        println!(
+            "✅ Connected to {} {} with {} tool(s)",
+            initialize.server_info.name,
+            initialize.server_info.version,
+            tools.len()
+        );
+        Ok(Self { client, tools })
+    }
+
+    fn call(&mut self, name: &str, arguments: Value, raw: bool) {
+        let result = self.client.request(
+            "tools/call",
+            json!({ "name": name, "arguments": arguments }),
+        );
+
+        let mut tools_changed = false;
+        for notification in self.client.take_notifications() {
+            let method = notification.method;
+            tools_changed |= method == "notifications/tools/list_changed";
+            println!("🔔 {method} {}", notification.params.unwrap_or(Value::Null));
+        }
+
+        match result {
+            Ok(result) if raw => {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&result).unwrap_or_default()
+                )
+            }
+            Ok(result) => print_result(&result),
+            Err(e) => println!("❌ {e}"),
+        }
+
+        if tools_changed {
+            match self.client.request("tools/list", json!({})) {
+                Ok(list) => self.tools = list["tools"].as_array().cloned().unwrap_or_default(),
+                Err(e) => println!("❌ Couldn't refresh the tool list: {e}"),
+            }
+        }
+    }
+}
+
+/// Parse `tool key=value ...` or `tool {json}` into a tool name and arguments
+pub fn parse_call(line: &str, tools: &[Value]) -> Result<(String, Value)> {
+    let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let rest = rest.trim();
+
+    let tool = find_tool(tools, command)
+        .ok_or_else(|| anyhow!("No tool named {command}. Type `tools` to list them."))?;
+    let name = tool["name"].as_str().unwrap_or_default().to_string();
+
+    if rest.starts_with('{') {
+        let arguments = serde_json::from_str(rest).map_err(|e| anyhow!("Invalid JSON: {e}"))?;
+        return Ok((name, arguments));
+    }
+
+    Ok((name, parse_arguments(tool, rest.split_whitespace())?))
+}
+
+/// Find the tool named `command`, which can also be spelled with dashes for underscores
+pub fn find_tool<'a>(tools: &'a [Value], command: &str) -> Option<&'a Value> {
+    tools.iter().find(|tool| {
+        tool["name"]
+            .as_str()
+            .is_some_and(|name| name == command || name.replace('_', "-") == command)
+    })
+}
+
+/// Parse `key=value` pairs into arguments for `tool`
+///
+/// A value is kept as a string if the schema says the parameter is a string, and is otherwise
+/// parsed as JSON if possible, so `count=3` and `enabled=true` work as expected.
+pub fn parse_arguments<'a>(
+    tool: &Value,
+    pairs: impl IntoIterator<Item = &'a str>,
+) -> Result<Value> {
+    let properties = &tool["inputSchema"]["properties"];
+    let mut arguments = Map::new();
+    for pair in pairs {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected key=value, found {pair}"))?;
+        let is_string = properties[key]["type"] == "string";
+        let value = if is_string {
+            Value::String(value.to_string())
+        } else {
+            serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()))
+        };
+        arguments.insert(key.to_string(), value);
+    }
+    Ok(Value::Object(arguments))
+}
+
+fn print_tools(tools: &[Value]) {
+    for tool in tools {
+        let name = tool["name"].as_str().unwrap_or_default();
+        let required: Vec<&str> = tool["inputSchema"]["required"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+        let parameters: Vec<String> = tool["inputSchema"]["properties"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(key, _)| {
+                if required.contains(&key.as_str()) {
+                    format!("{key}=…")
+                } else {
+                    format!("[{key}=…]")
+                }
+            })
+            .collect();
+        println!("  {name} {}", parameters.join(" "));
+        if let Some(description) = tool["description"].as_str() {
+            let first_line = description.lines().next().unwrap_or_default();
+            println!("      {first_line}");
+        }
+    }
+}
+
+/// Print a `tools/call` result the way a person wants to read it
+pub fn print_result(result: &Value) {
+    if result["isError"] == true {
+        println!("❌ The tool reported an error:");
+    }
+    for content in result["content"].as_array().into_iter().flatten() {
+        match content["type"].as_str() {
+            Some("text") => println!("{}", content["text"].as_str().unwrap_or_default()),
+            Some("image") | Some("audio") => println!(
+                "[{} {}, {} bytes of base64]",
+                content["type"].as_str().unwrap_or_default(),
+                content["mimeType"].as_str().unwrap_or_default(),
+                content["data"].as_str().map_or(0, str::len)
+            ),
+            Some("resource") => {
+                let resource = &content["resource"];
+                println!(
+                    "[resource {}]",
+                    resource["uri"].as_str().unwrap_or_default()
+                );
+                if let Some(text) = resource["text"].as_str() {
+                    println!("{text}");
+                }
+            }
+            _ => println!(
+                "{}",
+                serde_json::to_string_pretty(content).unwrap_or_default()
+            ),
+        }
+    }
+}
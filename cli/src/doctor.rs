@@ -0,0 +1,510 @@
+//! `mcplease doctor`: finding common problems in a project without building it
+
+use crate::{ToolEntry, ToolsFile, doc_comment, find_struct};
+use anyhow::{Result, anyhow};
+use quote::quote;
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display, Formatter},
+    fs,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+use syn::{File, Item, ItemStruct};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    fn error(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            help: None,
+        }
+    }
+
+    fn warning(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message: message.into(),
+            help: None,
+        }
+    }
+
+    fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "❌ error: {}", self.message)?,
+            Severity::Warning => write!(f, "⚠️  warning: {}", self.message)?,
+        }
+        if let Some(help) = &self.help {
+            write!(f, "\n   help: {help}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the project at `project_path` (or the current directory), returning every problem found
+///
+/// With `run`, the project is also built and asked for its tool list over MCP, which catches
+/// problems that only show up at runtime, like schema generation panicking.
+pub fn diagnose(project_path: Option<&Path>, run: bool) -> Result<Vec<Diagnostic>> {
+    let tools_file = ToolsFile::read(project_path)?;
+    let mut diagnostics = vec![];
+
+    for tool in &tools_file.args.tools {
+        check_tool(&tools_file, tool, &mut diagnostics);
+    }
+    check_unused_files(&tools_file, &mut diagnostics);
+    check_dependency_version(&tools_file.base_path, &mut diagnostics);
+    if run {
+        check_tools_list(&tools_file.base_path, &mut diagnostics);
+    }
+
+    Ok(diagnostics)
+}
+
+/// Print `diagnostics` and a summary, failing if there were any errors
+pub fn report(diagnostics: &[Diagnostic]) -> Result<()> {
+    for diagnostic in diagnostics {
+        println!("{diagnostic}");
+    }
+
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    let warnings = diagnostics.len() - errors;
+    match (errors, warnings) {
+        (0, 0) => {
+            println!("✅ No problems found");
+            Ok(())
+        }
+        (0, _) => {
+            println!("✅ No errors, {warnings} warning(s)");
+            Ok(())
+        }
+        _ => Err(anyhow!("found {errors} error(s) and {warnings} warning(s)")),
+    }
+}
+
+fn check_tool(tools_file: &ToolsFile, tool: &ToolEntry, diagnostics: &mut Vec<Diagnostic>) {
+    let name = tool.string_name.value();
+    let display_path = format!("src/tools/{}.rs", tool.mod_name);
+
+    let Ok(content) = fs::read_to_string(tools_file.tool_file_path(tool)) else {
+        diagnostics.push(
+            Diagnostic::error(format!(
+                "tool '{name}' is declared in src/tools.rs, but {display_path} does not exist"
+            ))
+            .with_help(format!(
+                "create it with `mcplease add {name}`, or remove the entry with \
+                 `mcplease remove --tool {name}`"
+            )),
+        );
+        return;
+    };
+
+    let file: File = match syn::parse_str(&content) {
+        Ok(file) => file,
+        Err(e) => {
+            diagnostics.push(Diagnostic::error(format!(
+                "{display_path} could not be parsed: {e}"
+            )));
+            return;
+        }
+    };
+
+    let struct_name = &tool.struct_name;
+    let Some(item) = find_struct(&file, struct_name) else {
+        diagnostics.push(
+            Diagnostic::error(format!(
+                "tool '{name}' expects a struct named {struct_name} in {display_path}, but there \
+                 isn't one"
+            ))
+            .with_help("the struct name in the tools! macro must match the struct in the file"),
+        );
+        return;
+    };
+
+    if !implements(&file, struct_name, &["Tool", "StructuredTool"]) {
+        diagnostics.push(
+            Diagnostic::error(format!(
+                "{struct_name} in {display_path} doesn't implement Tool or StructuredTool"
+            ))
+            .with_help(format!(
+                "add `impl Tool<{}> for {struct_name}` with an execute method",
+                tool.substate.as_ref().map_or_else(
+                    || tools_file.args.state_type.to_string(),
+                    |substate| quote!(#substate).to_string()
+                )
+            )),
+        );
+    }
+
+    if !implements(&file, struct_name, &["WithExamples"]) {
+        diagnostics.push(
+            Diagnostic::error(format!(
+                "{struct_name} in {display_path} doesn't implement WithExamples, which tools need \
+                 for their schema"
+            ))
+            .with_help(format!(
+                "add `impl WithExamples for {struct_name} {{}}`, or list examples in it"
+            )),
+        );
+    }
+
+    let derives = derives(item);
+    let missing: Vec<_> = ["Serialize", "Deserialize", "JsonSchema", "Args"]
+        .into_iter()
+        .filter(|derive| !derives.contains(*derive))
+        .collect();
+    if !missing.is_empty() {
+        diagnostics.push(
+            Diagnostic::error(format!(
+                "{struct_name} in {display_path} doesn't derive {}",
+                missing.join(", ")
+            ))
+            .with_help(
+                "tools derive Debug, Serialize, Deserialize, schemars::JsonSchema, and clap::Args",
+            ),
+        );
+    }
+
+    match serde_rename(item) {
+        Some(rename) if rename != name => diagnostics.push(
+            Diagnostic::error(format!(
+                "{struct_name} is #[serde(rename = \"{rename}\")], but is listed as \"{name}\" in \
+                 src/tools.rs, so clients will see a tool name that can't be called"
+            ))
+            .with_help("use the same name in both places, or `mcplease rename` to change it"),
+        ),
+        Some(_) => {}
+        None => diagnostics.push(
+            Diagnostic::warning(format!(
+                "{struct_name} in {display_path} has no #[serde(rename = \"{name}\")], so its \
+                 advertised name is \"{struct_name}\""
+            ))
+            .with_help(format!("add #[serde(rename = \"{name}\")] to the struct")),
+        ),
+    }
+
+    let description = doc_comment(&item.attrs);
+    if description.is_empty() {
+        diagnostics.push(
+            Diagnostic::warning(format!(
+                "{struct_name} in {display_path} has no doc comment, so clients get no \
+                 description of the tool"
+            ))
+            .with_help("add a /// comment describing what the tool does and when to use it"),
+        );
+    } else if description.starts_with("TODO") {
+        diagnostics.push(Diagnostic::warning(format!(
+            "{struct_name} in {display_path} still has the generated TODO description"
+        )));
+    }
+
+    check_parameter_docs(item, &display_path, diagnostics);
+}
+
+/// Doc comments on a tool's fields describe its parameters both in the schema, through schemars,
+/// and in `--help`, through clap. Warn about descriptions that only reach one of them.
+fn check_parameter_docs(item: &ItemStruct, display_path: &str, diagnostics: &mut Vec<Diagnostic>) {
+    let struct_name = &item.ident;
+    for field in &item.fields {
+        let Some(field_name) = &field.ident else {
+            continue;
+        };
+
+        if let Some((attribute, reaches)) = one_sided_description(&field.attrs) {
+            diagnostics.push(
+                Diagnostic::warning(format!(
+                    "{struct_name}::{field_name} in {display_path} is described with \
+                     #[{attribute}], which only reaches {reaches}"
+                ))
+                .with_help("use a /// comment instead, which both the schema and --help use"),
+            );
+        } else if doc_comment(&field.attrs).is_empty() && !is_flattened(&field.attrs) {
+            diagnostics.push(
+                Diagnostic::warning(format!(
+                    "{struct_name}::{field_name} in {display_path} has no doc comment, so neither \
+                     clients nor --help describe it"
+                ))
+                .with_help("add a /// comment describing the parameter"),
+            );
+        }
+    }
+}
+
+/// The first attribute in `attrs` that describes a field for only one of clap and schemars, like
+/// `arg(help = "...")`, and what it reaches
+fn one_sided_description(attrs: &[syn::Attribute]) -> Option<(String, &'static str)> {
+    let mut found = None;
+    for attr in attrs {
+        let (keys, reaches): (&[&str], _) =
+            if attr.path().is_ident("arg") || attr.path().is_ident("clap") {
+                (&["help", "long_help"], "--help")
+            } else if attr.path().is_ident("schemars") {
+                (&["description"], "the schema")
+            } else {
+                continue;
+            };
+
+        let _ = attr.parse_nested_meta(|meta| {
+            if let Some(key) = keys.iter().find(|key| meta.path.is_ident(key))
+                && found.is_none()
+            {
+                let name = attr.path().get_ident().map(ToString::to_string);
+                found = Some((
+                    format!("{}({key} = ...)", name.unwrap_or_default()),
+                    reaches,
+                ));
+            }
+            if meta.input.peek(syn::Token![=]) {
+                meta.value()?.parse::<syn::Expr>()?;
+            }
+            Ok(())
+        });
+    }
+    found
+}
+
+/// Whether the field is flattened for clap or serde, in which case its own fields are described
+/// instead
+fn is_flattened(attrs: &[syn::Attribute]) -> bool {
+    let mut flattened = false;
+    for attr in attrs {
+        if ["arg", "clap", "command", "serde"]
+            .iter()
+            .any(|name| attr.path().is_ident(name))
+        {
+            let _ = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("flatten") {
+                    flattened = true;
+                } else if meta.input.peek(syn::Token![=]) {
+                    meta.value()?.parse::<syn::Expr>()?;
+                }
+                Ok(())
+            });
+        }
+    }
+    flattened
+}
+
+/// Whether `file` has an `impl` of one of `traits` for `struct_name`
+fn implements(file: &File, struct_name: &syn::Ident, traits: &[&str]) -> bool {
+    file.items.iter().any(|item| {
+        let Item::Impl(item) = item else {
+            return false;
+        };
+        let Some((_, path, _)) = &item.trait_ else {
+            return false;
+        };
+        let is_trait = path
+            .segments
+            .last()
+            .is_some_and(|segment| traits.iter().any(|t| segment.ident == t));
+        let is_struct = matches!(
+            &*item.self_ty,
+            syn::Type::Path(ty) if ty.path.segments.last().is_some_and(|s| s.ident == *struct_name)
+        );
+        is_trait && is_struct
+    })
+}
+
+/// The last path segment of everything in the struct's `#[derive(...)]` attributes
+fn derives(item: &ItemStruct) -> BTreeSet<String> {
+    let mut derives = BTreeSet::new();
+    for attr in &item.attrs {
+        if attr.path().is_ident("derive") {
+            let _ = attr.parse_nested_meta(|meta| {
+                if let Some(segment) = meta.path.segments.last() {
+                    derives.insert(segment.ident.to_string());
+                }
+                Ok(())
+            });
+        }
+    }
+    derives
+}
+
+fn serde_rename(item: &ItemStruct) -> Option<String> {
+    let mut rename = None;
+    for attr in &item.attrs {
+        if attr.path().is_ident("serde") {
+            let _ = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    rename = Some(meta.value()?.parse::<syn::LitStr>()?.value());
+                } else if meta.input.peek(syn::Token![=]) {
+                    meta.value()?.parse::<syn::Expr>()?;
+                }
+                Ok(())
+            });
+        }
+    }
+    rename
+}
+
+/// Warn about files in src/tools that no tool uses, which are never compiled
+fn check_unused_files(tools_file: &ToolsFile, diagnostics: &mut Vec<Diagnostic>) {
+    let Ok(entries) = fs::read_dir(tools_file.base_path.join("src/tools")) else {
+        return;
+    };
+    let modules: BTreeSet<String> = tools_file
+        .args
+        .tools
+        .iter()
+        .map(|tool| tool.mod_name.to_string())
+        .collect();
+
+    let mut unused: Vec<String> = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| {
+            name.strip_suffix(".rs")
+                .is_some_and(|module| !modules.contains(module))
+        })
+        .collect();
+    unused.sort();
+
+    for name in unused {
+        let module = name.trim_end_matches(".rs");
+        diagnostics.push(
+            Diagnostic::warning(format!(
+                "src/tools/{name} isn't listed in the tools! macro, so it is never compiled"
+            ))
+            .with_help(format!(
+                "add ({}, {module}, \"{module}\") to the tools! macro, or delete the file",
+                heck::ToPascalCase::to_pascal_case(module)
+            )),
+        );
+    }
+}
+
+/// Warn if the project depends on a different mcplease release than the one this CLI generates
+/// code for
+fn check_dependency_version(base_path: &Path, diagnostics: &mut Vec<Diagnostic>) {
+    let Ok(cargo_toml) = fs::read_to_string(base_path.join("Cargo.toml")) else {
+        diagnostics.push(Diagnostic::warning("no Cargo.toml found"));
+        return;
+    };
+    let Some(requirement) = mcplease_requirement(&cargo_toml) else {
+        return; // a path or git dependency, or none at all
+    };
+    let Ok(version_req) = semver::VersionReq::parse(&requirement) else {
+        diagnostics.push(Diagnostic::warning(format!(
+            "couldn't understand the mcplease version requirement \"{requirement}\""
+        )));
+        return;
+    };
+    let current = semver::Version::parse(env!("CARGO_PKG_VERSION")).expect("valid version");
+    if !version_req.matches(&current) {
+        diagnostics.push(
+            Diagnostic::warning(format!(
+                "Cargo.toml depends on mcplease \"{requirement}\", but this CLI generates code \
+                 for mcplease {}.{}",
+                current.major, current.minor
+            ))
+            .with_help(format!(
+                "update to mcplease = \"{}.{}\", or use a matching version of mcplease-cli",
+                current.major, current.minor
+            )),
+        );
+    }
+}
+
+/// The version requirement of the `mcplease` dependency, from either `mcplease = "x"` or
+/// `mcplease = { version = "x", ... }`
+fn mcplease_requirement(cargo_toml: &str) -> Option<String> {
+    let value = cargo_toml.lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        (key.trim() == "mcplease").then(|| value.trim())
+    })?;
+
+    let quoted = |s: &str| Some(s.strip_prefix('"')?.split('"').next()?.to_string());
+    if value.starts_with('"') {
+        quoted(value)
+    } else {
+        let (_, version) = value.split_once("version")?;
+        quoted(version.trim_start().strip_prefix('=')?.trim_start())
+    }
+}
+
+/// Build and run the server, and ask it for its tools
+fn check_tools_list(base_path: &Path, diagnostics: &mut Vec<Diagnostic>) {
+    println!("🔨 Building and starting the server to check its tool schemas...");
+
+    let input = concat!(
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2025-06-18","capabilities":{},"clientInfo":{"name":"mcplease-doctor","version":"0"}}}"#,
+        "\n",
+        r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#,
+        "\n",
+    );
+
+    let output = Command::new("cargo")
+        .args(["run", "--quiet", "--", "serve"])
+        .current_dir(base_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            child
+                .stdin
+                .take()
+                .expect("stdin is piped")
+                .write_all(input.as_bytes())?;
+            child.wait_with_output()
+        });
+
+    let output = match output {
+        Ok(output) => output,
+        Err(e) => {
+            diagnostics.push(Diagnostic::error(format!("couldn't run cargo: {e}")));
+            return;
+        }
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let listed = stdout.lines().any(|line| {
+        line.contains(r#""id":2"#) && line.contains(r#""tools""#) && !line.contains(r#""error""#)
+    });
+
+    if !listed {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let tail: Vec<_> = stderr.lines().rev().take(10).collect();
+        let tail: Vec<_> = tail.into_iter().rev().collect();
+        diagnostics.push(
+            Diagnostic::error(format!(
+                "the server didn't answer tools/list{}",
+                if output.status.success() {
+                    String::new()
+                } else {
+                    format!(" ({})", output.status)
+                }
+            ))
+            .with_help(if tail.is_empty() {
+                "run `cargo run serve` to investigate".to_string()
+            } else {
+                tail.join("\n         ")
+            }),
+        );
+    }
+}
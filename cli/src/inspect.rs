@@ -0,0 +1,179 @@
+//! `mcplease inspect`: describe any MCP server that speaks stdio
+
+use anyhow::{Result, anyhow};
+use mcplease::client::Client;
+use serde_json::{Map, Value, json};
+use std::process::Command;
+
+/// Start `command`, perform the handshake, and print everything the server offers
+pub fn run(command: &[String], json: bool) -> Result<()> {
+    let [program, args @ ..] = command else {
+        return Err(anyhow!(
+            "No command given. Usage: mcplease inspect -- <COMMAND>..."
+        ));
+    };
+
+    let mut client = Client::spawn(Command::new(program).args(args))?;
+    // untyped, to report everything the server sent, whichever protocol version it speaks
+    let initialize: Value = client.initialize_as()?;
+    let capabilities = &initialize["capabilities"];
+
+    let mut report = Map::new();
+    report.insert("initialize".into(), initialize.clone());
+    for (capability, method, key) in [
+        ("tools", "tools/list", "tools"),
+        ("resources", "resources/list", "resources"),
+        ("resources", "resources/templates/list", "resourceTemplates"),
+        ("prompts", "prompts/list", "prompts"),
+    ] {
+        if capabilities.get(capability).is_none() {
+            continue;
+        }
+        match list(&mut client, method, key) {
+            Ok(items) => {
+                report.insert(key.into(), Value::Array(items));
+            }
+            // resource templates are optional even for servers with resources
+            Err(_) if method == "resources/templates/list" => {}
+            Err(e) => eprintln!("⚠️  {method} failed: {e}"),
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_report(&report);
+    }
+    Ok(())
+}
+
+/// Request every page of a list method
+fn list(client: &mut Client, method: &str, key: &str) -> Result<Vec<Value>> {
+    let mut items = vec![];
+    let mut cursor: Option<Value> = None;
+    loop {
+        let params = match &cursor {
+            Some(cursor) => json!({ "cursor": cursor }),
+            None => json!({}),
+        };
+        let mut page = client.request(method, params)?;
+        if let Some(Value::Array(page_items)) = page.get_mut(key).map(Value::take) {
+            items.extend(page_items);
+        }
+        match page.get("nextCursor") {
+            Some(next) if !next.is_null() => cursor = Some(next.clone()),
+            _ => return Ok(items),
+        }
+    }
+}
+
+fn print_report(report: &Map<String, Value>) {
+    let initialize = &report["initialize"];
+    let info = &initialize["serverInfo"];
+    println!(
+        "🖥️  {} {}",
+        info["name"].as_str().unwrap_or("(unnamed server)"),
+        info["version"].as_str().unwrap_or_default()
+    );
+    println!(
+        "   protocol version: {}",
+        initialize["protocolVersion"].as_str().unwrap_or("(none)")
+    );
+    if let Some(capabilities) = initialize["capabilities"].as_object() {
+        let names: Vec<String> = capabilities
+            .iter()
+            .map(|(name, value)| match value.as_object() {
+                Some(flags) if !flags.is_empty() => {
+                    let flags: Vec<&str> = flags
+                        .iter()
+                        .filter(|(_, enabled)| **enabled == true)
+                        .map(|(flag, _)| flag.as_str())
+                        .collect();
+                    format!("{name} ({})", flags.join(", "))
+                }
+                _ => name.clone(),
+            })
+            .collect();
+        println!("   capabilities: {}", names.join(", "));
+    }
+    if let Some(instructions) = initialize["instructions"].as_str() {
+        println!("   instructions:");
+        print_indented(instructions, 6);
+    }
+
+    if let Some(tools) = report.get("tools").and_then(Value::as_array) {
+        println!("\n🔧 Tools ({})", tools.len());
+        for tool in tools {
+            println!("\n   {}", tool["name"].as_str().unwrap_or_default());
+            if let Some(description) = tool["description"].as_str() {
+                print_indented(description, 6);
+            }
+            for (label, key) in [("input", "inputSchema"), ("output", "outputSchema")] {
+                if let Some(schema) = tool.get(key) {
+                    println!("      {label} schema:");
+                    print_indented(&serde_json::to_string_pretty(schema).unwrap_or_default(), 8);
+                }
+            }
+        }
+    }
+
+    if let Some(resources) = report.get("resources").and_then(Value::as_array) {
+        println!("\n📄 Resources ({})", resources.len());
+        for resource in resources {
+            println!(
+                "   {}  {}{}",
+                resource["uri"].as_str().unwrap_or_default(),
+                resource["name"].as_str().unwrap_or_default(),
+                resource["mimeType"]
+                    .as_str()
+                    .map(|mime| format!(" ({mime})"))
+                    .unwrap_or_default()
+            );
+            if let Some(description) = resource["description"].as_str() {
+                print_indented(description, 6);
+            }
+        }
+    }
+
+    if let Some(templates) = report.get("resourceTemplates").and_then(Value::as_array) {
+        println!("\n📄 Resource templates ({})", templates.len());
+        for template in templates {
+            println!(
+                "   {}  {}",
+                template["uriTemplate"].as_str().unwrap_or_default(),
+                template["name"].as_str().unwrap_or_default()
+            );
+        }
+    }
+
+    if let Some(prompts) = report.get("prompts").and_then(Value::as_array) {
+        println!("\n💬 Prompts ({})", prompts.len());
+        for prompt in prompts {
+            println!("   {}", prompt["name"].as_str().unwrap_or_default());
+            if let Some(description) = prompt["description"].as_str() {
+                print_indented(description, 6);
+            }
+            for argument in prompt["arguments"].as_array().into_iter().flatten() {
+                println!(
+                    "      - {}{}{}",
+                    argument["name"].as_str().unwrap_or_default(),
+                    if argument["required"] == true {
+                        " (required)"
+                    } else {
+                        ""
+                    },
+                    argument["description"]
+                        .as_str()
+                        .map(|description| format!(": {description}"))
+                        .unwrap_or_default()
+                );
+            }
+        }
+    }
+}
+
+fn print_indented(text: &str, indent: usize) {
+    for line in text.lines() {
+        println!("{:indent$}{line}", "");
+    }
+}
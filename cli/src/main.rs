@@ -1,18 +1,28 @@
 use anyhow::{Context, Result, anyhow};
 use clap::Parser;
 use heck::{ToPascalCase, ToSnakeCase};
-use proc_macro2::Span;
+use params::Param;
+use proc_macro2::{Delimiter, Span, TokenTree};
 use quote::{format_ident, quote};
+use std::ops::Range;
 use std::path::PathBuf;
 use std::{fs, path::Path};
 use syn::parse::ParseStream;
 use syn::{
-    File, Ident, Item, ItemImpl, ItemMacro, ItemStruct, LitStr, Token, parenthesized, parse::Parse,
-    parse_quote, parse2, punctuated::Punctuated,
+    File, Ident, Item, ItemImpl, ItemMacro, ItemStruct, LitStr, MacroDelimiter, Token, Type,
+    parenthesized, parse::Parse, parse_quote, parse2, punctuated::Punctuated,
 };
 
+mod call;
+mod dev;
+mod doctor;
+mod inspect;
+mod params;
+mod template;
 #[cfg(test)]
 mod tests;
+mod watch;
+mod workspace;
 
 #[derive(Parser)]
 #[command(name = "mcplease")]
@@ -34,6 +44,10 @@ enum Commands {
         #[arg(long, value_delimiter = ',')]
         tools: Vec<String>,
 
+        /// Parameters for each generated tool, like "path:string,recursive:bool,depth:u32?"
+        #[arg(long, value_delimiter = ',')]
+        params: Vec<String>,
+
         /// State type name
         #[arg(long, default_value = "State")]
         state: String,
@@ -49,11 +63,103 @@ enum Commands {
         /// Instructions for the MCP server
         #[arg(long)]
         instructions: Option<String>,
+
+        /// Persist session data with a SessionStore, and generate a tool that uses it
+        #[arg(long)]
+        with_sessions: bool,
+
+        /// Generate the project from a template instead of the built-in layout: a git url, a
+        /// directory, or the name of a directory in ~/.config/mcplease/templates
+        /// (%APPDATA%\mcplease\templates on Windows)
+        #[arg(long)]
+        template: Option<String>,
+
+        /// Add the project to the cargo workspace it is created in. This happens whenever there
+        /// is one, so this only makes it an error if there isn't
+        #[arg(long, conflicts_with = "no_workspace")]
+        workspace: bool,
+
+        /// Don't add the project to the cargo workspace it is created in, and make it a
+        /// workspace of its own instead
+        #[arg(long)]
+        no_workspace: bool,
     },
     /// Add a new tool to an existing project
     Add {
         /// Tool name to add
         tool: String,
+
+        /// The tool's parameters, like "path:string,recursive:bool,depth:u32?"
+        #[arg(long, value_delimiter = ',')]
+        params: Vec<String>,
+    },
+    /// Remove a tool from an existing project
+    Remove {
+        /// Tool name to remove
+        #[arg(long)]
+        tool: String,
+    },
+    /// Rename a tool in an existing project
+    Rename {
+        /// Current tool name
+        #[arg(long)]
+        from: String,
+
+        /// New tool name
+        #[arg(long)]
+        to: String,
+    },
+    /// List the tools in an existing project, with their descriptions and parameters
+    List,
+    /// Build and start the project's server, then call its tools interactively
+    Dev {
+        /// Print tool results as raw JSON
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Build and start the project's server, call one of its tools, and print the result
+    Call {
+        /// The tool's name
+        tool: String,
+
+        /// An argument as key=value, with a string or JSON value. Can be repeated
+        #[arg(long = "arg", value_name = "KEY=VALUE", conflicts_with = "json")]
+        args: Vec<String>,
+
+        /// All of the arguments as a JSON object
+        #[arg(long)]
+        json: Option<String>,
+
+        /// Print the result as raw JSON
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Serve the project over stdio, rebuilding and restarting it whenever its source changes
+    /// while the client stays connected
+    Watch {
+        /// Project directory, for clients that can't set the working directory
+        #[arg(long)]
+        path: Option<PathBuf>,
+
+        /// Arguments for the server, after `--` [default: serve]
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// Start any MCP server that speaks stdio and describe its tools, resources, and prompts
+    Inspect {
+        /// Print everything as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// The server command and its arguments, after `--`
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Check an existing project for common problems
+    Doctor {
+        /// Also build and start the server to check that every tool's schema can be generated
+        #[arg(long)]
+        run: bool,
     },
 }
 
@@ -64,57 +170,131 @@ fn main() -> Result<()> {
         Commands::Create {
             name,
             tools,
+            params,
             state,
             output,
             description,
             instructions,
+            with_sessions,
+            template,
+            workspace,
+            no_workspace,
         } => {
-            let output_dir = output.unwrap_or_else(|| PathBuf::from(&name));
+            let params = Param::parse_all(&params)?;
+            let enclosing =
+                workspace::Workspace::find(output.as_deref().unwrap_or(Path::new(".")))?;
+            if workspace && enclosing.is_none() {
+                return Err(anyhow!(
+                    "No cargo workspace contains {}",
+                    output.as_deref().unwrap_or(Path::new(".")).display()
+                ));
+            }
+            let mut workspace = enclosing.filter(|_| !no_workspace);
+
+            let output_dir = output
+                .or_else(|| workspace.as_ref()?.default_output(&name))
+                .unwrap_or_else(|| PathBuf::from(&name));
 
             if output_dir.exists() {
                 return Err(anyhow!("Directory {} already exists", output_dir.display()));
             }
 
-            create_project(
-                &CreateOptions {
-                    name: &name,
-                    tools: &tools,
-                    state: &state,
-                    description: description.as_deref(),
-                    instructions: instructions.as_deref(),
-                },
-                &output_dir,
-            )?;
-
-            println!("✅ Created MCP server project: {}", output_dir.display());
-            println!("📁 Project structure:");
-            println!("   {name}/");
-            println!("   ├── Cargo.toml");
-            println!("   └── src/");
-            println!("       ├── main.rs");
-            println!("       ├── state.rs");
-            println!("       ├── tools.rs");
-            println!("       └── tools/");
-            for (n, tool) in tools.iter().enumerate() {
-                let snake_case = tool.to_snake_case();
-                if n == tools.len() - 1 {
-                    println!("           └── {snake_case}.rs");
-                } else {
-                    println!("           ├── {snake_case}.rs");
+            // the project is a member before it's generated, so that cargo fmt can run in it
+            if let Some(workspace) = &mut workspace {
+                workspace.add_member(&output_dir)?;
+            }
+
+            let opts = CreateOptions {
+                name: &name,
+                tools: &tools,
+                params: &params,
+                state: &state,
+                description: description.as_deref(),
+                instructions: instructions.as_deref(),
+                with_sessions,
+            };
+
+            if let Some(template) = template {
+                let files = template::Template::load(&template)?.render(&opts, &output_dir)?;
+                println!(
+                    "✅ Created MCP server project: {} from {template}",
+                    output_dir.display()
+                );
+                println!("📁 Files:");
+                for file in files {
+                    println!("   {}", file.display());
+                }
+            } else {
+                create_project(&opts, &output_dir)?;
+
+                println!("✅ Created MCP server project: {}", output_dir.display());
+                println!("📁 Project structure:");
+                println!("   {name}/");
+                println!("   ├── Cargo.toml");
+                println!("   └── src/");
+                println!("       ├── config.rs");
+                println!("       ├── main.rs");
+                println!("       ├── state.rs");
+                println!("       ├── tools.rs");
+                println!("       └── tools/");
+                let tools = CreateOptions::all_tools(&tools, with_sessions);
+                for (n, tool) in tools.iter().enumerate() {
+                    let snake_case = tool.to_snake_case();
+                    if n == tools.len() - 1 {
+                        println!("           └── {snake_case}.rs");
+                    } else {
+                        println!("           ├── {snake_case}.rs");
+                    }
                 }
             }
+
+            let manifest_path = output_dir.join("Cargo.toml");
+            if let Some(workspace) = &workspace
+                && manifest_path.exists()
+            {
+                workspace.inherit(&manifest_path)?;
+                println!(
+                    "🧩 Added to the workspace at {}",
+                    workspace.root().display()
+                );
+            } else if manifest_path.exists() {
+                workspace::isolate_if_unlisted(&output_dir)?;
+            }
+
             println!();
             println!("🚀 Next steps:");
-            println!("   cd {name}");
+            println!("   cd {}", output_dir.display());
             println!("   cargo check  # Verify everything compiles");
             println!("   cargo run serve  # Start the MCP server");
 
             Ok(())
         }
-        Commands::Add { tool } => {
-            add_tool_to_project(&tool)?;
+        Commands::Add { tool, params } => {
+            add_tool_to_project(&tool, &Param::parse_all(&params)?)?;
             Ok(())
         }
+        Commands::Remove { tool } => {
+            remove_tool_from_project(&tool)?;
+            Ok(())
+        }
+        Commands::Rename { from, to } => {
+            rename_tool_in_project(&from, &to)?;
+            Ok(())
+        }
+        Commands::List => {
+            print_tools_table(&list_tools(None)?);
+            Ok(())
+        }
+        Commands::Dev { raw } => dev::run(None, raw),
+        Commands::Call {
+            tool,
+            args,
+            json,
+            raw,
+        } => call::run(None, &tool, &args, json.as_deref(), raw),
+        Commands::Watch { path, args } => watch::run(path.as_deref(), &args),
+        Commands::Inspect { json, command } => inspect::run(&command, json),
+        Commands::Doctor { run } => doctor::report(&doctor::diagnose(None, run)?),
     }
 }
 
@@ -130,6 +310,8 @@ struct ToolEntry {
     struct_name: Ident,
     mod_name: Ident,
     string_name: LitStr,
+    /// The sub-state the tool works with, like `GitState` in `(Status, status, "status", GitState)`
+    substate: Option<Type>,
 }
 
 impl Parse for ToolsMacroArgs {
@@ -153,11 +335,17 @@ impl Parse for ToolEntry {
         let mod_name = content.parse()?;
         content.parse::<Token![,]>()?;
         let string_name = content.parse()?;
+        let substate = if content.parse::<Option<Token![,]>>()?.is_some() {
+            Some(content.parse()?)
+        } else {
+            None
+        };
 
         Ok(ToolEntry {
             struct_name,
             mod_name,
             string_name,
+            substate,
         })
     }
 }
@@ -199,36 +387,242 @@ fn format_tools_file(project_path: &Path) -> Result<()> {
     Ok(())
 }
 
-fn add_tool_to_project_impl(tool_name: &str, project_path: Option<&std::path::Path>) -> Result<()> {
-    let base_path = project_path
-        .map(|p| p.to_path_buf())
-        .unwrap_or_else(|| PathBuf::from("."));
+/// The parsed `src/tools.rs` of a project, along with the arguments of its `tools!` macro
+///
+/// Edits change only the text of the entries they touch, so comments, attributes, and formatting
+/// anywhere else in the file, including between the entries, stay exactly as they were.
+struct ToolsFile {
+    base_path: PathBuf,
+    path: PathBuf,
+    /// The contents of src/tools.rs, with any edits so far
+    source: String,
+    args: ToolsMacroArgs,
+    /// Where each of `args.tools` is in `source`
+    entries: Vec<EntryText>,
+    /// Where the macro's closing delimiter is in `source`
+    close: usize,
+}
 
-    // 1. Check if we're in a project directory
-    let tools_rs_path = base_path.join("src/tools.rs");
-    if !tools_rs_path.exists() {
-        return Err(anyhow!(
-            "No src/tools.rs found at {}. Run this command from the root of an mcplease project.",
-            tools_rs_path.display()
-        ));
+/// Where a tool entry is in the source of src/tools.rs
+struct EntryText {
+    /// From the entry's opening parenthesis to its closing one
+    range: Range<usize>,
+    /// The comma after the entry, if any
+    comma: Option<Range<usize>>,
+}
+
+impl ToolsFile {
+    fn read(project_path: Option<&Path>) -> Result<Self> {
+        let base_path = project_path
+            .map(|p| p.to_path_buf())
+            .unwrap_or_else(|| PathBuf::from("."));
+
+        // 1. Check if we're in a project directory
+        let path = base_path.join("src/tools.rs");
+        if !path.exists() {
+            return Err(anyhow!(
+                "No src/tools.rs found at {}. Run this command from the root of an mcplease project.",
+                path.display()
+            ));
+        }
+
+        // 2. Parse tools.rs and its tools! macro
+        let source = fs::read_to_string(&path).context("Failed to read src/tools.rs")?;
+        let (args, entries, close) = parse_tools_source(&source)?;
+
+        Ok(Self {
+            base_path,
+            path,
+            source,
+            args,
+            entries,
+            close,
+        })
+    }
+
+    /// Find the entry for `tool_name`, which can be the tool's name or its struct name
+    fn position(&self, tool_name: &str) -> Option<usize> {
+        let snake_name = tool_name.to_snake_case();
+        self.args.tools.iter().position(|t| {
+            t.string_name.value() == snake_name || t.struct_name == tool_name.to_pascal_case()
+        })
+    }
+
+    fn tool_file_path(&self, tool: &ToolEntry) -> PathBuf {
+        self.base_path
+            .join("src/tools")
+            .join(format!("{}.rs", tool.mod_name))
+    }
+
+    /// Add `tool` after the last entry, on a line of its own if the last entry is on one
+    fn push(&mut self, tool: &ToolEntry) -> Result<()> {
+        let last = self.entries.last().context("tools! macro has no tools")?;
+        let text = entry_text(tool);
+        let indent = &self.source[self.line_start(last.range.start)..last.range.start];
+
+        let edits = if indent.trim().is_empty() {
+            // After the rest of the last entry's line, so a comment there stays with it
+            let end = last
+                .comma
+                .as_ref()
+                .map_or(last.range.end, |comma| comma.end);
+            let line_end = self.line_end(end).min(self.close);
+            match &last.comma {
+                Some(_) => vec![(line_end..line_end, format!("\n{indent}{text},"))],
+                None => vec![
+                    (last.range.end..last.range.end, ",".to_string()),
+                    (line_end..line_end, format!("\n{indent}{text}")),
+                ],
+            }
+        } else {
+            match &last.comma {
+                Some(comma) => vec![(comma.end..comma.end, format!(" {text},"))],
+                None => vec![(last.range.end..last.range.end, format!(", {text}"))],
+            }
+        };
+        self.edit(edits)
+    }
+
+    /// Remove the entry at `position`, along with its line if it is on one of its own
+    fn remove(&mut self, position: usize) -> Result<()> {
+        let entry = &self.entries[position];
+        let end = entry
+            .comma
+            .as_ref()
+            .map_or(entry.range.end, |comma| comma.end);
+        let line_start = self.line_start(entry.range.start);
+        let line_end = self.line_end(end);
+        let rest = self.source[end..line_end].trim();
+        // The comma before the last entry separates it from the one before, and goes with it
+        let previous_comma = match (&entry.comma, position.checked_sub(1)) {
+            (None, Some(previous)) => self.entries[previous].comma.clone(),
+            _ => None,
+        };
+
+        let mut edits = vec![];
+        if self.source[line_start..entry.range.start].trim().is_empty()
+            && (rest.is_empty() || rest.starts_with("//"))
+        {
+            let line_end = (line_end + 1).min(self.source.len());
+            edits.push((line_start..line_end, String::new()));
+            edits.extend(previous_comma.map(|comma| (comma, String::new())));
+        } else if let Some(comma) = previous_comma {
+            edits.push((comma.start..entry.range.end, String::new()));
+        } else {
+            let spaces =
+                self.source[end..].len() - self.source[end..].trim_start_matches([' ', '\t']).len();
+            edits.push((entry.range.start..end + spaces, String::new()));
+        }
+        self.edit(edits)
+    }
+
+    /// Replace the names in the entry at `position` with those of `tool`, keeping its sub-state
+    fn replace(&mut self, position: usize, tool: &ToolEntry) -> Result<()> {
+        let start = self.entries[position].range.start;
+        let names_end = self.args.tools[position]
+            .string_name
+            .span()
+            .byte_range()
+            .end;
+        self.edit(vec![(start..names_end, entry_names(tool))])
     }
 
-    // 2. Parse tools.rs
-    let tools_content =
-        fs::read_to_string(&tools_rs_path).context("Failed to read src/tools.rs")?;
-    let file: File = syn::parse_str(&tools_content).context("Failed to parse src/tools.rs")?;
+    /// Apply non-overlapping `edits` to the source, and parse it again. Insertions at the same
+    /// offset end up in the order they are given.
+    fn edit(&mut self, mut edits: Vec<(Range<usize>, String)>) -> Result<()> {
+        edits.sort_by_key(|(range, _)| range.start);
+        for (range, text) in edits.into_iter().rev() {
+            self.source.replace_range(range, &text);
+        }
+        (self.args, self.entries, self.close) = parse_tools_source(&self.source)?;
+        Ok(())
+    }
+
+    fn line_start(&self, offset: usize) -> usize {
+        self.source[..offset].rfind('\n').map_or(0, |n| n + 1)
+    }
+
+    /// Where the line that `offset` is on ends, before its newline
+    fn line_end(&self, offset: usize) -> usize {
+        self.source[offset..]
+            .find('\n')
+            .map_or(self.source.len(), |n| offset + n)
+    }
+
+    fn write(&self) -> Result<()> {
+        fs::write(&self.path, &self.source).context("Failed to write src/tools.rs")
+    }
+}
+
+/// Parse the `tools!` macro in the source of src/tools.rs, returning its arguments, where each
+/// tool entry is, and where the macro's closing delimiter is
+fn parse_tools_source(source: &str) -> Result<(ToolsMacroArgs, Vec<EntryText>, usize)> {
+    let file: File = syn::parse_str(source).context("Failed to parse src/tools.rs")?;
 
-    // 3. Find the tools! macro
     let tools_macro =
         find_tools_macro(&file).ok_or_else(|| anyhow!("No tools! macro found in src/tools.rs"))?;
 
-    // 4. Parse the macro arguments
-    let mut args: ToolsMacroArgs =
+    let args: ToolsMacroArgs =
         parse2(tools_macro.mac.tokens.clone()).context("Failed to parse tools! macro arguments")?;
 
-    // 5. Check if tool already exists
+    // The arguments parsed, so the parenthesized groups are the entries, and the commas after
+    // them are theirs
+    let mut entries: Vec<EntryText> = vec![];
+    for tree in tools_macro.mac.tokens.clone() {
+        match tree {
+            TokenTree::Group(group) if group.delimiter() == Delimiter::Parenthesis => {
+                entries.push(EntryText {
+                    range: group.span().byte_range(),
+                    comma: None,
+                });
+            }
+            TokenTree::Punct(punct) if punct.as_char() == ',' => {
+                if let Some(entry) = entries.last_mut() {
+                    entry.comma = Some(punct.span().byte_range());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let close = match &tools_macro.mac.delimiter {
+        MacroDelimiter::Paren(paren) => paren.span.close(),
+        MacroDelimiter::Brace(brace) => brace.span.close(),
+        MacroDelimiter::Bracket(bracket) => bracket.span.close(),
+    };
+
+    Ok((args, entries, close.byte_range().start))
+}
+
+/// A tool entry, the way `mcplease create` writes them
+fn entry_text(tool: &ToolEntry) -> String {
+    match &tool.substate {
+        Some(substate) => format!("{}, {})", entry_names(tool), quote!(#substate)),
+        None => format!("{})", entry_names(tool)),
+    }
+}
+
+/// The start of a tool entry, up to the end of its names
+fn entry_names(tool: &ToolEntry) -> String {
+    format!(
+        "({}, {}, {}",
+        tool.struct_name,
+        tool.mod_name,
+        tool.string_name.token()
+    )
+}
+
+fn add_tool_to_project_impl(
+    tool_name: &str,
+    params: &[Param],
+    project_path: Option<&std::path::Path>,
+) -> Result<()> {
+    let mut tools_file = ToolsFile::read(project_path)?;
+
+    // Check if tool already exists
     let snake_name = tool_name.to_snake_case();
-    if args
+    if tools_file
+        .args
         .tools
         .iter()
         .any(|t| t.string_name.value() == snake_name)
@@ -236,26 +630,23 @@ fn add_tool_to_project_impl(tool_name: &str, project_path: Option<&std::path::Pa
         return Err(anyhow!("Tool '{}' already exists", tool_name));
     }
 
-    // 6. Add the new tool
+    // Add the new tool
     let new_tool = ToolEntry {
         struct_name: format_ident!("{}", tool_name.to_pascal_case()),
         mod_name: format_ident!("{}", snake_name),
         string_name: LitStr::new(&snake_name, Span::call_site()),
+        substate: None,
     };
-    args.tools.push(new_tool);
+    tools_file.push(&new_tool)?;
+    tools_file.write()?;
 
-    // 7. Regenerate the file
-    let new_file = regenerate_tools_file(&file, &args)?;
-    let formatted = prettyplease::unparse(&new_file);
-    fs::write(&tools_rs_path, formatted).context("Failed to write src/tools.rs")?;
-
-    // 8. Format the file with cargo fmt for better macro formatting
-    format_tools_file(&base_path).unwrap_or_else(|e| {
-        eprintln!("Warning: cargo fmt failed ({e}), but file was generated successfully");
-    });
-
-    // 9. Generate the tool file
-    generate_tool_file(tool_name, &args.state_type.to_string(), &base_path)?;
+    // Generate the tool file
+    generate_tool_file(
+        tool_name,
+        params,
+        &tools_file.args.state_type.to_string(),
+        &tools_file.base_path,
+    )?;
 
     println!("✅ Added tool '{tool_name}' to the project");
     println!("📁 Generated: src/tools/{snake_name}.rs");
@@ -264,76 +655,326 @@ fn add_tool_to_project_impl(tool_name: &str, project_path: Option<&std::path::Pa
     Ok(())
 }
 
-fn add_tool_to_project(tool_name: &str) -> Result<()> {
-    add_tool_to_project_impl(tool_name, None)
+fn add_tool_to_project(tool_name: &str, params: &[Param]) -> Result<()> {
+    add_tool_to_project_impl(tool_name, params, None)
 }
 
 #[cfg(test)]
 fn add_tool_to_project_at_path(tool_name: &str, project_path: &std::path::Path) -> Result<()> {
-    add_tool_to_project_impl(tool_name, Some(project_path))
+    add_tool_to_project_impl(tool_name, &[], Some(project_path))
 }
 
-fn regenerate_tools_file(original: &File, args: &ToolsMacroArgs) -> Result<File> {
-    let mut new_items = Vec::new();
+fn remove_tool_from_project_impl(tool_name: &str, project_path: Option<&Path>) -> Result<()> {
+    let mut tools_file = ToolsFile::read(project_path)?;
 
-    // Copy all non-macro items
-    for item in &original.items {
-        if !matches!(item, syn::Item::Macro(_mac) if find_tools_macro(&syn::File {
-            shebang: None,
-            attrs: vec![],
-            items: vec![item.clone()],
-        }).is_some())
-        {
-            new_items.push(item.clone());
-        }
+    let position = tools_file
+        .position(tool_name)
+        .ok_or_else(|| anyhow!("Tool '{}' not found in src/tools.rs", tool_name))?;
+
+    // tools! needs at least one tool, so the last one can't be removed
+    if tools_file.args.tools.len() == 1 {
+        return Err(anyhow!(
+            "Tool '{}' is the only tool in the project. Add another tool before removing it.",
+            tool_name
+        ));
     }
 
-    // Generate new tools! macro call using quote!
-    let state_ident = &args.state_type;
-    let tool_entries = args.tools.iter().map(|tool| {
-        let struct_name = &tool.struct_name;
-        let mod_name = &tool.mod_name;
-        let string_name = &tool.string_name;
-        quote! {
-            (#struct_name, #mod_name, #string_name)
-        }
-    });
+    let removed = tools_file.args.tools[position].clone();
+    tools_file.remove(position)?;
+    tools_file.write()?;
 
-    let tools_macro_tokens = quote! {
-        #state_ident,
-        #(
-            #tool_entries
-        ),*
+    let tool_file_path = tools_file.tool_file_path(&removed);
+    let display_path = format!("src/tools/{}.rs", removed.mod_name);
+    println!(
+        "✅ Removed tool '{}' from the project",
+        removed.string_name.value()
+    );
+    if tool_file_path.exists() {
+        fs::remove_file(&tool_file_path)
+            .with_context(|| format!("Failed to remove {display_path}"))?;
+        println!("🗑️  Deleted: {display_path}");
+    } else {
+        eprintln!("Warning: {display_path} did not exist, so only src/tools.rs was changed");
+    }
+    println!("🔧 Updated: src/tools.rs");
+
+    Ok(())
+}
+
+fn remove_tool_from_project(tool_name: &str) -> Result<()> {
+    remove_tool_from_project_impl(tool_name, None)
+}
+
+#[cfg(test)]
+fn remove_tool_from_project_at_path(tool_name: &str, project_path: &Path) -> Result<()> {
+    remove_tool_from_project_impl(tool_name, Some(project_path))
+}
+
+fn rename_tool_in_project_impl(from: &str, to: &str, project_path: Option<&Path>) -> Result<()> {
+    let mut tools_file = ToolsFile::read(project_path)?;
+
+    let position = tools_file
+        .position(from)
+        .ok_or_else(|| anyhow!("Tool '{}' not found in src/tools.rs", from))?;
+    if tools_file.position(to).is_some() {
+        return Err(anyhow!("Tool '{}' already exists", to));
+    }
+
+    let old = tools_file.args.tools[position].clone();
+    let snake_name = to.to_snake_case();
+    let new = ToolEntry {
+        struct_name: format_ident!("{}", to.to_pascal_case()),
+        mod_name: format_ident!("{}", snake_name),
+        string_name: LitStr::new(&snake_name, Span::call_site()),
+        substate: old.substate.clone(),
     };
 
-    // Create the macro item
-    let tools_macro_item = Item::Macro(ItemMacro {
-        attrs: vec![],
-        ident: None,
-        mac: syn::Macro {
-            path: parse_quote! { mcplease::tools },
-            bang_token: Default::default(),
-            delimiter: syn::MacroDelimiter::Paren(Default::default()),
-            tokens: tools_macro_tokens,
+    let old_path = tools_file.tool_file_path(&old);
+    let new_path = tools_file.tool_file_path(&new);
+    if new_path.exists() {
+        return Err(anyhow!("src/tools/{}.rs already exists", new.mod_name));
+    }
+
+    // The tool file is edited as text rather than regenerated, to preserve its comments and
+    // formatting
+    let content = fs::read_to_string(&old_path)
+        .with_context(|| format!("Failed to read src/tools/{}.rs", old.mod_name))?;
+    let content = replace_ident(
+        &content,
+        &old.struct_name.to_string(),
+        &new.struct_name.to_string(),
+    );
+    let content = rename_serde_attribute(&content, &old.string_name.value(), &snake_name);
+    fs::write(&new_path, content)
+        .with_context(|| format!("Failed to write src/tools/{}.rs", new.mod_name))?;
+    if old_path != new_path {
+        fs::remove_file(&old_path)
+            .with_context(|| format!("Failed to remove src/tools/{}.rs", old.mod_name))?;
+    }
+
+    tools_file.replace(position, &new)?;
+    tools_file.write()?;
+
+    println!(
+        "✅ Renamed tool '{}' to '{}'",
+        old.string_name.value(),
+        new.string_name.value()
+    );
+    println!(
+        "📁 Moved: src/tools/{}.rs -> src/tools/{}.rs",
+        old.mod_name, new.mod_name
+    );
+    println!("🔧 Updated: src/tools.rs");
+
+    Ok(())
+}
+
+fn rename_tool_in_project(from: &str, to: &str) -> Result<()> {
+    rename_tool_in_project_impl(from, to, None)
+}
+
+#[cfg(test)]
+fn rename_tool_in_project_at_path(from: &str, to: &str, project_path: &Path) -> Result<()> {
+    rename_tool_in_project_impl(from, to, Some(project_path))
+}
+
+/// What `mcplease list` reports about a tool
+#[derive(Debug)]
+struct ToolInfo {
+    name: String,
+    struct_name: String,
+    /// The first paragraph of the struct's doc comment, or `None` if the tool file or struct
+    /// couldn't be found
+    description: Option<String>,
+    /// Field names and types
+    parameters: Vec<(String, String)>,
+}
+
+fn list_tools(project_path: Option<&Path>) -> Result<Vec<ToolInfo>> {
+    let tools_file = ToolsFile::read(project_path)?;
+
+    tools_file
+        .args
+        .tools
+        .iter()
+        .map(|tool| {
+            let mut info = ToolInfo {
+                name: tool.string_name.value(),
+                struct_name: tool.struct_name.to_string(),
+                description: None,
+                parameters: vec![],
+            };
+
+            let path = tools_file.tool_file_path(tool);
+            let Ok(content) = fs::read_to_string(&path) else {
+                return Ok(info);
+            };
+            let file: File = syn::parse_str(&content)
+                .with_context(|| format!("Failed to parse src/tools/{}.rs", tool.mod_name))?;
+            if let Some(item) = find_struct(&file, &tool.struct_name) {
+                info.description = Some(doc_comment(&item.attrs));
+                info.parameters = item
+                    .fields
+                    .iter()
+                    .enumerate()
+                    .map(|(n, field)| {
+                        let name = field
+                            .ident
+                            .as_ref()
+                            .map_or_else(|| n.to_string(), ToString::to_string);
+                        let ty = &field.ty;
+                        (name, type_to_string(&quote!(#ty).to_string()))
+                    })
+                    .collect();
+            }
+            Ok(info)
+        })
+        .collect()
+}
+
+fn find_struct<'a>(file: &'a File, name: &Ident) -> Option<&'a ItemStruct> {
+    file.items.iter().find_map(|item| match item {
+        Item::Struct(item) if item.ident == *name => Some(item),
+        _ => None,
+    })
+}
+
+/// The first paragraph of the doc comment in `attrs`, joined into one line
+fn doc_comment(attrs: &[syn::Attribute]) -> String {
+    let lines = attrs.iter().filter_map(|attr| match &attr.meta {
+        syn::Meta::NameValue(meta) if meta.path.is_ident("doc") => match &meta.value {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(doc),
+                ..
+            }) => Some(doc.value()),
+            _ => None,
         },
-        semi_token: Some(Default::default()),
+        _ => None,
     });
 
-    new_items.push(tools_macro_item);
+    lines
+        .map(|line| line.trim().to_string())
+        .skip_while(String::is_empty)
+        .take_while(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
 
-    Ok(File {
-        shebang: original.shebang.clone(),
-        attrs: original.attrs.clone(),
-        items: new_items,
-    })
+/// Tidy the spacing of a type printed by `quote`, `Option < Vec < String > >`
+fn type_to_string(tokens: &str) -> String {
+    tokens
+        .replace(" < ", "<")
+        .replace("< ", "<")
+        .replace(" >", ">")
+        .replace(" ,", ",")
+        .replace("& ", "&")
+        .replace(" :: ", "::")
+}
+
+fn print_tools_table(tools: &[ToolInfo]) {
+    let rows: Vec<[String; 4]> = tools
+        .iter()
+        .map(|tool| {
+            let description = match &tool.description {
+                Some(description) if description.is_empty() => "-".to_string(),
+                Some(description) => description.clone(),
+                None => "(tool file or struct not found)".to_string(),
+            };
+            let parameters = if tool.parameters.is_empty() {
+                "-".to_string()
+            } else {
+                tool.parameters
+                    .iter()
+                    .map(|(name, ty)| format!("{name}: {ty}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            [
+                tool.name.clone(),
+                tool.struct_name.clone(),
+                description,
+                parameters,
+            ]
+        })
+        .collect();
+
+    let header = ["NAME", "STRUCT", "DESCRIPTION", "PARAMETERS"].map(String::from);
+    let mut widths = header.each_ref().map(|column| column.chars().count());
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.chars().count());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(column, width)| format!("{column:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+}
+
+/// Replace every occurrence of the identifier `from` in `source` with `to`, leaving longer
+/// identifiers that merely contain `from` alone
+fn replace_ident(source: &str, from: &str, to: &str) -> String {
+    let is_ident_char = |c: char| c.is_alphanumeric() || c == '_';
+    let mut result = String::with_capacity(source.len());
+    let mut last = 0;
+    for (start, _) in source.match_indices(from) {
+        let end = start + from.len();
+        let before = source[..start].chars().next_back();
+        let after = source[end..].chars().next();
+        if start >= last && !before.is_some_and(is_ident_char) && !after.is_some_and(is_ident_char)
+        {
+            result.push_str(&source[last..start]);
+            result.push_str(to);
+            last = end;
+        }
+    }
+    result.push_str(&source[last..]);
+    result
+}
+
+/// Update `#[serde(rename = "from")]` to `#[serde(rename = "to")]`
+fn rename_serde_attribute(source: &str, from: &str, to: &str) -> String {
+    source
+        .replace(
+            &format!("rename = \"{from}\""),
+            &format!("rename = \"{to}\""),
+        )
+        .replace(&format!("rename=\"{from}\""), &format!("rename=\"{to}\""))
 }
 
 pub struct CreateOptions<'a> {
     pub name: &'a str,
     pub tools: &'a [String],
+    /// The parameters of each generated tool, or an `example_param` if empty
+    pub params: &'a [Param],
     pub state: &'a str,
     pub description: Option<&'a str>,
     pub instructions: Option<&'a str>,
+    pub with_sessions: bool,
+}
+
+/// The tool generated by `--with-sessions` to show reading and writing session data
+const SESSION_TOOL: &str = "remember";
+
+impl CreateOptions<'_> {
+    /// The requested tools, plus the session example tool if sessions are enabled
+    fn all_tools(tools: &[String], with_sessions: bool) -> Vec<String> {
+        let mut tools = tools.to_vec();
+        if with_sessions
+            && !tools
+                .iter()
+                .any(|tool| tool.to_snake_case() == SESSION_TOOL)
+        {
+            tools.push(SESSION_TOOL.to_string());
+        }
+        tools
+    }
 }
 
 pub fn create_project(opts: &CreateOptions, output_dir: &Path) -> Result<()> {
@@ -342,27 +983,48 @@ pub fn create_project(opts: &CreateOptions, output_dir: &Path) -> Result<()> {
     fs::create_dir_all(output_dir.join("src"))?;
     fs::create_dir_all(output_dir.join("src/tools"))?;
 
+    let tools = CreateOptions::all_tools(opts.tools, opts.with_sessions);
+    let opts = &CreateOptions {
+        tools: &tools,
+        ..*opts
+    };
+
     // Generate files
     generate_cargo_toml(opts, output_dir)?;
     generate_main_rs(opts, output_dir)?;
-    generate_state_rs(opts, output_dir)?;
+    generate_config_rs(opts, output_dir)?;
+    if opts.with_sessions {
+        generate_session_state_rs(opts, output_dir)?;
+    } else {
+        generate_state_rs(opts, output_dir)?;
+    }
     generate_tools_rs(opts, output_dir)?;
 
     // Generate individual tool files
     for tool in opts.tools {
-        generate_tool_file(tool, opts.state, output_dir)?;
+        if opts.with_sessions && tool.to_snake_case() == SESSION_TOOL {
+            generate_session_tool_file(opts.state, output_dir)?;
+        } else {
+            generate_tool_file(tool, opts.params, opts.state, output_dir)?;
+        }
     }
 
     Ok(())
 }
 
+/// The version requirement for mcplease in generated projects, the major and minor version of
+/// this CLI
+fn mcplease_version() -> Result<String> {
+    let version = semver::Version::parse(env!("CARGO_PKG_VERSION"))?;
+    Ok(format!("{}.{}", version.major, version.minor))
+}
+
 fn generate_cargo_toml(opts: &CreateOptions, output_dir: &Path) -> Result<()> {
     let description = opts
         .description
         .unwrap_or("An MCP server built with mcplease");
 
-    let mcplease_version = semver::Version::parse(env!("CARGO_PKG_VERSION"))?;
-    let mcplease_version = format!("{}.{}", mcplease_version.major, mcplease_version.minor);
+    let mcplease_version = mcplease_version()?;
 
     let content = format!(
         r#"[package]
@@ -384,6 +1046,7 @@ serde_json = "1"
     );
 
     fs::write(output_dir.join("Cargo.toml"), content).context("Failed to write Cargo.toml")?;
+    workspace::isolate_if_unlisted(output_dir)?;
 
     Ok(())
 }
@@ -395,17 +1058,20 @@ fn generate_main_rs(opts: &CreateOptions, output_dir: &Path) -> Result<()> {
         .unwrap_or("TODO: Add instructions for your MCP server");
 
     let file: File = parse_quote! {
+        mod config;
         mod state;
         mod tools;
 
         use anyhow::Result;
+        use config::Config;
         use mcplease::server_info;
         use state::#state_ident;
 
         const INSTRUCTIONS: &str = #instructions;
 
         fn main() -> Result<()> {
-            let mut state = #state_ident::new()?;
+            let config = Config::load()?;
+            let mut state = #state_ident::new(&config)?;
             mcplease::run::<tools::Tools, _>(&mut state, server_info!(), Some(INSTRUCTIONS))
         }
     };
@@ -416,10 +1082,55 @@ fn generate_main_rs(opts: &CreateOptions, output_dir: &Path) -> Result<()> {
     Ok(())
 }
 
+fn generate_config_rs(opts: &CreateOptions, output_dir: &Path) -> Result<()> {
+    let name = opts.name;
+    // the prefix mcplease::config::load reads variables with
+    let prefix = name.to_uppercase().replace(['-', '.', ' '], "_");
+    let summary = format!(
+        " Configuration for the MCP server, read from `{prefix}_*` environment variables and \
+         `config.toml`"
+    );
+    let sources = format!(
+        " in its config directory, or the file named by `{prefix}_CONFIG`. Variables take precedence."
+    );
+    let example = format!(
+        " `max_results: usize` is set by `max_results = 50` in the file or `{prefix}_MAX_RESULTS=50`."
+    );
+
+    let file: File = parse_quote! {
+        use anyhow::Result;
+        use serde::Deserialize;
+
+        #[doc = #summary]
+        #[doc = #sources]
+        ///
+        /// TODO: Add your configuration fields here, such as API endpoints or limits. A field like
+        #[doc = #example]
+        #[derive(Debug, Clone, Default, Deserialize)]
+        #[serde(default)]
+        pub struct Config {
+            // TODO: Add your configuration fields here
+        }
+
+        impl Config {
+            /// Read the configuration, failing with a message that names any invalid setting
+            pub fn load() -> Result<Self> {
+                mcplease::config::load(#name)
+            }
+        }
+    };
+
+    let content = prettyplease::unparse(&file);
+    fs::write(output_dir.join("src/config.rs"), content).context("Failed to write config.rs")?;
+
+    Ok(())
+}
+
 fn generate_state_rs(opts: &CreateOptions, output_dir: &Path) -> Result<()> {
     let state_ident = format_ident!("{}", opts.state);
 
     let file: File = parse_quote! {
+        use crate::config::Config;
         use anyhow::Result;
 
         /// State for the MCP server
@@ -427,7 +1138,7 @@ fn generate_state_rs(opts: &CreateOptions, output_dir: &Path) -> Result<()> {
         /// TODO: Add your state fields here. Common patterns include:
         /// - Working directory tracking
         /// - Session management with mcplease::session::SessionStore
-        /// - Configuration data
+        /// - Settings from the Config
         /// - Cache or temporary data
         #[derive(Debug)]
         pub struct #state_ident {
@@ -435,7 +1146,7 @@ fn generate_state_rs(opts: &CreateOptions, output_dir: &Path) -> Result<()> {
         }
 
         impl #state_ident {
-            pub fn new() -> Result<Self> {
+            pub fn new(_config: &Config) -> Result<Self> {
                 Ok(Self {
                     // TODO: Initialize your state
                 })
@@ -449,6 +1160,63 @@ fn generate_state_rs(opts: &CreateOptions, output_dir: &Path) -> Result<()> {
     Ok(())
 }
 
+fn generate_session_state_rs(opts: &CreateOptions, output_dir: &Path) -> Result<()> {
+    let state_ident = format_ident!("{}", opts.state);
+    let name = opts.name;
+
+    let file: File = parse_quote! {
+        use crate::config::Config;
+        use anyhow::Result;
+        use mcplease::context::ToolContext;
+        use mcplease::session::SessionStore;
+        use serde::{Deserialize, Serialize};
+
+        /// Data that is saved between runs of the server
+        ///
+        /// TODO: Add your session fields here, such as a working directory
+        #[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+        pub struct SessionData {
+            /// Notes saved with the remember tool
+            pub notes: Vec<String>,
+        }
+
+        /// State for the MCP server
+        #[derive(Debug)]
+        pub struct #state_ident {
+            session_store: SessionStore<SessionData>,
+        }
+
+        impl #state_ident {
+            pub fn new(_config: &Config) -> Result<Self> {
+                let session_store = SessionStore::new(Some(
+                    mcplease::paths::state_dir(#name)?.join("sessions.json"),
+                ))?;
+
+                Ok(Self { session_store })
+            }
+
+            /// The data for the session of the client making this call
+            pub fn session_data(&mut self, context: &ToolContext) -> Result<SessionData> {
+                self.session_store.scoped(context).get_or_create().cloned()
+            }
+
+            /// Change the data for the session of the client making this call and save it
+            pub fn update_session_data(
+                &mut self,
+                context: &ToolContext,
+                fun: impl FnOnce(&mut SessionData),
+            ) -> Result<()> {
+                self.session_store.scoped(context).update(fun)
+            }
+        }
+    };
+
+    let content = prettyplease::unparse(&file);
+    fs::write(output_dir.join("src/state.rs"), content).context("Failed to write state.rs")?;
+
+    Ok(())
+}
+
 fn generate_tools_rs(opts: &CreateOptions, output_dir: &Path) -> Result<()> {
     let state_ident = format_ident!("{}", opts.state);
 
@@ -496,43 +1264,88 @@ fn generate_tools_rs(opts: &CreateOptions, output_dir: &Path) -> Result<()> {
     Ok(())
 }
 
-fn generate_tool_file(tool_name: &str, state_name: &str, output_dir: &Path) -> Result<()> {
+fn generate_tool_file(
+    tool_name: &str,
+    params: &[Param],
+    state_name: &str,
+    output_dir: &Path,
+) -> Result<()> {
     let tool_ident = format_ident!("{}", tool_name.to_pascal_case());
     let state_ident = format_ident!("{}", state_name);
     let snake_name = tool_name.to_snake_case();
 
-    let tool_struct: ItemStruct = parse_quote! {
-        /// TODO: Add description for this tool
-        #[derive(Debug, Serialize, Deserialize, schemars::JsonSchema, clap::Args)]
-        #[serde(rename = #snake_name)]
-        pub struct #tool_ident {
-            /// TODO: Add parameter description
-            pub example_param: String,
-        }
-    };
-
-    let examples_impl: ItemImpl = parse_quote! {
-        impl WithExamples for #tool_ident {
-            fn examples() -> Vec<Example<Self>> {
-                vec![
-                    Example {
-                        description: "TODO: Add example description",
-                        item: Self {
-                            example_param: "example_value".into(),
-                        },
-                    },
-                ]
-            }
-        }
-    };
-
-    let tool_impl: ItemImpl = parse_quote! {
-        impl Tool<#state_ident> for #tool_ident {
-            fn execute(self, _state: &mut #state_ident) -> Result<String> {
-                // TODO: Implement tool logic
-                Ok(format!("{} executed with param: {}", #snake_name, self.example_param))
-            }
-        }
+    let (tool_struct, examples_impl, tool_impl): (ItemStruct, ItemImpl, ItemImpl) = if params
+        .is_empty()
+    {
+        (
+            parse_quote! {
+                /// TODO: Add description for this tool
+                #[derive(Debug, Serialize, Deserialize, schemars::JsonSchema, clap::Args)]
+                #[serde(rename = #snake_name)]
+                pub struct #tool_ident {
+                    /// TODO: Add parameter description
+                    pub example_param: String,
+                }
+            },
+            parse_quote! {
+                impl WithExamples for #tool_ident {
+                    fn examples() -> Vec<Example<Self>> {
+                        vec![
+                            Example::new(
+                                "TODO: Add example description",
+                                Self {
+                                    example_param: "example_value".into(),
+                                },
+                            ),
+                        ]
+                    }
+                }
+            },
+            parse_quote! {
+                impl Tool<#state_ident> for #tool_ident {
+                    fn execute(self, _state: &mut #state_ident) -> Result<ToolOutput> {
+                        // TODO: Implement tool logic
+                        Ok(format!("{} executed with param: {}", #snake_name, self.example_param).into())
+                    }
+                }
+            },
+        )
+    } else {
+        let fields = params.iter().map(Param::field);
+        let names = params.iter().map(|param| format_ident!("{}", param.name));
+        let examples = params.iter().map(Param::example);
+        (
+            parse_quote! {
+                /// TODO: Add description for this tool
+                #[derive(Debug, Serialize, Deserialize, schemars::JsonSchema, clap::Args)]
+                #[serde(rename = #snake_name)]
+                pub struct #tool_ident {
+                    #(#fields),*
+                }
+            },
+            parse_quote! {
+                impl WithExamples for #tool_ident {
+                    fn examples() -> Vec<Example<Self>> {
+                        vec![
+                            Example::new(
+                                "TODO: Add example description",
+                                Self {
+                                    #(#names: #examples),*
+                                },
+                            ),
+                        ]
+                    }
+                }
+            },
+            parse_quote! {
+                impl Tool<#state_ident> for #tool_ident {
+                    fn execute(self, _state: &mut #state_ident) -> Result<ToolOutput> {
+                        // TODO: Implement tool logic
+                        Ok(format!("{} executed with {:?}", #snake_name, self).into())
+                    }
+                }
+            },
+        )
     };
 
     let file = File {
@@ -543,7 +1356,7 @@ fn generate_tool_file(tool_name: &str, state_name: &str, output_dir: &Path) -> R
             parse_quote! { use crate::state::#state_ident; },
             parse_quote! { use anyhow::Result; },
             parse_quote! { use mcplease::traits::{Tool, WithExamples}; },
-            parse_quote! { use mcplease::types::Example; },
+            parse_quote! { use mcplease::types::{Example, ToolOutput}; },
             parse_quote! { use serde::{Deserialize, Serialize}; },
             // Actual items
             tool_struct.into(),
@@ -559,3 +1372,64 @@ fn generate_tool_file(tool_name: &str, state_name: &str, output_dir: &Path) -> R
 
     Ok(())
 }
+
+fn generate_session_tool_file(state_name: &str, output_dir: &Path) -> Result<()> {
+    let state_ident = format_ident!("{}", state_name);
+
+    let file: File = parse_quote! {
+        use crate::state::#state_ident;
+        use anyhow::Result;
+        use mcplease::context::ToolContext;
+        use mcplease::traits::{Tool, WithExamples};
+        use mcplease::types::{Example, ToolOutput};
+        use serde::{Deserialize, Serialize};
+
+        /// Save a note for later, and list the notes saved so far
+        #[derive(Debug, Serialize, Deserialize, schemars::JsonSchema, clap::Args)]
+        #[serde(rename = "remember")]
+        pub struct Remember {
+            /// A note to save. Leave this out to only list notes.
+            #[arg(long)]
+            pub note: Option<String>,
+        }
+
+        impl WithExamples for Remember {
+            fn examples() -> Vec<Example<Self>> {
+                vec![
+                    Example::new(
+                        "Save a note",
+                        Self {
+                            note: Some("the tests live in tests/".into()),
+                        },
+                    ),
+                    Example::new("List saved notes", Self { note: None }),
+                ]
+            }
+        }
+
+        impl Tool<#state_ident> for Remember {
+            fn execute_with_context(
+                self,
+                state: &mut #state_ident,
+                context: &ToolContext,
+            ) -> Result<ToolOutput> {
+                if let Some(note) = self.note {
+                    state.update_session_data(context, |data| data.notes.push(note))?;
+                }
+
+                let notes = state.session_data(context)?.notes;
+                if notes.is_empty() {
+                    Ok("No notes saved yet".into())
+                } else {
+                    Ok(notes.join("\n").into())
+                }
+            }
+        }
+    };
+
+    let content = prettyplease::unparse(&file);
+    fs::write(output_dir.join("src/tools/remember.rs"), content)
+        .context("Failed to write tool file for remember")?;
+
+    Ok(())
+}
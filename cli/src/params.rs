@@ -0,0 +1,147 @@
+//! Parameter specs for generated tools, like `path:string,recursive:bool,depth:u32?`
+//!
+//! Each spec is `name:type`, where the type is a Rust type or one of the JSON-ish names below,
+//! followed by `[]` for a list and `?` for an optional parameter. A spec without a type is a
+//! string.
+
+use anyhow::{Result, anyhow};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Ident, Type};
+
+/// The types a parameter can have, with the names they can be spelled with
+const TYPES: &[(&[&str], &str)] = &[
+    (&["string", "str", "String"], "String"),
+    (&["bool", "boolean"], "bool"),
+    (&["int", "integer"], "i64"),
+    (&["float", "number"], "f64"),
+    (&["path", "PathBuf"], "std::path::PathBuf"),
+    (&["u8"], "u8"),
+    (&["u16"], "u16"),
+    (&["u32"], "u32"),
+    (&["u64"], "u64"),
+    (&["usize"], "usize"),
+    (&["i8"], "i8"),
+    (&["i16"], "i16"),
+    (&["i32"], "i32"),
+    (&["i64"], "i64"),
+    (&["isize"], "isize"),
+    (&["f32"], "f32"),
+    (&["f64"], "f64"),
+];
+
+/// One field of a generated tool struct
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param {
+    pub name: String,
+    /// The Rust type of one value, like `String` or `u32`
+    pub scalar: &'static str,
+    /// Whether the parameter is a `Vec` of values
+    pub list: bool,
+    /// Whether the parameter is an `Option`
+    pub optional: bool,
+}
+
+impl Param {
+    /// Parse each of `specs`, refusing duplicate names
+    pub fn parse_all(specs: &[String]) -> Result<Vec<Self>> {
+        let mut params: Vec<Self> = vec![];
+        for spec in specs {
+            let param = Self::parse(spec)?;
+            if params.iter().any(|p| p.name == param.name) {
+                return Err(anyhow!(
+                    "Parameter '{}' is listed more than once",
+                    param.name
+                ));
+            }
+            params.push(param);
+        }
+        Ok(params)
+    }
+
+    /// Parse one spec, like `depth:u32?` or `paths:path[]`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        let (name, ty) = spec.split_once(':').unwrap_or((spec, "string"));
+        let name = name.trim();
+        if syn::parse_str::<Ident>(name).is_err() {
+            return Err(anyhow!(
+                "Parameter name '{name}' is not a valid Rust identifier"
+            ));
+        }
+
+        let ty = ty.trim();
+        let (ty, optional) = ty.strip_suffix('?').map_or((ty, false), |ty| (ty, true));
+        let (ty, list) = ty.strip_suffix("[]").map_or((ty, false), |ty| (ty, true));
+        let scalar = TYPES
+            .iter()
+            .find(|(names, _)| names.contains(&ty))
+            .map(|(_, scalar)| *scalar)
+            .ok_or_else(|| {
+                let names = TYPES
+                    .iter()
+                    .map(|(names, _)| names[0])
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                anyhow!("Unknown type '{ty}' for parameter '{name}'. Expected one of {names}, followed by [] for a list or ? for an optional parameter")
+            })?;
+
+        Ok(Self {
+            name: name.to_string(),
+            scalar,
+            list,
+            optional,
+        })
+    }
+
+    /// The field's type, like `Option<Vec<String>>`
+    pub fn rust_type(&self) -> String {
+        let mut ty = self.scalar.to_string();
+        if self.list {
+            ty = format!("Vec<{ty}>");
+        }
+        if self.optional {
+            ty = format!("Option<{ty}>");
+        }
+        ty
+    }
+
+    /// The field in the tool struct, with a doc comment stub
+    ///
+    /// Required values are positional on the command line, like the generated `example_param`,
+    /// and flags, lists, and optional values are `--options`. Flags and lists default to `false`
+    /// and empty, so clients can leave them out.
+    pub fn field(&self) -> TokenStream {
+        let name = format_ident!("{}", self.name);
+        let ty: Type = syn::parse_str(&self.rust_type()).expect("parameter types are valid");
+        let is_flag = self.scalar == "bool" && !self.list && !self.optional;
+        let arg = (is_flag || self.list || self.optional).then(|| quote!(#[arg(long)]));
+        let serde = ((is_flag || self.list) && !self.optional).then(|| quote!(#[serde(default)]));
+        quote! {
+            /// TODO: Add parameter description
+            #serde
+            #arg
+            pub #name: #ty
+        }
+    }
+
+    /// A value for the field in the generated example
+    pub fn example(&self) -> TokenStream {
+        let value = match self.scalar {
+            "String" | "std::path::PathBuf" => quote!("example".into()),
+            "bool" => quote!(true),
+            "f32" | "f64" => quote!(1.0),
+            _ => quote!(1),
+        };
+        let value = if self.list {
+            quote!(vec![#value])
+        } else {
+            value
+        };
+        if self.optional {
+            quote!(Some(#value))
+        } else {
+            value
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! `mcplease create --template`: generate a project from a directory of files instead of the
+//! built-in layout
+//!
+//! Files ending in `.hbs` are rendered with handlebars and written without the extension, and
+//! everything else is copied as it is. A file whose path mentions `{{tool.…}}`, like
+//! `src/tools/{{tool.mod_name}}.rs.hbs`, is written once for each tool.
+
+use crate::{CreateOptions, mcplease_version};
+use anyhow::{Context, Result, anyhow};
+use handlebars::Handlebars;
+use heck::{ToPascalCase, ToSnakeCase};
+use serde_json::{Value, json};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+
+/// The extension of files that are rendered rather than copied
+const TEMPLATE_EXTENSION: &str = ".hbs";
+
+/// Overrides the directory that templates are looked up in by name
+const TEMPLATES_DIR_VAR: &str = "MCPLEASE_TEMPLATES";
+
+pub struct Template {
+    root: PathBuf,
+    /// Whether `root` is a clone of a git repository, to be removed when done
+    cloned: bool,
+}
+
+impl Template {
+    /// Find the template `spec`, which is a git url, a path to a directory, or the name of a
+    /// directory in [`templates_dir`]
+    pub fn load(spec: &str) -> Result<Self> {
+        if is_git_url(spec) {
+            let root = env::temp_dir().join(format!("mcplease-template-{}", process::id()));
+            let _ = fs::remove_dir_all(&root);
+            let output = Command::new("git")
+                .args(["clone", "--quiet", "--depth", "1", spec])
+                .arg(&root)
+                .output()
+                .context("Failed to execute git clone")?;
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "git clone {spec} failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+            return Ok(Self { root, cloned: true });
+        }
+
+        let path = Path::new(spec);
+        if path.is_dir() {
+            return Ok(Self {
+                root: path.to_path_buf(),
+                cloned: false,
+            });
+        }
+
+        let templates_dir = templates_dir();
+        let is_name = !spec.contains(['/', '\\']) && !spec.starts_with('.');
+        if is_name
+            && let Some(dir) = &templates_dir
+            && dir.join(spec).is_dir()
+        {
+            return Ok(Self {
+                root: dir.join(spec),
+                cloned: false,
+            });
+        }
+
+        Err(anyhow!(
+            "No template found at {spec}. A template is a git url, a directory, or the name of a \
+             directory in {}",
+            templates_dir.map_or_else(
+                || format!("${TEMPLATES_DIR_VAR}"),
+                |dir| dir.display().to_string()
+            )
+        ))
+    }
+
+    /// Render the template into `output_dir`, returning the paths it wrote, relative to
+    /// `output_dir`
+    pub fn render(&self, opts: &CreateOptions, output_dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut handlebars = Handlebars::new();
+        handlebars.set_strict_mode(true);
+        handlebars.register_escape_fn(handlebars::no_escape);
+
+        let context = context(opts)?;
+        fs::create_dir_all(output_dir)?;
+
+        let mut written = vec![];
+        for relative in template_files(&self.root, Path::new(""))? {
+            let source = self.root.join(&relative);
+            let path = relative.to_string_lossy().replace('\\', "/");
+            let contexts = if path.contains("{{tool.") {
+                context["tools"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|tool| {
+                        let mut context = context.clone();
+                        context["tool"] = tool.clone();
+                        context
+                    })
+                    .collect()
+            } else {
+                vec![context.clone()]
+            };
+
+            for context in contexts {
+                let path = handlebars
+                    .render_template(&path, &context)
+                    .with_context(|| format!("Failed to render the path {}", relative.display()))?;
+                let (path, render) = match path.strip_suffix(TEMPLATE_EXTENSION) {
+                    Some(path) => (path.to_string(), true),
+                    None => (path, false),
+                };
+
+                let destination = output_dir.join(&path);
+                if let Some(parent) = destination.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                if render {
+                    let content = fs::read_to_string(&source)
+                        .with_context(|| format!("Failed to read {}", source.display()))?;
+                    let content = handlebars
+                        .render_template(&content, &context)
+                        .with_context(|| format!("Failed to render {}", relative.display()))?;
+                    fs::write(&destination, content)
+                } else {
+                    fs::copy(&source, &destination).map(|_| ())
+                }
+                .with_context(|| format!("Failed to write {path}"))?;
+
+                written.push(PathBuf::from(path));
+            }
+        }
+
+        Ok(written)
+    }
+}
+
+impl Drop for Template {
+    fn drop(&mut self) {
+        if self.cloned {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+}
+
+/// What templates can refer to
+///
+/// ```json
+/// {
+///   "name": "my-server",
+///   "crate_name": "my_server",
+///   "description": "...",
+///   "instructions": "...",
+///   "state": "State",
+///   "with_sessions": false,
+///   "mcplease_version": "0.2",
+///   "tools": [{ "name": "hello", "struct_name": "Hello", "mod_name": "hello" }],
+///   "params": [{ "name": "depth", "type": "Option<u32>" }]
+/// }
+/// ```
+fn context(opts: &CreateOptions) -> Result<Value> {
+    let tools: Vec<Value> = opts
+        .tools
+        .iter()
+        .map(|tool| {
+            json!({
+                "name": tool.to_snake_case(),
+                "struct_name": tool.to_pascal_case(),
+                "mod_name": tool.to_snake_case(),
+            })
+        })
+        .collect();
+    let params: Vec<Value> = opts
+        .params
+        .iter()
+        .map(|param| json!({ "name": param.name, "type": param.rust_type() }))
+        .collect();
+
+    Ok(json!({
+        "name": opts.name,
+        "crate_name": opts.name.to_snake_case(),
+        "description": opts.description.unwrap_or("An MCP server built with mcplease"),
+        "instructions": opts
+            .instructions
+            .unwrap_or("TODO: Add instructions for your MCP server"),
+        "state": opts.state,
+        "with_sessions": opts.with_sessions,
+        "mcplease_version": mcplease_version()?,
+        "tools": tools,
+        "params": params,
+    }))
+}
+
+/// The files in `dir`, relative to the template's root, skipping `.git`
+fn template_files(root: &Path, dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut entries = fs::read_dir(root.join(dir))
+        .with_context(|| format!("Failed to read {}", root.join(dir).display()))?
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut files = vec![];
+    for entry in entries {
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let relative = dir.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            files.extend(template_files(root, &relative)?);
+        } else {
+            files.push(relative);
+        }
+    }
+    Ok(files)
+}
+
+fn is_git_url(spec: &str) -> bool {
+    ["https://", "http://", "ssh://", "git://", "file://", "git@"]
+        .iter()
+        .any(|prefix| spec.starts_with(prefix))
+        || (spec.ends_with(".git") && !Path::new(spec).is_dir())
+}
+
+/// Where templates are looked up by name: `$MCPLEASE_TEMPLATES`, or
+/// `~/.config/mcplease/templates`, or `%APPDATA%\mcplease\templates` on Windows
+fn templates_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os(TEMPLATES_DIR_VAR) {
+        return Some(dir.into());
+    }
+    if cfg!(windows) {
+        dirs::config_dir().map(|dir| dir.join("mcplease").join("templates"))
+    } else {
+        dirs::home_dir().map(|home| home.join(".config").join("mcplease").join("templates"))
+    }
+}
@@ -1,4 +1,5 @@
 use super::*;
+use serde_json::json;
 use std::process::Command;
 use tempfile::TempDir;
 
@@ -14,9 +15,11 @@ fn test_create_project_compiles() {
             "greet".to_string(),
             "status".to_string(),
         ],
+        params: &[],
         state: "TestState",
         description: Some("A test MCP server"),
         instructions: Some("Test instructions for the server"),
+        with_sessions: false,
     };
 
     // Create the project
@@ -25,6 +28,7 @@ fn test_create_project_compiles() {
     // Verify basic structure exists
     assert!(project_path.join("Cargo.toml").exists());
     assert!(project_path.join("src/main.rs").exists());
+    assert!(project_path.join("src/config.rs").exists());
     assert!(project_path.join("src/state.rs").exists());
     assert!(project_path.join("src/tools.rs").exists());
     assert!(project_path.join("src/tools/hello.rs").exists());
@@ -77,9 +81,11 @@ fn test_cargo_toml_generation() {
     let opts = CreateOptions {
         name: "my-test-server",
         tools: &[],
+        params: &[],
         state: "State",
         description: Some("Custom description"),
         instructions: None,
+        with_sessions: false,
     };
 
     fs::create_dir_all(&project_path).expect("Failed to create project directory");
@@ -99,7 +105,7 @@ fn test_tool_file_generation() {
     let project_path = temp_dir.path().join("tool-gen");
     fs::create_dir_all(project_path.join("src/tools")).expect("Failed to create directories");
 
-    generate_tool_file("hello_world", "MyState", &project_path)
+    generate_tool_file("hello_world", &[], "MyState", &project_path)
         .expect("Failed to generate tool file");
 
     let content = fs::read_to_string(project_path.join("src/tools/hello_world.rs"))
@@ -120,9 +126,11 @@ fn test_formatting_with_quote_newlines() {
     let opts = CreateOptions {
         name: "test-formatting",
         tools: &["hello".to_string(), "goodbye".to_string()],
+        params: &[],
         state: "MyState",
         description: Some("Test formatting"),
         instructions: None,
+        with_sessions: false,
     };
 
     create_project(&opts, &project_path).expect("Failed to create project");
@@ -151,9 +159,11 @@ fn test_add_tool_functionality() {
     let opts = CreateOptions {
         name: "test-project",
         tools: &["hello".to_string()],
+        params: &[],
         state: "MyState",
         description: Some("Test project"),
         instructions: None,
+        with_sessions: false,
     };
 
     create_project(&opts, &project_path).expect("Failed to create project");
@@ -182,3 +192,654 @@ fn test_add_tool_functionality() {
     assert!(tool_names.contains(&"hello".to_string()));
     assert!(tool_names.contains(&"goodbye".to_string()));
 }
+
+#[test]
+fn test_remove_tool_functionality() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_path = temp_dir.path().join("remove-tool-test");
+
+    let opts = CreateOptions {
+        name: "test-project",
+        tools: &["hello".to_string(), "goodbye".to_string()],
+        params: &[],
+        state: "MyState",
+        description: Some("Test project"),
+        instructions: None,
+        with_sessions: false,
+    };
+
+    create_project(&opts, &project_path).expect("Failed to create project");
+
+    remove_tool_from_project_at_path("goodbye", &project_path).expect("Failed to remove tool");
+
+    // Verify the tool file was deleted and the other one kept
+    assert!(!project_path.join("src/tools/goodbye.rs").exists());
+    assert!(project_path.join("src/tools/hello.rs").exists());
+
+    let tools_content =
+        fs::read_to_string(project_path.join("src/tools.rs")).expect("Failed to read tools.rs");
+    let file: syn::File = syn::parse_str(&tools_content).expect("Failed to parse tools.rs");
+    let tools_macro = find_tools_macro(&file).expect("No tools macro found");
+    let args: ToolsMacroArgs =
+        parse2(tools_macro.mac.tokens.clone()).expect("Failed to parse macro args");
+
+    let tool_names: Vec<_> = args.tools.iter().map(|t| t.string_name.value()).collect();
+    assert_eq!(tool_names, vec!["hello".to_string()]);
+
+    // The last tool can't be removed, and nothing is changed when that's attempted
+    let error = remove_tool_from_project_at_path("hello", &project_path)
+        .expect_err("Removing the last tool should fail");
+    assert!(error.to_string().contains("only tool"));
+    assert!(project_path.join("src/tools/hello.rs").exists());
+
+    // Unknown tools are reported
+    assert!(remove_tool_from_project_at_path("missing", &project_path).is_err());
+}
+
+#[test]
+fn test_rename_tool_functionality() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_path = temp_dir.path().join("rename-tool-test");
+
+    let opts = CreateOptions {
+        name: "test-project",
+        tools: &["hello".to_string(), "goodbye".to_string()],
+        params: &[],
+        state: "MyState",
+        description: Some("Test project"),
+        instructions: None,
+        with_sessions: false,
+    };
+
+    create_project(&opts, &project_path).expect("Failed to create project");
+
+    rename_tool_in_project_at_path("hello", "say_hello", &project_path)
+        .expect("Failed to rename tool");
+
+    assert!(!project_path.join("src/tools/hello.rs").exists());
+    let content = fs::read_to_string(project_path.join("src/tools/say_hello.rs"))
+        .expect("Failed to read renamed tool file");
+    assert!(content.contains("pub struct SayHello"));
+    assert!(content.contains("impl Tool<MyState> for SayHello"));
+    assert!(content.contains("impl WithExamples for SayHello"));
+    assert!(content.contains("#[serde(rename = \"say_hello\")]"));
+
+    let tools_content =
+        fs::read_to_string(project_path.join("src/tools.rs")).expect("Failed to read tools.rs");
+    let file: syn::File = syn::parse_str(&tools_content).expect("Failed to parse tools.rs");
+    let tools_macro = find_tools_macro(&file).expect("No tools macro found");
+    let args: ToolsMacroArgs =
+        parse2(tools_macro.mac.tokens.clone()).expect("Failed to parse macro args");
+
+    let entries: Vec<_> = args
+        .tools
+        .iter()
+        .map(|t| {
+            (
+                t.struct_name.to_string(),
+                t.mod_name.to_string(),
+                t.string_name.value(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        entries,
+        vec![
+            ("SayHello".into(), "say_hello".into(), "say_hello".into()),
+            ("Goodbye".into(), "goodbye".into(), "goodbye".into()),
+        ]
+    );
+
+    // Renaming onto an existing tool is refused
+    assert!(rename_tool_in_project_at_path("say_hello", "goodbye", &project_path).is_err());
+}
+
+#[test]
+fn test_replace_ident() {
+    assert_eq!(
+        replace_ident(
+            "impl Tool<S> for Hello { Hello HelloWorld MyHello }",
+            "Hello",
+            "Hi"
+        ),
+        "impl Tool<S> for Hi { Hi HelloWorld MyHello }"
+    );
+}
+
+#[test]
+fn test_list_tools() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_path = temp_dir.path().join("list-tools-test");
+
+    let opts = CreateOptions {
+        name: "test-project",
+        tools: &["hello".to_string(), "goodbye".to_string()],
+        params: &[],
+        state: "MyState",
+        description: Some("Test project"),
+        instructions: None,
+        with_sessions: false,
+    };
+
+    create_project(&opts, &project_path).expect("Failed to create project");
+    fs::remove_file(project_path.join("src/tools/goodbye.rs")).expect("Failed to remove tool");
+
+    let tools = list_tools(Some(&project_path)).expect("Failed to list tools");
+    assert_eq!(tools.len(), 2);
+
+    assert_eq!(tools[0].name, "hello");
+    assert_eq!(tools[0].struct_name, "Hello");
+    assert_eq!(
+        tools[0].description.as_deref(),
+        Some("TODO: Add description for this tool")
+    );
+    assert_eq!(
+        tools[0].parameters,
+        vec![("example_param".to_string(), "String".to_string())]
+    );
+
+    assert_eq!(tools[1].name, "goodbye");
+    assert_eq!(tools[1].description, None);
+}
+
+#[test]
+fn test_doctor() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_path = temp_dir.path().join("doctor-test");
+
+    let opts = CreateOptions {
+        name: "test-project",
+        tools: &["hello".to_string(), "goodbye".to_string()],
+        params: &[],
+        state: "MyState",
+        description: Some("Test project"),
+        instructions: None,
+        with_sessions: false,
+    };
+
+    create_project(&opts, &project_path).expect("Failed to create project");
+
+    // A freshly generated project only has TODO descriptions to complain about
+    let diagnostics = doctor::diagnose(Some(&project_path), false).expect("Failed to diagnose");
+    assert!(
+        diagnostics
+            .iter()
+            .all(|d| d.severity == doctor::Severity::Warning && d.message.contains("TODO")),
+        "{diagnostics:#?}"
+    );
+
+    // Break the project in a few ways
+    fs::remove_file(project_path.join("src/tools/goodbye.rs")).expect("Failed to remove tool");
+    let hello_path = project_path.join("src/tools/hello.rs");
+    let hello = fs::read_to_string(&hello_path).expect("Failed to read tool file");
+    fs::write(
+        &hello_path,
+        hello
+            .replace("impl WithExamples for Hello", "impl Other for Hello")
+            .replace("rename = \"hello\"", "rename = \"hi\"")
+            .replace(
+                "/// TODO: Add parameter description",
+                "#[arg(help = \"only in --help\")]",
+            ),
+    )
+    .expect("Failed to write tool file");
+    fs::write(project_path.join("src/tools/stray.rs"), "").expect("Failed to write stray file");
+
+    let diagnostics = doctor::diagnose(Some(&project_path), false).expect("Failed to diagnose");
+    let errors: Vec<_> = diagnostics
+        .iter()
+        .filter(|d| d.severity == doctor::Severity::Error)
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(errors.len(), 3, "{diagnostics:#?}");
+    assert!(errors[0].contains("WithExamples"));
+    assert!(errors[1].contains("rename = \"hi\""));
+    assert!(errors[2].contains("src/tools/goodbye.rs does not exist"));
+    assert!(
+        diagnostics
+            .iter()
+            .any(|d| d.message.contains("src/tools/stray.rs"))
+    );
+    assert!(diagnostics.iter().any(
+        |d| d.message.contains("Hello::example_param") && d.message.contains("arg(help = ...)")
+    ));
+    assert!(doctor::report(&diagnostics).is_err());
+}
+
+#[test]
+fn test_create_project_with_sessions() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_path = temp_dir.path().join("sessions-test");
+
+    let opts = CreateOptions {
+        name: "sessions-test",
+        tools: &["hello".to_string()],
+        params: &[],
+        state: "MyState",
+        description: None,
+        instructions: None,
+        with_sessions: true,
+    };
+
+    create_project(&opts, &project_path).expect("Failed to create project");
+
+    let state = fs::read_to_string(project_path.join("src/state.rs")).expect("Failed to read");
+    assert!(state.contains("session_store: SessionStore<SessionData>"));
+    assert!(state.contains("mcplease::paths::state_dir(\"sessions-test\")?"));
+
+    let config = fs::read_to_string(project_path.join("src/config.rs")).expect("Failed to read");
+    assert!(config.contains("mcplease::config::load(\"sessions-test\")"));
+    assert!(config.contains("`SESSIONS_TEST_*` environment variables"));
+
+    let tool = fs::read_to_string(project_path.join("src/tools/remember.rs"))
+        .expect("Failed to read session tool");
+    assert!(tool.contains("impl Tool<MyState> for Remember"));
+    assert!(tool.contains("update_session_data"));
+
+    let tools = list_tools(Some(&project_path)).expect("Failed to list tools");
+    let names: Vec<_> = tools.iter().map(|tool| tool.name.as_str()).collect();
+    assert_eq!(names, ["hello", "remember"]);
+}
+
+#[test]
+fn test_dev_parse_call() {
+    let tools = vec![json!({
+        "name": "set_count",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "label": { "type": "string" },
+                "count": { "type": "integer" },
+            },
+        },
+    })];
+
+    let (name, arguments) =
+        dev::parse_call("set-count label=42 count=3", &tools).expect("Failed to parse");
+    assert_eq!(name, "set_count");
+    assert_eq!(arguments, json!({ "label": "42", "count": 3 }));
+
+    let (_, arguments) =
+        dev::parse_call(r#"set_count {"count": 1}"#, &tools).expect("Failed to parse");
+    assert_eq!(arguments, json!({ "count": 1 }));
+
+    assert!(dev::parse_call("missing", &tools).is_err());
+    assert!(dev::parse_call("set_count label", &tools).is_err());
+}
+
+#[test]
+fn test_watch_sources() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_path = temp_dir.path();
+    std::fs::create_dir_all(project_path.join("src/tools")).unwrap();
+    std::fs::write(project_path.join("Cargo.toml"), "[package]").unwrap();
+    std::fs::write(project_path.join("src/main.rs"), "fn main() {}").unwrap();
+    std::fs::create_dir(project_path.join("target")).unwrap();
+
+    let sources = watch::sources(project_path);
+    assert_eq!(
+        sources.keys().collect::<Vec<_>>(),
+        [
+            project_path.join("Cargo.toml"),
+            project_path.join("src/main.rs")
+        ]
+        .iter()
+        .collect::<Vec<_>>()
+    );
+
+    // build output and editor swap files aren't sources
+    std::fs::write(project_path.join("target/out"), "").unwrap();
+    std::fs::write(project_path.join("src/.main.rs.swp"), "").unwrap();
+    std::fs::write(project_path.join("src/main.rs~"), "").unwrap();
+    assert_eq!(watch::sources(project_path), sources);
+
+    std::fs::write(project_path.join("src/tools/hello.rs"), "").unwrap();
+    assert_ne!(watch::sources(project_path), sources);
+}
+
+#[test]
+fn test_call_arguments() {
+    let tool = json!({
+        "name": "search",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "query": { "type": "string" },
+                "limit": { "type": "integer" },
+                "paths": { "type": "array", "items": { "type": "string" } },
+            },
+        },
+    });
+
+    let arguments = dev::parse_arguments(
+        &tool,
+        ["query=hello world", "limit=10", r#"paths=["src", "tests"]"#],
+    )
+    .expect("Failed to parse");
+    assert_eq!(
+        arguments,
+        json!({ "query": "hello world", "limit": 10, "paths": ["src", "tests"] })
+    );
+
+    assert!(dev::find_tool(&[tool], "search").is_some());
+    assert!(dev::parse_arguments(&json!({}), ["query"]).is_err());
+}
+
+#[test]
+fn test_create_from_template() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let template_path = temp_dir.path().join("template");
+    let project_path = temp_dir.path().join("from-template");
+
+    std::fs::create_dir_all(template_path.join("src/tools")).unwrap();
+    std::fs::create_dir_all(template_path.join(".git")).unwrap();
+    std::fs::write(template_path.join(".git/HEAD"), "ref: refs/heads/main").unwrap();
+    std::fs::write(
+        template_path.join("Cargo.toml.hbs"),
+        "[package]\nname = \"{{name}}\"\n\n[dependencies]\nmcplease = \"{{mcplease_version}}\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        template_path.join("src/tools.rs.hbs"),
+        "mcplease::tools!(\n    {{state}},\n{{#each tools}}    ({{struct_name}}, {{mod_name}}, \"{{name}}\"),\n{{/each}});\n",
+    )
+    .unwrap();
+    std::fs::write(
+        template_path.join("src/tools/{{tool.mod_name}}.rs.hbs"),
+        "pub struct {{tool.struct_name}};\n",
+    )
+    .unwrap();
+    // files without .hbs are copied as they are, braces and all
+    std::fs::write(template_path.join("src/main.rs"), "fn main() {{}}").unwrap();
+
+    let opts = CreateOptions {
+        name: "from-template",
+        tools: &["hello".to_string(), "list_files".to_string()],
+        params: &[],
+        state: "AppState",
+        description: None,
+        instructions: None,
+        with_sessions: false,
+    };
+    let template =
+        template::Template::load(template_path.to_str().unwrap()).expect("Failed to load template");
+    let files = template
+        .render(&opts, &project_path)
+        .expect("Failed to render template");
+    assert_eq!(
+        files,
+        [
+            "Cargo.toml",
+            "src/main.rs",
+            "src/tools/hello.rs",
+            "src/tools/list_files.rs",
+            "src/tools.rs",
+        ]
+        .map(std::path::PathBuf::from)
+    );
+
+    let read = |path: &str| std::fs::read_to_string(project_path.join(path)).unwrap();
+    assert!(read("Cargo.toml").contains("name = \"from-template\""));
+    assert_eq!(
+        read("src/tools.rs"),
+        "mcplease::tools!(\n    AppState,\n    (Hello, hello, \"hello\"),\n    (ListFiles, list_files, \"list_files\"),\n);\n"
+    );
+    assert_eq!(read("src/tools/list_files.rs"), "pub struct ListFiles;\n");
+    assert_eq!(read("src/main.rs"), "fn main() {{}}");
+    assert!(!project_path.join(".git").exists());
+
+    assert!(template::Template::load("no-such-template").is_err());
+}
+
+#[test]
+fn test_create_in_workspace() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let root = temp_dir.path();
+    std::fs::write(
+        root.join("Cargo.toml"),
+        r#"[workspace]
+# the servers
+members = ["crates/*"]
+resolver = "3"
+
+[workspace.package]
+edition = "2024"
+license = "MIT"
+
+[workspace.dependencies]
+anyhow = "1.0.98"
+clap = { version = "4.5", features = ["env"] }
+
+[workspace.lints.rust]
+unsafe_code = "forbid"
+"#,
+    )
+    .unwrap();
+    std::fs::create_dir_all(root.join("crates")).unwrap();
+
+    let mut workspace = workspace::Workspace::find(&root.join("crates/new"))
+        .unwrap()
+        .expect("Failed to find the workspace");
+    assert_eq!(workspace.root(), root);
+    assert_eq!(
+        workspace.default_output("new"),
+        Some(root.join("crates/new"))
+    );
+
+    // covered by crates/*, so the workspace manifest is unchanged
+    workspace.add_member(&root.join("crates/new")).unwrap();
+    // not covered, so added to members
+    let project_path = root.join("servers/weather");
+    workspace.add_member(&project_path).unwrap();
+    let workspace_toml = std::fs::read_to_string(root.join("Cargo.toml")).unwrap();
+    assert!(
+        workspace_toml.contains("# the servers\nmembers = [\"crates/*\", \"servers/weather\"]")
+    );
+
+    let opts = CreateOptions {
+        name: "weather",
+        tools: &["forecast".to_string()],
+        params: &[],
+        state: "State",
+        description: None,
+        instructions: None,
+        with_sessions: false,
+    };
+    create_project(&opts, &project_path).expect("Failed to create project");
+    workspace
+        .inherit(&project_path.join("Cargo.toml"))
+        .expect("Failed to inherit from the workspace");
+
+    let cargo_toml = std::fs::read_to_string(project_path.join("Cargo.toml")).unwrap();
+    assert!(cargo_toml.contains("version = \"0.1.0\"\n"));
+    assert!(cargo_toml.contains("edition.workspace = true\n"));
+    assert!(cargo_toml.contains("license.workspace = true\n"));
+    assert!(cargo_toml.contains("anyhow = { workspace = true }\n"));
+    assert!(cargo_toml.contains("clap = { workspace = true, features = [\"derive\"] }\n"));
+    assert!(cargo_toml.contains("serde_json = \"1\"\n"));
+    assert!(cargo_toml.contains("[lints]\nworkspace = true\n"));
+
+    let standalone = TempDir::new().expect("Failed to create temp directory");
+    assert!(
+        workspace::Workspace::find(standalone.path())
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn test_tool_params() {
+    let params = Param::parse_all(&[
+        "path:string".to_string(),
+        "recursive:bool".to_string(),
+        "depth:u32?".to_string(),
+        "globs:path[]".to_string(),
+        "label".to_string(),
+    ])
+    .expect("Failed to parse params");
+    let types: Vec<_> = params.iter().map(Param::rust_type).collect();
+    assert_eq!(
+        types,
+        [
+            "String",
+            "bool",
+            "Option<u32>",
+            "Vec<std::path::PathBuf>",
+            "String"
+        ]
+    );
+
+    assert!(Param::parse("depth:u128").is_err());
+    assert!(Param::parse("type:string").is_err());
+    assert!(Param::parse_all(&["a:bool".to_string(), "a:u8".to_string()]).is_err());
+
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_path = temp_dir.path().join("params");
+    fs::create_dir_all(project_path.join("src/tools")).expect("Failed to create directories");
+    generate_tool_file("list_files", &params, "State", &project_path)
+        .expect("Failed to generate tool file");
+    let content = fs::read_to_string(project_path.join("src/tools/list_files.rs"))
+        .expect("Failed to read tool file");
+
+    assert!(content.contains("    pub path: String,\n"));
+    assert!(content.contains("#[serde(default)]\n    #[arg(long)]\n    pub recursive: bool,\n"));
+    assert!(content.contains("#[arg(long)]\n    pub depth: Option<u32>,\n"));
+    assert!(content.contains("pub globs: Vec<std::path::PathBuf>,\n"));
+    assert!(content.contains("Some(1)"));
+    assert!(!content.contains("example_param"));
+    syn::parse_file(&content).expect("Generated tool file doesn't parse");
+}
+
+#[test]
+fn test_tools_file_edits_preserve_surroundings() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_path = temp_dir.path().join("preserve");
+
+    let opts = CreateOptions {
+        name: "test-project",
+        tools: &["hello".to_string()],
+        params: &[],
+        state: "MyState",
+        description: Some("Test project"),
+        instructions: None,
+        with_sessions: false,
+    };
+
+    create_project(&opts, &project_path).expect("Failed to create project");
+
+    let before = "//! Tools for the test project\n\n\
+                  // Keep this comment exactly where it is\n\
+                  use   crate::state::MyState ;\n\n\
+                  #[allow(dead_code)]\nconst    UNFORMATTED:u8=1;\n\n";
+    let after = "\n\n/// Trailing items survive too\nfn helper() -> u8 {   UNFORMATTED }\n";
+    let tools_rs = project_path.join("src/tools.rs");
+    fs::write(
+        &tools_rs,
+        format!("{before}mcplease::tools!(MyState, (Hello, hello, \"hello\"));{after}"),
+    )
+    .expect("Failed to write tools.rs");
+
+    add_tool_to_project_at_path("goodbye", &project_path).expect("Failed to add tool");
+    rename_tool_in_project_at_path("hello", "greet", &project_path).expect("Failed to rename tool");
+    remove_tool_from_project_at_path("goodbye", &project_path).expect("Failed to remove tool");
+
+    let tools_content = fs::read_to_string(&tools_rs).expect("Failed to read tools.rs");
+    assert_eq!(
+        tools_content,
+        format!("{before}mcplease::tools!(MyState, (Greet, greet, \"greet\"));{after}")
+    );
+}
+
+#[test]
+fn test_tools_file_edits_preserve_entries() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_path = temp_dir.path().join("entries");
+
+    let opts = CreateOptions {
+        name: "test-project",
+        tools: &["hello".to_string(), "goodbye".to_string()],
+        params: &[],
+        state: "MyState",
+        description: Some("Test project"),
+        instructions: None,
+        with_sessions: false,
+    };
+
+    create_project(&opts, &project_path).expect("Failed to create project");
+
+    let tools_rs = project_path.join("src/tools.rs");
+    fs::write(
+        &tools_rs,
+        r#"#[cfg(not(test))]
+mcplease::tools!(
+    MyState,
+    // Greetings
+    (Hello,   hello, "hello"), // the first tool
+    /* Farewells */
+    (Goodbye, goodbye, "goodbye"),
+);
+"#,
+    )
+    .expect("Failed to write tools.rs");
+    let read = || fs::read_to_string(&tools_rs).expect("Failed to read tools.rs");
+
+    add_tool_to_project_at_path("wave", &project_path).expect("Failed to add tool");
+    assert_eq!(
+        read(),
+        r#"#[cfg(not(test))]
+mcplease::tools!(
+    MyState,
+    // Greetings
+    (Hello,   hello, "hello"), // the first tool
+    /* Farewells */
+    (Goodbye, goodbye, "goodbye"),
+    (Wave, wave, "wave"),
+);
+"#
+    );
+
+    rename_tool_in_project_at_path("goodbye", "bye", &project_path).expect("Failed to rename tool");
+    remove_tool_from_project_at_path("hello", &project_path).expect("Failed to remove tool");
+    assert_eq!(
+        read(),
+        r#"#[cfg(not(test))]
+mcplease::tools!(
+    MyState,
+    // Greetings
+    /* Farewells */
+    (Bye, bye, "bye"),
+    (Wave, wave, "wave"),
+);
+"#
+    );
+
+    // Without a trailing comma, the comma before the last entry goes with it
+    fs::write(
+        &tools_rs,
+        "mcplease::tools!(\n    MyState,\n    (Bye, bye, \"bye\"),\n    (Wave, wave, \"wave\")\n);\n",
+    )
+    .expect("Failed to write tools.rs");
+    remove_tool_from_project_at_path("wave", &project_path).expect("Failed to remove tool");
+    assert_eq!(
+        read(),
+        "mcplease::tools!(\n    MyState,\n    (Bye, bye, \"bye\")\n);\n"
+    );
+    add_tool_to_project_at_path("hello", &project_path).expect("Failed to add tool");
+    assert_eq!(
+        read(),
+        "mcplease::tools!(\n    MyState,\n    (Bye, bye, \"bye\"),\n    (Hello, hello, \"hello\")\n);\n"
+    );
+
+    // An entry's sub-state is kept when it is renamed
+    fs::write(
+        &tools_rs,
+        "mcplease::tools!(MyState, (Bye, bye, \"bye\", crate::Farewells), (Hello, hello, \"hello\"));\n",
+    )
+    .expect("Failed to write tools.rs");
+    rename_tool_in_project_at_path("bye", "farewell", &project_path)
+        .expect("Failed to rename tool");
+    assert_eq!(
+        read(),
+        "mcplease::tools!(MyState, (Farewell, farewell, \"farewell\", crate::Farewells), (Hello, hello, \"hello\"));\n"
+    );
+}
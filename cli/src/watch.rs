@@ -0,0 +1,429 @@
+//! `mcplease watch`: rebuild and restart a project's server whenever its source changes, while the
+//! client stays connected
+//!
+//! The client talks to this process over stdio, and every message is passed through to the
+//! current server. After a restart, the client's `initialize` request and `initialized`
+//! notification are replayed to the new server, so the client never sees the handshake again; it
+//! is only told that the tools, resources, and prompts may have changed.
+
+use anyhow::{Context, Result, anyhow};
+use serde_json::{Value, json};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    mem,
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, Sender},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How often the project's files are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+/// The id of the replayed `initialize` request, whose response is not passed to the client
+const REPLAY_ID: &str = "mcplease-watch-initialize";
+
+enum Event {
+    /// A line from the client, or `None` once it disconnects
+    Client(Option<String>),
+    /// A line from the server started as the given generation, or `None` once it exits
+    Server(u64, Option<String>),
+    /// The project's source changed
+    Changed,
+}
+
+/// Build and start the server in `project_path` (or the current directory) with `args`, and
+/// proxy stdio to it until the client disconnects, restarting it whenever the source changes
+pub fn run(project_path: Option<&Path>, args: &[String]) -> Result<()> {
+    let project_path = project_path.unwrap_or(Path::new("."));
+    if !project_path.join("Cargo.toml").exists() {
+        return Err(anyhow!(
+            "No Cargo.toml found at {}. Run this command from the root of an mcplease project.",
+            project_path.display()
+        ));
+    }
+
+    let (sender, events) = mpsc::channel();
+    read_client(sender.clone());
+    watch_sources(project_path.to_path_buf(), sender.clone());
+
+    let mut proxy = Proxy {
+        project_path,
+        args: if args.is_empty() {
+            vec!["serve".into()]
+        } else {
+            args.to_vec()
+        },
+        sender,
+        server: None,
+        generation: 0,
+        ready: false,
+        initialize: None,
+        initialized: None,
+        queue: vec![],
+        pending: vec![],
+    };
+    proxy.restart()?;
+
+    for event in events {
+        match event {
+            Event::Client(Some(line)) => proxy.client_sent(line)?,
+            Event::Client(None) => break,
+            Event::Server(generation, line) if generation == proxy.generation => {
+                proxy.server_sent(line)?
+            }
+            // a line from a server that has since been replaced
+            Event::Server(..) => {}
+            Event::Changed => {
+                eprintln!("🔁 Source changed, rebuilding...");
+                proxy.restart()?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+struct Proxy<'a> {
+    project_path: &'a Path,
+    args: Vec<String>,
+    sender: Sender<Event>,
+    server: Option<Server>,
+    /// Incremented for each server, to ignore the output of servers that have been replaced
+    generation: u64,
+    /// Whether the server can be sent the client's messages, which is not the case while there
+    /// is no server or while the handshake is being replayed
+    ready: bool,
+    /// The client's `initialize` request, replayed to each new server
+    initialize: Option<Value>,
+    /// The client's `initialized` notification, replayed after `initialize`
+    initialized: Option<Value>,
+    /// Lines from the client waiting for the server to be ready
+    queue: Vec<String>,
+    /// The ids of the client's requests that the server hasn't responded to
+    pending: Vec<Value>,
+}
+
+impl Proxy<'_> {
+    fn client_sent(&mut self, line: String) -> Result<()> {
+        if let Ok(message) = serde_json::from_str::<Value>(&line)
+            && message.get("method").is_some()
+            && let Some(id) = message.get("id")
+        {
+            self.pending.push(id.clone());
+        }
+
+        if self.ready {
+            self.send_to_server(line);
+        } else {
+            self.queue.push(line);
+        }
+        Ok(())
+    }
+
+    fn server_sent(&mut self, line: Option<String>) -> Result<()> {
+        let Some(line) = line else {
+            eprintln!("⚠️  The server exited. Save a change to rebuild and restart it.");
+            return self.stop();
+        };
+
+        let message = serde_json::from_str::<Value>(&line).unwrap_or_default();
+        let is_response = message.get("method").is_none();
+        if is_response && message["id"] == REPLAY_ID {
+            return self.replayed(&message);
+        }
+        if is_response && let Some(id) = message.get("id") {
+            self.pending.retain(|pending| pending != id);
+        }
+        write_client(&line)
+    }
+
+    /// Finish the replayed handshake, then send the new server everything the client sent in
+    /// the meantime
+    fn replayed(&mut self, response: &Value) -> Result<()> {
+        if let Some(error) = response.get("error") {
+            eprintln!("⚠️  The restarted server refused to initialize: {error}");
+        }
+        if let Some(initialized) = &self.initialized {
+            let initialized = initialized.to_string();
+            self.write_server(&initialized);
+        }
+        self.ready = true;
+        for line in mem::take(&mut self.queue) {
+            self.send_to_server(line);
+        }
+
+        let capabilities = &response["result"]["capabilities"];
+        for capability in ["tools", "resources", "prompts"] {
+            if capabilities.get(capability).is_some() {
+                write_client(
+                    &json!({
+                        "jsonrpc": "2.0",
+                        "method": format!("notifications/{capability}/list_changed"),
+                    })
+                    .to_string(),
+                )?;
+            }
+        }
+        eprintln!("✅ Restarted the server");
+        Ok(())
+    }
+
+    /// Pass a line from the client to the server, remembering the handshake for replaying
+    fn send_to_server(&mut self, line: String) {
+        if let Ok(message) = serde_json::from_str::<Value>(&line) {
+            match message["method"].as_str() {
+                Some("initialize") => self.initialize = Some(message),
+                Some("notifications/initialized") => self.initialized = Some(message),
+                _ => {}
+            }
+        }
+        self.write_server(&line);
+    }
+
+    fn write_server(&mut self, line: &str) {
+        let Some(stdin) = self
+            .server
+            .as_mut()
+            .and_then(|server| server.stdin.as_mut())
+        else {
+            return;
+        };
+        // a server that has exited is reported when its output closes
+        let _ = writeln!(stdin, "{line}").and_then(|()| stdin.flush());
+    }
+
+    /// Build the project and replace the running server with the new one
+    ///
+    /// If the build fails, the old server keeps running and the error is printed, so the client
+    /// stays usable until the next change.
+    fn restart(&mut self) -> Result<()> {
+        // a running executable can't be replaced on windows, so the old server goes first
+        if cfg!(windows) {
+            self.stop()?;
+        }
+
+        let executable = match build(self.project_path) {
+            Ok(executable) => executable,
+            Err(e) => {
+                eprintln!("❌ {e:#}");
+                eprintln!("   Save a change to try again.");
+                return Ok(());
+            }
+        };
+
+        self.stop()?;
+        self.generation += 1;
+        self.server = match Server::spawn(
+            &executable,
+            &self.args,
+            self.project_path,
+            self.generation,
+            self.sender.clone(),
+        ) {
+            Ok(server) => Some(server),
+            Err(e) => {
+                eprintln!("❌ {e:#}");
+                return Ok(());
+            }
+        };
+
+        if let Some(initialize) = &self.initialize {
+            let mut replay = initialize.clone();
+            replay["id"] = json!(REPLAY_ID);
+            self.write_server(&replay.to_string());
+        } else {
+            // the client hasn't connected yet, and will perform the handshake itself
+            self.ready = true;
+            for line in mem::take(&mut self.queue) {
+                self.send_to_server(line);
+            }
+            eprintln!("✅ Started the server");
+        }
+        Ok(())
+    }
+
+    /// Stop the server, if there is one, and fail the requests it hadn't responded to
+    ///
+    /// Requests that arrive before the next server is ready wait for it.
+    fn stop(&mut self) -> Result<()> {
+        self.ready = false;
+        self.server = None;
+        for id in mem::take(&mut self.pending) {
+            write_client(
+                &json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": {
+                        "code": -32603,
+                        "message": "The server was restarted before it responded",
+                    },
+                })
+                .to_string(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn write_client(line: &str) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{line}")
+        .and_then(|()| stdout.flush())
+        .context("Failed to write to the client")
+}
+
+/// Send each line from stdin to the proxy, then [`Event::Client`]`(None)` once it closes
+fn read_client(sender: Sender<Event>) {
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if !line.trim().is_empty() && sender.send(Event::Client(Some(line))).is_err() {
+                return;
+            }
+        }
+        let _ = sender.send(Event::Client(None));
+    });
+}
+
+/// Send [`Event::Changed`] whenever the project's [`sources`] change
+fn watch_sources(project_path: PathBuf, sender: Sender<Event>) {
+    thread::spawn(move || {
+        let mut sources = self::sources(&project_path);
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let mut changed = self::sources(&project_path);
+            if changed == sources {
+                continue;
+            }
+            // an editor may save several files at once, so wait for the writes to settle
+            loop {
+                thread::sleep(POLL_INTERVAL);
+                let settled = self::sources(&project_path);
+                if settled == changed {
+                    break;
+                }
+                changed = settled;
+            }
+            sources = changed;
+            if sender.send(Event::Changed).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// The modification times of the files that go into a build: `Cargo.toml`, `build.rs`, and
+/// everything in `src/` except hidden files and editor backups
+pub fn sources(project_path: &Path) -> BTreeMap<PathBuf, SystemTime> {
+    fn visit(path: &Path, sources: &mut BTreeMap<PathBuf, SystemTime>) {
+        let Ok(metadata) = fs::metadata(path) else {
+            return;
+        };
+        if metadata.is_dir() {
+            for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                if !name.starts_with('.') && !name.ends_with('~') {
+                    visit(&entry.path(), sources);
+                }
+            }
+        } else if let Ok(modified) = metadata.modified() {
+            sources.insert(path.to_path_buf(), modified);
+        }
+    }
+
+    let mut sources = BTreeMap::new();
+    for path in ["Cargo.toml", "build.rs", "src"] {
+        visit(&project_path.join(path), &mut sources);
+    }
+    sources
+}
+
+/// Run `cargo build`, returning the path of the executable it built
+///
+/// Cargo's progress and any compiler errors go to stderr, which clients usually keep as the
+/// server's log.
+fn build(project_path: &Path) -> Result<PathBuf> {
+    eprintln!("🔨 Building the server...");
+    let output = Command::new("cargo")
+        .args(["build", "--message-format=json-render-diagnostics"])
+        .current_dir(project_path)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .context("Failed to execute cargo build")?;
+    if !output.status.success() {
+        return Err(anyhow!("The build failed ({})", output.status));
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|message| message["reason"] == "compiler-artifact")
+        .filter_map(|message| message["executable"].as_str().map(PathBuf::from))
+        .next_back()
+        .ok_or_else(|| anyhow!("cargo build didn't produce an executable"))
+}
+
+/// A running server, whose output is sent to the proxy as [`Event::Server`]s
+struct Server {
+    child: Child,
+    stdin: Option<ChildStdin>,
+}
+
+impl Server {
+    fn spawn(
+        executable: &Path,
+        args: &[String],
+        project_path: &Path,
+        generation: u64,
+        sender: Sender<Event>,
+    ) -> Result<Self> {
+        let mut child = Command::new(executable)
+            .args(args)
+            .current_dir(project_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to start {}", executable.display()))?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+
+        thread::spawn(move || {
+            for line in stdout.lines() {
+                let Ok(line) = line else { break };
+                if !line.trim().is_empty()
+                    && sender.send(Event::Server(generation, Some(line))).is_err()
+                {
+                    return;
+                }
+            }
+            let _ = sender.send(Event::Server(generation, None));
+        });
+
+        Ok(Self {
+            child,
+            stdin: Some(stdin),
+        })
+    }
+}
+
+impl Drop for Server {
+    /// Close stdin so the server can shut down on its own, and kill it if it doesn't
+    fn drop(&mut self) {
+        self.stdin.take();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while Instant::now() < deadline {
+            if !matches!(self.child.try_wait(), Ok(None)) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,204 @@
+//! Creating a project inside an existing cargo workspace
+//!
+//! The project is added to the workspace's `members`, and its manifest inherits the workspace's
+//! shared package fields, dependencies, and lints with `workspace = true`.
+
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    path::{self, Path, PathBuf},
+};
+use toml_edit::{DocumentMut, InlineTable, Item, Table, value};
+
+/// The `[package]` fields a new project inherits when the workspace sets them. Fields that
+/// describe one crate, like `description`, are left alone.
+const INHERITED_PACKAGE_FIELDS: [&str; 7] = [
+    "version",
+    "edition",
+    "authors",
+    "license",
+    "repository",
+    "homepage",
+    "rust-version",
+];
+
+/// A cargo workspace, with its root manifest
+pub struct Workspace {
+    root: PathBuf,
+    manifest: DocumentMut,
+}
+
+impl Workspace {
+    /// The workspace whose root is `dir` or the nearest of its ancestors with a `[workspace]`
+    /// table, like cargo looks for it
+    pub fn find(dir: &Path) -> Result<Option<Self>> {
+        let dir = path::absolute(dir)?;
+        for ancestor in dir.ancestors() {
+            let path = ancestor.join("Cargo.toml");
+            let Ok(content) = fs::read_to_string(&path) else {
+                continue;
+            };
+            let manifest: DocumentMut = content
+                .parse()
+                .with_context(|| format!("Failed to parse {}", path.display()))?;
+            if manifest.contains_key("workspace") {
+                return Ok(Some(Self {
+                    root: ancestor.to_path_buf(),
+                    manifest,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Where a project named `name` goes when no output directory is given: in the directory of
+    /// the first `members` glob like `crates/*`, if there is one
+    pub fn default_output(&self, name: &str) -> Option<PathBuf> {
+        self.members()
+            .find_map(|member| member.strip_suffix("/*"))
+            .map(|dir| self.root.join(dir).join(name))
+    }
+
+    /// Whether `project_dir` is listed in the workspace's `members`, or covered by a glob there
+    pub fn has_member(&self, project_dir: &Path) -> Result<bool> {
+        let relative = self.relative(project_dir)?;
+        let parent = relative.rsplit_once('/').map_or("", |(parent, _)| parent);
+        Ok(self.members().any(|member| {
+            member == relative || member.strip_suffix("/*").is_some_and(|dir| dir == parent)
+        }))
+    }
+
+    /// Add `project_dir` to the workspace's `members`, unless it is already a member
+    pub fn add_member(&mut self, project_dir: &Path) -> Result<()> {
+        if self.has_member(project_dir)? {
+            return Ok(());
+        }
+        let relative = self.relative(project_dir)?;
+
+        let workspace = self.manifest["workspace"]
+            .as_table_like_mut()
+            .context("[workspace] in the workspace's Cargo.toml is not a table")?;
+        if !workspace.contains_key("members") {
+            workspace.insert("members", value(toml_edit::Array::new()));
+        }
+        workspace
+            .get_mut("members")
+            .and_then(Item::as_array_mut)
+            .context("workspace.members is not an array")?
+            .push(relative);
+
+        let path = self.root.join("Cargo.toml");
+        fs::write(&path, self.manifest.to_string())
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Make the project manifest at `manifest_path` inherit the workspace's shared package fields,
+    /// dependencies, and lints
+    ///
+    /// A dependency inherits from the workspace if the workspace declares it, keeping the features
+    /// the project asked for, since those add to the workspace's.
+    pub fn inherit(&self, manifest_path: &Path) -> Result<()> {
+        let content = fs::read_to_string(manifest_path)
+            .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+        let mut manifest: DocumentMut = content
+            .parse()
+            .with_context(|| format!("Failed to parse {}", manifest_path.display()))?;
+        let workspace = &self.manifest["workspace"];
+
+        if let Some(shared) = workspace.get("package").and_then(Item::as_table_like)
+            && let Some(package) = manifest
+                .get_mut("package")
+                .and_then(Item::as_table_like_mut)
+        {
+            for field in INHERITED_PACKAGE_FIELDS {
+                if shared.contains_key(field) {
+                    let mut inherited = Table::new();
+                    inherited.set_dotted(true);
+                    inherited.insert("workspace", value(true));
+                    package.insert(field, Item::Table(inherited));
+                }
+            }
+        }
+
+        if let Some(shared) = workspace.get("dependencies").and_then(Item::as_table_like)
+            && let Some(dependencies) = manifest
+                .get_mut("dependencies")
+                .and_then(Item::as_table_like_mut)
+        {
+            for (name, dependency) in dependencies.iter_mut() {
+                if !shared.contains_key(name.get()) {
+                    continue;
+                }
+                let mut inherited = InlineTable::new();
+                inherited.insert("workspace", true.into());
+                if let Some(features) = dependency.get("features").and_then(Item::as_value) {
+                    inherited.insert("features", features.clone());
+                }
+                *dependency = value(inherited);
+            }
+        }
+
+        if workspace.get("lints").is_some() {
+            let mut lints = Table::new();
+            lints.insert("workspace", value(true));
+            manifest.insert("lints", Item::Table(lints));
+        }
+
+        fs::write(manifest_path, manifest.to_string())
+            .with_context(|| format!("Failed to write {}", manifest_path.display()))
+    }
+
+    /// `path` relative to the root, with forward slashes as in `members`
+    fn relative(&self, path: &Path) -> Result<String> {
+        Ok(path::absolute(path)?
+            .strip_prefix(&self.root)
+            .with_context(|| {
+                format!(
+                    "{} is not inside the workspace at {}",
+                    path.display(),
+                    self.root.display()
+                )
+            })?
+            .to_string_lossy()
+            .replace('\\', "/"))
+    }
+
+    fn members(&self) -> impl Iterator<Item = &str> {
+        self.manifest["workspace"]
+            .get("members")
+            .and_then(Item::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|member| member.as_str())
+    }
+}
+
+/// Give the project in `project_dir` an empty `[workspace]` table if it is inside a workspace
+/// that doesn't list it, so that cargo treats it as a workspace of its own instead of refusing to
+/// build it
+pub fn isolate_if_unlisted(project_dir: &Path) -> Result<()> {
+    let Some(parent) = path::absolute(project_dir)?.parent().map(Path::to_path_buf) else {
+        return Ok(());
+    };
+    match Workspace::find(&parent)? {
+        Some(workspace) if !workspace.has_member(project_dir)? => {}
+        _ => return Ok(()),
+    }
+
+    let manifest_path = project_dir.join("Cargo.toml");
+    let mut content = fs::read_to_string(&manifest_path)
+        .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+    let manifest: DocumentMut = content
+        .parse()
+        .with_context(|| format!("Failed to parse {}", manifest_path.display()))?;
+    if manifest.contains_key("workspace") {
+        return Ok(());
+    }
+    content.push_str("\n[workspace]\n");
+    fs::write(&manifest_path, content)
+        .with_context(|| format!("Failed to write {}", manifest_path.display()))
+}
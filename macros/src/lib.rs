@@ -0,0 +1,661 @@
+//! Derive and attribute macros for [mcplease](https://docs.rs/mcplease)
+//!
+//! These are re-exported from `mcplease` and should be used from there.
+
+use heck::ToSnakeCase;
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    DeriveInput, Fields, ItemEnum, LitStr, Type, parse::Parser, parse_macro_input, spanned::Spanned,
+};
+
+/// Implement `mcplease::traits::McpTool` for a tool struct
+///
+/// The wire name is taken from `#[tool(name = "...")]` if present, then from
+/// `#[serde(rename = "...")]`, and otherwise is the snake_case struct name.
+#[proc_macro_derive(McpTool, attributes(tool))]
+pub fn derive_mcp_tool(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match mcp_tool(input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn mcp_tool(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let mut tool_name = None;
+    let mut serde_rename = None;
+    for attr in &input.attrs {
+        if attr.path().is_ident("tool") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    tool_name = Some(meta.value()?.parse::<LitStr>()?.value());
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `name = \"...\"`"))
+                }
+            })?;
+        } else if attr.path().is_ident("serde") {
+            // other serde attributes are none of our business
+            let _ = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    serde_rename = Some(meta.value()?.parse::<LitStr>()?.value());
+                } else if meta.input.peek(syn::Token![=]) {
+                    meta.value()?.parse::<syn::Expr>()?;
+                }
+                Ok(())
+            });
+        }
+    }
+
+    let name = tool_name
+        .or(serde_rename)
+        .unwrap_or_else(|| ident.to_string().to_snake_case());
+
+    Ok(quote! {
+        impl #impl_generics ::mcplease::traits::McpTool for #ident #ty_generics #where_clause {
+            const NAME: &'static str = #name;
+        }
+    })
+}
+
+/// Implement `clap::Args` for a tool struct through a separate command-line struct, so that its
+/// fields can have types and parsers that suit the command line without changing what clients
+/// send
+///
+/// Derive this instead of `clap::Args`. Each field's `#[arg(...)]` attributes and doc comments are
+/// copied to the generated struct, and its `#[serde(...)]` attributes stay behind, so a field can
+/// have a clap `value_parser` and a serde `deserialize_with` that would otherwise trip each other
+/// up. A field marked `#[cli(ty = T)]` is parsed as a `T` on the command line and converted with
+/// `From`:
+///
+/// ```ignore
+/// #[derive(Debug, Serialize, Deserialize, JsonSchema, McpTool, CliArgs)]
+/// pub struct Wait {
+///     /// How long to wait, in seconds
+///     #[arg(long, value_parser = parse_seconds)]
+///     #[serde(with = "seconds")]
+///     pub duration: Duration,
+///
+///     /// Which files to watch
+///     #[arg(long)]
+///     #[cli(ty = String)]
+///     pub glob: Glob,
+/// }
+/// ```
+#[proc_macro_derive(CliArgs, attributes(arg, cli))]
+pub fn derive_cli_args(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match cli_args(input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn cli_args(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            input.generics.span(),
+            "CliArgs can't be derived for generic tools",
+        ));
+    }
+    let syn::Data::Struct(syn::DataStruct {
+        fields: Fields::Named(fields),
+        ..
+    }) = &input.data
+    else {
+        return Err(syn::Error::new(
+            input.span(),
+            "CliArgs can only be derived for structs with named fields",
+        ));
+    };
+
+    let docs = input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"));
+    let group_id = LitStr::new(&ident.to_string(), ident.span());
+    let mut cli_fields = vec![];
+    let mut conversions = vec![];
+    for field in &fields.named {
+        let name = field.ident.as_ref().unwrap();
+        let mut cli_ty = None;
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("cli"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("ty") {
+                    cli_ty = Some(meta.value()?.parse::<Type>()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `ty = Type`"))
+                }
+            })?;
+        }
+        let attrs = field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("doc") || attr.path().is_ident("arg"));
+        let ty = cli_ty.as_ref().unwrap_or(&field.ty);
+        cli_fields.push(quote!(#(#attrs)* #name: #ty));
+        conversions.push(match cli_ty {
+            Some(_) => quote!(#name: ::std::convert::From::from(cli.#name)),
+            None => quote!(#name: cli.#name),
+        });
+    }
+
+    Ok(quote! {
+        const _: () = {
+            #[derive(::mcplease::clap::Args)]
+            #[group(id = #group_id)]
+            #(#docs)*
+            struct Cli {
+                #(#cli_fields,)*
+            }
+
+            impl ::std::convert::From<Cli> for #ident {
+                fn from(cli: Cli) -> Self {
+                    Self {
+                        #(#conversions,)*
+                    }
+                }
+            }
+
+            impl ::mcplease::clap::FromArgMatches for #ident {
+                fn from_arg_matches(
+                    matches: &::mcplease::clap::ArgMatches,
+                ) -> ::std::result::Result<Self, ::mcplease::clap::Error> {
+                    <Cli as ::mcplease::clap::FromArgMatches>::from_arg_matches(matches).map(Self::from)
+                }
+
+                fn update_from_arg_matches(
+                    &mut self,
+                    matches: &::mcplease::clap::ArgMatches,
+                ) -> ::std::result::Result<(), ::mcplease::clap::Error> {
+                    *self = <Self as ::mcplease::clap::FromArgMatches>::from_arg_matches(matches)?;
+                    Ok(())
+                }
+            }
+
+            impl ::mcplease::clap::Args for #ident {
+                fn group_id() -> ::std::option::Option<::mcplease::clap::Id> {
+                    <Cli as ::mcplease::clap::Args>::group_id()
+                }
+
+                fn augment_args(command: ::mcplease::clap::Command) -> ::mcplease::clap::Command {
+                    <Cli as ::mcplease::clap::Args>::augment_args(command)
+                }
+
+                fn augment_args_for_update(
+                    command: ::mcplease::clap::Command,
+                ) -> ::mcplease::clap::Command {
+                    <Cli as ::mcplease::clap::Args>::augment_args_for_update(command)
+                }
+            }
+        };
+    })
+}
+
+/// Build a `Tools` enum from an enum whose variants each wrap one `#[derive(McpTool)]` tool
+///
+/// ```ignore
+/// #[mcplease::tool_router(State)]
+/// pub enum Tools {
+///     Hello(Hello),
+///     Goodbye(goodbye::Goodbye),
+/// }
+/// ```
+///
+/// This generates the same impls as the `tools!` macro, without requiring any particular module
+/// layout.
+///
+/// A variant marked `#[group]` wraps a whole `Tools` enum instead, generated by `tools!` or
+/// `tool_router` in another module or crate, and serves all of its tools. With
+/// `#[group(prefix = "fs")]`, each of the group's wire names is prefixed, so `read` is served as
+/// `fs_read`, and on the command line the group's tools are under an `fs` subcommand. Because
+/// prefixed names are built at runtime, `name` returns a `String` for an enum with groups.
+///
+/// ```ignore
+/// #[mcplease::tool_router(State)]
+/// pub enum Tools {
+///     Hello(Hello),
+///     #[group(prefix = "fs")]
+///     Fs(fs::Tools),
+///     #[group(prefix = "git")]
+///     Git(git::Tools),
+/// }
+/// ```
+///
+/// A variant marked `#[state(GitState)]` wraps a tool or group that works with `GitState` rather
+/// than the whole state, projected with `mcplease::traits::FromState`.
+///
+/// ```ignore
+/// #[mcplease::tool_router(State)]
+/// pub enum Tools {
+///     Hello(Hello),
+///     #[state(GitState)]
+///     #[group(prefix = "git")]
+///     Git(git::Tools),
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn tool_router(args: TokenStream, input: TokenStream) -> TokenStream {
+    let state = parse_macro_input!(args as Type);
+    let input = parse_macro_input!(input as ItemEnum);
+    match router(state, input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// What one variant of a `tool_router` enum wraps
+enum Routed {
+    /// A single `#[derive(McpTool)]` tool
+    Tool,
+
+    /// Another `Tools` enum, with an optional prefix for its wire names
+    Group(Option<String>),
+}
+
+/// Remove a `#[group]` or `#[group(prefix = "...")]` attribute from `attrs`, returning what the
+/// variant wraps
+fn routed(attrs: &mut Vec<syn::Attribute>) -> syn::Result<Routed> {
+    let Some(index) = attrs.iter().position(|attr| attr.path().is_ident("group")) else {
+        return Ok(Routed::Tool);
+    };
+    let attr = attrs.remove(index);
+    let mut prefix = None;
+    if let syn::Meta::List(_) = attr.meta {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("prefix") {
+                prefix = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("expected `prefix = \"...\"`"))
+            }
+        })?;
+    }
+    Ok(Routed::Group(prefix))
+}
+
+/// Remove a `#[state(SubState)]` attribute from `attrs`, returning the sub-state it names
+fn substate(attrs: &mut Vec<syn::Attribute>) -> syn::Result<Option<Type>> {
+    let Some(index) = attrs.iter().position(|attr| attr.path().is_ident("state")) else {
+        return Ok(None);
+    };
+    attrs.remove(index).parse_args().map(Some)
+}
+
+fn router(state: Type, mut input: ItemEnum) -> syn::Result<TokenStream2> {
+    let mut debug_arms = vec![];
+    let mut deserialize = vec![];
+    let mut serialize_arms = vec![];
+    let mut execute_arms = vec![];
+    let mut execute_with_context_arms = vec![];
+    let mut complete = vec![];
+    let mut enabled = vec![];
+    let mut list = vec![];
+    let mut name_arms = vec![];
+    let mut tool_names = vec![];
+    let mut has_groups = false;
+
+    for variant in &mut input.variants {
+        let routed = routed(&mut variant.attrs)?;
+        let tool_state = substate(&mut variant.attrs)?.unwrap_or_else(|| state.clone());
+        let Fields::Unnamed(fields) = &mut variant.fields else {
+            return Err(syn::Error::new(
+                variant.span(),
+                "tool_router variants must wrap exactly one tool, like `Hello(Hello)`",
+            ));
+        };
+        if fields.unnamed.len() != 1 {
+            return Err(syn::Error::new(
+                fields.span(),
+                "tool_router variants must wrap exactly one tool, like `Hello(Hello)`",
+            ));
+        }
+
+        let field = fields.unnamed.first_mut().unwrap();
+        let ty = field.ty.clone();
+        let ident = variant.ident.clone();
+
+        debug_arms.push(quote!(Self::#ident(tool) => ::std::fmt::Debug::fmt(tool, f),));
+        execute_arms.push(quote! {
+            Self::#ident(tool) => <#ty as Tool<#tool_state>>::execute(
+                tool,
+                FromState::from_state_mut(state),
+            ),
+        });
+        execute_with_context_arms.push(quote! {
+            Self::#ident(tool) => <#ty as Tool<#tool_state>>::execute_with_context(
+                tool,
+                FromState::from_state_mut(state),
+                context,
+            ),
+        });
+
+        match routed {
+            Routed::Tool => {
+                field
+                    .attrs
+                    .extend(syn::Attribute::parse_outer.parse2(quote!(#[clap(flatten)]))?);
+                deserialize.push(quote! {
+                    if name == <#ty as McpTool>::NAME {
+                        return ::mcplease::serde_json::from_value(arguments.clone())
+                            .map_err(de::Error::custom)
+                            .map(Self::#ident);
+                    }
+                });
+                serialize_arms.push(quote! {
+                    Self::#ident(args) => {
+                        let mut state = serializer.serialize_struct(TYPE_NAME, 2)?;
+                        state.serialize_field("name", <#ty as McpTool>::NAME)?;
+                        state.serialize_field("arguments", args)?;
+                        state.end()
+                    }
+                });
+                complete.push(quote! {
+                    if name == <#ty as McpTool>::NAME {
+                        return Some(<#ty as Tool<#tool_state>>::complete(
+                            argument,
+                            value,
+                            FromState::from_state_mut(state),
+                        ));
+                    }
+                });
+                enabled.push(quote! {
+                    if name == <#ty as McpTool>::NAME {
+                        return Some(<#ty as Tool<#tool_state>>::enabled(FromState::from_state(state)));
+                    }
+                });
+                list.push(quote! {
+                    let mut schema = ::mcplease::types::ToolSchema::for_tool::<#ty, #tool_state>();
+                    schema.name = <#ty as McpTool>::NAME.to_string();
+                    tools.push(schema);
+                });
+                name_arms.push((
+                    quote!(Self::#ident(_)),
+                    quote!(<#ty as McpTool>::NAME),
+                    true,
+                ));
+                tool_names.push(quote!(<#ty as McpTool>::NAME));
+            }
+
+            Routed::Group(None) => {
+                has_groups = true;
+                variant
+                    .attrs
+                    .extend(syn::Attribute::parse_outer.parse2(quote!(#[clap(flatten)]))?);
+                deserialize.push(quote! {
+                    if <#ty as AsToolsList>::tools_list().iter().any(|tool| tool.name == name) {
+                        return ::mcplease::serde_json::from_value(value.clone())
+                            .map_err(de::Error::custom)
+                            .map(Self::#ident);
+                    }
+                });
+                serialize_arms.push(quote! {
+                    Self::#ident(tools) => ::mcplease::serde::Serialize::serialize(tools, serializer),
+                });
+                complete.push(quote! {
+                    if let Some(completions) = <#ty as CompleteArgument<#tool_state>>::complete_argument(
+                        name,
+                        argument,
+                        value,
+                        FromState::from_state_mut(state),
+                    ) {
+                        return Some(completions);
+                    }
+                });
+                enabled.push(quote! {
+                    if let Some(enabled) =
+                        <#ty as EnabledTools<#tool_state>>::tool_enabled(name, FromState::from_state(state))
+                    {
+                        return Some(enabled);
+                    }
+                });
+                list.push(quote!(tools.extend(<#ty as AsToolsList>::tools_list());));
+                name_arms.push((
+                    quote!(Self::#ident(tools)),
+                    quote!(tools.name().to_string()),
+                    false,
+                ));
+            }
+
+            Routed::Group(Some(prefix)) => {
+                has_groups = true;
+                variant.attrs.extend(
+                    syn::Attribute::parse_outer
+                        .parse2(quote!(#[clap(subcommand, name = #prefix)]))?,
+                );
+                let separated = format!("{prefix}_");
+                deserialize.push(quote! {
+                    // the generated code is compiled with the caller's edition, so no let chains
+                    if let Some(name) = name.strip_prefix(#separated) {
+                        if <#ty as AsToolsList>::tools_list().iter().any(|tool| tool.name == name) {
+                            return ::mcplease::serde_json::from_value(
+                                ::mcplease::serde_json::json!({ "name": name, "arguments": arguments }),
+                            )
+                            .map_err(de::Error::custom)
+                            .map(Self::#ident);
+                        }
+                    }
+                });
+                serialize_arms.push(quote! {
+                    Self::#ident(tools) => {
+                        let mut state = serializer.serialize_struct(TYPE_NAME, 2)?;
+                        let value = ::mcplease::serde_json::to_value(tools)
+                            .map_err(::mcplease::serde::ser::Error::custom)?;
+                        state.serialize_field("name", &format!("{}{}", #separated, tools.name()))?;
+                        state.serialize_field("arguments", &value["arguments"])?;
+                        state.end()
+                    }
+                });
+                complete.push(quote! {
+                    if let Some(name) = name.strip_prefix(#separated) {
+                        if let Some(completions) = <#ty as CompleteArgument<#tool_state>>::complete_argument(
+                            name, argument, value, FromState::from_state_mut(state),
+                        ) {
+                            return Some(completions);
+                        }
+                    }
+                });
+                enabled.push(quote! {
+                    if let Some(name) = name.strip_prefix(#separated) {
+                        if let Some(enabled) = <#ty as EnabledTools<#tool_state>>::tool_enabled(
+                            name,
+                            FromState::from_state(state),
+                        ) {
+                            return Some(enabled);
+                        }
+                    }
+                });
+                list.push(quote! {
+                    tools.extend(<#ty as AsToolsList>::tools_list().into_iter().map(|mut schema| {
+                        schema.name = format!("{}{}", #separated, schema.name);
+                        for alias in &mut schema.aliases {
+                            *alias = format!("{}{}", #separated, alias);
+                        }
+                        schema
+                    }));
+                });
+                name_arms.push((
+                    quote!(Self::#ident(tools)),
+                    quote!(format!("{}{}", #separated, tools.name())),
+                    false,
+                ));
+            }
+        }
+    }
+
+    input
+        .attrs
+        .extend(syn::Attribute::parse_outer.parse2(quote!(
+            #[derive(::mcplease::clap::Subcommand)]
+        ))?);
+
+    let ident = &input.ident;
+
+    let unknown = if has_groups {
+        quote!(Err(de::Error::custom(format!("unknown tool `{name}`"))))
+    } else {
+        quote!(Err(de::Error::unknown_variant(name, &[#(#tool_names),*])))
+    };
+
+    let name = if has_groups {
+        let name_arms = name_arms.iter().map(|(pattern, name, is_tool)| {
+            if *is_tool {
+                quote!(#pattern => #name.to_string(),)
+            } else {
+                quote!(#pattern => #name,)
+            }
+        });
+        quote! {
+            pub fn name(&self) -> ::std::string::String {
+                use ::mcplease::traits::McpTool;
+                match self {
+                    #(#name_arms)*
+                }
+            }
+        }
+    } else {
+        let name_arms = name_arms
+            .iter()
+            .map(|(pattern, name, _)| quote!(#pattern => #name,));
+        quote! {
+            pub fn name(&self) -> &'static str {
+                use ::mcplease::traits::McpTool;
+                match self {
+                    #(#name_arms)*
+                }
+            }
+        }
+    };
+
+    Ok(quote! {
+        #input
+
+        impl ::std::fmt::Debug for #ident {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                match self {
+                    #(#debug_arms)*
+                }
+            }
+        }
+
+        impl<'de> ::mcplease::serde::Deserialize<'de> for #ident {
+            fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+            where
+                D: ::mcplease::serde::Deserializer<'de>,
+            {
+                use ::mcplease::serde::de;
+                #[allow(unused_imports)]
+                use ::mcplease::traits::{AsToolsList, McpTool};
+
+                let value: ::mcplease::serde_json::Value =
+                    ::mcplease::serde::Deserialize::deserialize(deserializer)?;
+
+                let obj = value
+                    .as_object()
+                    .ok_or_else(|| de::Error::custom("expected object"))?;
+
+                let name = obj
+                    .get("name")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| de::Error::missing_field("name"))?;
+
+                let arguments = obj
+                    .get("arguments")
+                    .ok_or_else(|| de::Error::missing_field("arguments"))?;
+
+                #(#deserialize)*
+
+                #unknown
+            }
+        }
+
+        impl ::mcplease::serde::Serialize for #ident {
+            fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+            where
+                S: ::mcplease::serde::Serializer,
+            {
+                #[allow(unused_imports)]
+                use ::mcplease::serde::ser::SerializeStruct;
+                #[allow(unused_imports)]
+                use ::mcplease::traits::McpTool;
+
+                const TYPE_NAME: &str = stringify!(#ident);
+                match self {
+                    #(#serialize_arms)*
+                }
+            }
+        }
+
+        impl ::mcplease::traits::Tool<#state> for #ident {
+            fn execute(
+                self,
+                state: &mut #state,
+            ) -> ::mcplease::anyhow::Result<::mcplease::types::ToolOutput> {
+                use ::mcplease::traits::{FromState, Tool};
+                match self {
+                    #(#execute_arms)*
+                }
+            }
+
+            fn execute_with_context(
+                self,
+                state: &mut #state,
+                context: &::mcplease::context::ToolContext,
+            ) -> ::mcplease::anyhow::Result<::mcplease::types::ToolOutput> {
+                use ::mcplease::traits::{FromState, Tool};
+                match self {
+                    #(#execute_with_context_arms)*
+                }
+            }
+        }
+
+        impl ::mcplease::traits::CompleteArgument<#state> for #ident {
+            fn complete_argument(
+                name: &str,
+                argument: &str,
+                value: &str,
+                state: &mut #state,
+            ) -> ::std::option::Option<::mcplease::anyhow::Result<::std::vec::Vec<::std::string::String>>> {
+                #[allow(unused_imports)]
+                use ::mcplease::traits::{CompleteArgument, FromState, McpTool, Tool};
+                #(#complete)*
+                None
+            }
+        }
+
+        impl ::mcplease::traits::EnabledTools<#state> for #ident {
+            fn tool_enabled(name: &str, state: &#state) -> ::std::option::Option<bool> {
+                #[allow(unused_imports)]
+                use ::mcplease::traits::{EnabledTools, FromState, McpTool, Tool};
+                #(#enabled)*
+                None
+            }
+        }
+
+        impl ::mcplease::traits::AsToolsList for #ident {
+            fn tools_list() -> ::std::vec::Vec<::mcplease::types::ToolSchema> {
+                #[allow(unused_imports)]
+                use ::mcplease::traits::{AsToolsList, McpTool};
+                let mut tools = vec![];
+                #(#list)*
+                tools
+            }
+        }
+
+        impl #ident {
+            #[allow(dead_code)]
+            #name
+        }
+    })
+}
@@ -0,0 +1,229 @@
+use clap::Parser;
+use mcplease::anyhow::Result;
+use mcplease::{
+    CliArgs, McpTool, tool_router,
+    traits::{AsToolsList, FromState, McpTool as _, Tool, WithExamples},
+    types::ToolOutput,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+#[derive(Debug, Default)]
+struct State {
+    greeting: String,
+    git: GitState,
+}
+
+#[derive(Debug, Default)]
+struct GitState {
+    commits: Vec<String>,
+}
+
+impl FromState<State> for GitState {
+    fn from_state(state: &State) -> &Self {
+        &state.git
+    }
+
+    fn from_state_mut(state: &mut State) -> &mut Self {
+        &mut state.git
+    }
+}
+
+/// Greet someone
+#[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema, clap::Args, McpTool)]
+struct Hello {
+    /// Who to greet
+    #[arg(long)]
+    name: String,
+}
+
+impl WithExamples for Hello {}
+
+impl Tool<State> for Hello {
+    fn execute(self, state: &mut State) -> Result<ToolOutput> {
+        Ok(format!("{}, {}", state.greeting, self.name).into())
+    }
+}
+
+/// Text that is shouted
+#[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+struct Loud(String);
+
+impl From<String> for Loud {
+    fn from(text: String) -> Self {
+        Self(text.to_uppercase())
+    }
+}
+
+/// Say something loudly
+#[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema, McpTool, CliArgs)]
+#[tool(name = "yell")]
+struct Shout {
+    /// What to say
+    #[arg(long)]
+    #[cli(ty = String)]
+    text: Loud,
+}
+
+impl WithExamples for Shout {}
+
+impl Tool<State> for Shout {
+    fn execute(self, _: &mut State) -> Result<ToolOutput> {
+        Ok(self.text.0.into())
+    }
+}
+
+mod git {
+    use super::*;
+
+    /// Record a change
+    #[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema, clap::Args, McpTool)]
+    #[serde(rename = "commit")]
+    pub struct CommitChanges {
+        /// What changed
+        #[arg(long)]
+        pub message: String,
+    }
+
+    impl WithExamples for CommitChanges {}
+
+    impl Tool<GitState> for CommitChanges {
+        fn execute(self, state: &mut GitState) -> Result<ToolOutput> {
+            state.commits.push(self.message);
+            Ok(format!("{} commits", state.commits.len()).into())
+        }
+    }
+
+    /// List the changes so far
+    #[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema, clap::Args, McpTool)]
+    pub struct Log {}
+
+    impl WithExamples for Log {}
+
+    impl Tool<GitState> for Log {
+        fn execute(self, state: &mut GitState) -> Result<ToolOutput> {
+            Ok(state.commits.join("\n").into())
+        }
+    }
+
+    #[tool_router(GitState)]
+    pub enum Tools {
+        CommitChanges(CommitChanges),
+        Log(Log),
+    }
+}
+
+#[tool_router(State)]
+enum Tools {
+    Hello(Hello),
+    Shout(Shout),
+    #[state(GitState)]
+    #[group(prefix = "git")]
+    Git(git::Tools),
+}
+
+#[derive(Parser)]
+struct Cli {
+    #[command(subcommand)]
+    tool: Tools,
+}
+
+fn tool(name: &str, arguments: Value) -> Tools {
+    serde_json::from_value(json!({ "name": name, "arguments": arguments })).unwrap()
+}
+
+#[test]
+fn names() {
+    assert_eq!(Hello::NAME, "hello");
+    assert_eq!(Shout::NAME, "yell");
+    assert_eq!(git::CommitChanges::NAME, "commit");
+    assert_eq!(git::Log::NAME, "log");
+}
+
+#[test]
+fn tools_list() {
+    let tools = Tools::tools_list();
+    let names = tools
+        .iter()
+        .map(|tool| tool.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["hello", "yell", "git_commit", "git_log"]);
+
+    let hello = serde_json::to_value(&tools[0]).unwrap();
+    assert_eq!(hello["description"], "Greet someone");
+    assert_eq!(hello["inputSchema"]["type"], "object");
+    assert_eq!(
+        hello["inputSchema"]["properties"]["name"]["description"],
+        "Who to greet"
+    );
+    assert_eq!(hello["inputSchema"]["required"], json!(["name"]));
+
+    let commit = serde_json::to_value(&tools[2]).unwrap();
+    assert_eq!(commit["description"], "Record a change");
+    assert_eq!(commit["inputSchema"]["required"], json!(["message"]));
+}
+
+#[test]
+fn arguments_round_trip() {
+    for (name, arguments) in [
+        ("hello", json!({ "name": "world" })),
+        ("yell", json!({ "text": "hi" })),
+        ("git_commit", json!({ "message": "first" })),
+        ("git_log", json!({})),
+    ] {
+        let tool = tool(name, arguments.clone());
+        assert_eq!(tool.name(), name);
+        assert_eq!(
+            serde_json::to_value(&tool).unwrap(),
+            json!({ "name": name, "arguments": arguments })
+        );
+    }
+
+    for unknown in ["commit", "git_hello", "fs_read", "nothing"] {
+        let error = serde_json::from_value::<Tools>(json!({ "name": unknown, "arguments": {} }))
+            .unwrap_err();
+        assert!(error.to_string().contains("unknown tool"), "{error}");
+    }
+}
+
+#[test]
+fn execute_with_sub_states() {
+    let mut state = State {
+        greeting: "Hello".into(),
+        ..State::default()
+    };
+    assert_eq!(
+        tool("hello", json!({ "name": "world" }))
+            .execute(&mut state)
+            .unwrap(),
+        ToolOutput::text("Hello, world")
+    );
+
+    for message in ["first", "second"] {
+        tool("git_commit", json!({ "message": message }))
+            .execute(&mut state)
+            .unwrap();
+    }
+    assert_eq!(state.git.commits, ["first", "second"]);
+    assert_eq!(
+        tool("git_log", json!({})).execute(&mut state).unwrap(),
+        ToolOutput::text("first\nsecond")
+    );
+}
+
+#[test]
+fn command_line() {
+    let Cli { tool } = Cli::try_parse_from(["server", "hello", "--name", "world"]).unwrap();
+    assert!(matches!(tool, Tools::Hello(Hello { name }) if name == "world"));
+
+    // `CliArgs` converts what was parsed
+    let Cli { tool } = Cli::try_parse_from(["server", "shout", "--text", "hi"]).unwrap();
+    assert!(matches!(tool, Tools::Shout(Shout { text: Loud(text) }) if text == "HI"));
+
+    // a prefixed group is a subcommand of its own
+    let Cli { tool } =
+        Cli::try_parse_from(["server", "git", "commit-changes", "--message", "first"]).unwrap();
+    assert_eq!(tool.name(), "git_commit");
+    assert!(Cli::try_parse_from(["server", "commit-changes", "--message", "first"]).is_err());
+}
@@ -0,0 +1,229 @@
+//! Deciding whether a tool call may go ahead, for servers with tools that shouldn't run for just
+//! anyone
+
+use crate::{Error, context::ToolContext};
+use serde_json::Value;
+
+/// Checks each `tools/call` before the tool runs, registered with
+/// [`ServerConfig::with_authorizer`](crate::server::ServerConfig::with_authorizer)
+///
+/// Returning an error refuses the call, and the client receives that error, so use
+/// [`Error::Unauthorized`] or an [`Error::custom`] with data explaining what approval is needed.
+/// Any function or closure with the same signature as [`authorize`](Self::authorize) is an
+/// authorizer.
+///
+/// ```ignore
+/// Server::builder()
+///     .with_authorizer(|request: &AuthorizationRequest| {
+///         if request.tool() == "delete" && request.bearer_token() != Some(ADMIN_TOKEN) {
+///             return Err(Error::Unauthorized("delete requires the admin token".into()));
+///         }
+///         Ok(())
+///     })
+/// ```
+pub trait Authorizer: Send + Sync {
+    /// `Ok` to let the call go ahead, or the error to refuse it with
+    fn authorize(&self, request: &AuthorizationRequest<'_>) -> Result<(), Error>;
+}
+
+impl<F> Authorizer for F
+where
+    F: Fn(&AuthorizationRequest<'_>) -> Result<(), Error> + Send + Sync,
+{
+    fn authorize(&self, request: &AuthorizationRequest<'_>) -> Result<(), Error> {
+        self(request)
+    }
+}
+
+/// A tool call waiting for an [`Authorizer`]'s decision
+#[derive(Debug, Clone, Copy)]
+pub struct AuthorizationRequest<'a> {
+    tool: &'a str,
+    arguments: &'a Value,
+    context: &'a ToolContext,
+}
+
+impl<'a> AuthorizationRequest<'a> {
+    pub(crate) fn new(tool: &'a str, arguments: &'a Value, context: &'a ToolContext) -> Self {
+        Self {
+            tool,
+            arguments,
+            context,
+        }
+    }
+
+    /// The name of the tool being called, never an alias
+    pub fn tool(&self) -> &'a str {
+        self.tool
+    }
+
+    /// The arguments as the client sent them, not yet validated, or null if it sent none
+    pub fn arguments(&self) -> &'a Value {
+        self.arguments
+    }
+
+    /// The context the tool will run with, for the client's info and session id
+    pub fn context(&self) -> &'a ToolContext {
+        self.context
+    }
+
+    /// The headers of the HTTP request that carried the call, for transports that use HTTP
+    pub fn headers(&self) -> Option<&'a HttpHeaders> {
+        self.context.http_headers()
+    }
+
+    /// The token from an `Authorization: Bearer` header, if the call came with one
+    pub fn bearer_token(&self) -> Option<&'a str> {
+        self.headers()?.bearer_token()
+    }
+}
+
+/// The headers of an HTTP request, with lowercase names
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HttpHeaders(Vec<(String, String)>);
+
+impl HttpHeaders {
+    pub(crate) fn new(headers: Vec<(String, String)>) -> Self {
+        Self(headers)
+    }
+
+    /// The value of the first header named `name`, ignoring case
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Every header, in the order they were sent
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    /// The token from an `Authorization: Bearer` header
+    pub fn bearer_token(&self) -> Option<&str> {
+        let (scheme, token) = self.get("authorization")?.split_once(' ')?;
+        scheme
+            .eq_ignore_ascii_case("bearer")
+            .then(|| token.trim())
+            .filter(|token| !token.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        registry::{NoTools, ToolRegistry},
+        server::ServerConfig,
+        testing::TestClient,
+        types::{Info, ToolOutput, ToolSchema},
+    };
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    type Names = Arc<Mutex<Vec<String>>>;
+
+    fn schema(name: &str, aliases: &[&str]) -> ToolSchema {
+        ToolSchema {
+            name: name.into(),
+            description: None,
+            input_schema: serde_json::from_value(json!({ "type": "object" })).unwrap(),
+            output_schema: None,
+            aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+            meta: None,
+        }
+    }
+
+    /// A client for a server with `read` and `delete`, also called `remove`, that refuses
+    /// `delete` without the admin token, along with the tools that were asked about and the tools
+    /// that ran
+    fn client() -> (TestClient<(), NoTools>, Names, Names) {
+        let asked = Arc::new(Mutex::new(vec![]));
+        let ran = Arc::new(Mutex::new(vec![]));
+
+        let registry = ToolRegistry::new();
+        for tool in [schema("read", &[]), schema("delete", &["remove"])] {
+            let ran = Arc::clone(&ran);
+            let name = tool.name.clone();
+            registry.register_fn(tool, move |_, _, _| {
+                ran.lock().unwrap().push(name.clone());
+                Ok(ToolOutput::text("done"))
+            });
+        }
+
+        let config = ServerConfig::new(Info {
+            name: "test".into(),
+            version: "0.0.0".into(),
+        })
+        .with_authorizer({
+            let asked = Arc::clone(&asked);
+            move |request: &AuthorizationRequest| {
+                asked.lock().unwrap().push(request.tool().to_string());
+                if request.tool() == "delete" && request.bearer_token() != Some("admin") {
+                    return Err(Error::Unauthorized(
+                        "delete requires the admin token".into(),
+                    ));
+                }
+                Ok(())
+            }
+        });
+
+        let client = TestClient::new(())
+            .with_config(config)
+            .with_tool_registry(registry);
+        (client, asked, ran)
+    }
+
+    #[test]
+    fn allowed_calls_run() {
+        let (mut client, asked, ran) = client();
+        client.initialize().unwrap();
+        client.call_tool("read", json!({})).unwrap();
+        assert_eq!(*asked.lock().unwrap(), ["read"]);
+        assert_eq!(*ran.lock().unwrap(), ["read"]);
+    }
+
+    #[test]
+    fn refused_calls_do_not_run() {
+        let (mut client, asked, ran) = client();
+        client.initialize().unwrap();
+        let response = client.request(
+            "tools/call",
+            json!({ "name": "delete", "arguments": { "path": "notes.md" } }),
+        );
+        let error = response.error.expect("delete to be refused");
+        assert_eq!(error.code, -32001);
+        assert_eq!(error.message, "delete requires the admin token");
+        assert_eq!(*asked.lock().unwrap(), ["delete"]);
+        assert!(ran.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn aliases_are_resolved_before_authorizing() {
+        let (mut client, asked, ran) = client();
+        client.initialize().unwrap();
+        assert!(client.call_tool("remove", json!({})).is_err());
+        assert_eq!(*asked.lock().unwrap(), ["delete"]);
+        assert!(ran.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn headers_reach_the_authorizer() {
+        let (client, _, ran) = client();
+        let mut client = client.with_http_headers([("Authorization", "Bearer admin")]);
+        client.initialize().unwrap();
+        client.call_tool("remove", json!({})).unwrap();
+        assert_eq!(*ran.lock().unwrap(), ["delete"]);
+    }
+
+    #[test]
+    fn bearer_token() {
+        let headers = |value: &str| HttpHeaders::new(vec![("authorization".into(), value.into())]);
+        assert_eq!(headers("Bearer abc").bearer_token(), Some("abc"));
+        assert_eq!(headers("bearer  abc ").bearer_token(), Some("abc"));
+        assert_eq!(headers("Bearer ").bearer_token(), None);
+        assert_eq!(headers("Basic abc").bearer_token(), None);
+        assert_eq!(HttpHeaders::default().bearer_token(), None);
+    }
+}
@@ -0,0 +1,199 @@
+//! Remembering the results of expensive tools, for servers whose clients repeat calls
+
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// How many results are kept across all tools, after which the oldest are forgotten first
+const MAX_ENTRIES: usize = 1024;
+
+#[derive(Debug, Clone)]
+struct Entry {
+    result: Value,
+    stored: Instant,
+    expires: Instant,
+}
+
+/// Which call a result answers: the tool's name, the client's session, and the arguments
+/// serialized as JSON
+type Key = (String, Option<String>, String);
+
+/// The results of the tools configured with
+/// [`ServerConfig::with_cache_for`](crate::server::ServerConfig::with_cache_for), keyed by the
+/// tool's name, the session of the client that called it, and its arguments
+///
+/// Clones share their results, but a result is only reused for the session it was computed for,
+/// so clients of a server that serves several never see each other's results.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ResultCache {
+    ttls: HashMap<String, Duration>,
+    entries: Arc<Mutex<HashMap<Key, Entry>>>,
+}
+
+impl ResultCache {
+    pub(crate) fn set_tool(&mut self, tool: String, ttl: Duration) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|(cached_tool, _, _), _| *cached_tool != tool);
+        self.ttls.insert(tool, ttl);
+    }
+
+    /// The result of an earlier call to `tool` with `arguments` in `session`, if it is cached
+    /// and hasn't expired
+    pub(crate) fn get(
+        &self,
+        tool: &str,
+        session: Option<&str>,
+        arguments: &Value,
+    ) -> Option<Value> {
+        if !self.ttls.contains_key(tool) {
+            return None;
+        }
+        let key = key(tool, session, arguments);
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(&key) {
+            Some(entry) if entry.expires > Instant::now() => Some(entry.result.clone()),
+            Some(_) => {
+                entries.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Remember `result` as the result of calling `tool` with `arguments` in `session`, if
+    /// `tool` is cached and the call succeeded
+    pub(crate) fn insert(
+        &self,
+        tool: &str,
+        session: Option<&str>,
+        arguments: &Value,
+        result: &Value,
+    ) {
+        let Some(ttl) = self.ttls.get(tool) else {
+            return;
+        };
+        if result["isError"] == true {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| entry.expires > now);
+        if entries.len() >= MAX_ENTRIES
+            && let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.stored)
+                .map(|(key, _)| key.clone())
+        {
+            entries.remove(&oldest);
+        }
+        entries.insert(
+            key(tool, session, arguments),
+            Entry {
+                result: result.clone(),
+                stored: now,
+                expires: now + *ttl,
+            },
+        );
+    }
+}
+
+fn key(tool: &str, session: Option<&str>, arguments: &Value) -> Key {
+    (
+        tool.to_string(),
+        session.map(str::to_string),
+        arguments.to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::thread;
+
+    fn cache(ttl: Duration) -> ResultCache {
+        let mut cache = ResultCache::default();
+        cache.set_tool("search".into(), ttl);
+        cache
+    }
+
+    #[test]
+    fn results_expire() {
+        let cache = cache(Duration::from_millis(50));
+        let arguments = json!({ "query": "rust" });
+        cache.insert("search", None, &arguments, &json!({ "content": [] }));
+        assert_eq!(
+            cache.get("search", None, &arguments),
+            Some(json!({ "content": [] }))
+        );
+
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(cache.get("search", None, &arguments), None);
+        assert!(cache.entries.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn only_chosen_tools_and_successes_are_kept() {
+        let cache = cache(Duration::from_secs(60));
+        let arguments = json!({});
+        cache.insert("other", None, &arguments, &json!({ "content": [] }));
+        assert_eq!(cache.get("other", None, &arguments), None);
+
+        cache.insert("search", None, &arguments, &json!({ "isError": true }));
+        assert_eq!(cache.get("search", None, &arguments), None);
+
+        cache.insert("search", None, &arguments, &json!({ "isError": false }));
+        assert_eq!(
+            cache.get("search", None, &arguments),
+            Some(json!({ "isError": false }))
+        );
+    }
+
+    #[test]
+    fn the_oldest_result_is_evicted() {
+        let cache = cache(Duration::from_secs(60));
+        for n in 0..MAX_ENTRIES {
+            cache.insert("search", None, &json!(n), &json!(n));
+        }
+        assert_eq!(cache.get("search", None, &json!(0)), Some(json!(0)));
+
+        cache.insert("search", None, &json!(MAX_ENTRIES), &json!(MAX_ENTRIES));
+        assert_eq!(cache.entries.lock().unwrap().len(), MAX_ENTRIES);
+        assert_eq!(cache.get("search", None, &json!(0)), None);
+        assert_eq!(cache.get("search", None, &json!(1)), Some(json!(1)));
+        assert_eq!(
+            cache.get("search", None, &json!(MAX_ENTRIES)),
+            Some(json!(MAX_ENTRIES))
+        );
+    }
+
+    #[test]
+    fn sessions_have_their_own_results() {
+        let cache = cache(Duration::from_secs(60));
+        let arguments = json!({ "query": "mine" });
+        cache.insert("search", Some("a"), &arguments, &json!("a's result"));
+
+        assert_eq!(
+            cache.get("search", Some("a"), &arguments),
+            Some(json!("a's result"))
+        );
+        assert_eq!(cache.get("search", Some("b"), &arguments), None);
+        assert_eq!(cache.get("search", None, &arguments), None);
+
+        cache.insert("search", Some("b"), &arguments, &json!("b's result"));
+        assert_eq!(
+            cache.get("search", Some("a"), &arguments),
+            Some(json!("a's result"))
+        );
+        assert_eq!(
+            cache.get("search", Some("b"), &arguments),
+            Some(json!("b's result"))
+        );
+    }
+}
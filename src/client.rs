@@ -0,0 +1,745 @@
+//! A client for other MCP servers, over their stdin and stdout or HTTP+SSE
+//!
+//! This is the other side of what the rest of the crate serves: it starts or connects to a server,
+//! performs the handshake, and makes requests, for tools that drive other servers, for
+//! [proxying](crate::proxy) them, and for tests of a server binary from the outside.
+
+use crate::{
+    error::Error,
+    transport::{Stream, Transport},
+    types::{
+        Info, InitializeResult, InputSchema, McpNotification, ProtocolVersion, ToolOutput,
+        ToolSchema,
+    },
+};
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use std::{
+    fmt::{self, Debug, Formatter},
+    io::{self, BufRead, BufReader, Read, Write},
+    mem,
+    net::TcpStream,
+    process::{Child, Command, Stdio},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The JSON-RPC error code for requests from the server, which the client doesn't handle
+const METHOD_NOT_FOUND: i32 = -32601;
+
+/// The JSON-RPC error code for a server that responds with an error but no code
+const INTERNAL_ERROR: i32 = -32603;
+
+/// A connection to one MCP server, configured with `with_*` methods
+///
+/// Requests block until the server responds. Notifications that arrive in the meantime, like
+/// progress and log messages, are kept for [`take_notifications`](Self::take_notifications).
+/// Requests from the server, like sampling, are answered with an error, since the client advertises
+/// no capabilities.
+///
+/// ```ignore
+/// use mcplease::client::Client;
+///
+/// let mut client = Client::spawn(Command::new("my-server").arg("serve"))?;
+/// let server = client.initialize()?;
+/// for tool in client.list_tools()? {
+///     println!("{}: {}", tool.name, tool.description.unwrap_or_default());
+/// }
+/// let output = client.call_tool("hello", json!({ "name": "world" }))?;
+/// let stats: Stats = client.call_tool_as("stats", json!({}))?;
+/// ```
+pub struct Client {
+    /// `None` once dropped, so that a spawned server sees its stdin close
+    transport: Option<Box<dyn Transport>>,
+    child: Option<Child>,
+    info: Info,
+    next_id: u64,
+    notifications: Vec<McpNotification>,
+}
+
+impl Client {
+    /// A client for the server on the other end of `transport`, like a [`Stream`] over a
+    /// [`UnixStream`](std::os::unix::net::UnixStream)
+    pub fn new(transport: impl Transport) -> Self {
+        Self {
+            transport: Some(Box::new(transport)),
+            child: None,
+            info: Info {
+                name: "mcplease".into(),
+                version: env!("CARGO_PKG_VERSION").into(),
+            },
+            next_id: 1,
+            notifications: vec![],
+        }
+    }
+
+    /// Start `command` and talk to it over its stdin and stdout. Stderr is inherited, so the
+    /// server's logs stay visible.
+    ///
+    /// When the client is dropped, the server's stdin is closed so it can shut down on its own, and
+    /// it's killed if it hasn't after two seconds.
+    pub fn spawn(command: &mut Command) -> Result<Self> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to start {command:?}"))?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        let mut client = Self::new(Stream::new(stdout, stdin));
+        client.child = Some(child);
+        Ok(client)
+    }
+
+    /// Connect to the HTTP+SSE server whose event stream is at `url`, like
+    /// `http://localhost:8080/sse`
+    pub fn connect(url: &str) -> Result<Self> {
+        let sse = Sse::connect(url).with_context(|| format!("Failed to connect to {url}"))?;
+        Ok(Self::new(sse))
+    }
+
+    /// Identify the client to the server as `info` instead of as mcplease
+    pub fn with_info(mut self, info: Info) -> Self {
+        self.info = info;
+        self
+    }
+
+    /// Perform the `initialize` handshake with the newest protocol version
+    pub fn initialize(&mut self) -> Result<InitializeResult> {
+        self.initialize_as()
+    }
+
+    /// Perform the `initialize` handshake, deserializing the result as `T`, like a [`Value`] to
+    /// see everything the server sent
+    pub fn initialize_as<T: DeserializeOwned>(&mut self) -> Result<T> {
+        let result = self.request_as(
+            "initialize",
+            json!({
+                "protocolVersion": ProtocolVersion::ALL.iter().max().copied().unwrap_or_default(),
+                "capabilities": {},
+                "clientInfo": self.info,
+            }),
+        )?;
+        self.notify("notifications/initialized", json!({}))?;
+        Ok(result)
+    }
+
+    /// Every tool the server lists, following `nextCursor` through its pages
+    ///
+    /// Input schemas are kept exactly as the server sent them, as [`InputSchema::Raw`].
+    pub fn list_tools(&mut self) -> Result<Vec<ToolSchema>> {
+        let mut tools = vec![];
+        let mut cursor = None;
+        loop {
+            let params = match cursor.take() {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let mut result = self.request("tools/list", params)?;
+            let page: Vec<Value> = serde_json::from_value(result["tools"].take())
+                .context("The server's tools/list result has no tools")?;
+            for tool in page {
+                tools.push(tool_schema(tool)?);
+            }
+            match result["nextCursor"].as_str() {
+                Some(next) => cursor = Some(next.to_string()),
+                None => return Ok(tools),
+            }
+        }
+    }
+
+    /// Call the tool `name`, returning its output, or an error if the call failed, whether with
+    /// `isError` or as a JSON-RPC error
+    pub fn call_tool(&mut self, name: &str, arguments: impl Serialize) -> Result<ToolOutput> {
+        let result: Value = self.request(
+            "tools/call",
+            json!({ "name": name, "arguments": arguments }),
+        )?;
+        let failed = result["isError"] == true;
+        let output: ToolOutput = serde_json::from_value(result)?;
+        if failed {
+            bail!("{output}");
+        }
+        Ok(output)
+    }
+
+    /// Call the tool `name`, deserializing its `structuredContent` as `T`
+    pub fn call_tool_as<T: DeserializeOwned>(
+        &mut self,
+        name: &str,
+        arguments: impl Serialize,
+    ) -> Result<T> {
+        let output = self.call_tool(name, arguments)?;
+        let Some(structured_content) = output.structured_content() else {
+            bail!("{name} returned no structured content: {output}");
+        };
+        T::deserialize(structured_content)
+            .with_context(|| format!("{name} returned unexpected structured content"))
+    }
+
+    /// Send a request and wait for its result
+    ///
+    /// A JSON-RPC error becomes an [`Error`] with the same code, message, and data.
+    pub fn request(&mut self, method: &str, params: impl Serialize) -> Result<Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(&json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        }))?;
+
+        loop {
+            let mut message = self.receive()?;
+            match (message.get("method"), message.get("id")) {
+                (None, Some(response_id)) if *response_id == id => {
+                    if let Some(error) = message.get("error") {
+                        return Err(Error::custom(
+                            error["code"]
+                                .as_i64()
+                                .and_then(|code| i32::try_from(code).ok())
+                                .unwrap_or(INTERNAL_ERROR),
+                            error["message"].as_str().unwrap_or("unknown error"),
+                            error.get("data").cloned(),
+                        )
+                        .into());
+                    }
+                    return Ok(message["result"].take());
+                }
+                (Some(method), Some(request_id)) => {
+                    let response = json!({
+                        "jsonrpc": "2.0",
+                        "id": request_id,
+                        "error": {
+                            "code": METHOD_NOT_FOUND,
+                            "message": format!("{method} is not supported by this client"),
+                        },
+                    });
+                    self.send(&response)?;
+                }
+                (Some(_), None) => match serde_json::from_value(message) {
+                    Ok(notification) => self.notifications.push(notification),
+                    Err(e) => log::debug!("ignoring a malformed notification: {e}"),
+                },
+                (None, _) => log::debug!("ignoring a response to another request: {message}"),
+            }
+        }
+    }
+
+    /// Send a request and deserialize its result as `T`
+    pub fn request_as<T: DeserializeOwned>(
+        &mut self,
+        method: &str,
+        params: impl Serialize,
+    ) -> Result<T> {
+        let result = self.request(method, params)?;
+        serde_json::from_value(result)
+            .with_context(|| format!("The server's {method} result is not what was expected"))
+    }
+
+    pub fn notify(&mut self, method: &str, params: impl Serialize) -> Result<()> {
+        self.send(&json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        }))
+    }
+
+    /// The notifications received since the last call, oldest first
+    pub fn take_notifications(&mut self) -> Vec<McpNotification> {
+        mem::take(&mut self.notifications)
+    }
+
+    fn transport(&self) -> &dyn Transport {
+        self.transport.as_deref().expect("open until drop")
+    }
+
+    fn send(&mut self, message: &Value) -> Result<()> {
+        self.transport()
+            .send(&message.to_string())
+            .map_err(|e| self.exited().unwrap_or_else(|| anyhow!(e)))
+            .context("Failed to write to the server")
+    }
+
+    fn receive(&mut self) -> Result<Value> {
+        match self.transport().recv() {
+            Ok(Some(message)) => serde_json::from_str(&message)
+                .with_context(|| format!("The server sent something other than JSON: {message}")),
+            Ok(None) => Err(self
+                .exited()
+                .unwrap_or_else(|| anyhow!("The server closed its output"))),
+            Err(e) => Err(anyhow!(e).context("Failed to read from the server")),
+        }
+    }
+
+    /// An error describing how a spawned server exited, if it has
+    fn exited(&mut self) -> Option<anyhow::Error> {
+        let child = self.child.as_mut()?;
+        // give a server that is exiting a moment to finish, so its status is available
+        thread::sleep(Duration::from_millis(50));
+        match child.try_wait() {
+            Ok(Some(status)) => Some(anyhow!("The server exited ({status})")),
+            _ => None,
+        }
+    }
+}
+
+impl Debug for Client {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("child", &self.child.as_ref().map(Child::id))
+            .field("info", &self.info)
+            .field("next_id", &self.next_id)
+            .field("notifications", &self.notifications)
+            .finish()
+    }
+}
+
+impl Drop for Client {
+    /// Disconnect, and give a spawned server two seconds to shut down on its own before killing
+    /// it
+    fn drop(&mut self) {
+        self.transport.take();
+        let Some(child) = &mut self.child else {
+            return;
+        };
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while Instant::now() < deadline {
+            if !matches!(child.try_wait(), Ok(None)) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+/// A tool from a `tools/list` result, keeping its input schema exactly as the server sent it
+fn tool_schema(mut tool: Value) -> Result<ToolSchema> {
+    let Some(name) = tool["name"].as_str() else {
+        bail!("The server listed a tool without a name: {tool}");
+    };
+    Ok(ToolSchema {
+        name: name.to_string(),
+        description: tool["description"].as_str().map(String::from),
+        input_schema: InputSchema::Raw(tool["inputSchema"].take()),
+        output_schema: Some(tool["outputSchema"].take()).filter(|schema| !schema.is_null()),
+        aliases: vec![],
+        meta: match tool["_meta"].take() {
+            Value::Object(meta) => Some(meta),
+            _ => None,
+        },
+    })
+}
+
+/// The client side of the HTTP+SSE transport: messages arrive as `message` events on a
+/// long-lived event stream, and are sent by POSTing them to the url from its `endpoint` event
+struct Sse {
+    events: Mutex<BufReader<Body>>,
+    /// The `host:port` the endpoint is on
+    authority: String,
+    /// The path and query to POST messages to
+    endpoint: String,
+}
+
+impl Sse {
+    fn connect(url: &str) -> Result<Self> {
+        let (authority, path) = split_url(url)?;
+        let mut stream = TcpStream::connect(&authority)?;
+        write!(
+            stream,
+            "GET {path} HTTP/1.1\r\nHost: {authority}\r\nAccept: text/event-stream\r\n\r\n"
+        )?;
+        stream.flush()?;
+
+        let mut reader = BufReader::new(stream);
+        let (status, headers) = read_response_head(&mut reader)?;
+        if status != 200 {
+            bail!("the event stream responded with status {status}");
+        }
+        let chunked = headers.iter().any(|(name, value)| {
+            name == "transfer-encoding" && value.eq_ignore_ascii_case("chunked")
+        });
+        let mut events = BufReader::new(Body {
+            reader,
+            chunk: chunked.then_some(0),
+        });
+
+        let endpoint = loop {
+            match read_event(&mut events)? {
+                Some((event, data)) if event == "endpoint" => break data,
+                Some(_) => continue,
+                None => bail!("the event stream closed before naming an endpoint"),
+            }
+        };
+        // the endpoint may be absolute, but is usually a path on the same server
+        let endpoint = match endpoint.strip_prefix("http://") {
+            Some(_) => split_url(&endpoint)?.1,
+            None => endpoint,
+        };
+
+        Ok(Self {
+            events: Mutex::new(events),
+            authority,
+            endpoint,
+        })
+    }
+}
+
+impl Transport for Sse {
+    fn recv(&self) -> io::Result<Option<String>> {
+        let mut events = self.events.lock().unwrap();
+        loop {
+            match read_event(&mut *events)? {
+                Some((event, data)) if event == "message" => return Ok(Some(data)),
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn send(&self, message: &str) -> io::Result<()> {
+        let mut stream = TcpStream::connect(&self.authority)?;
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{message}",
+            self.endpoint,
+            self.authority,
+            message.len()
+        )?;
+        stream.flush()?;
+        let (status, _) = read_response_head(&mut BufReader::new(stream))?;
+        if (200..300).contains(&status) {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "the server responded to a message with status {status}"
+            )))
+        }
+    }
+}
+
+/// Split an `http://` url into its `host:port` and its path
+fn split_url(url: &str) -> Result<(String, String)> {
+    let Some(rest) = url.strip_prefix("http://") else {
+        bail!("only http:// urls are supported, not {url}");
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    let authority = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{authority}:80")
+    };
+    Ok((authority, path.to_string()))
+}
+
+/// Read a response's status code and headers, with lowercase names
+fn read_response_head(reader: &mut impl BufRead) -> io::Result<(u16, Vec<(String, String)>)> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("malformed status line: {line:?}"),
+            )
+        })?;
+
+    let mut headers = vec![];
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    Ok((status, headers))
+}
+
+/// Read one server-sent event's name and data, returning `None` at the end of the stream
+fn read_event(reader: &mut impl BufRead) -> io::Result<Option<(String, String)>> {
+    let mut event = String::from("message");
+    let mut data: Option<String> = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            match data.take() {
+                Some(data) => return Ok(Some((event, data))),
+                None => continue,
+            }
+        }
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "event" => event = value.to_string(),
+            "data" => match &mut data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => data = Some(value.to_string()),
+            },
+            // comments, which servers send to keep the connection open, and ids and retries
+            _ => {}
+        }
+    }
+}
+
+/// A response body, decoding `Transfer-Encoding: chunked` if the server used it
+struct Body {
+    reader: BufReader<TcpStream>,
+    /// The bytes left in the current chunk, if the body is chunked
+    chunk: Option<usize>,
+}
+
+impl Read for Body {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(remaining) = self.chunk else {
+            return self.reader.read(buf);
+        };
+
+        if remaining == 0 {
+            let mut line = String::new();
+            // the line ending the previous chunk, if there was one, then the next chunk's size
+            while line.trim().is_empty() {
+                line.clear();
+                if self.reader.read_line(&mut line)? == 0 {
+                    return Ok(0);
+                }
+            }
+            let size = line.trim().split(';').next().unwrap_or_default();
+            let size = usize::from_str_radix(size, 16).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("malformed chunk size: {line:?}"),
+                )
+            })?;
+            if size == 0 {
+                return Ok(0);
+            }
+            self.chunk = Some(size);
+        }
+
+        let remaining = self.chunk.unwrap_or_default();
+        let len = buf.len().min(remaining);
+        let read = self.reader.read(&mut buf[..len])?;
+        self.chunk = Some(remaining - read);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        collections::HashMap,
+        io::Cursor,
+        net::TcpListener,
+        sync::{Arc, mpsc},
+    };
+
+    /// A writer whose output can still be read once the client owns it
+    #[derive(Clone, Default)]
+    struct Sent(Arc<Mutex<Vec<u8>>>);
+
+    impl Sent {
+        fn messages(&self) -> Vec<Value> {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        }
+    }
+
+    impl Write for Sent {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn requests_and_responses() {
+        // what a server would send, in order: a request of its own, which the client refuses, then
+        // responses to the client's requests, with a notification in between
+        let server_output = [
+            json!({ "jsonrpc": "2.0", "id": "s1", "method": "roots/list" }),
+            json!({ "jsonrpc": "2.0", "id": 1, "result": {
+                "protocolVersion": "2025-06-18",
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": "fake", "version": "1.0.0" },
+            } }),
+            json!({ "jsonrpc": "2.0", "id": 2, "result": {
+                "tools": [{ "name": "stats", "inputSchema": { "type": "object" } }],
+                "nextCursor": "2",
+            } }),
+            json!({ "jsonrpc": "2.0", "id": 3, "result": {
+                "tools": [{ "name": "fail", "inputSchema": { "type": "object" } }],
+            } }),
+            json!({ "jsonrpc": "2.0", "method": "notifications/progress", "params": { "progress": 1 } }),
+            json!({ "jsonrpc": "2.0", "id": 4, "result": {
+                "content": [{ "type": "text", "text": "{\"count\": 3}" }],
+                "structuredContent": { "count": 3 },
+            } }),
+            json!({ "jsonrpc": "2.0", "id": 5, "result": {
+                "content": [{ "type": "text", "text": "no such thing" }],
+                "isError": true,
+            } }),
+            json!({ "jsonrpc": "2.0", "id": 6, "error": { "code": -32602, "message": "bad" } }),
+        ]
+        .map(|message| message.to_string())
+        .join("\n");
+
+        let sent = Sent::default();
+        let mut client = Client::new(Stream::new(Cursor::new(server_output), sent.clone()));
+        let initialize = client.initialize().unwrap();
+        assert_eq!(initialize.server_info.name, "fake");
+
+        let tools = client.list_tools().unwrap();
+        let names: Vec<_> = tools.iter().map(|tool| tool.name.as_str()).collect();
+        assert_eq!(names, ["stats", "fail"]);
+
+        let stats: HashMap<String, u32> = client.call_tool_as("stats", json!({})).unwrap();
+        assert_eq!(stats["count"], 3);
+        let notifications = client.take_notifications();
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].method, "notifications/progress");
+
+        let error = client.call_tool("fail", json!({})).unwrap_err();
+        assert_eq!(error.to_string(), "no such thing");
+
+        let error = Error::from(client.request("tools/call", json!({})).unwrap_err());
+        assert_eq!((error.code(), error.message()), (-32602, "bad"));
+
+        let sent = sent.messages();
+        assert_eq!(sent[0]["method"], "initialize");
+        assert_eq!(sent[1]["id"], "s1");
+        assert_eq!(sent[1]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(sent[2]["method"], "notifications/initialized");
+        assert_eq!(sent[4]["params"]["cursor"], "2");
+    }
+
+    #[test]
+    fn a_closed_server_is_an_error() {
+        let mut client = Client::new(Stream::new(Cursor::new(""), io::sink()));
+        let error = client.initialize().unwrap_err();
+        assert_eq!(error.to_string(), "The server closed its output");
+    }
+
+    #[test]
+    fn urls() {
+        assert_eq!(
+            split_url("http://localhost:8080/sse").unwrap(),
+            ("localhost:8080".into(), "/sse".into())
+        );
+        assert_eq!(
+            split_url("http://example.com").unwrap(),
+            ("example.com:80".into(), "/".into())
+        );
+        assert!(split_url("https://example.com/sse").is_err());
+    }
+
+    /// Read a request's target and body
+    fn read_request(stream: &TcpStream) -> (String, Vec<u8>) {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let target = line.split_whitespace().nth(1).unwrap().to_string();
+        let mut length = 0;
+        loop {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            match line.trim_end().split_once(": ") {
+                Some((name, value)) if name.eq_ignore_ascii_case("content-length") => {
+                    length = value.parse().unwrap()
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        (target, body)
+    }
+
+    /// Write one chunk of a `Transfer-Encoding: chunked` body
+    fn chunk(stream: &mut TcpStream, data: &str) {
+        write!(stream, "{:x}\r\n{data}\r\n", data.len()).unwrap();
+        stream.flush().unwrap();
+    }
+
+    #[test]
+    fn over_sse() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/sse", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+
+        // a server that answers every request with an empty result, on a chunked event stream
+        thread::spawn(move || {
+            let (mut events, _) = listener.accept().unwrap();
+            assert_eq!(read_request(&events).0, "/sse");
+            events
+                .write_all(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n")
+                .unwrap();
+            chunk(&mut events, ": ping\n\n");
+            chunk(
+                &mut events,
+                "event: endpoint\ndata: /messages?sessionId=1\n\n",
+            );
+
+            for mut stream in listener.incoming().map(Result::unwrap) {
+                let (target, body) = read_request(&stream);
+                assert_eq!(target, "/messages?sessionId=1");
+                stream
+                    .write_all(b"HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\n\r\n")
+                    .unwrap();
+                let message: Value = serde_json::from_slice(&body).unwrap();
+                if let Some(id) = message.get("id") {
+                    let response = json!({ "jsonrpc": "2.0", "id": id, "result": {} });
+                    // split across chunks, to be put back together
+                    let event = format!("event: message\ndata: {response}\n\n");
+                    let (first, second) = event.split_at(event.len() / 2);
+                    chunk(&mut events, first);
+                    chunk(&mut events, second);
+                }
+                sender.send(message).unwrap();
+            }
+        });
+
+        let mut client = Client::connect(&url).unwrap();
+        assert_eq!(client.request("ping", json!({})).unwrap(), json!({}));
+        assert_eq!(receiver.recv().unwrap()["method"], "ping");
+        client.notify("notifications/cancelled", json!({})).unwrap();
+        assert_eq!(
+            receiver.recv().unwrap()["method"],
+            "notifications/cancelled"
+        );
+    }
+}
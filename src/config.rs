@@ -0,0 +1,514 @@
+//! Loading a server's configuration from environment variables and an optional TOML file
+
+use crate::paths;
+use anyhow::{Result, anyhow};
+use serde::de::{
+    self, DeserializeOwned, Deserializer, IntoDeserializer, Visitor,
+    value::{MapAccessDeserializer, MapDeserializer, SeqDeserializer},
+};
+use std::{
+    collections::HashMap,
+    env,
+    error::Error as StdError,
+    fmt::{self, Display, Formatter},
+    fs, io,
+    path::PathBuf,
+};
+use toml::{Table, Value};
+
+/// The name of the file read from the app's [config directory](paths::config_dir)
+const CONFIG_FILE: &str = "config.toml";
+
+/// Load the configuration of the server `app`, as described for [`ConfigLoader`]
+///
+/// ```ignore
+/// let config: Config = mcplease::config::load("my-server")?;
+/// ```
+pub fn load<T: DeserializeOwned>(app: &str) -> Result<T> {
+    ConfigLoader::new(app).load()
+}
+
+/// Reads a configuration type that derives `Deserialize` from a TOML file and environment
+/// variables, configured with `with_*` methods
+///
+/// For the app `my-server`, the file is `config.toml` in its
+/// [config directory](paths::config_dir), like `~/.config/my-server/config.toml`, or the file
+/// named by `MY_SERVER_CONFIG`. The file is optional, and variables take precedence over it:
+/// `MY_SERVER_MAX_RESULTS` sets `max_results`, and `MY_SERVER_DB__URL` sets `url` in the `[db]`
+/// table.
+///
+/// Variables are read as whatever type their field has, so `MY_SERVER_MAX_RESULTS=50` fills a
+/// `u32`, `MY_SERVER_HOSTS=a,b` fills a `Vec<String>`, and an empty variable leaves an `Option`
+/// as `None`. Names are matched by lowercasing them, so fields should be snake_case. A value that
+/// doesn't fit is an error naming the key and, if it came from a variable, the variable.
+///
+/// ```ignore
+/// #[derive(Debug, Deserialize)]
+/// #[serde(default)]
+/// pub struct Config {
+///     pub api_url: String,
+///     pub max_results: u32,
+/// }
+///
+/// let config: Config = ConfigLoader::new("my-server").load()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConfigLoader {
+    app: String,
+    prefix: String,
+    file: Option<PathBuf>,
+}
+
+impl ConfigLoader {
+    /// Load the configuration of `app`, from variables named after it, like `MY_SERVER_` for
+    /// `my-server`
+    pub fn new(app: impl Into<String>) -> Self {
+        let app = app.into();
+        let prefix = format!("{}_", app.to_uppercase().replace(['-', '.', ' '], "_"));
+        Self {
+            app,
+            prefix,
+            file: None,
+        }
+    }
+
+    /// Read variables starting with `prefix`, like `MYSRV_`, instead of the app's name
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Read `file` instead of `config.toml` in the app's config directory, unless the config
+    /// variable names another. Unlike the default file, it must exist.
+    pub fn with_file(mut self, file: impl Into<PathBuf>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+
+    /// Read the file and the variables into a `T`
+    pub fn load<T: DeserializeOwned>(&self) -> Result<T> {
+        let (mut table, sources) = match self.file() {
+            Some((path, required)) => match fs::read_to_string(&path) {
+                Ok(contents) => {
+                    let table = toml::from_str::<Table>(&contents)
+                        .map_err(|e| anyhow!("{} is not valid TOML: {e}", path.display()))?;
+                    let sources = format!(
+                        "{} and {}* environment variables",
+                        path.display(),
+                        self.prefix
+                    );
+                    (table, sources)
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound && !required => (
+                    Table::new(),
+                    format!(
+                        "{}* environment variables, with no {}",
+                        self.prefix,
+                        path.display()
+                    ),
+                ),
+                Err(e) => return Err(anyhow!("could not read {}: {e}", path.display())),
+            },
+            None => (
+                Table::new(),
+                format!("{}* environment variables", self.prefix),
+            ),
+        };
+
+        let origins = self.apply_env(&mut table)?;
+        T::deserialize(Lenient {
+            value: Value::Table(table),
+            at: At {
+                path: String::new(),
+                origin: None,
+                origins: &origins,
+            },
+        })
+        .map_err(|e| {
+            anyhow!(
+                "invalid configuration for {} (from {sources}): {e}",
+                self.app
+            )
+        })
+    }
+
+    /// The file to read, and whether it must exist
+    fn file(&self) -> Option<(PathBuf, bool)> {
+        if let Some(path) = env::var_os(format!("{}CONFIG", self.prefix)) {
+            return Some((paths::expand_home(path), true));
+        }
+        if let Some(file) = &self.file {
+            return Some((file.clone(), true));
+        }
+        paths::config_dir(&self.app)
+            .ok()
+            .map(|dir| (dir.join(CONFIG_FILE), false))
+    }
+
+    /// Set the keys named by the app's variables in `table`, returning the variable each came
+    /// from by its path, like `db.url`
+    fn apply_env(&self, table: &mut Table) -> Result<HashMap<String, String>> {
+        let mut vars: Vec<_> = env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value)))
+            .filter(|(name, _)| {
+                name.strip_prefix(&self.prefix)
+                    .is_some_and(|key| !key.is_empty() && key != "CONFIG")
+            })
+            .collect();
+        vars.sort();
+
+        let mut origins = HashMap::new();
+        for (name, value) in vars {
+            let value = value
+                .into_string()
+                .map_err(|_| anyhow!("{name} is not valid unicode"))?;
+            let keys: Vec<String> = name[self.prefix.len()..]
+                .split("__")
+                .map(str::to_lowercase)
+                .collect();
+            if keys.iter().any(String::is_empty) {
+                continue;
+            }
+
+            let (key, parents) = keys.split_last().unwrap_or_else(|| unreachable!());
+            let mut current = &mut *table;
+            for (index, parent) in parents.iter().enumerate() {
+                current = current
+                    .entry(parent.clone())
+                    .or_insert_with(|| Value::Table(Table::new()))
+                    .as_table_mut()
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "{name} sets a key in {}, which is not a table",
+                            keys[..=index].join(".")
+                        )
+                    })?;
+            }
+            current.insert(key.clone(), Value::String(value));
+            origins.insert(keys.join("."), name);
+        }
+        Ok(origins)
+    }
+}
+
+/// An error deserializing the configuration, which says where the value came from once it is
+/// known
+#[derive(Debug)]
+struct ConfigError {
+    message: String,
+    located: bool,
+}
+
+impl de::Error for ConfigError {
+    fn custom<T: Display>(message: T) -> Self {
+        Self {
+            message: message.to_string(),
+            located: false,
+        }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl StdError for ConfigError {}
+
+/// A configuration value that can be read as whatever its field needs, so that variables, which
+/// are always strings, can fill numbers, booleans, lists, and options
+struct Lenient<'a> {
+    value: Value,
+    at: At<'a>,
+}
+
+/// Where a [`Lenient`] value is in the configuration
+#[derive(Clone)]
+struct At<'a> {
+    /// Like `db.url` or `hosts[1]`
+    path: String,
+    /// The variable the value came from, if it did
+    origin: Option<&'a str>,
+    origins: &'a HashMap<String, String>,
+}
+
+impl<'a> At<'a> {
+    fn child(&self, path: String, value: Value) -> Lenient<'a> {
+        let origin = self.origins.get(&path).map(String::as_str).or(self.origin);
+        Lenient {
+            value,
+            at: At {
+                path,
+                origin,
+                origins: self.origins,
+            },
+        }
+    }
+
+    fn key(&self, key: &str, value: Value) -> Lenient<'a> {
+        if self.path.is_empty() {
+            self.child(key.to_string(), value)
+        } else {
+            self.child(format!("{}.{key}", self.path), value)
+        }
+    }
+
+    fn index(&self, index: usize, value: Value) -> Lenient<'a> {
+        self.child(format!("{}[{index}]", self.path), value)
+    }
+
+    /// Say where `error` happened, unless a value inside this one already has
+    fn locate(&self, mut error: ConfigError) -> ConfigError {
+        if error.located || self.path.is_empty() {
+            return error;
+        }
+        error.message = match self.origin {
+            Some(origin) => format!("{}: {} (from {origin})", self.path, error.message),
+            None => format!("{}: {}", self.path, error.message),
+        };
+        error.located = true;
+        error
+    }
+}
+
+impl<'de> IntoDeserializer<'de, ConfigError> for Lenient<'_> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+/// Deserialize a string from a variable by parsing it, and anything else as it is
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident, $expected:literal;)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConfigError> {
+            let Value::String(string) = &self.value else {
+                return self.deserialize_any(visitor);
+            };
+            match string.trim().parse() {
+                Ok(parsed) => visitor.$visit(parsed).map_err(|e| self.at.locate(e)),
+                Err(_) => Err(self.at.locate(de::Error::custom(format!(
+                    "expected {}, found {string:?}",
+                    $expected
+                )))),
+            }
+        }
+    )*};
+}
+
+impl<'de> Deserializer<'de> for Lenient<'_> {
+    type Error = ConfigError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConfigError> {
+        let Self { value, at } = self;
+        let result = match value {
+            Value::String(string) => visitor.visit_string(string),
+            Value::Integer(integer) => visitor.visit_i64(integer),
+            Value::Float(float) => visitor.visit_f64(float),
+            Value::Boolean(boolean) => visitor.visit_bool(boolean),
+            Value::Datetime(datetime) => visitor.visit_string(datetime.to_string()),
+            Value::Array(values) => visitor.visit_seq(SeqDeserializer::new(
+                values
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, value)| at.index(index, value)),
+            )),
+            Value::Table(table) => visitor.visit_map(MapDeserializer::new(table.into_iter().map(
+                |(key, value)| {
+                    let value = at.key(&key, value);
+                    (key, value)
+                },
+            ))),
+        };
+        result.map_err(|e| at.locate(e))
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool, "true or false";
+        deserialize_i8 => visit_i8, "an integer";
+        deserialize_i16 => visit_i16, "an integer";
+        deserialize_i32 => visit_i32, "an integer";
+        deserialize_i64 => visit_i64, "an integer";
+        deserialize_u8 => visit_u8, "a positive integer";
+        deserialize_u16 => visit_u16, "a positive integer";
+        deserialize_u32 => visit_u32, "a positive integer";
+        deserialize_u64 => visit_u64, "a positive integer";
+        deserialize_f32 => visit_f32, "a number";
+        deserialize_f64 => visit_f64, "a number";
+    }
+
+    /// An empty variable is `None`, and anything else is `Some`
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConfigError> {
+        match &self.value {
+            Value::String(string) if self.at.origin.is_some() && string.is_empty() => {
+                visitor.visit_none()
+            }
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    /// A variable is split on commas
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConfigError> {
+        match self.value {
+            Value::String(string) if self.at.origin.is_some() => {
+                let values = string
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+                    .map(|value| Value::String(value.to_string()))
+                    .collect();
+                Self {
+                    value: Value::Array(values),
+                    at: self.at,
+                }
+                .deserialize_any(visitor)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, ConfigError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// A string is a unit variant, and a table with one key a variant with data
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ConfigError> {
+        let Self { value, at } = self;
+        let result = match value {
+            Value::String(string) => visitor.visit_enum(string.trim().into_deserializer()),
+            Value::Table(table) => visitor.visit_enum(MapAccessDeserializer::new(
+                MapDeserializer::new(table.into_iter().map(|(key, value)| {
+                    let value = at.key(&key, value);
+                    (key, value)
+                })),
+            )),
+            value => return Lenient { value, at }.deserialize_any(visitor),
+        };
+        result.map_err(|e| at.locate(e))
+    }
+
+    serde::forward_to_deserialize_any! {
+        i128 u128 char str string bytes byte_buf unit unit_struct tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use tempfile::TempDir;
+
+    #[test]
+    fn loading_a_file() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("config.toml");
+        let loader = ConfigLoader::new("my-server")
+            .with_prefix("MCPLEASE_CONFIG_TEST_")
+            .with_file(&file);
+
+        fs::write(&file, "max_results = 50\nretries = \"3\"\n").unwrap();
+        let config: HashMap<String, u32> = loader.load().unwrap();
+        assert_eq!(config["max_results"], 50);
+        assert_eq!(config["retries"], 3);
+
+        fs::write(&file, "max_results = \"lots\"\n").unwrap();
+        let error = loader
+            .load::<HashMap<String, u32>>()
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.starts_with("invalid configuration for my-server"),
+            "{error}"
+        );
+        assert!(
+            error.contains("max_results: expected a positive integer, found \"lots\""),
+            "{error}"
+        );
+
+        fs::write(&file, "max_results =\n").unwrap();
+        let error = loader
+            .load::<HashMap<String, u32>>()
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("is not valid TOML"), "{error}");
+
+        // a file named with `with_file` must exist
+        fs::remove_file(&file).unwrap();
+        assert!(loader.load::<HashMap<String, u32>>().is_err());
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Config {
+        hosts: Vec<String>,
+        proxy: Option<String>,
+        db: Db,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Db {
+        port: u16,
+    }
+
+    /// Read `toml` as a `Config`, as if the keys in `variables` came from the variables named
+    fn read(toml: &str, variables: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let table: Table = toml::from_str(toml).unwrap();
+        let origins = variables
+            .iter()
+            .map(|(key, name)| (key.to_string(), name.to_string()))
+            .collect();
+        Config::deserialize(Lenient {
+            value: Value::Table(table),
+            at: At {
+                path: String::new(),
+                origin: None,
+                origins: &origins,
+            },
+        })
+    }
+
+    #[test]
+    fn variables_are_read_as_their_field_type() {
+        let variables = [
+            ("hosts", "MY_SERVER_HOSTS"),
+            ("proxy", "MY_SERVER_PROXY"),
+            ("db.port", "MY_SERVER_DB__PORT"),
+        ];
+        let toml = "hosts = \"a, b,\"\nproxy = \"\"\n[db]\nport = \" 5432\"\n";
+        let config = read(toml, &variables).unwrap();
+        assert_eq!(config.hosts, ["a", "b"]);
+        assert_eq!(config.proxy, None);
+        assert_eq!(config.db.port, 5432);
+
+        // only values from variables are split, or empty for `None`
+        let config = read("hosts = [\"a,b\"]\nproxy = \"\"\n[db]\nport = 1\n", &[]).unwrap();
+        assert_eq!(config.hosts, ["a,b"]);
+        assert_eq!(config.proxy.as_deref(), Some(""));
+        let error = read("hosts = \"a,b\"\n[db]\nport = 1\n", &[]).unwrap_err();
+        assert!(error.to_string().starts_with("hosts: "), "{error}");
+    }
+
+    #[test]
+    fn errors_name_the_variable() {
+        let error = read(
+            "hosts = []\n[db]\nport = \"many\"\n",
+            &[("db.port", "MY_SERVER_DB__PORT")],
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "db.port: expected a positive integer, found \"many\" (from MY_SERVER_DB__PORT)"
+        );
+    }
+}
@@ -0,0 +1,971 @@
+//! Per-call context handed to tools
+
+use crate::{
+    auth::HttpHeaders,
+    extensions::Extensions,
+    server::ServerConfig,
+    types::{
+        CancelledNotification, ClientCapabilities, CreateMessageRequest, CreateMessageResult,
+        IncomingResponse, Info, ListRootsResult, LoggingLevel, LoggingMessageNotification,
+        McpMessage, McpNotification, McpRequest, McpResponse, ProgressNotification,
+        ProtocolVersion, Root, ToolOutput,
+    },
+};
+use anyhow::{Result, bail};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Debug, Display, Formatter},
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// A flag that is set when the client cancels the request a tool is executing
+///
+/// Long-running tools should check this periodically and return early once it is set. The
+/// response to a cancelled request is discarded, so there's no need to produce a meaningful one.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Returns an error if cancelled, for use with `?` in loops
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            bail!("request was cancelled");
+        }
+        Ok(())
+    }
+}
+
+/// Sends `notifications/progress` for the request a tool is executing
+///
+/// Progress is only reported if the client asked for it by including a `progressToken` in the
+/// request; otherwise reporting is a no-op, so tools can report unconditionally.
+#[derive(Debug, Clone, Default)]
+pub struct ProgressReporter {
+    token: Option<Value>,
+    outbound: Option<Outbound>,
+}
+
+impl ProgressReporter {
+    pub(crate) fn new(token: Option<Value>, outbound: Outbound) -> Self {
+        Self {
+            token,
+            outbound: Some(outbound),
+        }
+    }
+
+    /// Whether the client will receive progress reports for this request
+    pub fn is_enabled(&self) -> bool {
+        self.token.is_some() && self.outbound.is_some()
+    }
+
+    /// Report that `progress` out of an optional `total` units of work are done. `progress`
+    /// should increase with each call.
+    pub fn report(&self, progress: f64, total: Option<f64>) {
+        self.send(progress, total, None);
+    }
+
+    /// Like [`report`](Self::report), with a human-readable description of the current step
+    pub fn report_with_message(
+        &self,
+        progress: f64,
+        total: Option<f64>,
+        message: impl Into<String>,
+    ) {
+        self.send(progress, total, Some(message.into()));
+    }
+
+    fn send(&self, progress: f64, total: Option<f64>, message: Option<String>) {
+        let (Some(progress_token), Some(outbound)) = (&self.token, &self.outbound) else {
+            return;
+        };
+
+        let notification = McpNotification::new(
+            "notifications/progress",
+            ProgressNotification {
+                progress_token: progress_token.clone(),
+                progress,
+                total,
+                message,
+            },
+        );
+
+        if let Err(e) = outbound.send(&notification) {
+            log::error!("could not send progress: {e}");
+        }
+    }
+}
+
+/// Streams a tool's text output to the client as the tool produces it
+///
+/// Everything written is also collected, and [`finish`](Self::finish) turns it into the tool's
+/// output, so a tool that shells out can pass each line along as it arrives and still return the
+/// whole transcript. Chunks are only sent while the tool runs over transports that deliver
+/// notifications during a call, like HTTP+SSE, and only if the client asked for progress by
+/// including a `progressToken`. Each chunk is a `notifications/progress` message whose progress
+/// is the number of bytes written so far, so a tool streaming output shouldn't also report
+/// progress with [`ProgressReporter`].
+#[derive(Debug, Clone, Default)]
+pub struct OutputStream {
+    progress: ProgressReporter,
+    streaming: bool,
+    written: Arc<Mutex<String>>,
+}
+
+impl OutputStream {
+    /// Whether the client will receive chunks while the tool runs, rather than only the whole
+    /// output at the end
+    pub fn is_streaming(&self) -> bool {
+        self.streaming && self.progress.is_enabled()
+    }
+
+    /// Append `chunk` to the output, sending it to the client if streaming
+    pub fn write(&self, chunk: impl AsRef<str>) {
+        let chunk = chunk.as_ref();
+        if chunk.is_empty() {
+            return;
+        }
+        let mut written = self.written.lock().unwrap();
+        written.push_str(chunk);
+        if self.is_streaming() {
+            self.progress
+                .report_with_message(written.len() as f64, None, chunk);
+        }
+    }
+
+    /// Write each chunk from `chunks` in turn, then [`finish`](Self::finish)
+    pub fn stream<I>(&self, chunks: I) -> ToolOutput
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        for chunk in chunks {
+            self.write(chunk);
+        }
+        self.finish()
+    }
+
+    /// Everything written so far as a single text block, leaving the stream empty
+    pub fn finish(&self) -> ToolOutput {
+        ToolOutput::text(std::mem::take(&mut *self.written.lock().unwrap()))
+    }
+}
+
+/// A logging handle that tags each message with the request it was logged for
+///
+/// Messages are always written to the `log` crate (and so to `MCP_LOG_LOCATION`). When running
+/// over MCP, messages at or above the level the client chose with `logging/setLevel` are also
+/// sent to the client as `notifications/message`.
+#[derive(Debug, Clone, Default)]
+pub struct ClientLogger {
+    request_id: Option<Value>,
+    level: LogLevel,
+    outbound: Option<Outbound>,
+}
+
+impl ClientLogger {
+    pub fn log(&self, level: LoggingLevel, message: impl Display) {
+        match &self.request_id {
+            Some(id) => log::log!(target: "mcplease::tool", level.into(), "[{id}] {message}"),
+            None => log::log!(target: "mcplease::tool", level.into(), "{message}"),
+        }
+
+        let Some(outbound) = &self.outbound else {
+            return;
+        };
+        if level < self.level.get() {
+            return;
+        }
+        let notification = McpNotification::new(
+            "notifications/message",
+            LoggingMessageNotification {
+                level,
+                logger: None,
+                data: Value::String(message.to_string()),
+            },
+        );
+        if let Err(e) = outbound.send(&notification) {
+            log::error!("could not send log message: {e}");
+        }
+    }
+
+    pub fn error(&self, message: impl Display) {
+        self.log(LoggingLevel::Error, message);
+    }
+
+    pub fn warn(&self, message: impl Display) {
+        self.log(LoggingLevel::Warning, message);
+    }
+
+    pub fn info(&self, message: impl Display) {
+        self.log(LoggingLevel::Info, message);
+    }
+
+    pub fn debug(&self, message: impl Display) {
+        self.log(LoggingLevel::Debug, message);
+    }
+
+    pub(crate) fn set_level(&self, level: LoggingLevel) {
+        self.level.set(level);
+    }
+}
+
+/// The minimum level a client wants to receive, shared by every request on a connection
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LogLevel(Arc<Mutex<LoggingLevel>>);
+
+impl LogLevel {
+    fn get(&self) -> LoggingLevel {
+        *self.0.lock().unwrap()
+    }
+
+    fn set(&self, level: LoggingLevel) {
+        *self.0.lock().unwrap() = level;
+    }
+}
+
+/// Sends requests to the client and waits for the responses
+#[derive(Debug, Clone)]
+pub(crate) struct Requester {
+    outbound: Outbound,
+    in_flight: InFlight,
+    scope: String,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Requester {
+    /// Send `method` to the client and block until it responds, or until `cancellation` is set
+    pub(crate) fn request<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: impl Serialize,
+        cancellation: &CancellationToken,
+    ) -> Result<T> {
+        let id = Value::from(format!(
+            "mcplease-{}",
+            self.next_id.fetch_add(1, Ordering::Relaxed)
+        ));
+        let receiver = self.in_flight.expect_response(&self.scope, &id);
+        self.outbound
+            .send(&McpRequest::new(id.clone(), method, params))?;
+
+        let response = loop {
+            match receiver.recv_timeout(Duration::from_millis(100)) {
+                Ok(response) => break response,
+                Err(RecvTimeoutError::Timeout) if cancellation.is_cancelled() => {
+                    self.in_flight.forget_response(&self.scope, &id);
+                    let _ = self.outbound.send(&McpNotification::new(
+                        "notifications/cancelled",
+                        CancelledNotification {
+                            request_id: id,
+                            reason: Some("the tool call was cancelled".into()),
+                        },
+                    ));
+                    bail!("cancelled while waiting for {method}");
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => bail!("no response to {method}"),
+            }
+        };
+
+        if let Some(error) = response.error {
+            bail!("{method} failed: {}", error.message);
+        }
+        Ok(serde_json::from_value(response.result.unwrap_or_default())?)
+    }
+}
+
+/// Asks the client's model for completions with `sampling/createMessage`
+///
+/// Only available when running over MCP with a client that declared the `sampling` capability.
+#[derive(Debug, Clone, Default)]
+pub struct SamplingClient {
+    requester: Option<Requester>,
+    supported: bool,
+    cancellation: CancellationToken,
+}
+
+impl SamplingClient {
+    /// Whether the client can handle sampling requests
+    pub fn is_supported(&self) -> bool {
+        self.requester.is_some() && self.supported
+    }
+
+    /// Send `request` to the client and block until its model responds
+    ///
+    /// The client will usually ask the user to approve the request, so this can take a while.
+    /// It returns early with an error if the tool call is cancelled.
+    pub fn create_message(&self, request: &CreateMessageRequest) -> Result<CreateMessageResult> {
+        let Some(requester) = &self.requester else {
+            bail!("sampling is only available when running over MCP");
+        };
+        if !self.supported {
+            bail!("the client does not support sampling");
+        }
+        requester.request("sampling/createMessage", request, &self.cancellation)
+    }
+}
+
+/// The roots the client has exposed to the server, fetched with `roots/list`
+///
+/// Roots are fetched the first time they're needed and cached for the connection until the
+/// client sends `notifications/roots/list_changed`. Only available when running over MCP with a
+/// client that declared the `roots` capability.
+#[derive(Debug, Clone, Default)]
+pub struct RootsClient {
+    requester: Option<Requester>,
+    supported: bool,
+    cancellation: CancellationToken,
+    cache: Roots,
+}
+
+type Roots = Arc<Mutex<Option<Vec<Root>>>>;
+
+impl RootsClient {
+    /// Whether the client can list its roots
+    pub fn is_supported(&self) -> bool {
+        self.requester.is_some() && self.supported
+    }
+
+    /// The client's current roots
+    pub fn list(&self) -> Result<Vec<Root>> {
+        let Some(requester) = &self.requester else {
+            bail!("roots are only available when running over MCP");
+        };
+        if !self.supported {
+            bail!("the client does not support roots");
+        }
+        if let Some(roots) = &*self.cache.lock().unwrap() {
+            return Ok(roots.clone());
+        }
+
+        let ListRootsResult { roots } =
+            requester.request("roots/list", serde_json::json!({}), &self.cancellation)?;
+        log::debug!("client roots: {roots:?}");
+        *self.cache.lock().unwrap() = Some(roots.clone());
+        Ok(roots)
+    }
+
+    /// Whether `path` is inside one of the client's `file://` roots
+    ///
+    /// `path` should be absolute; it is compared lexically, without resolving symlinks.
+    pub fn contains(&self, path: &Path) -> Result<bool> {
+        Ok(self
+            .list()?
+            .iter()
+            .filter_map(Root::path)
+            .any(|root| path.starts_with(root)))
+    }
+}
+
+/// Where a connection is in the MCP lifecycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Phase {
+    /// Only `initialize` and pings are answered
+    #[default]
+    AwaitingInitialize,
+
+    /// `initialize` has been answered, but the client hasn't sent `notifications/initialized`.
+    /// The client may already send requests, and it may send `initialize` again if that failed.
+    AwaitingInitialized,
+
+    /// The handshake is complete
+    Operating,
+}
+
+/// What a transport knows about one connected client
+#[derive(Debug, Clone)]
+pub(crate) struct Connection {
+    scope: String,
+    in_flight: InFlight,
+    outbound: Outbound,
+    phase: Phase,
+    client_info: Option<Info>,
+    client_capabilities: ClientCapabilities,
+    protocol_version: ProtocolVersion,
+    log_level: LogLevel,
+    next_request_id: Arc<AtomicU64>,
+    roots: Roots,
+    session_id: Option<String>,
+    streams_output: bool,
+}
+
+impl Connection {
+    /// `scope` distinguishes this connection's request ids from those of other connections
+    /// sharing `in_flight`
+    pub(crate) fn new(scope: impl Into<String>, in_flight: InFlight, outbound: Outbound) -> Self {
+        Self {
+            scope: scope.into(),
+            in_flight,
+            outbound,
+            phase: Phase::default(),
+            client_info: None,
+            client_capabilities: ClientCapabilities::default(),
+            protocol_version: ProtocolVersion::default(),
+            log_level: LogLevel::default(),
+            next_request_id: Arc::default(),
+            roots: Roots::default(),
+            session_id: None,
+            streams_output: false,
+        }
+    }
+
+    /// Identify the client to tools as `session_id`, for transports that may serve more than one
+    pub(crate) fn with_session_id(mut self, session_id: Option<String>) -> Self {
+        self.session_id = session_id;
+        self
+    }
+
+    /// Send [`OutputStream`] chunks to the client while tools run, for transports that deliver
+    /// notifications alongside a pending response
+    pub(crate) fn with_output_streaming(mut self, streams_output: bool) -> Self {
+        self.streams_output = streams_output;
+        self
+    }
+
+    pub(crate) fn outbound(&self) -> &Outbound {
+        &self.outbound
+    }
+
+    /// An error response to `request` if it isn't allowed at this point in the handshake
+    ///
+    /// Before `initialize`, only pings and health checks are answered. A second `initialize`
+    /// after the client has sent `notifications/initialized` is refused.
+    pub(crate) fn refuse(&self, request: &McpRequest) -> Option<McpResponse> {
+        let method = request.method.as_str();
+        let (code, message) = match (self.phase, method) {
+            (_, "ping" | "mcplease/health") => return None,
+            (Phase::Operating, "initialize") => (-32600, "initialize was already completed".into()),
+            (_, "initialize") if request.initialize_request().is_none() => (
+                -32602,
+                "initialize requires protocolVersion, capabilities, and clientInfo".into(),
+            ),
+            (Phase::AwaitingInitialize, method) if method != "initialize" => {
+                (-32600, format!("received {method} before initialize"))
+            }
+            _ => return None,
+        };
+        log::warn!("{message}");
+        Some(McpResponse::error_with_code(
+            request.id.clone(),
+            code,
+            message,
+        ))
+    }
+
+    /// Build the context for a request about to be dispatched on this connection
+    pub(crate) fn context(&mut self, request: &McpRequest, config: &ServerConfig) -> ToolContext {
+        if let Some(initialize) = request.initialize_request() {
+            let info = initialize.client_info;
+            self.protocol_version = config.negotiate(&initialize.protocol_version);
+            log::info!(
+                "client: {} {}, protocol version {} (requested {})",
+                info.name,
+                info.version,
+                self.protocol_version,
+                initialize.protocol_version
+            );
+            self.phase = Phase::AwaitingInitialized;
+            self.client_info = Some(info);
+            self.client_capabilities = ClientCapabilities::from_value(&initialize.capabilities);
+            self.roots.lock().unwrap().take();
+        }
+
+        let cancellation = self.in_flight.token(&self.scope, &request.id);
+        let requester = Requester {
+            outbound: self.outbound.clone(),
+            in_flight: self.in_flight.clone(),
+            scope: self.scope.clone(),
+            next_id: Arc::clone(&self.next_request_id),
+        };
+
+        let progress = ProgressReporter::new(request.progress_token(), self.outbound.clone());
+        ToolContext {
+            request_id: Some(request.id.clone()),
+            client_info: self.client_info.clone(),
+            protocol_version: self.protocol_version,
+            client_capabilities: self.client_capabilities.clone(),
+            output: OutputStream {
+                progress: progress.clone(),
+                streaming: self.streams_output,
+                written: Arc::default(),
+            },
+            progress,
+            logger: ClientLogger {
+                request_id: Some(request.id.clone()),
+                level: self.log_level.clone(),
+                outbound: Some(self.outbound.clone()),
+            },
+            sampling: SamplingClient {
+                requester: Some(requester.clone()),
+                supported: self.client_capabilities.supports_sampling(),
+                cancellation: cancellation.clone(),
+            },
+            roots: RootsClient {
+                requester: Some(requester),
+                supported: self.client_capabilities.supports_roots(),
+                cancellation: cancellation.clone(),
+                cache: Arc::clone(&self.roots),
+            },
+            cancellation,
+            outbound: Some(self.outbound.clone()),
+            session_id: self.session_id.clone(),
+            http_headers: None,
+            extensions: config.extensions().clone(),
+        }
+    }
+
+    /// Handle a notification from the client
+    pub(crate) fn notify(&mut self, notification: &McpNotification) {
+        match notification.method.as_str() {
+            "notifications/initialized" => match self.phase {
+                Phase::AwaitingInitialized => self.phase = Phase::Operating,
+                phase => log::warn!("received notifications/initialized in phase {phase:?}"),
+            },
+            "notifications/roots/list_changed" => {
+                log::debug!("client roots changed");
+                self.roots.lock().unwrap().take();
+            }
+            method => log::trace!("received {method}, ignoring"),
+        }
+    }
+
+    /// Stop tracking a request once it has been answered
+    pub(crate) fn finish(&self, request_id: &Value) {
+        self.in_flight.finish(&self.scope, request_id);
+    }
+}
+
+/// Everything a tool might need to know about the call it is executing, beyond its arguments
+/// and the server state
+///
+/// When a tool is invoked from the command line rather than over MCP, it receives a default
+/// context with no request id or client info, that is never cancelled, and that discards
+/// progress.
+#[derive(Debug, Clone, Default, fieldwork::Fieldwork)]
+#[fieldwork(get)]
+pub struct ToolContext {
+    /// The JSON-RPC id of the `tools/call` request
+    request_id: Option<Value>,
+
+    /// The `clientInfo` the client sent with `initialize`
+    client_info: Option<Info>,
+
+    /// The protocol version agreed on during `initialize`
+    protocol_version: ProtocolVersion,
+
+    /// The capabilities the client declared with `initialize`, for tools that behave differently
+    /// depending on whether the client supports sampling, roots, or elicitation. Empty from the
+    /// command line.
+    client_capabilities: ClientCapabilities,
+
+    /// Set when the client sends `notifications/cancelled` for this request
+    cancellation: CancellationToken,
+
+    /// Reports progress to the client
+    progress: ProgressReporter,
+
+    /// Streams text output to the client as the tool produces it
+    output: OutputStream,
+
+    /// Logs messages tagged with the request id, forwarding them to the client
+    logger: ClientLogger,
+
+    /// Asks the client's model for completions
+    sampling: SamplingClient,
+
+    /// The directories and files the client has exposed to the server
+    roots: RootsClient,
+
+    /// Where a [`Watchdog`] sends its timeout error
+    #[fieldwork(skip)]
+    outbound: Option<Outbound>,
+
+    /// Identifies the client, for transports that may serve more than one
+    #[fieldwork(skip)]
+    session_id: Option<String>,
+
+    /// The headers of the HTTP request that carried the call, for transports that use HTTP
+    #[fieldwork(skip)]
+    http_headers: Option<HttpHeaders>,
+
+    /// Process-wide services added to the server with
+    /// [`with_extension`](crate::server::ServerConfig::with_extension)
+    extensions: Extensions,
+}
+
+impl ToolContext {
+    /// The context of a tool invoked from the command line, which only has the server's
+    /// extensions
+    pub(crate) fn for_cli(config: &ServerConfig) -> Self {
+        Self {
+            extensions: config.extensions().clone(),
+            ..Self::default()
+        }
+    }
+
+    /// The id of the client's session: the HTTP+SSE session id, or one generated for each TCP
+    /// connection. `None` over stdio and from the command line, where there is only one client.
+    ///
+    /// [`SessionStore::scoped`](crate::session::SessionStore::scoped) uses this to give each
+    /// client its own session.
+    pub fn session_id(&self) -> Option<&str> {
+        self.session_id.as_deref()
+    }
+
+    /// The headers of the HTTP request that carried the call, for transports that use HTTP
+    ///
+    /// An [`Authorizer`](crate::auth::Authorizer) can use these to check a bearer token, and
+    /// tools can use them to act on behalf of whoever sent it.
+    pub fn http_headers(&self) -> Option<&HttpHeaders> {
+        self.http_headers.as_ref()
+    }
+
+    pub(crate) fn with_http_headers(mut self, headers: HttpHeaders) -> Self {
+        self.http_headers = Some(headers);
+        self
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// Answer the `tools/call` request for `tool` with `response` if it is still running after
+    /// `timeout`
+    pub(crate) fn watchdog(
+        &self,
+        tool: &str,
+        timeout: Duration,
+        response: McpResponse,
+    ) -> Watchdog {
+        let (done, receiver) = mpsc::channel::<()>();
+        let tool = tool.to_string();
+        let cancellation = self.cancellation.clone();
+        let outbound = self.outbound.clone();
+        let thread = thread::spawn(move || {
+            if receiver.recv_timeout(timeout) != Err(RecvTimeoutError::Timeout)
+                || cancellation.is_cancelled()
+            {
+                return false;
+            }
+
+            log::error!(
+                "{tool} is still running after its timeout of {timeout:?}, and other requests \
+                 wait until it returns"
+            );
+            // the tool's own response is discarded, like that of a cancelled request
+            cancellation.cancel();
+            if let Some(outbound) = outbound
+                && let Err(e) = outbound.send(&response)
+            {
+                log::error!("could not send timeout error: {e}");
+            }
+            true
+        });
+        Watchdog { done, thread }
+    }
+}
+
+/// Watches a tool call for [`ToolContext::watchdog`]
+pub(crate) struct Watchdog {
+    done: Sender<()>,
+    thread: JoinHandle<bool>,
+}
+
+impl Watchdog {
+    /// Stop watching once the tool returns, returning whether it ran past its timeout
+    pub(crate) fn finish(self) -> bool {
+        let _ = self.done.send(());
+        self.thread.join().unwrap_or(false)
+    }
+}
+
+/// Requests that have been received but not yet answered, and requests the server has sent the
+/// client that are awaiting a response
+///
+/// Transports feed every inbound message through [`InFlight::receive`] on their reader thread so
+/// that cancellations and responses are delivered even while the dispatch thread is busy
+/// executing a tool.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct InFlight {
+    received: Arc<Mutex<HashMap<String, CancellationToken>>>,
+    sent: Arc<Mutex<HashMap<String, Sender<IncomingResponse>>>>,
+    disconnected: Arc<Mutex<HashSet<String>>>,
+}
+
+impl InFlight {
+    /// Track a newly received message, returning it if it still needs to be dispatched.
+    ///
+    /// `scope` distinguishes request ids from different clients of the same server.
+    pub(crate) fn receive(&self, scope: &str, message: McpMessage) -> Option<McpMessage> {
+        match message {
+            McpMessage::Request(request) => {
+                self.received
+                    .lock()
+                    .unwrap()
+                    .entry(key(scope, &request.id))
+                    .or_default();
+                Some(McpMessage::Request(request))
+            }
+
+            McpMessage::Notification(notification)
+                if notification.method == "notifications/cancelled" =>
+            {
+                match serde_json::from_value::<CancelledNotification>(
+                    notification.params.unwrap_or_default(),
+                ) {
+                    Ok(CancelledNotification { request_id, reason }) => {
+                        log::debug!("cancelling {request_id}: {reason:?}");
+                        if let Some(token) =
+                            self.received.lock().unwrap().get(&key(scope, &request_id))
+                        {
+                            token.cancel();
+                        }
+                    }
+                    Err(e) => log::error!("invalid cancellation: {e}"),
+                }
+                None
+            }
+
+            McpMessage::Response(response) => {
+                match self.sent.lock().unwrap().remove(&key(scope, &response.id)) {
+                    Some(sender) => {
+                        let _ = sender.send(response);
+                    }
+                    None => log::warn!("received a response to unknown request {}", response.id),
+                }
+                None
+            }
+
+            other => Some(other),
+        }
+    }
+
+    /// Cancel every request that is being handled, because the server is shutting down
+    pub(crate) fn cancel_all(&self) {
+        for token in self.received.lock().unwrap().values() {
+            token.cancel();
+        }
+    }
+
+    /// The token for a request about to be dispatched
+    pub(crate) fn token(&self, scope: &str, id: &Value) -> CancellationToken {
+        self.received
+            .lock()
+            .unwrap()
+            .entry(key(scope, id))
+            .or_default()
+            .clone()
+    }
+
+    pub(crate) fn finish(&self, scope: &str, id: &Value) {
+        self.received.lock().unwrap().remove(&key(scope, id));
+    }
+
+    /// Register a request the server is about to send, returning where its response will arrive
+    pub(crate) fn expect_response(&self, scope: &str, id: &Value) -> Receiver<IncomingResponse> {
+        let (sender, receiver) = mpsc::channel();
+        // if the client is already gone, dropping the sender fails the request right away
+        if !self.disconnected.lock().unwrap().contains(scope) {
+            self.sent.lock().unwrap().insert(key(scope, id), sender);
+        }
+        receiver
+    }
+
+    pub(crate) fn forget_response(&self, scope: &str, id: &Value) {
+        self.sent.lock().unwrap().remove(&key(scope, id));
+    }
+
+    /// Fail every request still waiting on a response in `scope`, once the client has gone away
+    ///
+    /// `scope` is remembered as disconnected, so that tools still running for it fail to send
+    /// requests, until [`forget`](Self::forget) is called.
+    pub(crate) fn disconnect(&self, scope: &str) {
+        self.disconnected.lock().unwrap().insert(scope.to_string());
+        let prefix = format!("{scope}/");
+        self.sent
+            .lock()
+            .unwrap()
+            .retain(|key, _| !key.starts_with(&prefix));
+    }
+
+    /// Stop tracking `scope`, once it has disconnected and nothing more is dispatched for it
+    pub(crate) fn forget(&self, scope: &str) {
+        self.disconnected.lock().unwrap().remove(scope);
+        let prefix = format!("{scope}/");
+        self.received
+            .lock()
+            .unwrap()
+            .retain(|key, _| !key.starts_with(&prefix));
+    }
+}
+
+fn key(scope: &str, id: &Value) -> String {
+    format!("{scope}/{id}")
+}
+
+/// A handle for sending messages to the client, shared by responses and anything a tool sends
+/// while it executes
+#[derive(Clone)]
+pub(crate) struct Outbound(Arc<SendFn>);
+
+type SendFn = dyn Fn(&str) -> std::io::Result<()> + Send + Sync;
+
+impl Outbound {
+    pub(crate) fn new(send: impl Fn(&str) -> std::io::Result<()> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(send))
+    }
+
+    pub(crate) fn send(&self, message: &impl Serialize) -> Result<()> {
+        let message = serde_json::to_string(message)?;
+        log::trace!("-> {message}");
+        (self.0)(&message)?;
+        Ok(())
+    }
+}
+
+impl Debug for Outbound {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("Outbound")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const METHODS: &[&str] = &[
+        "tools/list",
+        "tools/call",
+        "resources/list",
+        "resources/read",
+        "prompts/list",
+        "prompts/get",
+        "completion/complete",
+        "logging/setLevel",
+    ];
+
+    fn connection() -> Connection {
+        Connection::new("", InFlight::default(), Outbound::new(|_| Ok(())))
+    }
+
+    fn config() -> ServerConfig {
+        ServerConfig::new(Info {
+            name: "test".into(),
+            version: "0.0.0".into(),
+        })
+    }
+
+    fn request(method: &str) -> McpRequest {
+        let params = match method {
+            "initialize" => json!({
+                "protocolVersion": "2025-06-18",
+                "capabilities": {},
+                "clientInfo": { "name": "client", "version": "1.0.0" },
+            }),
+            _ => json!({}),
+        };
+        McpRequest::new(json!(1), method, params)
+    }
+
+    /// The code `connection` refuses `method` with, if it does
+    fn refused(connection: &Connection, method: &str) -> Option<i32> {
+        let response = connection.refuse(&request(method))?;
+        Some(response.error.expect("refusals are errors").code)
+    }
+
+    fn initialized() -> McpNotification {
+        McpNotification::new("notifications/initialized", json!({}))
+    }
+
+    #[test]
+    fn before_initialize() {
+        let connection = connection();
+        assert_eq!(connection.phase, Phase::AwaitingInitialize);
+        for method in METHODS {
+            assert_eq!(refused(&connection, method), Some(-32600), "{method}");
+        }
+        assert_eq!(refused(&connection, "ping"), None);
+        assert_eq!(refused(&connection, "mcplease/health"), None);
+        assert_eq!(refused(&connection, "health"), Some(-32600));
+        assert_eq!(refused(&connection, "initialize"), None);
+
+        let incomplete = McpRequest::new(json!(1), "initialize", json!({ "capabilities": {} }));
+        let response = connection.refuse(&incomplete).unwrap();
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[test]
+    fn initialized_before_initialize_is_ignored() {
+        let mut connection = connection();
+        connection.notify(&initialized());
+        assert_eq!(connection.phase, Phase::AwaitingInitialize);
+        assert_eq!(refused(&connection, "tools/list"), Some(-32600));
+    }
+
+    #[test]
+    fn after_initialize() {
+        let mut connection = connection();
+        connection.context(&request("initialize"), &config());
+        assert_eq!(connection.phase, Phase::AwaitingInitialized);
+        assert_eq!(connection.client_info.as_ref().unwrap().name, "client");
+
+        // clients may send requests before notifications/initialized, and may retry initialize
+        for method in METHODS.iter().chain(&["ping", "initialize"]) {
+            assert_eq!(refused(&connection, method), None, "{method}");
+        }
+    }
+
+    #[test]
+    fn operating() {
+        let mut connection = connection();
+        connection.context(&request("initialize"), &config());
+        connection.notify(&initialized());
+        assert_eq!(connection.phase, Phase::Operating);
+
+        for method in METHODS.iter().chain(&["ping"]) {
+            assert_eq!(refused(&connection, method), None, "{method}");
+        }
+        assert_eq!(refused(&connection, "initialize"), Some(-32600));
+
+        // a second notifications/initialized changes nothing
+        connection.notify(&initialized());
+        assert_eq!(connection.phase, Phase::Operating);
+    }
+
+    #[test]
+    fn disconnected_scopes_are_forgotten() {
+        let in_flight = InFlight::default();
+        in_flight.token("a", &json!(1));
+        let waiting = in_flight.expect_response("a", &json!("s1"));
+        in_flight.disconnect("a");
+        // requests waiting on the client, and any sent after it left, fail right away
+        assert!(waiting.recv().is_err());
+        assert!(in_flight.expect_response("a", &json!("s2")).recv().is_err());
+
+        in_flight.forget("a");
+        assert!(in_flight.disconnected.lock().unwrap().is_empty());
+        assert!(in_flight.received.lock().unwrap().is_empty());
+    }
+}
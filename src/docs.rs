@@ -0,0 +1,165 @@
+//! Rendering a server's tools as documentation, for a README or a published tool catalog
+//!
+//! Servers print this with `<server> docs`, or `<server> docs --json` for the tools as clients
+//! see them in `tools/list`, so that documentation can be regenerated instead of kept in sync by
+//! hand.
+
+use crate::types::ToolSchema;
+use serde_json::Value;
+use std::fmt::Write;
+
+/// Render `tools` as Markdown: a `###` section for each tool with its description, parameters,
+/// and examples
+///
+/// Parameters are listed by name, with their types, defaults, and descriptions, and the
+/// parameters of nested objects are listed under them.
+///
+/// ```ignore
+/// std::fs::write("TOOLS.md", mcplease::docs::markdown(&Tools::tools_list()))?;
+/// ```
+pub fn markdown(tools: &[ToolSchema]) -> String {
+    let mut markdown = String::new();
+    for tool in tools {
+        if !markdown.is_empty() {
+            markdown.push('\n');
+        }
+        tool_markdown(&mut markdown, tool);
+    }
+    markdown
+}
+
+fn tool_markdown(markdown: &mut String, tool: &ToolSchema) {
+    let _ = writeln!(markdown, "### `{}`\n", tool.name);
+    if let Some(description) = &tool.description {
+        let _ = writeln!(markdown, "{}\n", description.trim());
+    }
+    if !tool.aliases.is_empty() {
+        let aliases = tool
+            .aliases
+            .iter()
+            .map(|alias| format!("`{alias}`"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let _ = writeln!(markdown, "Also called {aliases}.\n");
+    }
+
+    let schema = serde_json::to_value(&tool.input_schema).unwrap_or_default();
+    let mut parameters = String::new();
+    parameters_markdown(&mut parameters, &schema, 0);
+    if parameters.is_empty() {
+        markdown.push_str("No parameters.\n");
+    } else {
+        let _ = write!(markdown, "Parameters:\n\n{parameters}");
+    }
+
+    if let Some(examples) = schema["examples"].as_array()
+        && !examples.is_empty()
+    {
+        markdown.push_str("\nExamples:\n");
+        for example in examples {
+            example_markdown(markdown, example);
+        }
+    }
+}
+
+/// List the properties of the object `schema`, indented for nesting under a list item
+fn parameters_markdown(markdown: &mut String, schema: &Value, depth: usize) {
+    let Some(properties) = schema["properties"].as_object() else {
+        return;
+    };
+    let required = schema["required"].as_array();
+    let indent = "  ".repeat(depth);
+
+    let mut names = properties.keys().collect::<Vec<_>>();
+    names.sort();
+    for name in names {
+        let property = &properties[name];
+        let mut details = vec![type_name(property)];
+        if required.is_some_and(|required| required.iter().any(|r| r == name.as_str())) {
+            details.push("required".into());
+        }
+        if let Some(default) = property.get("default") {
+            details.push(format!("default `{default}`"));
+        }
+        let _ = write!(markdown, "{indent}- `{name}` ({})", details.join(", "));
+
+        let description = property["description"].as_str().unwrap_or_default().trim();
+        let mut lines = description.lines();
+        if let Some(first) = lines.next() {
+            let _ = write!(markdown, ": {first}");
+        }
+        markdown.push('\n');
+        for line in lines {
+            if line.is_empty() {
+                markdown.push('\n');
+            } else {
+                let _ = writeln!(markdown, "{indent}  {line}");
+            }
+        }
+        if property.get("enumDescriptions").is_none()
+            && let Some(values) = property["enum"].as_array()
+        {
+            let values = values
+                .iter()
+                .map(|value| format!("`{}`", value.as_str().unwrap_or_default()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let _ = writeln!(markdown, "{indent}  One of {values}.");
+        }
+
+        parameters_markdown(markdown, property, depth + 1);
+        if let Some(items) = property.get("items") {
+            parameters_markdown(markdown, items, depth + 1);
+        }
+    }
+}
+
+/// A short name for the type `schema` describes, like `string` or `array of integer`
+fn type_name(schema: &Value) -> String {
+    if let Some(variants) = schema
+        .get("anyOf")
+        .or_else(|| schema.get("oneOf"))
+        .and_then(Value::as_array)
+    {
+        return variants
+            .iter()
+            .map(type_name)
+            .collect::<Vec<_>>()
+            .join(" or ");
+    }
+    if schema.get("$ref").is_some() {
+        return "object".into();
+    }
+    match &schema["type"] {
+        Value::String(name) if name == "array" => {
+            format!("array of {}", type_name(&schema["items"]))
+        }
+        Value::String(name) => name.clone(),
+        Value::Array(names) => names
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join(" or "),
+        _ if schema.get("const").is_some() => format!("`{}`", schema["const"]),
+        _ => "any".into(),
+    }
+}
+
+/// Describe one of a tool's `examples`, showing its arguments as a client would send them
+fn example_markdown(markdown: &mut String, example: &Value) {
+    let mut arguments = example.as_object().cloned().unwrap_or_default();
+    let description = arguments.remove("description");
+    let expected_output = arguments.remove("expectedOutput");
+    let negative = arguments.remove("negative") == Some(Value::Bool(true));
+
+    let description = description.as_ref().and_then(Value::as_str).unwrap_or("");
+    let note = if negative { " (don't do this)" } else { "" };
+    let arguments = serde_json::to_string_pretty(&Value::Object(arguments)).unwrap_or_default();
+    let _ = writeln!(
+        markdown,
+        "\n{description}{note}:\n\n```json\n{arguments}\n```"
+    );
+    if let Some(Value::String(output)) = expected_output {
+        let _ = writeln!(markdown, "\nReturns:\n\n```text\n{output}\n```");
+    }
+}
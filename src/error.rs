@@ -0,0 +1,121 @@
+//! Failures that map to a specific JSON-RPC error code
+
+use crate::types::McpError;
+use serde_json::Value;
+use std::fmt::{self, Display, Formatter};
+
+/// A failure with a JSON-RPC error code, and optionally structured data, for the client
+///
+/// Tools, resources, and prompts return `anyhow::Result`, so return one of these with
+/// `Err(Error::NotFound(..))?` or `bail!(Error::NotFound(..))`. The server finds it inside the
+/// `anyhow::Error` and responds with its code and data. Any other error is reported as
+/// [`Error::Internal`], which for a tool means a result with `isError` rather than a JSON-RPC
+/// error.
+///
+/// ```ignore
+/// let Some(note) = state.notes().get(&self.id) else {
+///     bail!(Error::NotFound(format!("no note with id {}", self.id)));
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The arguments were well-formed but not acceptable, code -32602
+    InvalidParams(String),
+
+    /// Something the request refers to doesn't exist, code -32002
+    NotFound(String),
+
+    /// The client isn't allowed to do this, code -32001
+    Unauthorized(String),
+
+    /// Anything else that went wrong, code -32603
+    Internal(String),
+
+    /// Any other code, with optional structured data
+    Custom {
+        code: i32,
+        message: String,
+        data: Option<Value>,
+    },
+}
+
+impl Error {
+    /// An error with any code and structured `data`
+    pub fn custom(code: i32, message: impl Into<String>, data: impl Into<Option<Value>>) -> Self {
+        Self::Custom {
+            code,
+            message: message.into(),
+            data: data.into(),
+        }
+    }
+
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::InvalidParams(_) => -32602,
+            Self::NotFound(_) => -32002,
+            Self::Unauthorized(_) => -32001,
+            Self::Internal(_) => -32603,
+            Self::Custom { code, .. } => *code,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Self::InvalidParams(message)
+            | Self::NotFound(message)
+            | Self::Unauthorized(message)
+            | Self::Internal(message)
+            | Self::Custom { message, .. } => message,
+        }
+    }
+
+    pub fn data(&self) -> Option<&Value> {
+        match self {
+            Self::Custom { data, .. } => data.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<anyhow::Error> for Error {
+    /// The [`Error`] inside `error`, or an [`Error::Internal`] with its message
+    fn from(error: anyhow::Error) -> Self {
+        error
+            .downcast::<Self>()
+            .unwrap_or_else(|error| Self::Internal(error.to_string()))
+    }
+}
+
+impl From<&anyhow::Error> for Error {
+    /// A copy of the [`Error`] inside `error`, or an [`Error::Internal`] with its message
+    fn from(error: &anyhow::Error) -> Self {
+        error
+            .downcast_ref::<Self>()
+            .cloned()
+            .unwrap_or_else(|| Self::Internal(error.to_string()))
+    }
+}
+
+impl From<Error> for McpError {
+    fn from(error: Error) -> Self {
+        Self {
+            code: error.code(),
+            data: error.data().cloned(),
+            message: match error {
+                Error::InvalidParams(message)
+                | Error::NotFound(message)
+                | Error::Unauthorized(message)
+                | Error::Internal(message)
+                | Error::Custom { message, .. } => message,
+            },
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Process-wide services shared with every tool call, like HTTP clients, database pools, or
+//! configuration
+
+use crate::Error;
+use anyhow::Result;
+use std::{
+    any::{Any, TypeId, type_name},
+    collections::HashMap,
+    fmt::{self, Debug, Formatter},
+    sync::Arc,
+};
+
+/// Values added to a server at startup, at most one of each type, that every tool call can reach
+/// through [`ToolContext::extensions`](crate::context::ToolContext::extensions)
+///
+/// The server state belongs to a connection and is borrowed mutably by each call. Extensions are
+/// for what doesn't change and isn't owned by any connection. They are shared, so a service that
+/// changes, like a cache, needs its own locking.
+///
+/// ```ignore
+/// Server::builder()
+///     .with_state(State::default())
+///     .with_tools::<tools::Tools>()
+///     .with_extension(HttpClient::new(&server_info!()))
+///     .run()
+///
+/// // in a tool
+/// fn execute_with_context(self, state: &mut State, context: &ToolContext) -> Result<ToolOutput> {
+///     let client = context.extensions().require::<HttpClient>()?;
+///     // ...
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct Extensions(Arc<HashMap<TypeId, Extension>>);
+
+#[derive(Clone)]
+struct Extension {
+    value: Arc<dyn Any + Send + Sync>,
+    type_name: &'static str,
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `value`, replacing any earlier value of the same type
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        Arc::make_mut(&mut self.0).insert(
+            TypeId::of::<T>(),
+            Extension {
+                value: Arc::new(value),
+                type_name: type_name::<T>(),
+            },
+        );
+    }
+
+    /// Add `value`, replacing any earlier value of the same type
+    pub fn with<T: Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.insert(value);
+        self
+    }
+
+    /// The value of type `T`, if one was added
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.0
+            .get(&TypeId::of::<T>())
+            .and_then(|extension| extension.value.downcast_ref())
+    }
+
+    /// The value of type `T`, or an internal error naming the type if none was added, for use with
+    /// `?` in tools
+    pub fn require<T: 'static>(&self) -> Result<&T> {
+        self.get().ok_or_else(|| {
+            Error::Internal(format!(
+                "the server has no {} extension; add one with with_extension",
+                type_name::<T>()
+            ))
+            .into()
+        })
+    }
+
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.0.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Debug for Extensions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut type_names: Vec<_> = self
+            .0
+            .values()
+            .map(|extension| extension.type_name)
+            .collect();
+        type_names.sort_unstable();
+        f.debug_set().entries(type_names).finish()
+    }
+}
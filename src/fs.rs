@@ -0,0 +1,463 @@
+//! Serving a directory of files as resources, without writing a [`Resource`](crate::traits::Resource)
+//! for each one
+
+use crate::{
+    Error,
+    types::{ResourceContents, ResourceDescriptor, ResourceTemplateDescriptor, percent_decode},
+};
+use anyhow::{Result, bail};
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+/// The largest file served unless configured otherwise, one MiB
+const DEFAULT_MAX_SIZE: u64 = 1024 * 1024;
+
+/// How many files are listed in `resources/list` unless configured otherwise
+const DEFAULT_MAX_LISTED: usize = 1000;
+
+/// How many paths a completion offers, which is as many as a client is sent
+const MAX_COMPLETIONS: usize = 100;
+
+/// How many directories deep files are listed and completed
+const MAX_DEPTH: usize = 16;
+
+/// Serves the files under a directory as resources, registered with
+/// [`ServerConfig::with_fs_resources`](crate::server::ServerConfig::with_fs_resources)
+///
+/// Each file is a resource with a `file://` uri. Files are listed in `resources/list`, up to
+/// [`with_max_listed`](Self::with_max_listed) of them, and a resource template covering the whole
+/// directory is listed in `resources/templates/list`, so clients can read files that weren't
+/// listed and complete their paths. Files more than 16 directories deep are neither listed nor
+/// completed, but can still be read.
+///
+/// Only files inside the directory are served: uris with `..` components, and symlinks that lead
+/// outside the directory, are refused. Hidden files and directories, like `.env` and `.git`, are
+/// neither listed nor served, and neither are files larger than
+/// [`with_max_size`](Self::with_max_size). Utf-8 files are sent as text and anything else as a
+/// base64 blob, with a mime type guessed from the file's extension.
+///
+/// ```ignore
+/// Server::builder()
+///     .with_fs_resources(FsResourceProvider::new("docs")?.with_name("Documentation"))
+/// ```
+#[derive(Debug, Clone)]
+pub struct FsResourceProvider {
+    root: PathBuf,
+    root_uri: String,
+    name: String,
+    max_size: u64,
+    max_listed: usize,
+}
+
+impl FsResourceProvider {
+    /// Serve the files under the directory `root`
+    pub fn new(root: impl AsRef<Path>) -> Result<Self> {
+        let root = root.as_ref();
+        let root = root
+            .canonicalize()
+            .map_err(|e| anyhow::anyhow!("could not open {}: {e}", root.display()))?;
+        if !root.is_dir() {
+            bail!("{} is not a directory", root.display());
+        }
+
+        let name = root.file_name().map_or_else(
+            || root.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        Ok(Self {
+            root_uri: format!("file://{}", percent_encode(&root.to_string_lossy())),
+            root,
+            name,
+            max_size: DEFAULT_MAX_SIZE,
+            max_listed: DEFAULT_MAX_LISTED,
+        })
+    }
+
+    /// The name of the resource template, which defaults to the directory's name
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Refuse to serve files larger than `max_size` bytes, instead of one MiB
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// List at most `max_listed` files in `resources/list`, instead of 1000
+    ///
+    /// Files past the limit can still be read through the template.
+    pub fn with_max_listed(mut self, max_listed: usize) -> Self {
+        self.max_listed = max_listed;
+        self
+    }
+
+    /// The directory being served, canonicalized
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The resource template covering every file in the directory
+    pub fn template(&self) -> ResourceTemplateDescriptor {
+        ResourceTemplateDescriptor::new(&format!("{}/{{+path}}", self.root_uri), &self.name)
+    }
+
+    /// A descriptor for each file, in order of path, up to the listing limit
+    pub fn list(&self) -> Vec<ResourceDescriptor> {
+        let mut files = vec![];
+        self.walk(&self.root, "", &mut files, self.max_listed, MAX_DEPTH);
+        files
+            .into_iter()
+            .map(|relative| {
+                let descriptor = ResourceDescriptor::new(self.uri(&relative), &relative);
+                match mime_type(Path::new(&relative)) {
+                    Some(mime_type) => descriptor.with_mime_type(mime_type),
+                    None => descriptor,
+                }
+            })
+            .collect()
+    }
+
+    /// The paths of files, relative to the directory, that start with `value`, up to 100 of them
+    pub fn complete(&self, value: &str) -> Vec<String> {
+        let mut files = vec![];
+        self.walk(&self.root, value, &mut files, MAX_COMPLETIONS, MAX_DEPTH);
+        files
+    }
+
+    /// Read the file at `uri`, or `None` if it isn't under this directory's uri
+    pub fn read(&self, uri: &str) -> Option<Result<ResourceContents>> {
+        let relative = uri.strip_prefix(&self.root_uri)?.strip_prefix('/')?;
+        Some(self.read_relative(uri, relative))
+    }
+
+    fn read_relative(&self, uri: &str, relative: &str) -> Result<ResourceContents> {
+        let Some(relative) = percent_decode(relative) else {
+            bail!(Error::InvalidParams(format!("{uri} is not a valid uri")));
+        };
+        let outside = || Error::Unauthorized(format!("{uri} is outside of {}", self.name));
+        let hidden = |path: &Path| path.components().any(|c| is_hidden(c.as_os_str()));
+
+        let relative = Path::new(&relative);
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            bail!(outside());
+        }
+        if hidden(relative) {
+            bail!(Error::NotFound(format!("no file at {uri}")));
+        }
+
+        let Ok(path) = self.root.join(relative).canonicalize() else {
+            bail!(Error::NotFound(format!("no file at {uri}")));
+        };
+        let Ok(target) = path.strip_prefix(&self.root) else {
+            bail!(outside());
+        };
+        // a symlink can lead to a hidden file without its own name being hidden
+        if hidden(target) {
+            bail!(Error::NotFound(format!("no file at {uri}")));
+        }
+
+        let metadata = fs::metadata(&path)?;
+        if !metadata.is_file() {
+            bail!(Error::NotFound(format!("{uri} is not a file")));
+        }
+        if metadata.len() > self.max_size {
+            bail!(Error::InvalidParams(format!(
+                "{uri} is {} bytes, more than the limit of {}",
+                metadata.len(),
+                self.max_size
+            )));
+        }
+
+        let mime_type = mime_type(&path);
+        Ok(match String::from_utf8(fs::read(&path)?) {
+            Ok(text) => {
+                ResourceContents::text(uri, text).with_mime_type(mime_type.unwrap_or("text/plain"))
+            }
+            Err(e) => ResourceContents::blob_bytes(uri, e.as_bytes())
+                .with_mime_type(mime_type.unwrap_or("application/octet-stream")),
+        })
+    }
+
+    fn uri(&self, relative: &str) -> String {
+        format!("{}/{}", self.root_uri, percent_encode(relative))
+    }
+
+    /// Collect the relative paths of the files under `dir` that start with `prefix`, skipping
+    /// hidden files and symlinks, until there are `limit` of them, going at most `depth`
+    /// directories deeper
+    fn walk(&self, dir: &Path, prefix: &str, files: &mut Vec<String>, limit: usize, depth: usize) {
+        let mut entries = match fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(Result::ok).collect::<Vec<_>>(),
+            Err(e) => {
+                log::error!("could not list {}: {e}", dir.display());
+                return;
+            }
+        };
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            if files.len() >= limit {
+                return;
+            }
+            if is_hidden(&entry.file_name()) {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            let Ok(relative) = path.strip_prefix(&self.root) else {
+                continue;
+            };
+            let relative = relative.to_string_lossy();
+            if file_type.is_dir() {
+                // only directories that could hold a path starting with `prefix`
+                let dir = format!("{relative}/");
+                if depth > 0 && (dir.starts_with(prefix) || prefix.starts_with(&dir)) {
+                    self.walk(&path, prefix, files, limit, depth - 1);
+                }
+            } else if file_type.is_file() && relative.starts_with(prefix) {
+                files.push(relative.into_owned());
+            }
+        }
+    }
+}
+
+fn is_hidden(name: &std::ffi::OsStr) -> bool {
+    name.to_string_lossy().starts_with('.')
+}
+
+/// Escape everything in `path` but unreserved characters and `/`
+pub(crate) fn percent_encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(byte as char);
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+/// The mime type of a file with `path`'s extension, for common types
+pub(crate) fn mime_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    Some(match extension.as_str() {
+        "txt" | "text" | "log" => "text/plain",
+        "md" | "markdown" => "text/markdown",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "csv" => "text/csv",
+        "js" | "mjs" => "text/javascript",
+        "ts" => "text/x-typescript",
+        "rs" => "text/x-rust",
+        "py" => "text/x-python",
+        "sh" => "application/x-sh",
+        "json" => "application/json",
+        "toml" => "application/toml",
+        "yaml" | "yml" => "application/yaml",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "wasm" => "application/wasm",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn docs() -> (TempDir, FsResourceProvider) {
+        let dir = TempDir::new().unwrap();
+        let docs = dir.path().join("docs");
+        fs::create_dir_all(docs.join("guide")).unwrap();
+        fs::create_dir(docs.join(".git")).unwrap();
+        fs::write(docs.join("index.md"), "# Docs").unwrap();
+        fs::write(docs.join("guide/start here.txt"), "start").unwrap();
+        fs::write(docs.join("logo.png"), [0x89, b'P', b'N', b'G', 0xff]).unwrap();
+        fs::write(docs.join(".env"), "SECRET=1").unwrap();
+        fs::write(docs.join(".git/config"), "").unwrap();
+        fs::write(dir.path().join("secret"), "secret").unwrap();
+        let provider = FsResourceProvider::new(&docs).unwrap();
+        (dir, provider)
+    }
+
+    fn read(provider: &FsResourceProvider, relative: &str) -> Result<ResourceContents> {
+        provider
+            .read(&format!("{}/{relative}", provider.root_uri))
+            .expect("the uri to be under the root")
+    }
+
+    fn error(result: Result<ResourceContents>) -> Error {
+        result.unwrap_err().downcast().unwrap()
+    }
+
+    #[test]
+    fn list() {
+        let (_dir, provider) = docs();
+        let names = provider
+            .list()
+            .into_iter()
+            .map(|resource| (resource.name, resource.mime_type))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                ("guide/start here.txt".into(), Some("text/plain".into())),
+                ("index.md".into(), Some("text/markdown".into())),
+                ("logo.png".into(), Some("image/png".into())),
+            ]
+        );
+        assert!(
+            provider.list()[0]
+                .uri
+                .ends_with("/docs/guide/start%20here.txt")
+        );
+        assert_eq!(provider.with_max_listed(1).list().len(), 1);
+    }
+
+    #[test]
+    fn complete() {
+        let (_dir, provider) = docs();
+        assert_eq!(provider.complete("gu"), ["guide/start here.txt"]);
+        assert!(provider.complete(".").is_empty());
+    }
+
+    #[test]
+    fn completions_are_limited() {
+        let (_dir, provider) = docs();
+        for n in 0..MAX_COMPLETIONS + 10 {
+            fs::write(provider.root().join(format!("note-{n:03}.md")), "").unwrap();
+        }
+        let completions = provider.complete("note-");
+        assert_eq!(completions.len(), MAX_COMPLETIONS);
+        assert_eq!(completions[0], "note-000.md");
+
+        let mut deep = provider.root().to_path_buf();
+        for _ in 0..=MAX_DEPTH {
+            deep.push("d");
+        }
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(deep.join("deep.md"), "deep").unwrap();
+        assert!(provider.complete("d/").is_empty());
+        let relative = deep.join("deep.md");
+        let relative = relative.strip_prefix(provider.root()).unwrap();
+        assert!(read(&provider, &relative.to_string_lossy()).is_ok());
+    }
+
+    #[test]
+    fn read_files() {
+        let (_dir, provider) = docs();
+        let index = read(&provider, "index.md").unwrap();
+        assert_eq!(index.text.as_deref(), Some("# Docs"));
+        assert_eq!(index.mime_type.as_deref(), Some("text/markdown"));
+
+        let guide = read(&provider, "guide/start%20here.txt").unwrap();
+        assert_eq!(guide.text.as_deref(), Some("start"));
+
+        let logo = read(&provider, "logo.png").unwrap();
+        assert!(logo.text.is_none());
+        assert!(logo.blob.is_some());
+        assert_eq!(logo.mime_type.as_deref(), Some("image/png"));
+
+        assert!(provider.read("file:///elsewhere/index.md").is_none());
+    }
+
+    #[test]
+    fn missing_hidden_and_large_files() {
+        let (_dir, provider) = docs();
+        assert!(matches!(
+            error(read(&provider, "missing.md")),
+            Error::NotFound(_)
+        ));
+        assert!(matches!(
+            error(read(&provider, "guide")),
+            Error::NotFound(_)
+        ));
+        assert!(matches!(error(read(&provider, ".env")), Error::NotFound(_)));
+        assert!(matches!(
+            error(read(&provider, ".git/config")),
+            Error::NotFound(_)
+        ));
+
+        let provider = provider.with_max_size(3);
+        assert!(matches!(
+            error(read(&provider, "index.md")),
+            Error::InvalidParams(_)
+        ));
+    }
+
+    #[test]
+    fn traversal_is_refused() {
+        let (dir, provider) = docs();
+        assert!(matches!(
+            error(read(&provider, "../secret")),
+            Error::Unauthorized(_)
+        ));
+        assert!(matches!(
+            error(read(&provider, "%2E%2E/secret")),
+            Error::Unauthorized(_)
+        ));
+        assert!(matches!(
+            error(read(&provider, "guide/../../secret")),
+            Error::Unauthorized(_)
+        ));
+        assert!(matches!(
+            error(read(&provider, "%2Fetc")),
+            Error::Unauthorized(_)
+        ));
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.path().join("secret"), provider.root().join("link"))
+                .unwrap();
+            assert!(matches!(
+                error(read(&provider, "link")),
+                Error::Unauthorized(_)
+            ));
+            assert!(
+                provider.complete("link").is_empty(),
+                "symlinks aren't listed"
+            );
+
+            // a symlink with an ordinary name can't lead to a hidden file
+            std::os::unix::fs::symlink(provider.root().join(".env"), provider.root().join("env"))
+                .unwrap();
+            assert!(matches!(error(read(&provider, "env")), Error::NotFound(_)));
+            std::os::unix::fs::symlink(provider.root().join(".git"), provider.root().join("git"))
+                .unwrap();
+            assert!(matches!(
+                error(read(&provider, "git/config")),
+                Error::NotFound(_)
+            ));
+        }
+        #[cfg(not(unix))]
+        let _ = dir;
+    }
+
+    #[test]
+    fn template() {
+        let (_dir, provider) = docs();
+        let uri = provider.list()[0].uri.clone();
+        let template = provider.with_name("Documentation").template();
+        assert_eq!(template.name, "Documentation");
+        let variables = template.uri_template.matches(&uri).unwrap();
+        assert_eq!(variables["path"], "guide/start here.txt");
+    }
+}
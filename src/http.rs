@@ -0,0 +1,260 @@
+//! An HTTP client for tools that wrap web APIs, with the `http` feature
+//!
+//! Requests time out, identify the server in their `User-Agent`, and refuse responses over a
+//! size limit. Failures become an [`HttpError`] that says which request failed and why, which
+//! reads well as a tool result and can be inspected for the status.
+
+use crate::types::Info;
+use anyhow::Result;
+use serde::{Serialize, de::DeserializeOwned};
+use std::{
+    error::Error as StdError,
+    fmt::{self, Debug, Display, Formatter},
+    time::Duration,
+};
+use ureq::{Agent, Body, http::Response};
+
+/// How long a request may take unless configured otherwise
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How large a response body may be unless configured otherwise, ten MiB
+const DEFAULT_MAX_RESPONSE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// How much of the body of an unsuccessful response is included in its [`HttpError`]
+const ERROR_BODY_EXCERPT: usize = 500;
+
+/// A preconfigured HTTP client, configured with `with_*` methods
+///
+/// Requests time out after 30 seconds and refuse response bodies over ten MiB unless configured
+/// otherwise. It's cheap to clone, and clones share their connections, so build one at startup
+/// and share it with tools as an [extension](crate::extensions::Extensions).
+///
+/// ```ignore
+/// let client = HttpClient::new(&server_info!())
+///     .with_header("Authorization", format!("Bearer {token}"))
+///     .with_timeout(Duration::from_secs(10));
+///
+/// // in a tool
+/// let issues: Vec<Issue> = client.get_json(&format!("{API}/repos/{}/issues", self.repo))?;
+/// ```
+#[derive(Clone)]
+pub struct HttpClient {
+    agent: Agent,
+    user_agent: String,
+    timeout: Option<Duration>,
+    max_response_size: u64,
+    headers: Vec<(String, String)>,
+}
+
+impl HttpClient {
+    /// A client that identifies itself as `info`, usually [`server_info!`](crate::server_info),
+    /// like `my-server/0.1.0`
+    pub fn new(info: &Info) -> Self {
+        let user_agent = format!("{}/{}", info.name, info.version);
+        Self {
+            agent: agent(&user_agent, Some(DEFAULT_TIMEOUT)),
+            user_agent,
+            timeout: Some(DEFAULT_TIMEOUT),
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            headers: vec![],
+        }
+    }
+
+    /// Give up on a request after `timeout`, including reading its response, instead of after
+    /// 30 seconds, or never if `None`
+    pub fn with_timeout(mut self, timeout: impl Into<Option<Duration>>) -> Self {
+        self.timeout = timeout.into();
+        self.agent = agent(&self.user_agent, self.timeout);
+        self
+    }
+
+    /// Refuse response bodies larger than `max_response_size` bytes, instead of ten MiB
+    pub fn with_max_response_size(mut self, max_response_size: u64) -> Self {
+        self.max_response_size = max_response_size;
+        self
+    }
+
+    /// Send the header `name` with every request, like an `Authorization` header for the API
+    /// being wrapped
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// The underlying `ureq` agent, for requests the helpers don't cover
+    ///
+    /// Requests made with it have the timeout and `User-Agent`, but not the headers or the
+    /// response size limit, and unsuccessful statuses aren't errors.
+    pub fn agent(&self) -> &Agent {
+        &self.agent
+    }
+
+    /// `GET` `url`, returning the body as text
+    pub fn get_text(&self, url: &str) -> Result<String> {
+        let body = self.get(url)?;
+        String::from_utf8(body)
+            .map_err(|_| HttpError::new("GET", url, None, "the response is not text").into())
+    }
+
+    /// `GET` `url`, returning the body as JSON
+    pub fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let body = self.get(url)?;
+        parse_json("GET", url, &body)
+    }
+
+    /// `POST` `body` as JSON to `url`, returning the response body as JSON
+    pub fn post_json<T: DeserializeOwned>(&self, url: &str, body: &impl Serialize) -> Result<T> {
+        let mut request = self.agent.post(url);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        let response = self.read("POST", url, request.send_json(body))?;
+        parse_json("POST", url, &response)
+    }
+
+    fn get(&self, url: &str) -> Result<Vec<u8>> {
+        let mut request = self.agent.get(url);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        self.read("GET", url, request.call())
+    }
+
+    /// The body of `response`, or an error if the request failed, was unsuccessful, or its body
+    /// is too large
+    fn read(
+        &self,
+        method: &'static str,
+        url: &str,
+        response: Result<Response<Body>, ureq::Error>,
+    ) -> Result<Vec<u8>> {
+        let mut response = response.map_err(|e| HttpError::new(method, url, None, e))?;
+        let status = response.status();
+        let body = response
+            .body_mut()
+            .with_config()
+            .limit(self.max_response_size)
+            .read_to_vec();
+
+        if !status.is_success() {
+            let excerpt = body
+                .map(|body| String::from_utf8_lossy(&body).trim().to_string())
+                .unwrap_or_default();
+            let excerpt = match excerpt.char_indices().nth(ERROR_BODY_EXCERPT) {
+                Some((end, _)) => format!("{}...", &excerpt[..end]),
+                None => excerpt,
+            };
+            let message = if excerpt.is_empty() {
+                status.to_string()
+            } else {
+                format!("{status}: {excerpt}")
+            };
+            return Err(HttpError::new(method, url, Some(status.as_u16()), message).into());
+        }
+
+        body.map_err(|e| match e {
+            ureq::Error::BodyExceedsLimit(limit) => HttpError::new(
+                method,
+                url,
+                Some(status.as_u16()),
+                format!("the response is larger than {limit} bytes"),
+            ),
+            e => HttpError::new(method, url, Some(status.as_u16()), e),
+        })
+        .map_err(Into::into)
+    }
+}
+
+impl Debug for HttpClient {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // header values are left out, since they are often credentials
+        f.debug_struct("HttpClient")
+            .field("user_agent", &self.user_agent)
+            .field("timeout", &self.timeout)
+            .field("max_response_size", &self.max_response_size)
+            .field(
+                "headers",
+                &self
+                    .headers
+                    .iter()
+                    .map(|(name, _)| name)
+                    .collect::<Vec<_>>(),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+/// A request made by [`HttpClient`] that failed
+///
+/// It is returned inside an `anyhow::Error`, so tools that treat some statuses specially can
+/// find it with `downcast_ref`:
+///
+/// ```ignore
+/// match client.get_json::<Repo>(&url) {
+///     Err(e) if e.downcast_ref::<HttpError>().and_then(HttpError::status) == Some(404) => {
+///         Ok(format!("There is no repository called {}", self.repo).into())
+///     }
+///     result => Ok(ToolOutput::structured(&result?)?),
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpError {
+    method: &'static str,
+    url: String,
+    status: Option<u16>,
+    message: String,
+}
+
+impl HttpError {
+    fn new(method: &'static str, url: &str, status: Option<u16>, message: impl Display) -> Self {
+        Self {
+            method,
+            url: url.to_string(),
+            status,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn method(&self) -> &str {
+        self.method
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The response's status, or `None` if there was no response, like when the request timed
+    /// out or the host couldn't be reached
+    pub fn status(&self) -> Option<u16> {
+        self.status
+    }
+}
+
+impl Display for HttpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} failed: {}", self.method, self.url, self.message)
+    }
+}
+
+impl StdError for HttpError {}
+
+fn agent(user_agent: &str, timeout: Option<Duration>) -> Agent {
+    Agent::config_builder()
+        .user_agent(user_agent)
+        .timeout_global(timeout)
+        .http_status_as_error(false)
+        .build()
+        .into()
+}
+
+fn parse_json<T: DeserializeOwned>(method: &'static str, url: &str, body: &[u8]) -> Result<T> {
+    serde_json::from_slice(body).map_err(|e| {
+        HttpError::new(
+            method,
+            url,
+            None,
+            format!("the response is not the expected JSON: {e}"),
+        )
+        .into()
+    })
+}
@@ -1,122 +1,922 @@
 #[macro_use]
 mod macros;
+pub mod auth;
+mod cache;
+pub mod client;
+pub mod config;
+pub mod context;
+pub mod docs;
+mod error;
+pub mod extensions;
+pub mod fs;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod logging;
+pub mod metrics;
+pub mod paths;
+pub mod process;
+pub mod profiles;
+pub mod proxy;
+pub mod rate_limit;
+pub mod registry;
+pub mod server;
 pub mod session;
+mod signal;
+pub mod testing;
 pub mod traits;
+pub mod transport;
 pub mod types;
 
 pub use anyhow;
 pub use clap;
 pub use dirs;
+pub use error::Error;
 pub use fieldwork;
 pub use log;
+pub use mcplease_macros::{CliArgs, McpTool, tool_router};
 pub use schemars;
 pub use serde;
 pub use serde_json;
 pub use shellexpand;
+#[cfg(feature = "http")]
+pub use ureq;
 
 use std::{
+    borrow::Cow,
     fmt::Debug,
-    fs::OpenOptions,
-    io::{BufRead, BufReader, Write},
+    marker::PhantomData,
     path::PathBuf,
+    sync::{
+        Arc,
+        mpsc::{self, Receiver, Sender},
+    },
+    thread,
 };
 
 use crate::{
-    traits::{AsToolsList, Tool},
-    types::Info,
+    auth::HttpHeaders,
+    context::{Connection, InFlight, Outbound, ToolContext},
+    registry::ToolRegistry,
+    server::{Hooks, Server, ServerConfig},
+    traits::{
+        AsPromptsList, AsResourcesList, AsToolsList, CompleteArgument, EnabledTools, GetPrompt,
+        ReadResource, Tool,
+    },
+    transport::{
+        MESSAGE_TOO_LARGE, MessageTooLarge, Serving, Transport,
+        record::{self, Direction, Recorder},
+    },
+    types::{ContentResponse, Info, InputSchema, Manifest, McpRequest, McpResponse, Tagged},
 };
-use anyhow::Result;
-use clap::{Parser, Subcommand};
-use env_logger::{Builder, Target};
+use anyhow::{Result, bail};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
 use types::McpMessage;
 
-fn serve<Tools: Debug + AsToolsList + Tool<State>, State>(
+/// Serve MCP to the one client on `transport`, until it disconnects or the process is asked to
+/// stop
+///
+/// `session_id` identifies the client to tools, when there may be more than one.
+fn serve<Tools, Resources, Prompts, State>(
     state: &mut State,
-    server_info: Info,
-    instructions: Option<&'static str>,
-) -> Result<()> {
-    let stdin = std::io::stdin();
-    let mut stdout = std::io::stdout();
-    let mut reader = BufReader::new(stdin);
-    let mut line = String::new();
+    config: &ServerConfig,
+    registry: &ToolRegistry<State>,
+    hooks: &Hooks<State>,
+    transport: Arc<dyn Transport>,
+    session_id: Option<String>,
+) -> Result<()>
+where
+    Tools: Debug + AsToolsList + Tool<State> + CompleteArgument<State> + EnabledTools<State>,
+    Resources: AsResourcesList + ReadResource<State>,
+    Prompts: AsPromptsList + GetPrompt<State> + CompleteArgument<State>,
+{
+    let in_flight = InFlight::default();
+    let recorder = config.recording().map(Recorder::create).transpose()?;
+    let outbound = Outbound::new({
+        let transport = Arc::clone(&transport);
+        let recorder = recorder.clone();
+        move |message| {
+            if let Some(recorder) = &recorder {
+                recorder.record(Direction::Out, message);
+            }
+            transport.send(message)
+        }
+    });
+    let mut connection =
+        Connection::new("", in_flight.clone(), outbound.clone()).with_session_id(session_id);
+    registry.subscribe(outbound.clone());
 
     log::trace!("started!");
 
+    // the transport is read on its own thread so that cancellations are observed while a tool is
+    // running. `None` means stop serving, either because the client disconnected or because of a
+    // signal.
+    let (sender, receiver) = mpsc::channel();
+    let _signal = {
+        let in_flight = in_flight.clone();
+        let sender = sender.clone();
+        signal::on_terminate(move || {
+            in_flight.cancel_all();
+            let _ = sender.send(None);
+        })
+    };
+    {
+        let in_flight = in_flight.clone();
+        thread::spawn(move || read(transport, in_flight, outbound, recorder, sender));
+    }
+
+    dispatch::<Tools, Resources, Prompts, State>(
+        state,
+        config,
+        registry,
+        hooks,
+        &mut connection,
+        &receiver,
+    )
+}
+
+/// Handle the messages from `receiver` on this thread, one at a time, until it yields `None`
+pub(crate) fn dispatch<Tools, Resources, Prompts, State>(
+    state: &mut State,
+    config: &ServerConfig,
+    registry: &ToolRegistry<State>,
+    hooks: &Hooks<State>,
+    connection: &mut Connection,
+    receiver: &Receiver<Option<McpMessage>>,
+) -> Result<()>
+where
+    Tools: Debug + AsToolsList + Tool<State> + CompleteArgument<State> + EnabledTools<State>,
+    Resources: AsResourcesList + ReadResource<State>,
+    Prompts: AsPromptsList + GetPrompt<State> + CompleteArgument<State>,
+{
+    while let Ok(Some(message)) = receiver.recv() {
+        match message {
+            McpMessage::Request(request) => {
+                let response = handle::<Tools, Resources, Prompts, State>(
+                    state, config, registry, hooks, connection, request, None,
+                );
+                if let Some(response) = response
+                    && !respond(connection, &response)
+                {
+                    break;
+                }
+            }
+            McpMessage::Notification(notification) => connection.notify(&notification),
+            other => {
+                log::trace!("received {other:?}, ignoring");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Answer `request` from a client on `connection`, or return `None` if it was cancelled, since
+/// the client isn't waiting for a response then
+///
+/// Requests the handshake doesn't allow yet are refused without being executed. Every transport
+/// handles requests this way, and so does [`TestClient`](testing::TestClient).
+pub(crate) fn handle<Tools, Resources, Prompts, State>(
+    state: &mut State,
+    config: &ServerConfig,
+    registry: &ToolRegistry<State>,
+    hooks: &Hooks<State>,
+    connection: &mut Connection,
+    request: McpRequest,
+    http_headers: Option<HttpHeaders>,
+) -> Option<McpResponse>
+where
+    Tools: Debug + AsToolsList + Tool<State> + CompleteArgument<State> + EnabledTools<State>,
+    Resources: AsResourcesList + ReadResource<State>,
+    Prompts: AsPromptsList + GetPrompt<State> + CompleteArgument<State>,
+{
+    let id = request.id.clone();
+    if let Some(response) = connection.refuse(&request) {
+        connection.finish(&id);
+        return Some(response);
+    }
+
+    let mut context = connection.context(&request, config);
+    if let Some(http_headers) = http_headers {
+        context = context.with_http_headers(http_headers);
+    }
+    let response = request
+        .execute::<State, Tools, Resources, Prompts>(state, config, registry, hooks, &context);
+    connection.finish(&id);
+    if context.is_cancelled() {
+        log::debug!("request {id} was cancelled, not responding");
+        return None;
+    }
+    Some(response)
+}
+
+/// Send `response`, returning whether the client is still there to receive it
+fn respond(connection: &Connection, response: &McpResponse) -> bool {
+    match connection.outbound().send(response) {
+        Ok(()) => true,
+        Err(e) => {
+            log::info!("could not respond, so the client must have disconnected: {e}");
+            false
+        }
+    }
+}
+
+fn read(
+    transport: Arc<dyn Transport>,
+    in_flight: InFlight,
+    outbound: Outbound,
+    recorder: Option<Recorder>,
+    sender: Sender<Option<McpMessage>>,
+) {
     loop {
-        line.clear();
-        match reader.read_line(&mut line) {
-            Ok(0) => break, // EOF
-            Ok(_) => {
+        match transport.recv() {
+            Ok(None) => break,
+            Ok(Some(line)) => {
                 log::trace!("<- {line}");
-                match serde_json::from_str(&line) {
-                    Ok(McpMessage::Request(request)) => {
-                        let response =
-                            request.execute::<State, Tools>(state, instructions, &server_info);
-                        let response_str = serde_json::to_string(&response)?;
-                        log::trace!("-> {response_str}");
-                        stdout.write_all(response_str.as_bytes())?;
-                        stdout.write_all(b"\n")?;
-                        stdout.flush()?;
-                    }
-                    Ok(McpMessage::Notification(n)) => {
-                        log::trace!("received {n:?}, ignoring");
+                if line.trim().is_empty() {
+                    continue;
+                }
+                if let Some(recorder) = &recorder {
+                    recorder.record(Direction::In, &line);
+                }
+                match McpMessage::parse(line.as_bytes()) {
+                    Ok(message) => {
+                        if let Some(message) = in_flight.receive("", message)
+                            && sender.send(Some(message)).is_err()
+                        {
+                            break;
+                        }
                     }
 
-                    Err(e) => {
-                        log::error!("{e:?}");
+                    Err(response) => {
+                        if let Err(e) = outbound.send(&response) {
+                            log::error!("{e}");
+                            break;
+                        }
                     }
                 }
             }
             Err(e) => {
-                log::error!("Error reading line: {e}");
-                break;
+                let Some(too_large) = e
+                    .get_ref()
+                    .and_then(|e| e.downcast_ref::<MessageTooLarge>())
+                else {
+                    log::error!("Error reading message: {e}");
+                    break;
+                };
+                log::warn!("{too_large}, skipping it");
+                let response = McpResponse::from_error(
+                    Value::Null,
+                    Error::custom(
+                        MESSAGE_TOO_LARGE,
+                        format!(
+                            "Message too large: {} bytes, over the limit of {} bytes",
+                            too_large.size, too_large.max_size
+                        ),
+                        json!({ "maxSize": too_large.max_size }),
+                    ),
+                );
+                if let Err(e) = outbound.send(&response) {
+                    log::error!("{e}");
+                    break;
+                }
             }
         }
     }
 
-    Ok(())
+    in_flight.disconnect("");
+    let _ = sender.send(None);
 }
 
 #[derive(clap::Parser)]
+#[command(
+    after_help = "Any tool can instead read its arguments as a JSON object with \
+                  `<TOOL> --args-json <FILE>`, where FILE can be - for stdin.\n\n\
+                  `--manifest` prints the server's info, instructions, capabilities, and tool \
+                  schemas as JSON, without serving."
+)]
 struct Cli<T: Subcommand> {
     #[command(subcommand)]
     tool: T,
+
+    /// Print the result as the JSON-RPC response an MCP client would receive
+    #[arg(long, global = true)]
+    json: bool,
+}
+
+/// How to serve MCP, from the options of the `serve` command
+#[derive(clap::Parser, Debug, PartialEq, Eq)]
+#[command(
+    name = "serve",
+    about = "Serve MCP, over stdio unless an address is given"
+)]
+struct Serve {
+    /// Serve the HTTP+SSE transport at ADDR, like 127.0.0.1:8080
+    #[arg(long, value_name = "ADDR", conflicts_with_all = ["tcp", "record"])]
+    sse: Option<String>,
+
+    /// Serve newline-delimited JSON-RPC over TCP at ADDR, requiring the token in MCP_TCP_TOKEN
+    /// if it is set
+    #[arg(long, value_name = "ADDR")]
+    tcp: Option<String>,
+
+    /// Also write every message to FILE, to replay later with `replay <FILE>`
+    #[arg(long, value_name = "FILE")]
+    record: Option<PathBuf>,
 }
 
-pub fn run<Tools: Debug + Subcommand + AsToolsList + Tool<State>, State>(
+impl Serve {
+    /// Parse the options after `serve`, failing on any that are unknown, missing their value,
+    /// or conflicting, unless they asked for help, which is printed instead
+    fn parse(bin: &str, options: &[&str]) -> Result<Option<Self>> {
+        let command = format!("{bin} serve");
+        match Self::try_parse_from(std::iter::once(command.as_str()).chain(options.iter().copied()))
+        {
+            Ok(serve) => Ok(Some(serve)),
+            Err(e) if e.kind() == clap::error::ErrorKind::DisplayHelp => {
+                print!("{e}");
+                Ok(None)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn serving(self) -> Serving {
+        match (self.sse, self.tcp) {
+            (Some(addr), _) => Serving::Sse(addr),
+            (None, Some(addr)) => Serving::Tcp {
+                addr,
+                token: std::env::var("MCP_TCP_TOKEN").ok(),
+            },
+            (None, None) => Serving::default(),
+        }
+    }
+}
+
+/// Run the tool named on the command line, or serve MCP over stdio with `serve`
+///
+/// This is shorthand for the most common [`Server::builder`](server::Server::builder)
+/// configuration, without resources or prompts. `instructions` can be a `&'static str` or a
+/// `String`, like one read from a file; without any, pass `None::<&str>`.
+pub fn run<Tools, State>(
     state: &mut State,
     server_info: Info,
-    instructions: Option<&'static str>,
-) -> Result<()> {
-    if let Ok(log_location) = std::env::var("MCP_LOG_LOCATION") {
-        let path = PathBuf::from(&*shellexpand::tilde(&log_location));
-        if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent).unwrap();
+    instructions: Option<impl Into<Cow<'static, str>>>,
+) -> Result<()>
+where
+    Tools: Debug
+        + Subcommand
+        + AsToolsList
+        + Tool<State>
+        + CompleteArgument<State>
+        + EnabledTools<State>,
+{
+    let mut server = Server::builder()
+        .with_state_type::<State>()
+        .with_tools::<Tools>()
+        .with_info(server_info);
+    if let Some(instructions) = instructions {
+        server = server.with_instructions(instructions);
+    }
+    server.run_on(state)
+}
+
+/// Run the tool named in `args`, a command line starting with the binary's name, or serve MCP if
+/// the command is `serve`
+pub(crate) fn run_cli<Tools, Resources, Prompts, State>(
+    state: &mut State,
+    config: &ServerConfig,
+    registry: &ToolRegistry<State>,
+    hooks: &Hooks<State>,
+    args: impl IntoIterator<Item = String>,
+) -> Result<()>
+where
+    Tools: Debug
+        + Subcommand
+        + AsToolsList
+        + Tool<State>
+        + CompleteArgument<State>
+        + EnabledTools<State>,
+    Resources: AsResourcesList + ReadResource<State>,
+    Prompts: AsPromptsList + GetPrompt<State> + CompleteArgument<State>,
+{
+    let mut args = args.into_iter();
+    let bin = args.next().unwrap_or_default();
+    let args: Vec<String> = args.collect();
+    let (tool, json) = match tool_from_args_json::<Tools>(&args)? {
+        Some(parsed) => parsed,
+        None => match parse_cli::<Tools>(&bin, &args) {
+            Ok(Cli { tool, json }) => (tool, json),
+            Err(e) => {
+                match &*args.iter().map(String::as_str).collect::<Vec<_>>() {
+                    ["serve", options @ ..] => {
+                        let Some(mut serve) = Serve::parse(&bin, options)? else {
+                            return Ok(());
+                        };
+                        let recording;
+                        let config = match serve.record.take() {
+                            Some(path) => {
+                                recording = config.clone().with_recording(path);
+                                &recording
+                            }
+                            None => config,
+                        };
+                        serve_transport::<Tools, Resources, Prompts, State>(
+                            state,
+                            config,
+                            registry,
+                            hooks,
+                            &serve.serving(),
+                        )?;
+                    }
+                    ["replay", path] => {
+                        replay::<Tools, Resources, Prompts, State>(
+                            state, config, registry, hooks, path,
+                        )?;
+                    }
+                    ["--manifest"] => {
+                        let manifest = Manifest::for_server::<Tools, Resources, Prompts, State>(
+                            state, config, registry, hooks,
+                        );
+                        println!("{}", serde_json::to_string_pretty(&manifest)?);
+                    }
+                    ["docs"] => print_docs::<Tools, State>(state, config, registry, hooks, false)?,
+                    ["docs", "--json"] => {
+                        print_docs::<Tools, State>(state, config, registry, hooks, true)?
+                    }
+                    _ => eprintln!("{e}"),
+                }
+                return Ok(());
+            }
+        },
+    };
+
+    let name = serde_json::to_value(&tool)?["name"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    if Tools::tool_enabled(&name, state) == Some(false) {
+        bail!(Error::InvalidParams(format!("{name} is not enabled")));
+    }
+
+    if json {
+        // the error is still returned afterwards, so that the exit status reflects it
+        match tool.execute_with_context(state, &ToolContext::for_cli(config)) {
+            Ok(output) => {
+                let response = McpResponse::success(Value::Null, ContentResponse::from(output));
+                println!("{}", serde_json::to_string(&response)?);
+            }
+            Err(e) => {
+                let response = types::tool_error_response(Value::Null, &e);
+                println!("{}", serde_json::to_string(&response)?);
+                return Err(e);
+            }
+        }
+    } else {
+        let result = tool.execute_with_context(state, &ToolContext::for_cli(config))?;
+        println!("{result}");
+    }
+
+    Ok(())
+}
+
+/// Parse the command line, with each tool's examples at the end of its `--help` and the defaults
+/// from its schema applied to its arguments
+fn parse_cli<Tools>(bin: &str, args: &[String]) -> Result<Cli<Tools>, clap::Error>
+where
+    Tools: Subcommand + AsToolsList,
+{
+    let mut command = Cli::<Tools>::command();
+    for tool in Tools::tools_list() {
+        let Some(name) = command
+            .get_subcommands()
+            .map(|subcommand| subcommand.get_name().to_string())
+            .find(|name| *name == tool.name || *name == tool.name.replace('_', "-"))
+        else {
+            continue;
+        };
+        command = command.mut_subcommand(&name, |subcommand| {
+            cli_defaults(subcommand, &tool.input_schema)
+        });
+
+        let schema = serde_json::to_value(&tool.input_schema).unwrap_or_default();
+        let Some(examples) = schema["examples"].as_array() else {
+            continue;
+        };
+        let help = examples_help(examples);
+        command = command.mut_subcommand(name, |subcommand| subcommand.after_help(help));
+    }
+    Cli::from_arg_matches(
+        &command
+            .try_get_matches_from(std::iter::once(bin).chain(args.iter().map(String::as_str)))?,
+    )
+}
+
+/// Give each argument of `subcommand` that takes a value the default from the tool's input
+/// schema for the field of the same name, so that it's shown in `--help` and can be left out
+///
+/// Arguments that already have a default keep it, and flags are left alone since they already
+/// default to false.
+fn cli_defaults(mut subcommand: clap::Command, schema: &InputSchema) -> clap::Command {
+    let InputSchema::Tagged(Tagged::Object { properties, .. }) = schema else {
+        return subcommand;
+    };
+    for (name, property) in properties {
+        let Some(values) = property.default_value().and_then(cli_default_values) else {
+            continue;
+        };
+        let long = name.replace('_', "-");
+        let Some(id) = subcommand
+            .get_arguments()
+            .find(|arg| {
+                (arg.get_id() == name || arg.get_long() == Some(&long))
+                    && arg.get_action().takes_values()
+                    && (values.len() == 1 || matches!(arg.get_action(), clap::ArgAction::Append))
+                    && arg.get_default_values().is_empty()
+            })
+            .map(|arg| arg.get_id().clone())
+        else {
+            continue;
+        };
+        subcommand = subcommand.mut_arg(id, |arg| arg.default_values(values).required(false));
+    }
+    subcommand
+}
+
+/// A default from a schema as it would be typed on the command line, or `None` if it can't be
+/// typed or there's nothing to type
+fn cli_default_values(default: &Value) -> Option<Vec<String>> {
+    let scalar = |value: &Value| match value {
+        Value::String(string) => Some(string.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(boolean) => Some(boolean.to_string()),
+        _ => None,
+    };
+    match default {
+        Value::Array(values) if !values.is_empty() => values.iter().map(scalar).collect(),
+        Value::Array(_) => None,
+        value => scalar(value).map(|value| vec![value]),
+    }
+}
+
+/// Describe the `examples` from a tool's input schema, showing the arguments as they would be
+/// passed to `--args-json`
+fn examples_help(examples: &[Value]) -> String {
+    let mut help = String::from("Examples:");
+    for example in examples {
+        let mut arguments = example.as_object().cloned().unwrap_or_default();
+        let description = arguments.remove("description");
+        let expected_output = arguments.remove("expectedOutput");
+        let negative = arguments.remove("negative") == Some(Value::Bool(true));
+
+        help.push_str("\n  ");
+        help.push_str(description.as_ref().and_then(Value::as_str).unwrap_or(""));
+        if negative {
+            help.push_str(" (don't do this)");
         }
-        Builder::from_default_env()
-            .target(Target::Pipe(Box::new(
-                OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .open(path)
-                    .unwrap(),
-            )))
-            .init();
-    }
-
-    match Cli::<Tools>::try_parse() {
-        Ok(Cli { tool }) => {
-            let result = tool.execute(state)?;
-            println!("{result}");
+        help.push_str(&format!("\n    {}", Value::Object(arguments)));
+        if let Some(Value::String(output)) = expected_output {
+            help.push_str(&format!("\n    => {output}"));
         }
-        Err(e) => {
-            if std::env::args().nth(1).as_deref() == Some("serve") {
-                serve::<Tools, State>(state, server_info, instructions)?;
+    }
+    help
+}
+
+/// Parse `<tool> --args-json <file>`, which reads the tool's arguments as a JSON object from a
+/// file, or from stdin if the file is `-`. Returns `None` for any other command line, which clap
+/// handles.
+///
+/// The second element is whether `--json` was also passed.
+fn tool_from_args_json<Tools>(args: &[String]) -> Result<Option<(Tools, bool)>>
+where
+    Tools: AsToolsList + DeserializeOwned,
+{
+    if !args.iter().any(|arg| arg == "--args-json") {
+        return Ok(None);
+    }
+    let json = args.iter().any(|arg| arg == "--json");
+    let args: Vec<&str> = args
+        .iter()
+        .map(String::as_str)
+        .filter(|arg| *arg != "--json")
+        .collect();
+    let [command, "--args-json", source] = *args else {
+        bail!("usage: <TOOL> --args-json <FILE> [--json], where FILE can be - for stdin");
+    };
+
+    let Some(schema) = Tools::tools_list()
+        .into_iter()
+        .find(|tool| tool.name == command || tool.name.replace('_', "-") == command)
+    else {
+        bail!("unknown tool {command}");
+    };
+
+    let mut arguments: Value = if source == "-" {
+        serde_json::from_reader(std::io::stdin().lock())?
+    } else {
+        serde_json::from_str(&std::fs::read_to_string(source)?)?
+    };
+    schema.input_schema.apply_defaults(&mut arguments);
+    let tool = serde_json::from_value(json!({ "name": schema.name, "arguments": arguments }))?;
+    Ok(Some((tool, json)))
+}
+
+/// Parse a command-line argument into a `T` the way clients send it, as a JSON string
+///
+/// Use this as a field's `value_parser` for an enum that derives `JsonSchema` and `Deserialize`,
+/// instead of also deriving clap's `ValueEnum`. The command line then accepts exactly the values
+/// clients can send, and lists them in `--help` with the variants' doc comments.
+///
+/// ```ignore
+/// /// How to compare files
+/// #[arg(long, value_parser = mcplease::schema_value_parser::<Comparison>())]
+/// pub comparison: Comparison,
+/// ```
+pub fn schema_value_parser<T>() -> SchemaValueParser<T>
+where
+    T: JsonSchema + DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    SchemaValueParser(PhantomData)
+}
+
+/// The clap value parser returned by [`schema_value_parser`]
+#[derive(Debug)]
+pub struct SchemaValueParser<T>(PhantomData<fn() -> T>);
+
+impl<T> Clone for SchemaValueParser<T> {
+    fn clone(&self) -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T> SchemaValueParser<T>
+where
+    T: JsonSchema,
+{
+    /// The values of `T`'s string enum schema, with their descriptions
+    fn values() -> Vec<(String, Option<String>)> {
+        let schema = traits::root_schema_for::<T>();
+        let strings = |key| {
+            schema
+                .get(key)
+                .and_then(Value::as_array)
+                .map(|values| {
+                    values
+                        .iter()
+                        .map(|value| value.as_str().unwrap_or_default().to_string())
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        };
+        let descriptions = strings("enumDescriptions");
+        strings("enum")
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| {
+                let description = descriptions.get(index).filter(|d| !d.is_empty()).cloned();
+                (value, description)
+            })
+            .collect()
+    }
+}
+
+impl<T> clap::builder::TypedValueParser for SchemaValueParser<T>
+where
+    T: JsonSchema + DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    type Value = T;
+
+    fn parse_ref(
+        &self,
+        command: &clap::Command,
+        arg: Option<&clap::Arg>,
+        value: &std::ffi::OsStr,
+    ) -> Result<T, clap::Error> {
+        let invalid = |message: String| {
+            let arg = arg.map_or_else(|| "...".to_string(), ToString::to_string);
+            clap::Error::raw(
+                clap::error::ErrorKind::InvalidValue,
+                format!(
+                    "invalid value '{}' for '{arg}': {message}\n",
+                    value.to_string_lossy()
+                ),
+            )
+            .with_cmd(command)
+        };
+        let Some(value) = value.to_str() else {
+            return Err(invalid("not valid utf-8".into()));
+        };
+        serde_json::from_value(Value::String(value.to_string())).map_err(|e| {
+            let values = Self::values();
+            if values.is_empty() {
+                invalid(e.to_string())
             } else {
-                eprintln!("{e}");
+                let values = values
+                    .into_iter()
+                    .map(|(value, _)| value)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                invalid(format!("expected one of {values}"))
             }
+        })
+    }
+
+    fn possible_values(
+        &self,
+    ) -> Option<Box<dyn Iterator<Item = clap::builder::PossibleValue> + '_>> {
+        let values = Self::values();
+        if values.is_empty() {
+            return None;
         }
+        Some(Box::new(values.into_iter().map(|(value, description)| {
+            let value = clap::builder::PossibleValue::new(value);
+            match description {
+                Some(description) => value.help(description),
+                None => value,
+            }
+        })))
+    }
+}
+
+/// Replay a session recorded with `serve --record`, failing if anything the server sends differs
+/// from the recording
+fn replay<Tools, Resources, Prompts, State>(
+    state: &mut State,
+    config: &ServerConfig,
+    registry: &ToolRegistry<State>,
+    hooks: &Hooks<State>,
+    path: &str,
+) -> Result<()>
+where
+    Tools: Debug + AsToolsList + Tool<State> + CompleteArgument<State> + EnabledTools<State>,
+    Resources: AsResourcesList + ReadResource<State>,
+    Prompts: AsPromptsList + GetPrompt<State> + CompleteArgument<State>,
+{
+    let recording = record::read(path)?;
+    let differences = record::replay::<Tools, Resources, Prompts, State>(
+        state, config, registry, hooks, &recording,
+    )?;
+    if differences.is_empty() {
+        println!("replayed {} messages with no differences", recording.len());
+        return Ok(());
     }
 
+    for difference in &differences {
+        println!("{difference}");
+    }
+    bail!(
+        "{} of the server's messages differ from the recording",
+        differences.len()
+    );
+}
+
+/// Print the tools a client would see in `tools/list`, as Markdown under the server's name and
+/// instructions, or as the `tools/list` result if `json`
+fn print_docs<Tools, State>(
+    state: &mut State,
+    config: &ServerConfig,
+    registry: &ToolRegistry<State>,
+    hooks: &Hooks<State>,
+    json: bool,
+) -> Result<()>
+where
+    Tools: AsToolsList + EnabledTools<State>,
+{
+    let tools = types::list_tools::<State, Tools>(state, config, registry);
+    if json {
+        let response = types::ToolsListResponse { tools };
+        println!("{}", serde_json::to_string_pretty(&response)?);
+        return Ok(());
+    }
+
+    let info = config.info();
+    println!("# {} {}\n", info.name, info.version);
+    if let Some(instructions) = types::server_instructions(state, config, hooks) {
+        println!("{}\n", instructions.trim());
+    }
+    println!("## Tools\n");
+    print!("{}", docs::markdown(&tools));
+    Ok(())
+}
+
+/// Serve MCP as `serving` says until the client disconnects or the process is asked to stop
+pub(crate) fn serve_transport<Tools, Resources, Prompts, State>(
+    state: &mut State,
+    config: &ServerConfig,
+    registry: &ToolRegistry<State>,
+    hooks: &Hooks<State>,
+    serving: &Serving,
+) -> Result<()>
+where
+    Tools: Debug + AsToolsList + Tool<State> + CompleteArgument<State> + EnabledTools<State>,
+    Resources: AsResourcesList + ReadResource<State>,
+    Prompts: AsPromptsList + GetPrompt<State> + CompleteArgument<State>,
+{
+    match serving {
+        Serving::Sse(_) if config.recording().is_some() => {
+            bail!("recording is not supported over HTTP+SSE")
+        }
+        Serving::Transport(transport) => serve::<Tools, Resources, Prompts, State>(
+            state,
+            config,
+            registry,
+            hooks,
+            Arc::clone(transport),
+            None,
+        )?,
+        Serving::Sse(addr) => transport::sse::serve::<Tools, Resources, Prompts, State>(
+            state, config, registry, hooks, addr,
+        )?,
+        Serving::Tcp { addr, token } => transport::tcp::serve::<Tools, Resources, Prompts, State>(
+            state,
+            config,
+            registry,
+            hooks,
+            addr,
+            token.as_deref(),
+        )?,
+    }
+    config.shut_down();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::NoTools;
+
+    #[test]
+    fn dispatch_ends_cleanly_when_the_client_is_gone() {
+        let outbound = Outbound::new(|_| Err(std::io::ErrorKind::BrokenPipe.into()));
+        let mut connection = Connection::new("", InFlight::default(), outbound);
+        let (sender, receiver) = mpsc::channel();
+        for message in [
+            r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#,
+        ] {
+            sender
+                .send(Some(McpMessage::parse(message.as_bytes()).unwrap()))
+                .unwrap();
+        }
+        sender.send(None).unwrap();
+
+        let config = ServerConfig::new(Info {
+            name: "test".into(),
+            version: "0.0.0".into(),
+        });
+        let result = dispatch::<NoTools, (), (), ()>(
+            &mut (),
+            &config,
+            &ToolRegistry::new(),
+            &Hooks::default(),
+            &mut connection,
+            &receiver,
+        );
+        assert!(result.is_ok());
+        // the first failed response stopped dispatching, leaving the rest unread
+        assert!(receiver.try_recv().is_ok());
+    }
+
+    fn serve(options: &[&str]) -> Result<Serve> {
+        Ok(Serve::parse("server", options)?.expect("options, not help"))
+    }
+
+    #[test]
+    fn serve_options() {
+        assert_eq!(
+            serve(&[]).unwrap(),
+            Serve {
+                sse: None,
+                tcp: None,
+                record: None
+            }
+        );
+        let sse = serve(&["--sse", "127.0.0.1:8080"]).unwrap();
+        assert_eq!(sse.sse.as_deref(), Some("127.0.0.1:8080"));
+        assert!(matches!(sse.serving(), Serving::Sse(addr) if addr == "127.0.0.1:8080"));
+
+        let tcp = serve(&["--record", "session.jsonl", "--tcp", "0.0.0.0:9000"]).unwrap();
+        assert_eq!(tcp.record, Some(PathBuf::from("session.jsonl")));
+        assert!(matches!(tcp.serving(), Serving::Tcp { addr, .. } if addr == "0.0.0.0:9000"));
+
+        assert!(serve(&["--sse=127.0.0.1:8080"]).is_ok());
+        assert!(Serve::parse("server", &["--help"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn invalid_serve_options_are_refused() {
+        for options in [
+            &["--tcp"][..],
+            &["--sse"],
+            &["--ssee", "127.0.0.1:8080"],
+            &["127.0.0.1:8080"],
+            &["--sse", "127.0.0.1:8080", "--record", "session.jsonl"],
+            &["--sse", "127.0.0.1:8080", "--tcp", "127.0.0.1:9000"],
+            &["--tcp", "127.0.0.1:9000", "--tcp", "127.0.0.1:9001"],
+        ] {
+            assert!(serve(options).is_err(), "{options:?}");
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Where log output goes, which is never stdout
+//!
+//! The stdio transport owns stdout, and a single stray log line there corrupts the JSON-RPC stream,
+//! so every server logs either to the file named by `MCP_LOG_LOCATION` or to stderr. Which messages
+//! are logged is controlled by `RUST_LOG` as usual.
+//!
+//! With the `tracing` feature, each request is a `request` span with `method` and `id` fields, and
+//! each tool call within it a `tool_call` span with a `tool` field. Output goes to the same place,
+//! filtered by `MCP_TRACE_FILTER`, or `RUST_LOG` if that isn't set.
+
+use env_logger::{Builder, Target};
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// The environment variable naming the file to append log output to
+pub const LOG_LOCATION_VAR: &str = "MCP_LOG_LOCATION";
+
+/// The environment variable with the `tracing` filter directives, like `mcplease=debug`
+#[cfg(feature = "tracing")]
+pub const TRACE_FILTER_VAR: &str = "MCP_TRACE_FILTER";
+
+/// Start logging to `MCP_LOG_LOCATION`, or to stderr if it isn't set or can't be opened
+///
+/// [`run`](crate::run) and [`ServerBuilder`](crate::server::ServerBuilder) call this, so it's only
+/// needed when serving with a network transport's `serve` directly. It does nothing if a logger
+/// is already installed, so a server can install its own instead by doing so first.
+///
+/// With the `tracing` feature, this installs a `tracing` subscriber that also receives `log`
+/// records. If the server already installed a subscriber, spans go to that one and log records
+/// are written as they would be without the feature.
+pub fn init() {
+    let (file, fallback) = match log_location() {
+        Some(path) => match open(&path) {
+            Ok(file) => (Some(file), None),
+            Err(e) => (
+                None,
+                Some(format!(
+                    "could not open {} for logging, logging to stderr instead: {e}",
+                    path.display()
+                )),
+            ),
+        },
+        None => (None, None),
+    };
+
+    #[cfg(feature = "tracing")]
+    let file = match init_tracing(file) {
+        Ok(()) => {
+            if let Some(message) = fallback {
+                log::warn!("{message}");
+            }
+            return;
+        }
+        Err(file) => file,
+    };
+
+    let mut builder = Builder::from_default_env();
+    builder.target(match file {
+        Some(file) => Target::Pipe(Box::new(file)),
+        None => Target::Stderr,
+    });
+    if builder.try_init().is_ok()
+        && let Some(message) = fallback
+    {
+        log::warn!("{message}");
+    }
+}
+
+/// Install a subscriber writing to `file` or stderr, handing `file` back if one is already
+/// installed
+#[cfg(feature = "tracing")]
+fn init_tracing(file: Option<File>) -> Result<(), Option<File>> {
+    use std::sync::Arc;
+    use tracing_subscriber::{EnvFilter, fmt::writer::BoxMakeWriter};
+
+    if tracing::dispatcher::has_been_set() {
+        return Err(file);
+    }
+
+    let filter = EnvFilter::try_from_env(TRACE_FILTER_VAR)
+        .or_else(|_| EnvFilter::try_from_default_env())
+        .unwrap_or_else(|_| EnvFilter::new("error"));
+    let writer = match file {
+        Some(file) => BoxMakeWriter::new(Arc::new(file)),
+        None => BoxMakeWriter::new(io::stderr),
+    };
+
+    // another subscriber or logger could be installed between the check above and here, in which
+    // case logging is already taken care of
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(false)
+        .try_init();
+    Ok(())
+}
+
+/// `MCP_LOG_LOCATION` with `~` [expanded](crate::paths::expand_home), if it is set to anything
+fn log_location() -> Option<PathBuf> {
+    let location = std::env::var(LOG_LOCATION_VAR).ok()?;
+    if location.trim().is_empty() {
+        return None;
+    }
+    Some(crate::paths::expand_home(location))
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    OpenOptions::new().create(true).append(true).open(path)
+}
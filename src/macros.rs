@@ -1,6 +1,19 @@
+/// The state a `tools!` entry's tool works with: the sub-state named after it, if there is one,
+/// or else the server's state
+#[doc(hidden)]
+#[macro_export]
+macro_rules! tool_state {
+    ($state:ty) => {
+        $state
+    };
+    ($state:ty, $substate:ty) => {
+        $substate
+    };
+}
+
 #[macro_export]
 macro_rules! tools {
-    ($state:tt, $(($capitalized:tt, $lowercase:tt, $string:literal)),+) => {
+    ($state:tt, $(($capitalized:tt, $lowercase:tt, $string:literal $(, $substate:ty)?)),+ $(,)?) => {
         $(mod $lowercase;)+
         $(pub use $lowercase::$capitalized;)+
 
@@ -73,18 +86,65 @@ macro_rules! tools {
 
 
         impl $crate::traits::Tool<$state> for Tools {
-            fn execute(self, state: &mut $state) -> $crate::anyhow::Result<String> {
+            fn execute(
+                self,
+                state: &mut $state,
+            ) -> $crate::anyhow::Result<$crate::types::ToolOutput> {
+                match self {
+                    $(Tools::$capitalized(tool) => <$capitalized as $crate::traits::Tool<$crate::tool_state!($state $(, $substate)?)>>::execute(
+                        tool,
+                        $crate::traits::FromState::from_state_mut(state),
+                    ),)+
+                }
+            }
+
+            fn execute_with_context(
+                self,
+                state: &mut $state,
+                context: &$crate::context::ToolContext,
+            ) -> $crate::anyhow::Result<$crate::types::ToolOutput> {
                 match self {
-                    $(Tools::$capitalized(tool) => tool.execute(state),)+
+                    $(Tools::$capitalized(tool) => <$capitalized as $crate::traits::Tool<$crate::tool_state!($state $(, $substate)?)>>::execute_with_context(
+                        tool,
+                        $crate::traits::FromState::from_state_mut(state),
+                        context,
+                    ),)+
                 }
             }
+        }
 
+        impl $crate::traits::CompleteArgument<$state> for Tools {
+            fn complete_argument(
+                name: &str,
+                argument: &str,
+                value: &str,
+                state: &mut $state,
+            ) -> Option<$crate::anyhow::Result<Vec<String>>> {
+                match name {
+                    $($string => Some(<$capitalized as $crate::traits::Tool<$crate::tool_state!($state $(, $substate)?)>>::complete(
+                        argument,
+                        value,
+                        $crate::traits::FromState::from_state_mut(state),
+                    )),)+
+                    _ => None,
+                }
+            }
+        }
+
+        impl $crate::traits::EnabledTools<$state> for Tools {
+            fn tool_enabled(name: &str, state: &$state) -> Option<bool> {
+                match name {
+                    $($string => Some(<$capitalized as $crate::traits::Tool<$crate::tool_state!($state $(, $substate)?)>>::enabled(
+                        $crate::traits::FromState::from_state(state),
+                    )),)+
+                    _ => None,
+                }
+            }
         }
 
         impl $crate::traits::AsToolsList for Tools {
             fn tools_list() -> Vec<$crate::types::ToolSchema> {
-                use $crate::traits::AsToolSchema;
-                vec![$($capitalized::schema(),)+]
+                vec![$($crate::types::ToolSchema::for_tool::<$capitalized, $crate::tool_state!($state $(, $substate)?)>(),)+]
             }
         }
 
@@ -99,6 +159,119 @@ macro_rules! tools {
     };
 }
 
+#[macro_export]
+macro_rules! resources {
+    (
+        $state:tt $(, ($capitalized:tt, $lowercase:tt))*
+        $(; templates: $(($template:tt, $template_lowercase:tt)),+)?
+    ) => {
+        $(mod $lowercase;)*
+        $(pub use $lowercase::$capitalized;)*
+        $($(mod $template_lowercase;)+)?
+        $($(pub use $template_lowercase::$template;)+)?
+
+        #[derive(Debug, Clone, Copy)]
+        pub struct Resources;
+
+        impl $crate::traits::AsResourcesList for Resources {
+            fn resources_list() -> Vec<$crate::types::ResourceDescriptor> {
+                vec![$(<$capitalized as $crate::traits::Resource<$state>>::descriptor(),)*]
+            }
+
+            fn resource_templates_list() -> Vec<$crate::types::ResourceTemplateDescriptor> {
+                vec![$($(<$template as $crate::traits::ResourceTemplate<$state>>::descriptor(),)+)?]
+            }
+        }
+
+        impl $crate::traits::ReadResource<$state> for Resources {
+            fn read_resource(
+                uri: &str,
+                state: &mut $state,
+            ) -> Option<$crate::anyhow::Result<$crate::types::ResourceContents>> {
+                $(
+                    if <$capitalized as $crate::traits::Resource<$state>>::descriptor().uri == uri {
+                        return Some(<$capitalized as $crate::traits::Resource<$state>>::read(state));
+                    }
+                )*
+                $($(
+                    let descriptor = <$template as $crate::traits::ResourceTemplate<$state>>::descriptor();
+                    if let Some(parameters) = descriptor.uri_template.matches(uri) {
+                        return Some(<$template as $crate::traits::ResourceTemplate<$state>>::read(uri, &parameters, state));
+                    }
+                )+)?
+                let _ = (uri, state);
+                None
+            }
+
+            fn complete_template_argument(
+                uri_template: &str,
+                argument: &str,
+                value: &str,
+                state: &mut $state,
+            ) -> Option<$crate::anyhow::Result<Vec<String>>> {
+                $($(
+                    if <$template as $crate::traits::ResourceTemplate<$state>>::descriptor().uri_template.as_str() == uri_template {
+                        return Some(<$template as $crate::traits::ResourceTemplate<$state>>::complete(argument, value, state));
+                    }
+                )+)?
+                let _ = (uri_template, argument, value, state);
+                None
+            }
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! prompts {
+    ($state:tt, $(($capitalized:tt, $lowercase:tt, $string:literal)),+) => {
+        $(mod $lowercase;)+
+        $(pub use $lowercase::$capitalized;)+
+
+        #[derive(Debug, Clone, Copy)]
+        pub struct Prompts;
+
+        impl $crate::traits::AsPromptsList for Prompts {
+            fn prompts_list() -> Vec<$crate::types::PromptDescriptor> {
+                use $crate::traits::AsPromptSchema;
+                vec![$($capitalized::prompt_schema(),)+]
+            }
+        }
+
+        impl $crate::traits::GetPrompt<$state> for Prompts {
+            fn get_prompt(
+                name: &str,
+                arguments: $crate::serde_json::Value,
+                state: &mut $state,
+            ) -> Option<$crate::anyhow::Result<$crate::types::GetPromptResponse>> {
+                match name {
+                    $(
+                        $string => Some(
+                            $crate::serde_json::from_value::<$capitalized>(arguments)
+                                .map_err($crate::anyhow::Error::from)
+                                .and_then(|prompt| $crate::traits::Prompt::<$state>::render(prompt, state)),
+                        ),
+                    )+
+                    _ => None,
+                }
+            }
+        }
+
+        impl $crate::traits::CompleteArgument<$state> for Prompts {
+            fn complete_argument(
+                name: &str,
+                argument: &str,
+                value: &str,
+                state: &mut $state,
+            ) -> Option<$crate::anyhow::Result<Vec<String>>> {
+                match name {
+                    $($string => Some(<$capitalized as $crate::traits::Prompt<$state>>::complete(argument, value, state)),)+
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! server_info {
     () => {
@@ -108,3 +281,28 @@ macro_rules! server_info {
         }
     };
 }
+
+/// Check the schemas of a server's tools against a snapshot checked in with the crate, at
+/// `snapshots/tool_schemas.json` unless another path relative to the crate is given
+///
+/// ```ignore
+/// #[test]
+/// fn tool_schemas() {
+///     mcplease::testing::assert_schemas_snapshot!(tools::Tools);
+/// }
+/// ```
+///
+/// See [`assert_snapshot`](crate::testing::assert_snapshot) for how snapshots are written and
+/// updated.
+#[macro_export]
+macro_rules! assert_schemas_snapshot {
+    ($tools:ty) => {
+        $crate::assert_schemas_snapshot!($tools, "snapshots/tool_schemas.json")
+    };
+    ($tools:ty, $path:expr) => {
+        $crate::testing::assert_snapshot(
+            ::std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join($path),
+            &$crate::testing::schemas_snapshot::<$tools>(),
+        )
+    };
+}
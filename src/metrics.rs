@@ -0,0 +1,158 @@
+//! Per-tool call counts, durations, and error rates
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// The name of the debug tool that reports metrics, when enabled with
+/// [`with_debug_tool`](Metrics::with_debug_tool)
+pub const METRICS_TOOL: &str = "__metrics";
+
+/// Records how often each tool is called, how long calls take, and how many fail
+///
+/// Metrics are opt-in: pass a `Metrics` to [`ServerConfig::with_metrics`], and keep a clone to
+/// read them while the server runs. Every clone shares the same counts.
+///
+/// [`ServerConfig::with_metrics`]: crate::server::ServerConfig::with_metrics
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    tools: Arc<Mutex<BTreeMap<String, ToolMetrics>>>,
+    dump_path: Option<PathBuf>,
+    debug_tool: bool,
+}
+
+/// Metrics for a single tool
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolMetrics {
+    pub calls: u64,
+    pub errors: u64,
+    #[serde(rename = "totalMs", serialize_with = "as_millis")]
+    pub total_duration: Duration,
+    #[serde(rename = "minMs", serialize_with = "as_millis")]
+    pub min_duration: Duration,
+    #[serde(rename = "maxMs", serialize_with = "as_millis")]
+    pub max_duration: Duration,
+}
+
+impl ToolMetrics {
+    /// The fraction of calls that failed, from 0 to 1
+    pub fn error_rate(&self) -> f64 {
+        if self.calls == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.calls as f64
+        }
+    }
+
+    pub fn mean_duration(&self) -> Duration {
+        if self.calls == 0 {
+            Duration::ZERO
+        } else {
+            self.total_duration.div_f64(self.calls as f64)
+        }
+    }
+
+    fn record(&mut self, duration: Duration, success: bool) {
+        self.min_duration = if self.calls == 0 {
+            duration
+        } else {
+            self.min_duration.min(duration)
+        };
+        self.max_duration = self.max_duration.max(duration);
+        self.total_duration += duration;
+        self.calls += 1;
+        if !success {
+            self.errors += 1;
+        }
+    }
+}
+
+fn as_millis<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the metrics to `path` as JSON when the server shuts down
+    pub fn with_dump_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.dump_path = Some(path.into());
+        self
+    }
+
+    pub fn dump_path(&self) -> Option<&Path> {
+        self.dump_path.as_deref()
+    }
+
+    /// Serve a `__metrics` tool that returns the current metrics, for debugging
+    pub fn with_debug_tool(mut self, debug_tool: bool) -> Self {
+        self.debug_tool = debug_tool;
+        self
+    }
+
+    pub fn debug_tool(&self) -> bool {
+        self.debug_tool
+    }
+
+    /// Record one call to `tool`
+    pub fn record(&self, tool: &str, duration: Duration, success: bool) {
+        self.tools
+            .lock()
+            .unwrap()
+            .entry(tool.to_string())
+            .or_default()
+            .record(duration, success);
+    }
+
+    /// The metrics for `tool`, if it has been called
+    pub fn tool(&self, tool: &str) -> Option<ToolMetrics> {
+        self.tools.lock().unwrap().get(tool).copied()
+    }
+
+    /// The metrics for every tool that has been called, by name
+    pub fn snapshot(&self) -> BTreeMap<String, ToolMetrics> {
+        self.tools.lock().unwrap().clone()
+    }
+
+    /// Forget everything recorded so far
+    pub fn reset(&self) {
+        self.tools.lock().unwrap().clear();
+    }
+
+    /// The metrics as a JSON object keyed by tool name, with durations in milliseconds and each
+    /// tool's mean duration and error rate
+    pub fn to_json(&self) -> Value {
+        self.snapshot()
+            .into_iter()
+            .map(|(name, metrics)| {
+                let mut value = serde_json::to_value(metrics).unwrap();
+                value["meanMs"] = (metrics.mean_duration().as_secs_f64() * 1000.0).into();
+                value["errorRate"] = metrics.error_rate().into();
+                (name, value)
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    }
+
+    /// Write the metrics to the [`dump_path`](Self::dump_path), if there is one
+    pub(crate) fn dump(&self) -> Result<()> {
+        if let Some(path) = &self.dump_path {
+            fs::write(path, serde_json::to_string_pretty(&self.to_json())?)?;
+            log::info!("wrote metrics to {}", path.display());
+        }
+        Ok(())
+    }
+}
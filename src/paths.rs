@@ -0,0 +1,374 @@
+//! Checking paths from clients before tools touch the filesystem, expanding `~` in them, and
+//! where servers keep their own files
+
+use crate::Error;
+use anyhow::{Result, anyhow, bail};
+use std::{
+    env,
+    fmt::{self, Display, Formatter},
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+/// The directories a server's tools may read and write, for checking paths the client sends
+///
+/// [`resolve`](Self::resolve) turns a path from the client into an absolute path with every
+/// symlink resolved, and refuses it unless that is inside one of the roots. Checking the resolved
+/// path is what stops `../` and symlinks that lead elsewhere: a path that merely starts with a
+/// root, like `/workspace/../etc/passwd` or `/workspace/link-to-etc/passwd`, is refused.
+///
+/// Paths that don't exist yet, for tools that create files, are allowed if their nearest existing
+/// ancestor is inside a root and the rest of the path has no `..`. A symlink that points nowhere
+/// is refused, since writing through it could create a file anywhere.
+///
+/// ```ignore
+/// let sandbox = Sandbox::new(&state.workspace)?.with_root("/tmp/scratch")?;
+/// let path = sandbox.resolve_from(state.working_directory()?, &self.path)?;
+/// let contents = std::fs::read_to_string(path)?;
+/// ```
+///
+/// A path is only checked when it is resolved, so a tool should use the resolved path straight
+/// away rather than the one the client sent.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    roots: Vec<PathBuf>,
+}
+
+impl Sandbox {
+    /// Allow the directory `root` and everything in it
+    pub fn new(root: impl AsRef<Path>) -> Result<Self> {
+        Self { roots: vec![] }.with_root(root)
+    }
+
+    /// Also allow the directory `root` and everything in it
+    pub fn with_root(mut self, root: impl AsRef<Path>) -> Result<Self> {
+        let root = root.as_ref();
+        let canonical = root
+            .canonicalize()
+            .map_err(|e| anyhow!("could not open {}: {e}", root.display()))?;
+        if !canonical.is_dir() {
+            bail!("{} is not a directory", root.display());
+        }
+        self.roots.push(canonical);
+        Ok(self)
+    }
+
+    /// The allowed directories, canonicalized, in the order they were added
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Resolve `path` relative to the first root, refusing it unless it is inside a root
+    ///
+    /// `~` is expanded to the home directory. The error for a path outside every root is an
+    /// [`Error::Unauthorized`].
+    pub fn resolve(&self, path: impl AsRef<Path>) -> Result<PathBuf> {
+        self.resolve_from(&self.roots[0], path)
+    }
+
+    /// Resolve `path` relative to `base`, like a working directory a tool keeps in its state,
+    /// refusing it unless it is inside a root
+    pub fn resolve_from(&self, base: impl AsRef<Path>, path: impl AsRef<Path>) -> Result<PathBuf> {
+        let path = path.as_ref();
+        let absolute = base.as_ref().join(expand_home(path));
+        let outside = || Error::Unauthorized(format!("{} is outside of {}", path.display(), self));
+
+        let resolved = canonicalize_allowing_missing(&absolute)
+            .map_err(|e| anyhow!("could not resolve {}: {e}", path.display()))?;
+        let Some(resolved) = resolved else {
+            bail!(outside());
+        };
+        if !self.contains_canonical(&resolved) {
+            bail!(outside());
+        }
+        Ok(resolved)
+    }
+
+    /// Whether `path`, which must be absolute, [resolves](Self::resolve) to somewhere inside a
+    /// root
+    pub fn contains(&self, path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref();
+        path.is_absolute()
+            && canonicalize_allowing_missing(path)
+                .ok()
+                .flatten()
+                .is_some_and(|resolved| self.contains_canonical(&resolved))
+    }
+
+    fn contains_canonical(&self, path: &Path) -> bool {
+        self.roots.iter().any(|root| path.starts_with(root))
+    }
+}
+
+impl Display for Sandbox {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (index, root) in self.roots.iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", root.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// `path` with a leading `~` replaced by the home directory, like `~/notes` or, on Windows,
+/// `~\notes`
+///
+/// Paths that don't start with `~`, or that start with another user's home like `~alice`, are
+/// returned as they are, as is every path when there is no home directory. Unlike
+/// `shellexpand::tilde`, this works with either separator on Windows and with paths that aren't
+/// UTF-8.
+pub fn expand_home(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) if rest.as_os_str().is_empty() => home,
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Where `app` keeps data that should survive, like notes or an index it would be slow to rebuild
+///
+/// This is `$XDG_DATA_HOME/app` if that is set, or else `~/.local/share/app` on Linux,
+/// `~/Library/Application Support/app` on macOS, and `%APPDATA%\app` on Windows. The directory
+/// isn't created.
+pub fn data_dir(app: &str) -> Result<PathBuf> {
+    xdg_dir("XDG_DATA_HOME")
+        .or_else(dirs::data_dir)
+        .map(|dir| dir.join(app))
+        .ok_or_else(|| unknown_home("data", app))
+}
+
+/// Where `app` keeps state that is useful between runs but not worth backing up, like sessions
+/// and history
+///
+/// This is `$XDG_STATE_HOME/app` if that is set, or else `~/.local/state/app` on Linux,
+/// `~/Library/Application Support/app` on macOS, and `%LOCALAPPDATA%\app` on Windows. The
+/// directory isn't created.
+///
+/// ```ignore
+/// let session_store = SessionStore::new(Some(paths::state_dir("my-server")?.join("sessions.json")))?;
+/// ```
+pub fn state_dir(app: &str) -> Result<PathBuf> {
+    xdg_dir("XDG_STATE_HOME")
+        .or_else(dirs::state_dir)
+        .or_else(dirs::data_local_dir)
+        .map(|dir| dir.join(app))
+        .ok_or_else(|| unknown_home("state", app))
+}
+
+/// Where `app` keeps files that can be deleted at any time and recreated, like downloads or the
+/// results of slow searches
+///
+/// This is `$XDG_CACHE_HOME/app` if that is set, or else `~/.cache/app` on Linux,
+/// `~/Library/Caches/app` on macOS, and `%LOCALAPPDATA%\app\cache` on Windows, which keeps it
+/// apart from the state directory there. The directory isn't created.
+pub fn cache_dir(app: &str) -> Result<PathBuf> {
+    if let Some(dir) = xdg_dir("XDG_CACHE_HOME") {
+        return Ok(dir.join(app));
+    }
+    let dir = dirs::cache_dir()
+        .map(|dir| dir.join(app))
+        .ok_or_else(|| unknown_home("cache", app))?;
+    Ok(if cfg!(windows) {
+        dir.join("cache")
+    } else {
+        dir
+    })
+}
+
+/// Where `app` reads its configuration from, like the `config.toml` that
+/// [`config::load`](crate::config::load) reads
+///
+/// This is `$XDG_CONFIG_HOME/app` if that is set, or else `~/.config/app` on Linux,
+/// `~/Library/Application Support/app` on macOS, and `%APPDATA%\app` on Windows. The directory
+/// isn't created.
+pub fn config_dir(app: &str) -> Result<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME")
+        .or_else(dirs::config_dir)
+        .map(|dir| dir.join(app))
+        .ok_or_else(|| unknown_home("config", app))
+}
+
+/// The directory named by the XDG variable `var`, if it is set
+///
+/// The XDG variables are used on every platform, so that they can move everything on macOS and
+/// Windows too, but, as the XDG specification says, only if they are absolute.
+fn xdg_dir(var: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+}
+
+fn unknown_home(kind: &str, app: &str) -> anyhow::Error {
+    anyhow!("could not find a {kind} directory for {app}: the home directory is unknown")
+}
+
+/// Canonicalize the longest existing ancestor of `path` and append the rest, or `None` if the
+/// rest can't be trusted to stay where it looks like it goes: it has `..`, or starts with a
+/// symlink that points nowhere
+fn canonicalize_allowing_missing(path: &Path) -> io::Result<Option<PathBuf>> {
+    let mut existing = path;
+    let mut missing = vec![];
+    loop {
+        match existing.canonicalize() {
+            Ok(canonical) => {
+                let mut resolved = canonical;
+                for component in missing.iter().rev() {
+                    resolved.push(component);
+                }
+                return Ok(Some(resolved));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                if existing.symlink_metadata().is_ok() {
+                    // a dangling symlink
+                    return Ok(None);
+                }
+                let Some(parent) = existing.parent() else {
+                    return Err(e);
+                };
+                match existing.components().next_back() {
+                    Some(Component::Normal(name)) => missing.push(name.to_owned()),
+                    Some(Component::CurDir) => {}
+                    _ => return Ok(None),
+                }
+                existing = parent;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn workspace() -> (TempDir, Sandbox) {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("workspace")).unwrap();
+        std::fs::write(dir.path().join("workspace/notes.md"), "notes").unwrap();
+        std::fs::write(dir.path().join("secret"), "secret").unwrap();
+        let sandbox = Sandbox::new(dir.path().join("workspace")).unwrap();
+        (dir, sandbox)
+    }
+
+    fn is_unauthorized(result: Result<PathBuf>) -> bool {
+        result
+            .unwrap_err()
+            .downcast_ref::<Error>()
+            .is_some_and(|e| matches!(e, Error::Unauthorized(_)))
+    }
+
+    #[test]
+    fn paths_inside_the_root() {
+        let (_dir, sandbox) = workspace();
+        let root = &sandbox.roots()[0];
+        assert_eq!(sandbox.resolve("notes.md").unwrap(), root.join("notes.md"));
+        assert_eq!(
+            sandbox.resolve(root.join("./notes.md")).unwrap(),
+            root.join("notes.md")
+        );
+        assert_eq!(sandbox.resolve(".").unwrap(), *root);
+        // files that don't exist yet, for tools that create them
+        assert_eq!(
+            sandbox.resolve("drafts/new.md").unwrap(),
+            root.join("drafts/new.md")
+        );
+        assert!(sandbox.contains(root.join("notes.md")));
+    }
+
+    #[test]
+    fn traversal_is_refused() {
+        let (dir, sandbox) = workspace();
+        assert!(is_unauthorized(sandbox.resolve("../secret")));
+        assert!(is_unauthorized(sandbox.resolve(dir.path().join("secret"))));
+        assert!(is_unauthorized(
+            sandbox.resolve(dir.path().join("workspace/../secret"))
+        ));
+        assert!(is_unauthorized(sandbox.resolve("missing/../../secret")));
+        assert!(!sandbox.contains(dir.path().join("workspace/../secret")));
+        assert!(!sandbox.contains("notes.md"), "relative paths are refused");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_escapes_are_refused() {
+        let (dir, sandbox) = workspace();
+        let workspace = dir.path().join("workspace");
+        std::os::unix::fs::symlink(dir.path(), workspace.join("parent")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("nowhere"), workspace.join("dangling")).unwrap();
+        std::os::unix::fs::symlink(workspace.join("notes.md"), workspace.join("inside")).unwrap();
+
+        assert!(is_unauthorized(sandbox.resolve("parent/secret")));
+        assert!(is_unauthorized(sandbox.resolve("dangling")));
+        assert!(is_unauthorized(sandbox.resolve("dangling/new.md")));
+        assert_eq!(
+            sandbox.resolve("inside").unwrap(),
+            sandbox.roots()[0].join("notes.md")
+        );
+    }
+
+    #[test]
+    fn several_roots() {
+        let (dir, sandbox) = workspace();
+        let scratch = TempDir::new().unwrap();
+        let sandbox = sandbox.with_root(scratch.path()).unwrap();
+        assert!(sandbox.resolve(scratch.path().join("out.txt")).is_ok());
+        assert!(sandbox.resolve_from(scratch.path(), "out.txt").is_ok());
+        assert!(is_unauthorized(sandbox.resolve(dir.path().join("secret"))));
+
+        assert!(Sandbox::new(dir.path().join("secret")).is_err());
+        assert!(Sandbox::new(dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn expand_home_paths() {
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(expand_home("~"), home);
+        assert_eq!(
+            expand_home("~/logs/server.log"),
+            home.join("logs").join("server.log")
+        );
+        assert_eq!(expand_home("~alice/logs"), Path::new("~alice/logs"));
+        assert_eq!(
+            expand_home("logs/~/server.log"),
+            Path::new("logs/~/server.log")
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn expand_home_windows_paths() {
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(
+            expand_home(r"~\logs\server.log"),
+            home.join("logs").join("server.log")
+        );
+        assert_eq!(
+            expand_home(r"~/logs\server.log"),
+            home.join("logs").join("server.log")
+        );
+        assert_eq!(
+            expand_home(r"C:\~\server.log"),
+            Path::new(r"C:\~\server.log")
+        );
+    }
+
+    #[test]
+    fn app_dirs() {
+        let dirs = [
+            data_dir("my-server"),
+            state_dir("my-server"),
+            cache_dir("my-server"),
+            config_dir("my-server"),
+        ]
+        .map(Result::unwrap);
+        for dir in &dirs {
+            assert!(dir.is_absolute());
+            assert!(dir.iter().any(|component| component == "my-server"));
+        }
+        assert_ne!(dirs[1], dirs[2]);
+    }
+}
@@ -0,0 +1,519 @@
+//! Running commands from tools, without a shell in between
+//!
+//! Arguments are passed to the program as they are, so a path or pattern from the client can't
+//! smuggle in another command. Output is captured up to a size limit, the command is killed if it
+//! runs too long or the client cancels the call, and the result converts into a tool's output or
+//! error.
+
+use crate::{
+    context::CancellationToken,
+    types::{Content, ToolOutput},
+};
+use anyhow::{Result, anyhow, bail};
+use std::{
+    ffi::{OsStr, OsString},
+    fmt::{self, Display, Formatter},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, ExitStatus, Stdio},
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, RecvTimeoutError},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long a command may run unless configured otherwise
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How much of each of stdout and stderr is kept unless configured otherwise, one MiB
+const DEFAULT_MAX_OUTPUT: usize = 1024 * 1024;
+
+/// How often a running command is checked for having exited, timed out, or been cancelled
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Run `program` with `args` and the default limits, for the common case
+///
+/// ```ignore
+/// let output = run_command("git", ["log", "--oneline", "-n", "10"])?;
+/// ```
+pub fn run_command<I>(program: impl AsRef<OsStr>, args: I) -> Result<CommandOutput>
+where
+    I: IntoIterator,
+    I::Item: AsRef<OsStr>,
+{
+    RunCommand::new(program).with_args(args).run()
+}
+
+/// A command to run, configured with `with_*` methods and started with [`run`](Self::run)
+///
+/// Commands time out after a minute and keep the first MiB of each of stdout and stderr unless
+/// configured otherwise. Stdin is empty unless given with [`with_stdin`](Self::with_stdin).
+///
+/// ```ignore
+/// impl Tool<State> for Grep {
+///     fn execute_with_context(self, state: &mut State, context: &ToolContext) -> Result<ToolOutput> {
+///         RunCommand::new("rg")
+///             .with_args(["--line-number", "--", &self.pattern])
+///             .with_current_dir(state.working_directory()?)
+///             .with_timeout(Duration::from_secs(10))
+///             .with_cancellation(context.cancellation())
+///             .run()?
+///             .into_tool_output()
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RunCommand {
+    program: OsString,
+    args: Vec<OsString>,
+    current_dir: Option<PathBuf>,
+    env: Vec<(OsString, OsString)>,
+    stdin: Option<Vec<u8>>,
+    timeout: Option<Duration>,
+    max_output: usize,
+    cancellation: Option<CancellationToken>,
+}
+
+impl RunCommand {
+    /// Run `program`, found on the `PATH` if it isn't a path
+    pub fn new(program: impl AsRef<OsStr>) -> Self {
+        Self {
+            program: program.as_ref().to_owned(),
+            args: vec![],
+            current_dir: None,
+            env: vec![],
+            stdin: None,
+            timeout: Some(DEFAULT_TIMEOUT),
+            max_output: DEFAULT_MAX_OUTPUT,
+            cancellation: None,
+        }
+    }
+
+    /// Pass `arg` to the program as a single argument
+    pub fn with_arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    /// Pass each of `args` to the program as a single argument
+    pub fn with_args<I>(mut self, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<OsStr>,
+    {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_owned()));
+        self
+    }
+
+    /// Run in `dir`, like the working directory a tool keeps in its state, rather than the
+    /// server's
+    pub fn with_current_dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.current_dir = Some(dir.as_ref().to_owned());
+        self
+    }
+
+    /// Set the environment variable `key` for the program, which otherwise inherits the
+    /// server's environment
+    pub fn with_env(mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> Self {
+        self.env
+            .push((key.as_ref().to_owned(), value.as_ref().to_owned()));
+        self
+    }
+
+    /// Write `stdin` to the program's standard input
+    pub fn with_stdin(mut self, stdin: impl Into<Vec<u8>>) -> Self {
+        self.stdin = Some(stdin.into());
+        self
+    }
+
+    /// Kill the program if it is still running after `timeout`, instead of after a minute, or
+    /// never if `None`
+    pub fn with_timeout(mut self, timeout: impl Into<Option<Duration>>) -> Self {
+        self.timeout = timeout.into();
+        self
+    }
+
+    /// Keep the first `max_output` bytes of each of stdout and stderr, instead of one MiB
+    ///
+    /// The rest is read and discarded, so a chatty program doesn't block on a full pipe.
+    pub fn with_max_output(mut self, max_output: usize) -> Self {
+        self.max_output = max_output;
+        self
+    }
+
+    /// Kill the program once `cancellation` is set, like the
+    /// [`ToolContext::cancellation`](crate::context::ToolContext::cancellation) of the call
+    /// running it
+    pub fn with_cancellation(mut self, cancellation: &CancellationToken) -> Self {
+        self.cancellation = Some(cancellation.clone());
+        self
+    }
+
+    /// Run the program to completion
+    ///
+    /// Fails if the program can't be started, times out, or is cancelled. A program that runs
+    /// and exits unsuccessfully is not a failure here; see [`CommandOutput::check`].
+    ///
+    /// Output is read until the program and anything it started close stdout and stderr. If
+    /// something it left running in the background holds them open past the timeout or a
+    /// cancellation, the output so far is returned without waiting for the rest.
+    pub fn run(&self) -> Result<CommandOutput> {
+        let name = self.program.to_string_lossy();
+        let mut command = std::process::Command::new(&self.program);
+        command
+            .args(&self.args)
+            .envs(self.env.iter().map(|(key, value)| (key, value)))
+            .stdin(if self.stdin.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+
+        log::debug!("running {name} {:?}", self.args);
+        let mut child = command
+            .spawn()
+            .map_err(|e| anyhow!("could not run {name}: {e}"))?;
+
+        if let (Some(stdin), Some(mut pipe)) = (self.stdin.clone(), child.stdin.take()) {
+            // a program that exits without reading its input closes the pipe, which is fine
+            thread::spawn(move || pipe.write_all(&stdin));
+        }
+        let stdout = capture(child.stdout.take(), self.max_output);
+        let stderr = capture(child.stderr.take(), self.max_output);
+
+        // the output isn't waited for when the program is killed, since anything it started
+        // may still hold the pipes open
+        let start = Instant::now();
+        let status = match self.wait(&mut child, start)? {
+            Ok(status) => status,
+            Err(Stopped::TimedOut(timeout)) => bail!("{name} timed out after {timeout:?}"),
+            Err(Stopped::Cancelled) => bail!("request was cancelled"),
+        };
+        log::debug!("{name} exited with {status} after {:?}", start.elapsed());
+
+        Ok(CommandOutput {
+            status,
+            stdout: self.finish(stdout, start),
+            stderr: self.finish(stderr, start),
+        })
+    }
+
+    /// Wait for `capture` to reach the end of its pipe, or until the timeout or a cancellation,
+    /// returning what it has read
+    fn finish(&self, capture: Capture, start: Instant) -> Captured {
+        while let Err(RecvTimeoutError::Timeout) = capture.finished.recv_timeout(POLL_INTERVAL) {
+            let timed_out = self
+                .timeout
+                .is_some_and(|timeout| start.elapsed() >= timeout);
+            let cancelled = self
+                .cancellation
+                .as_ref()
+                .is_some_and(CancellationToken::is_cancelled);
+            if timed_out || cancelled {
+                log::warn!(
+                    "{} exited but its output is still open, returning what it wrote so far",
+                    self.program.to_string_lossy()
+                );
+                break;
+            }
+        }
+        capture.captured.lock().unwrap().clone()
+    }
+
+    /// Wait for `child` to exit, killing it if it times out or is cancelled
+    fn wait(&self, child: &mut Child, start: Instant) -> io::Result<Result<ExitStatus, Stopped>> {
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(Ok(status));
+            }
+
+            let stopped = match (self.timeout, &self.cancellation) {
+                (Some(timeout), _) if start.elapsed() >= timeout => Stopped::TimedOut(timeout),
+                (_, Some(cancellation)) if cancellation.is_cancelled() => Stopped::Cancelled,
+                _ => {
+                    thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+            };
+            child.kill()?;
+            child.wait()?;
+            return Ok(Err(stopped));
+        }
+    }
+}
+
+/// Why a command was killed before it exited
+enum Stopped {
+    TimedOut(Duration),
+    Cancelled,
+}
+
+/// Output being read from a pipe on its own thread
+struct Capture {
+    /// What has been read so far
+    captured: Arc<Mutex<Captured>>,
+    /// Disconnects once the pipe has been read to its end
+    finished: Receiver<()>,
+}
+
+/// Read all of `pipe` on its own thread, keeping the first `max` bytes
+fn capture<R: Read + Send + 'static>(pipe: Option<R>, max: usize) -> Capture {
+    let captured = Arc::new(Mutex::new(Captured::default()));
+    let (finished, receiver) = mpsc::channel::<()>();
+    let capture = Capture {
+        captured: Arc::clone(&captured),
+        finished: receiver,
+    };
+    let Some(mut pipe) = pipe else {
+        return capture;
+    };
+
+    thread::spawn(move || {
+        let _finished = finished;
+        let mut buf = [0; 8192];
+        loop {
+            let read = match pipe.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    log::warn!("could not read command output: {e}");
+                    break;
+                }
+            };
+            let mut captured = captured.lock().unwrap();
+            let kept = read.min(max - captured.bytes.len());
+            captured.bytes.extend_from_slice(&buf[..kept]);
+            captured.discarded += read - kept;
+        }
+    });
+    capture
+}
+
+/// Everything a command wrote to stdout or stderr, up to the limit
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Captured {
+    bytes: Vec<u8>,
+    discarded: usize,
+}
+
+impl Captured {
+    /// The output, with invalid utf-8 replaced
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.bytes).into_owned()
+    }
+
+    /// The output as it was written
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// How many bytes past the limit were discarded
+    pub fn discarded(&self) -> usize {
+        self.discarded
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+impl Display for Captured {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&String::from_utf8_lossy(&self.bytes))?;
+        if self.discarded > 0 {
+            write!(f, "\n[{} more bytes not shown]", self.discarded)?;
+        }
+        Ok(())
+    }
+}
+
+/// How a command exited and what it wrote, returned from [`RunCommand::run`]
+#[derive(Debug, Clone)]
+pub struct CommandOutput {
+    status: ExitStatus,
+    stdout: Captured,
+    stderr: Captured,
+}
+
+impl CommandOutput {
+    pub fn status(&self) -> ExitStatus {
+        self.status
+    }
+
+    /// Whether the command exited with status zero
+    pub fn success(&self) -> bool {
+        self.status.success()
+    }
+
+    pub fn stdout(&self) -> &Captured {
+        &self.stdout
+    }
+
+    pub fn stderr(&self) -> &Captured {
+        &self.stderr
+    }
+
+    /// `self` if the command succeeded, or an error with its status and stderr
+    pub fn check(self) -> Result<Self> {
+        if !self.success() {
+            bail!("{}", self.failure());
+        }
+        Ok(self)
+    }
+
+    /// The command's stdout as the tool's output, with stderr after it if there is any, or
+    /// the error from [`check`](Self::check) if it failed
+    ///
+    /// A failing command becomes a tool result with `isError`, so the model can see what went
+    /// wrong.
+    pub fn into_tool_output(self) -> Result<ToolOutput> {
+        let output = self.check()?;
+        let mut tool_output = ToolOutput::text(output.stdout.to_string());
+        if !output.stderr.is_empty() {
+            tool_output.push(Content::text(format!("stderr:\n{}", output.stderr)));
+        }
+        Ok(tool_output)
+    }
+
+    /// The status, and stderr or stdout if the command said anything, since some programs
+    /// explain their failures on stdout
+    fn failure(&self) -> String {
+        let status = format!("command failed with {}", self.status);
+        if !self.stderr.is_empty() {
+            format!("{status}\nstderr:\n{}", self.stderr)
+        } else if !self.stdout.is_empty() {
+            format!("{status}\nstdout:\n{}", self.stdout)
+        } else {
+            status
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> RunCommand {
+        RunCommand::new("sh").with_args(["-c", script])
+    }
+
+    #[test]
+    fn output_and_status() {
+        let output = sh("echo out; echo err >&2; exit 3").run().unwrap();
+        assert_eq!(output.status().code(), Some(3));
+        assert!(!output.success());
+        assert_eq!(output.stdout().text(), "out\n");
+        assert_eq!(output.stderr().text(), "err\n");
+    }
+
+    #[test]
+    fn arguments_are_not_interpreted_by_a_shell() {
+        let output = run_command("echo", ["$HOME; rm -rf /"]).unwrap();
+        assert_eq!(output.stdout().text(), "$HOME; rm -rf /\n");
+    }
+
+    #[test]
+    fn stdin() {
+        let output = RunCommand::new("cat").with_stdin("hello").run().unwrap();
+        assert_eq!(output.stdout().text(), "hello");
+
+        // without any, the program reads an empty input rather than waiting
+        let output = RunCommand::new("cat").run().unwrap();
+        assert!(output.stdout().is_empty());
+    }
+
+    #[test]
+    fn output_past_the_limit_is_discarded() {
+        let output = sh("printf 0123456789; printf abc >&2")
+            .with_max_output(4)
+            .run()
+            .unwrap();
+        assert_eq!(output.stdout().bytes(), b"0123");
+        assert_eq!(output.stdout().discarded(), 6);
+        assert_eq!(
+            output.stdout().to_string(),
+            "0123\n[6 more bytes not shown]"
+        );
+        assert_eq!(output.stderr().text(), "abc");
+        assert_eq!(output.stderr().discarded(), 0);
+    }
+
+    #[test]
+    fn timeout() {
+        let start = Instant::now();
+        let error = sh("sleep 10")
+            .with_timeout(Duration::from_millis(100))
+            .run()
+            .unwrap_err();
+        assert!(error.to_string().contains("timed out"), "{error}");
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn cancellation() {
+        let cancellation = CancellationToken::new();
+        let cancel = cancellation.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            cancel.cancel();
+        });
+        let start = Instant::now();
+        let error = sh("sleep 10")
+            .with_cancellation(&cancellation)
+            .run()
+            .unwrap_err();
+        assert_eq!(error.to_string(), "request was cancelled");
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn background_processes_holding_the_output_do_not_outlast_the_timeout() {
+        let start = Instant::now();
+        let output = sh("echo started; sleep 10 &")
+            .with_timeout(Duration::from_millis(300))
+            .run()
+            .unwrap();
+        assert!(output.success());
+        assert_eq!(output.stdout().text(), "started\n");
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn tool_output() {
+        let output = sh("echo out; echo warning >&2").run().unwrap();
+        assert_eq!(
+            output.into_tool_output().unwrap(),
+            ToolOutput::text("out\n").with_content(Content::text("stderr:\nwarning\n"))
+        );
+
+        let error = sh("echo out; echo broken >&2; exit 2")
+            .run()
+            .unwrap()
+            .into_tool_output()
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "command failed with exit status: 2\nstderr:\nbroken\n"
+        );
+
+        // programs that only explain themselves on stdout
+        let error = sh("echo usage; exit 1")
+            .run()
+            .unwrap()
+            .into_tool_output()
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "command failed with exit status: 1\nstdout:\nusage\n"
+        );
+    }
+}
@@ -0,0 +1,204 @@
+//! Several servers in one binary, sharing a state type, of which each run serves one
+
+use crate::{
+    server::{Server, ServerBuilder},
+    traits::{
+        AsPromptsList, AsResourcesList, AsToolsList, CompleteArgument, EnabledTools, GetPrompt,
+        ReadResource, Tool,
+    },
+    types::Info,
+};
+use anyhow::{Result, anyhow, bail};
+use clap::Subcommand;
+use std::{
+    env,
+    fmt::{self, Debug, Formatter},
+};
+
+/// The environment variable that chooses a profile when the command line doesn't
+pub const PROFILE_VAR: &str = "MCP_PROFILE";
+
+type RunProfile<State> = Box<dyn FnOnce(State, Option<Info>, Vec<String>) -> Result<()>>;
+
+/// Servers with their own tools, names, and instructions, built into one binary, configured with
+/// `with_*` methods
+///
+/// Each run serves one profile, chosen by `--profile <NAME>` anywhere on the command line, or else
+/// by the `MCP_PROFILE` environment variable, or else the [default](Self::with_default). The rest
+/// of the command line behaves as in [`ServerBuilder::run`], so a client configured with
+/// `mytool serve --profile git` is served the git tools, and `mytool --profile fs read-file --path
+/// notes.md` runs one fs tool.
+///
+/// ```ignore
+/// Profiles::new(State::new()?)
+///     .with_info(server_info!())
+///     .with_profile("git", |server| {
+///         server
+///             .with_tools::<git::Tools>()
+///             .with_instructions(GIT_INSTRUCTIONS)
+///     })
+///     .with_profile("fs", |server| {
+///         server
+///             .with_tools::<fs::Tools>()
+///             .with_instructions(FS_INSTRUCTIONS)
+///             .with_tool_timeout(Duration::from_secs(10))
+///     })
+///     .with_default("git")
+///     .run()
+/// ```
+pub struct Profiles<State> {
+    state: State,
+    info: Option<Info>,
+    profiles: Vec<(String, RunProfile<State>)>,
+    default: Option<String>,
+}
+
+impl<State: 'static> Profiles<State> {
+    /// Profiles that share `state`, which is given to whichever profile runs
+    pub fn new(state: State) -> Self {
+        Self {
+            state,
+            info: None,
+            profiles: vec![],
+            default: None,
+        }
+    }
+
+    /// Name each profile's server after `info`, usually [`server_info!`](crate::server_info), and
+    /// the profile, like `mytool-git`, unless the profile sets its own with
+    /// [`with_info`](ServerBuilder::with_info)
+    pub fn with_info(mut self, info: Info) -> Self {
+        self.info = Some(info);
+        self
+    }
+
+    /// Add the profile `name`, whose server `configure` sets up from a builder that already has
+    /// the shared state
+    ///
+    /// A profile added with the name of an earlier one replaces it.
+    pub fn with_profile<Tools, Resources, Prompts>(
+        mut self,
+        name: impl Into<String>,
+        configure: impl FnOnce(
+            ServerBuilder<(), (), (), State>,
+        ) -> ServerBuilder<Tools, Resources, Prompts, State>
+        + 'static,
+    ) -> Self
+    where
+        Tools: Debug
+            + Subcommand
+            + AsToolsList
+            + Tool<State>
+            + CompleteArgument<State>
+            + EnabledTools<State>,
+        Resources: AsResourcesList + ReadResource<State>,
+        Prompts: AsPromptsList + GetPrompt<State> + CompleteArgument<State>,
+    {
+        let name = name.into();
+        let run: RunProfile<State> = Box::new(move |state, info, args| {
+            let mut server = Server::builder().with_state(state);
+            if let Some(info) = info {
+                server = server.with_info(info);
+            }
+            configure(server).run_from(args)
+        });
+        self.profiles.retain(|(existing, _)| *existing != name);
+        self.profiles.push((name, run));
+        self
+    }
+
+    /// Run the profile `name` when neither the command line nor `MCP_PROFILE` chooses one
+    ///
+    /// Without a default, a run that doesn't choose fails with a list of the profiles, unless
+    /// there is only one.
+    pub fn with_default(mut self, name: impl Into<String>) -> Self {
+        self.default = Some(name.into());
+        self
+    }
+
+    /// The names of the profiles, in the order they were added
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Run the chosen profile as [`ServerBuilder::run`] does, reading the rest of the command line
+    pub fn run(self) -> Result<()> {
+        self.run_from(env::args())
+    }
+
+    fn run_from(mut self, args: impl IntoIterator<Item = String>) -> Result<()> {
+        let mut args: Vec<String> = args.into_iter().collect();
+        let name = match take_profile_arg(&mut args)? {
+            Some(name) => name,
+            None => match env::var(PROFILE_VAR) {
+                Ok(name) if !name.is_empty() => name,
+                _ => match (&self.default, &*self.profiles) {
+                    (Some(default), _) => default.clone(),
+                    (None, [(only, _)]) => only.clone(),
+                    (None, _) => bail!(
+                        "choose a profile with --profile <NAME> or {PROFILE_VAR}: {}",
+                        self.list()
+                    ),
+                },
+            },
+        };
+
+        let index = self
+            .profiles
+            .iter()
+            .position(|(existing, _)| *existing == name)
+            .ok_or_else(|| {
+                anyhow!(
+                    "there is no profile {name:?}; choose one of {}",
+                    self.list()
+                )
+            })?;
+        let (name, run) = self.profiles.swap_remove(index);
+        let info = self.info.map(|info| Info {
+            name: format!("{}-{name}", info.name).into(),
+            version: info.version,
+        });
+        run(self.state, info, args)
+    }
+
+    fn list(&self) -> String {
+        self.names().collect::<Vec<_>>().join(", ")
+    }
+}
+
+/// Remove `--profile <NAME>` or `--profile=<NAME>` from `args`, returning the name
+fn take_profile_arg(args: &mut Vec<String>) -> Result<Option<String>> {
+    let Some(index) = args
+        .iter()
+        .skip(1)
+        .position(|arg| arg == "--profile" || arg.starts_with("--profile="))
+        .map(|index| index + 1)
+    else {
+        return Ok(None);
+    };
+
+    let arg = args.remove(index);
+    match arg.strip_prefix("--profile=") {
+        Some(name) => Ok(Some(name.to_string())),
+        None if index < args.len() => Ok(Some(args.remove(index))),
+        None => bail!("--profile needs the name of a profile"),
+    }
+}
+
+impl<State: Debug> Debug for Profiles<State> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Profiles")
+            .field("state", &self.state)
+            .field("info", &self.info)
+            .field(
+                "profiles",
+                &self
+                    .profiles
+                    .iter()
+                    .map(|(name, _)| name)
+                    .collect::<Vec<_>>(),
+            )
+            .field("default", &self.default)
+            .finish()
+    }
+}
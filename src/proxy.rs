@@ -0,0 +1,240 @@
+//! Serving the tools of other MCP servers alongside your own
+//!
+//! A [`Downstream`] is a connection to another server, over its stdin and stdout or HTTP+SSE.
+//! Registering it adds each of its tools to a [`ToolRegistry`] under a prefix, and calls to them
+//! are forwarded to the server that provides them, so one server can gather several behind it.
+
+use crate::{
+    client::Client,
+    registry::{ToolRegistry, WeakToolRegistry},
+    types::{Info, ToolOutput, ToolSchema},
+};
+use anyhow::Result;
+use serde_json::Value;
+use std::{
+    borrow::Cow,
+    fmt::{self, Debug, Formatter},
+    process::Command,
+    sync::{Arc, Mutex},
+};
+
+/// Another MCP server whose tools are served under a prefix
+///
+/// ```ignore
+/// let registry = ToolRegistry::new();
+/// Downstream::spawn("git", Command::new("mcp-git").arg("serve"))?.register(&registry);
+/// Downstream::connect("search", "http://localhost:8080/sse")?.register(&registry);
+///
+/// Server::builder()
+///     .with_state(State::default())
+///     .with_tools::<NoTools>()
+///     .with_tool_registry(registry)
+///     .run()
+/// ```
+///
+/// The server's `status` tool is then served as `git_status`. Arguments are passed along
+/// unchecked, for the server that provides the tool to validate, and its errors are returned as
+/// they are.
+///
+/// There is one connection to each server, and a call waits for its response before the next is
+/// sent, so calls to one server's tools are made one at a time. A slow tool holds up the rest, as
+/// it would if it were one of this server's own.
+///
+/// The server's messages are only read during a call, so its
+/// `notifications/tools/list_changed` is noticed once one of its tools is next called. Its tools
+/// are then listed again and the registry updated. Call [`refresh`](Self::refresh) to pick up
+/// changes sooner. Other notifications are logged and dropped.
+pub struct Downstream {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    prefix: String,
+    info: Option<Info>,
+    client: Mutex<Client>,
+    /// The tools as last listed, under their own names
+    tools: Mutex<Vec<ToolSchema>>,
+}
+
+impl Downstream {
+    /// Start `command` and talk to it over its stdin and stdout, serving its tools as
+    /// `{prefix}_{name}`
+    ///
+    /// Its stderr is inherited, so its logs appear with this server's. It is asked to shut down
+    /// when the last of its tools is dropped, and killed if it hasn't after two seconds.
+    pub fn spawn(prefix: impl Into<String>, command: &mut Command) -> Result<Self> {
+        Self::new(prefix.into(), Client::spawn(command)?.with_info(info()))
+    }
+
+    /// Connect to the HTTP+SSE server whose event stream is at `url`, like
+    /// `http://localhost:8080/sse`, serving its tools as `{prefix}_{name}`
+    pub fn connect(prefix: impl Into<String>, url: &str) -> Result<Self> {
+        Self::new(prefix.into(), Client::connect(url)?.with_info(info()))
+    }
+
+    fn new(prefix: String, mut client: Client) -> Result<Self> {
+        let initialized: Value = client.initialize_as()?;
+        let info = serde_json::from_value(initialized["serverInfo"].clone()).ok();
+        let tools = client.list_tools()?;
+        log::debug!(
+            "{} tools from {}",
+            tools.len(),
+            info.as_ref()
+                .map_or(prefix.as_str(), |info: &Info| &info.name)
+        );
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                prefix,
+                info,
+                client: Mutex::new(client),
+                tools: Mutex::new(tools),
+            }),
+        })
+    }
+
+    /// The server's name and version, as it gave them when connecting
+    pub fn info(&self) -> Option<&Info> {
+        self.inner.info.as_ref()
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.inner.prefix
+    }
+
+    /// The server's tools as they were last listed, under their own names
+    pub fn tools(&self) -> Vec<ToolSchema> {
+        self.inner.tools.lock().unwrap().clone()
+    }
+
+    /// The name a tool of this server is served as: `{prefix}_{name}`, or just its name with an
+    /// empty prefix
+    pub fn prefixed(&self, name: &str) -> String {
+        self.inner.prefixed(name)
+    }
+
+    /// Add each of the server's tools to `registry` under its prefixed name, replacing any tool
+    /// already registered with that name
+    pub fn register<State: 'static>(&self, registry: &ToolRegistry<State>) {
+        let tools = self.tools();
+        self.inner.register(&tools, registry);
+    }
+
+    /// List the server's tools again, updating `registry` with any that were added, changed, or
+    /// removed since they were last listed
+    pub fn refresh<State: 'static>(&self, registry: &ToolRegistry<State>) -> Result<()> {
+        self.inner.refresh(registry)
+    }
+}
+
+impl Inner {
+    fn prefixed(&self, name: &str) -> String {
+        if self.prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}_{name}", self.prefix)
+        }
+    }
+
+    fn register<State: 'static>(
+        self: &Arc<Self>,
+        tools: &[ToolSchema],
+        registry: &ToolRegistry<State>,
+    ) {
+        for tool in tools {
+            let inner = Arc::clone(self);
+            // the registry owns this call, so it is held weakly to let both be dropped
+            let weak = registry.downgrade();
+            let name = tool.name.clone();
+            let mut schema = tool.clone();
+            schema.name = self.prefixed(&tool.name);
+            registry.register_fn(schema, move |arguments, _, _| {
+                inner.call(&name, arguments, &weak)
+            });
+        }
+    }
+
+    fn call<State: 'static>(
+        self: &Arc<Self>,
+        name: &str,
+        arguments: Value,
+        registry: &WeakToolRegistry<State>,
+    ) -> Result<ToolOutput> {
+        let mut client = self.client.lock().unwrap();
+        let output = client.call_tool(name, arguments);
+        let mut list_changed = false;
+        for notification in client.take_notifications() {
+            if notification.method == "notifications/tools/list_changed" {
+                list_changed = true;
+            } else {
+                log::debug!("{name} sent {}, ignoring", notification.method);
+            }
+        }
+        drop(client);
+
+        if list_changed && let Some(registry) = registry.upgrade() {
+            log::debug!("the tools of {} changed, listing them again", self.prefix);
+            if let Err(e) = self.refresh(&registry) {
+                log::error!("could not list the tools of {} again: {e}", self.prefix);
+            }
+        }
+        output
+    }
+
+    fn refresh<State: 'static>(self: &Arc<Self>, registry: &ToolRegistry<State>) -> Result<()> {
+        let tools = {
+            let mut client = self.client.lock().unwrap();
+            let tools = client.list_tools()?;
+            // the list is already fresh, whatever else changed while listing it
+            client.take_notifications();
+            tools
+        };
+
+        let mut current = self.tools.lock().unwrap();
+        for removed in current
+            .iter()
+            .filter(|old| !tools.iter().any(|tool| tool.name == old.name))
+        {
+            registry.unregister(&self.prefixed(&removed.name));
+        }
+        let changed: Vec<_> = tools
+            .iter()
+            // schemas are compared as they were sent, since they don't implement PartialEq
+            .filter(|tool| {
+                let sent = serde_json::to_value(tool).ok();
+                !current
+                    .iter()
+                    .any(|old| old.name == tool.name && serde_json::to_value(old).ok() == sent)
+            })
+            .cloned()
+            .collect();
+        self.register(&changed, registry);
+        *current = tools;
+        Ok(())
+    }
+}
+
+/// How the proxy identifies itself to downstream servers
+fn info() -> Info {
+    Info {
+        name: Cow::Borrowed("mcplease-proxy"),
+        version: Cow::Borrowed(env!("CARGO_PKG_VERSION")),
+    }
+}
+
+impl Debug for Downstream {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Downstream")
+            .field("prefix", &self.inner.prefix)
+            .field("info", &self.inner.info)
+            .field(
+                "tools",
+                &self
+                    .tools()
+                    .iter()
+                    .map(|tool| tool.name.clone())
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
@@ -0,0 +1,299 @@
+//! Limiting how often tools can be called, for servers that wrap something expensive
+
+use crate::Error;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// The JSON-RPC error code of a call refused because of a [`RateLimit`]
+pub const RATE_LIMITED: i32 = -32003;
+
+/// How often calls are allowed: `calls` every `per`, with bursts of up to `burst` calls at once
+///
+/// This is a token bucket. It starts full with `burst` tokens, each call takes one, and tokens
+/// come back at a steady `calls / per`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    calls: u32,
+    per: Duration,
+    burst: u32,
+}
+
+impl RateLimit {
+    /// Allow `calls` calls every `per`, all at once if they come together
+    ///
+    /// # Panics
+    ///
+    /// If `calls` or `per` is zero
+    #[track_caller]
+    pub fn new(calls: u32, per: Duration) -> Self {
+        assert!(calls > 0, "a rate limit must allow at least one call");
+        assert!(
+            !per.is_zero(),
+            "a rate limit must be over a nonzero duration"
+        );
+        Self {
+            calls,
+            per,
+            burst: calls,
+        }
+    }
+
+    #[track_caller]
+    pub fn per_second(calls: u32) -> Self {
+        Self::new(calls, Duration::from_secs(1))
+    }
+
+    #[track_caller]
+    pub fn per_minute(calls: u32) -> Self {
+        Self::new(calls, Duration::from_secs(60))
+    }
+
+    /// Allow at most `burst` calls at once, instead of all of `calls`
+    ///
+    /// # Panics
+    ///
+    /// If `burst` is zero
+    #[track_caller]
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        assert!(
+            burst > 0,
+            "a rate limit must allow bursts of at least one call"
+        );
+        self.burst = burst;
+        self
+    }
+
+    /// Tokens regained per second
+    fn rate(&self) -> f64 {
+        f64::from(self.calls) / self.per.as_secs_f64()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(limit: &RateLimit, now: Instant) -> Self {
+        Self {
+            tokens: f64::from(limit.burst),
+            updated: now,
+        }
+    }
+
+    /// How long until a token is available, or zero if one is available now
+    fn wait(&mut self, limit: &RateLimit, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.rate()).min(f64::from(limit.burst));
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / limit.rate())
+        }
+    }
+}
+
+/// The limits configured with [`ServerConfig::with_rate_limit`] and
+/// [`ServerConfig::with_rate_limit_for`], and how much of each is used
+///
+/// Clones share their buckets, so a limit holds across every connection a server serves.
+///
+/// [`ServerConfig::with_rate_limit`]: crate::server::ServerConfig::with_rate_limit
+/// [`ServerConfig::with_rate_limit_for`]: crate::server::ServerConfig::with_rate_limit_for
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RateLimiter {
+    global: Option<RateLimit>,
+    tools: HashMap<String, RateLimit>,
+    /// Keyed by tool name, with `None` for the global bucket
+    buckets: Arc<Mutex<HashMap<Option<String>, Bucket>>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn set_global(&mut self, limit: RateLimit) {
+        self.global = Some(limit);
+        self.buckets.lock().unwrap().remove(&None);
+    }
+
+    pub(crate) fn set_tool(&mut self, tool: String, limit: RateLimit) {
+        self.buckets.lock().unwrap().remove(&Some(tool.clone()));
+        self.tools.insert(tool, limit);
+    }
+
+    /// Take a token for a call to `tool` from its bucket and the global one, or return a
+    /// [`RATE_LIMITED`] error saying when to retry if either is empty
+    ///
+    /// A refused call takes no tokens, so it doesn't count against either limit.
+    pub(crate) fn acquire(&self, tool: &str) -> Result<(), Error> {
+        let limits = [
+            self.global.map(|limit| (None, limit)),
+            self.tools
+                .get(tool)
+                .map(|limit| (Some(tool.to_string()), *limit)),
+        ];
+        if limits.iter().all(Option::is_none) {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let mut retry_after = Duration::ZERO;
+        for (key, limit) in limits.iter().flatten() {
+            let bucket = buckets
+                .entry(key.clone())
+                .or_insert_with(|| Bucket::full(limit, now));
+            retry_after = retry_after.max(bucket.wait(limit, now));
+        }
+
+        if !retry_after.is_zero() {
+            let retry_after_ms = (retry_after.as_secs_f64() * 1000.0).ceil() as u64;
+            return Err(Error::custom(
+                RATE_LIMITED,
+                format!(
+                    "{tool} is rate limited, retry after {:.1}s",
+                    retry_after.as_secs_f64()
+                ),
+                serde_json::json!({ "retryAfterMs": retry_after_ms }),
+            ));
+        }
+
+        for (key, _) in limits.iter().flatten() {
+            if let Some(bucket) = buckets.get_mut(key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        registry::{NoTools, ToolRegistry},
+        server::ServerConfig,
+        testing::TestClient,
+        types::{Info, ToolOutput, ToolSchema},
+    };
+    use serde_json::json;
+
+    #[test]
+    fn buckets_refill_at_the_rate() {
+        let limit = RateLimit::new(2, Duration::from_secs(1)).with_burst(2);
+        let start = Instant::now();
+        let mut bucket = Bucket::full(&limit, start);
+        assert_eq!(bucket.wait(&limit, start), Duration::ZERO);
+        bucket.tokens = 0.0;
+        assert_eq!(bucket.wait(&limit, start), Duration::from_millis(500));
+        assert_eq!(
+            bucket.wait(&limit, start + Duration::from_millis(250)),
+            Duration::from_millis(250)
+        );
+        assert_eq!(
+            bucket.wait(&limit, start + Duration::from_millis(500)),
+            Duration::ZERO
+        );
+        // never more than the burst
+        bucket.wait(&limit, start + Duration::from_secs(60));
+        assert_eq!(bucket.tokens, 2.0);
+    }
+
+    #[test]
+    fn tools_without_limits_are_not_limited() {
+        let limiter = RateLimiter::default();
+        for _ in 0..100 {
+            limiter.acquire("search").unwrap();
+        }
+    }
+
+    #[test]
+    fn per_tool_limits() {
+        let mut limiter = RateLimiter::default();
+        limiter.set_tool("search".into(), RateLimit::per_minute(2));
+        limiter.acquire("search").unwrap();
+        limiter.acquire("search").unwrap();
+        limiter.acquire("other").unwrap();
+
+        let error = limiter.acquire("search").unwrap_err();
+        assert_eq!(error.code(), RATE_LIMITED);
+        assert!(
+            error
+                .message()
+                .starts_with("search is rate limited, retry after")
+        );
+        let retry_after_ms = error.data().unwrap()["retryAfterMs"].as_u64().unwrap();
+        assert!(retry_after_ms > 29_000 && retry_after_ms <= 30_000);
+
+        // clones share their buckets
+        assert!(limiter.clone().acquire("search").is_err());
+    }
+
+    #[test]
+    fn calls_need_both_limits() {
+        let mut limiter = RateLimiter::default();
+        limiter.set_global(RateLimit::per_minute(2));
+        limiter.set_tool("search".into(), RateLimit::per_minute(1));
+        limiter.acquire("search").unwrap();
+        // refused by the tool's limit, so it takes nothing from the global one
+        assert!(limiter.acquire("search").is_err());
+        limiter.acquire("other").unwrap();
+        assert!(limiter.acquire("other").is_err());
+    }
+
+    #[test]
+    fn bursts() {
+        let mut limiter = RateLimiter::default();
+        limiter.set_global(RateLimit::per_second(100).with_burst(1));
+        limiter.acquire("search").unwrap();
+        let error = limiter.acquire("search").unwrap_err();
+        assert!(error.data().unwrap()["retryAfterMs"].as_u64().unwrap() <= 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one call")]
+    fn limits_allow_calls() {
+        RateLimit::per_second(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "nonzero duration")]
+    fn limits_take_time() {
+        RateLimit::new(1, Duration::ZERO);
+    }
+
+    #[test]
+    fn limited_calls_are_refused() {
+        let registry = ToolRegistry::new();
+        registry.register_fn(
+            ToolSchema {
+                name: "search".into(),
+                description: None,
+                input_schema: serde_json::from_value(json!({ "type": "object" })).unwrap(),
+                output_schema: None,
+                aliases: vec![],
+                meta: None,
+            },
+            |_, _, _| Ok(ToolOutput::text("found")),
+        );
+        let config = ServerConfig::new(Info {
+            name: "test".into(),
+            version: "0.0.0".into(),
+        })
+        .with_rate_limit_for("search", RateLimit::per_minute(1));
+        let mut client = TestClient::<(), NoTools>::new(())
+            .with_config(config)
+            .with_tool_registry(registry);
+        client.initialize().unwrap();
+
+        client.call_tool("search", json!({})).unwrap();
+        let response = client.request("tools/call", json!({ "name": "search" }));
+        let error = response.error.expect("the second call to be refused");
+        assert_eq!(error.code, RATE_LIMITED);
+        assert!(error.data.unwrap()["retryAfterMs"].as_u64().is_some());
+    }
+}
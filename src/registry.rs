@@ -0,0 +1,402 @@
+//! Tools that are added and removed while the server is running
+
+use crate::{
+    context::{Outbound, ToolContext},
+    traits::{AsToolSchema, AsToolsList, CompleteArgument, EnabledTools, StructuredTool, Tool},
+    types::{McpNotification, ToolOutput, ToolSchema},
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fmt::{self, Debug, Formatter},
+    sync::{Arc, Mutex, Weak},
+};
+
+type CallFn<State> = fn(Value, &mut State, &ToolContext) -> Result<ToolOutput>;
+type SharedCallFn<State> =
+    Arc<dyn Fn(Value, &mut State, &ToolContext) -> Result<ToolOutput> + Send + Sync>;
+type CompleteFn<State> = fn(&str, &str, &mut State) -> Result<Vec<String>>;
+type EnabledFn<State> = fn(&State) -> bool;
+
+/// How a registered tool is run: a tool type's [`call`], or a function given to
+/// [`ToolRegistry::register_fn`]
+enum Call<State> {
+    Tool(CallFn<State>),
+    Fn(SharedCallFn<State>),
+}
+
+impl<State> Clone for Call<State> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Tool(call) => Self::Tool(*call),
+            Self::Fn(call) => Self::Fn(Arc::clone(call)),
+        }
+    }
+}
+
+struct RegisteredTool<State> {
+    schema: ToolSchema,
+    call: Call<State>,
+    complete: CompleteFn<State>,
+    enabled: EnabledFn<State>,
+}
+
+struct Registry<State> {
+    tools: Vec<RegisteredTool<State>>,
+    listeners: Vec<Outbound>,
+}
+
+/// A set of tools that can change at runtime, served alongside the static `Tools` enum
+///
+/// Registries are cheap to clone and every clone shares the same tools, so a server can keep one
+/// in its state and register or unregister tools from inside a tool call. Each change sends
+/// `notifications/tools/list_changed` to connected clients so they fetch `tools/list` again.
+///
+/// Registered tools take precedence over static tools with the same name. They are only
+/// available over MCP, not as command line subcommands.
+pub struct ToolRegistry<State> {
+    inner: Arc<Mutex<Registry<State>>>,
+}
+
+impl<State> ToolRegistry<State> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `T` to the registry, replacing any registered tool with the same name
+    pub fn register<T>(&self)
+    where
+        T: Tool<State> + AsToolSchema + Debug,
+    {
+        self.insert(RegisteredTool {
+            schema: ToolSchema::for_tool::<T, State>(),
+            call: Call::Tool(call::<State, T>),
+            complete: T::complete,
+            enabled: T::enabled,
+        });
+    }
+
+    /// Add a tool described by `schema` that runs `call` with its arguments, for tools that
+    /// aren't Rust types, like those of another server. It replaces any registered tool with the
+    /// same name.
+    ///
+    /// Arguments are checked against `schema` before `call` sees them, unless its input schema is
+    /// [`InputSchema::Raw`](crate::types::InputSchema::Raw).
+    pub fn register_fn(
+        &self,
+        schema: ToolSchema,
+        call: impl Fn(Value, &mut State, &ToolContext) -> Result<ToolOutput> + Send + Sync + 'static,
+    ) {
+        self.insert(RegisteredTool {
+            schema,
+            call: Call::Fn(Arc::new(call)),
+            complete: |_, _, _| Ok(vec![]),
+            enabled: |_| true,
+        });
+    }
+
+    fn insert(&self, tool: RegisteredTool<State>) {
+        log::debug!("registering tool {}", tool.schema.name);
+        let mut registry = self.inner.lock().unwrap();
+        registry
+            .tools
+            .retain(|existing| existing.schema.name != tool.schema.name);
+        registry.tools.push(tool);
+        registry.notify();
+    }
+
+    /// Remove the tool named `name`, returning whether it was registered
+    pub fn unregister(&self, name: &str) -> bool {
+        let mut registry = self.inner.lock().unwrap();
+        let len = registry.tools.len();
+        registry.tools.retain(|tool| tool.schema.name != name);
+        let removed = registry.tools.len() != len;
+        if removed {
+            log::debug!("unregistered tool {name}");
+            registry.notify();
+        }
+        removed
+    }
+
+    pub(crate) fn downgrade(&self) -> WeakToolRegistry<State> {
+        WeakToolRegistry {
+            inner: Arc::downgrade(&self.inner),
+        }
+    }
+
+    /// Whether a tool named `name` is registered
+    pub fn contains(&self, name: &str) -> bool {
+        self.schema(name).is_some()
+    }
+
+    /// The names of the registered tools, in registration order
+    pub fn names(&self) -> Vec<String> {
+        self.inner
+            .lock()
+            .unwrap()
+            .tools
+            .iter()
+            .map(|tool| tool.schema.name.clone())
+            .collect()
+    }
+
+    /// The schemas of the registered tools that are [enabled](Tool::enabled) for `state`
+    pub(crate) fn tools_list(&self, state: &State) -> Vec<ToolSchema> {
+        self.inner
+            .lock()
+            .unwrap()
+            .tools
+            .iter()
+            .filter(|tool| (tool.enabled)(state))
+            .map(|tool| tool.schema.clone())
+            .collect()
+    }
+
+    /// Whether the registered tool named `name` is [enabled](Tool::enabled), or `None` if there
+    /// is no such tool
+    pub(crate) fn enabled(&self, name: &str, state: &State) -> Option<bool> {
+        let enabled = self
+            .inner
+            .lock()
+            .unwrap()
+            .tools
+            .iter()
+            .find(|tool| tool.schema.name == name)?
+            .enabled;
+        Some(enabled(state))
+    }
+
+    pub(crate) fn schema(&self, name: &str) -> Option<ToolSchema> {
+        self.inner
+            .lock()
+            .unwrap()
+            .tools
+            .iter()
+            .find(|tool| tool.schema.answers_to(name))
+            .map(|tool| tool.schema.clone())
+    }
+
+    /// Call the registered tool named `name`, or `None` if there is no such tool
+    pub(crate) fn call(
+        &self,
+        name: &str,
+        arguments: Value,
+        state: &mut State,
+        context: &ToolContext,
+    ) -> Option<Result<ToolOutput>> {
+        // the lock is released before calling, so that tools can change the registry
+        let call = self
+            .inner
+            .lock()
+            .unwrap()
+            .tools
+            .iter()
+            .find(|tool| tool.schema.name == name)?
+            .call
+            .clone();
+        Some(match call {
+            Call::Tool(call) => call(arguments, state, context),
+            Call::Fn(call) => call(arguments, state, context),
+        })
+    }
+
+    /// Complete `argument` of the registered tool named `name`, or `None` if there is no such tool
+    pub(crate) fn complete(
+        &self,
+        name: &str,
+        argument: &str,
+        value: &str,
+        state: &mut State,
+    ) -> Option<Result<Vec<String>>> {
+        let complete = self
+            .inner
+            .lock()
+            .unwrap()
+            .tools
+            .iter()
+            .find(|tool| tool.schema.name == name)?
+            .complete;
+        Some(complete(argument, value, state))
+    }
+
+    /// Send `notifications/tools/list_changed` through `outbound` whenever the registry changes
+    pub(crate) fn subscribe(&self, outbound: Outbound) {
+        self.inner.lock().unwrap().listeners.push(outbound);
+    }
+}
+
+impl<State> Registry<State> {
+    fn notify(&mut self) {
+        let notification =
+            McpNotification::new("notifications/tools/list_changed", serde_json::json!({}));
+        // a listener that can't be reached has disconnected
+        self.listeners
+            .retain(|outbound| outbound.send(&notification).is_ok());
+    }
+}
+
+fn call<State, T>(arguments: Value, state: &mut State, context: &ToolContext) -> Result<ToolOutput>
+where
+    T: Tool<State> + Debug,
+{
+    let arguments = match arguments {
+        Value::Null => Value::Object(Default::default()),
+        arguments => arguments,
+    };
+    let tool: T = serde_json::from_value(arguments)?;
+    log::info!("{tool:?}");
+    tool.execute_with_context(state, context)
+}
+
+impl<State> Default for ToolRegistry<State> {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Registry {
+                tools: vec![],
+                listeners: vec![],
+            })),
+        }
+    }
+}
+
+/// A [`ToolRegistry`] that doesn't keep its tools alive, for a registered tool that changes the
+/// registry it is in
+pub(crate) struct WeakToolRegistry<State> {
+    inner: Weak<Mutex<Registry<State>>>,
+}
+
+impl<State> WeakToolRegistry<State> {
+    pub(crate) fn upgrade(&self) -> Option<ToolRegistry<State>> {
+        self.inner.upgrade().map(|inner| ToolRegistry { inner })
+    }
+}
+
+impl<State> Clone for ToolRegistry<State> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<State> Debug for ToolRegistry<State> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ToolRegistry")
+            .field("tools", &self.names())
+            .finish()
+    }
+}
+
+/// A `Tools` type without any tools, for servers whose tools are all in a [`ToolRegistry`], like
+/// a [proxy](crate::proxy) for other servers
+///
+/// ```ignore
+/// Server::builder()
+///     .with_state(())
+///     .with_tools::<NoTools>()
+///     .with_tool_registry(registry)
+///     .run()
+/// ```
+#[derive(Debug, Clone, Copy, clap::Subcommand, Serialize, Deserialize)]
+pub enum NoTools {}
+
+// implemented through `StructuredTool`, since its blanket `Tool` implementation would conflict
+impl<State> StructuredTool<State> for NoTools {
+    type Output = ();
+
+    fn call(self, _state: &mut State, _context: &ToolContext) -> Result<()> {
+        match self {}
+    }
+}
+
+impl AsToolsList for NoTools {
+    fn tools_list() -> Vec<ToolSchema> {
+        vec![]
+    }
+}
+
+impl<State> CompleteArgument<State> for NoTools {
+    fn complete_argument(
+        _name: &str,
+        _argument: &str,
+        _value: &str,
+        _state: &mut State,
+    ) -> Option<Result<Vec<String>>> {
+        None
+    }
+}
+
+impl<State> EnabledTools<State> for NoTools {
+    fn tool_enabled(_name: &str, _state: &State) -> Option<bool> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::TestClient, types::InputSchema};
+    use serde_json::json;
+
+    fn count() -> ToolSchema {
+        ToolSchema {
+            name: "count".to_string(),
+            description: Some("Add to the count".to_string()),
+            input_schema: InputSchema::Raw(json!({
+                "type": "object",
+                "properties": { "by": { "type": "integer" } },
+            })),
+            output_schema: None,
+            aliases: vec![],
+            meta: None,
+        }
+    }
+
+    #[test]
+    fn register_fn() {
+        let registry = ToolRegistry::<u32>::new();
+        registry.register_fn(count(), |arguments, count, _| {
+            *count += arguments["by"].as_u64().unwrap_or(1) as u32;
+            Ok(ToolOutput::text(count.to_string()))
+        });
+
+        let mut client = TestClient::<u32, NoTools>::new(0).with_tool_registry(registry);
+        client.initialize().unwrap();
+        let tools = client.list_tools().unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, "count");
+        assert_eq!(
+            serde_json::to_value(&tools[0]).unwrap()["inputSchema"]["properties"]["by"]["type"],
+            "integer"
+        );
+
+        let output = client.call_tool("count", json!({ "by": 2 })).unwrap();
+        assert_eq!(output.to_string(), "2");
+        client.call_tool("count", json!({})).unwrap();
+        assert_eq!(*client.state(), 3);
+    }
+
+    #[test]
+    fn registering_and_removing_tools_notifies_clients() {
+        let registry = ToolRegistry::<u32>::new();
+        let mut client = TestClient::<u32, NoTools>::new(0).with_tool_registry(registry.clone());
+        client.initialize().unwrap();
+        client.take_sent();
+
+        registry.register_fn(count(), |_, _, _| Ok(ToolOutput::text("counted")));
+        // registering again replaces the tool
+        registry.register_fn(count(), |_, _, _| Ok(ToolOutput::text("counted again")));
+        assert_eq!(client.list_tools().unwrap().len(), 1);
+        assert_eq!(
+            client.call_tool("count", json!({})).unwrap().to_string(),
+            "counted again"
+        );
+        let sent = client.take_sent();
+        assert_eq!(sent[0]["method"], "notifications/tools/list_changed");
+
+        assert!(registry.unregister("count"));
+        assert!(!registry.unregister("count"));
+        assert!(client.list_tools().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,853 @@
+//! Server-wide configuration shared by every transport, and the builder that runs a server
+
+use crate::{
+    auth::Authorizer,
+    cache::ResultCache,
+    extensions::Extensions,
+    fs::FsResourceProvider,
+    metrics::Metrics,
+    rate_limit::{RateLimit, RateLimiter},
+    registry::ToolRegistry,
+    traits::{
+        AsPromptsList, AsResourcesList, AsToolsList, CompleteArgument, EnabledTools, GetPrompt,
+        ReadResource, ServerState, Tool,
+    },
+    transport::{Serving, Transport},
+    types::{Capabilities, Health, Info, PromptTemplate, ProtocolVersion},
+};
+use anyhow::{Result, bail};
+use clap::Subcommand;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt::{self, Debug, Formatter},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+/// Everything about a server that isn't its tools, resources, prompts, or state
+#[derive(Debug, Clone, fieldwork::Fieldwork)]
+pub struct ServerConfig {
+    /// The name and version reported to clients, usually from [`server_info!`](crate::server_info)
+    #[fieldwork(get, with)]
+    info: Info,
+
+    /// Guidance for the model, sent with the `initialize` response
+    instructions: Option<Cow<'static, str>>,
+
+    /// The protocol versions this server will agree to, from oldest to newest
+    ///
+    /// A client asking for a version in this list gets it. Any other client is offered the
+    /// newest version listed, and may disconnect if it doesn't support it.
+    #[fieldwork(get, with)]
+    protocol_versions: Vec<ProtocolVersion>,
+
+    /// The capabilities sent with the `initialize` response, if not [detected](Capabilities::detect)
+    capabilities: Option<Capabilities>,
+
+    /// Where tool calls are recorded, if anywhere
+    metrics: Option<Metrics>,
+
+    /// How long any tool may run before its call is answered with a timeout error
+    tool_timeout: Option<Duration>,
+
+    /// Timeouts for particular tools, overriding `tool_timeout`
+    tool_timeouts: HashMap<String, Duration>,
+
+    /// How often tools may be called, together and individually
+    rate_limiter: RateLimiter,
+
+    /// The results of tools whose results are reused
+    result_cache: ResultCache,
+
+    /// Decides whether each tool call may go ahead
+    authorizer: Option<SharedAuthorizer>,
+
+    /// Where every message of a [`Transport`] session is written, if anywhere
+    recording: Option<PathBuf>,
+
+    /// Origins besides local ones whose pages may connect over HTTP+SSE
+    allowed_origins: Vec<String>,
+
+    /// Served alongside the `Prompts` type, taking precedence over prompts with the same name
+    prompt_templates: Vec<PromptTemplate>,
+
+    /// Directories served alongside the `Resources` type
+    fs_resources: Vec<FsResourceProvider>,
+
+    /// Run in order when the server stops
+    shutdown_hooks: Vec<ShutdownHook>,
+
+    /// Shared with every tool call through its [`ToolContext`](crate::context::ToolContext)
+    extensions: Extensions,
+}
+
+#[derive(Clone)]
+struct SharedAuthorizer(Arc<dyn Authorizer>);
+
+impl Debug for SharedAuthorizer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("Authorizer")
+    }
+}
+
+#[derive(Clone)]
+struct ShutdownHook(Arc<dyn Fn() -> Result<()> + Send + Sync>);
+
+impl Debug for ShutdownHook {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("ShutdownHook")
+    }
+}
+
+impl ServerConfig {
+    pub fn new(info: Info) -> Self {
+        Self {
+            info,
+            instructions: None,
+            protocol_versions: ProtocolVersion::ALL.to_vec(),
+            capabilities: None,
+            metrics: None,
+            tool_timeout: None,
+            tool_timeouts: HashMap::new(),
+            rate_limiter: RateLimiter::default(),
+            result_cache: ResultCache::default(),
+            authorizer: None,
+            recording: None,
+            allowed_origins: vec![],
+            prompt_templates: vec![],
+            fs_resources: vec![],
+            shutdown_hooks: vec![],
+            extensions: Extensions::new(),
+        }
+    }
+
+    /// Advertise exactly `capabilities` instead of [detecting](Capabilities::detect) them
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = Some(capabilities);
+        self
+    }
+
+    pub fn capabilities(&self) -> Option<&Capabilities> {
+        self.capabilities.as_ref()
+    }
+
+    /// Record every tool call in `metrics`
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn metrics(&self) -> Option<&Metrics> {
+        self.metrics.as_ref()
+    }
+
+    /// Answer any tool call that is still running after `timeout` with a timeout error
+    ///
+    /// This cancels the call and reports it; it doesn't stop the tool. The tool keeps running,
+    /// because it has the state borrowed, but it is [cancelled](crate::context::CancellationToken)
+    /// and its eventual result is discarded. Requests are handled one at a time, so a tool that
+    /// ignores cancellation still blocks every request after it until it returns. Long-running
+    /// tools should check for cancellation, or run commands with
+    /// [`RunCommand::with_cancellation`](crate::process::RunCommand::with_cancellation).
+    pub fn with_tool_timeout(mut self, timeout: Duration) -> Self {
+        self.tool_timeout = Some(timeout);
+        self
+    }
+
+    /// Like [`with_tool_timeout`](Self::with_tool_timeout), for the tool named `tool` only
+    pub fn with_tool_timeout_for(mut self, tool: impl Into<String>, timeout: Duration) -> Self {
+        self.tool_timeouts.insert(tool.into(), timeout);
+        self
+    }
+
+    /// How long `tool` may run before its call is answered with a timeout error, if limited
+    pub fn tool_timeout(&self, tool: &str) -> Option<Duration> {
+        self.tool_timeouts.get(tool).copied().or(self.tool_timeout)
+    }
+
+    /// Refuse calls to any tool beyond `limit`, counting calls to all tools together
+    ///
+    /// A refused call is answered with a [`RATE_LIMITED`](crate::rate_limit::RATE_LIMITED) error
+    /// whose data has `retryAfterMs`, the milliseconds until a call would be allowed.
+    pub fn with_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limiter.set_global(limit);
+        self
+    }
+
+    /// Like [`with_rate_limit`](Self::with_rate_limit), for calls to the tool named `tool` only.
+    /// A call must be allowed by both limits, if there are both.
+    pub fn with_rate_limit_for(mut self, tool: impl Into<String>, limit: RateLimit) -> Self {
+        self.rate_limiter.set_tool(tool.into(), limit);
+        self
+    }
+
+    pub(crate) fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    /// Answer a call to the tool named `tool` with the result of an earlier call with the same
+    /// arguments, for `ttl` after that call, instead of running the tool again
+    ///
+    /// Only successful results are kept, and each is only reused for the
+    /// [session](crate::context::ToolContext::session_id) it was computed in. The state isn't part
+    /// of the key, so only cache tools whose results depend on nothing but their arguments,
+    /// like searches of something that rarely changes. Cached answers aren't
+    /// [rate limited](Self::with_rate_limit).
+    pub fn with_cache_for(mut self, tool: impl Into<String>, ttl: Duration) -> Self {
+        self.result_cache.set_tool(tool.into(), ttl);
+        self
+    }
+
+    pub(crate) fn result_cache(&self) -> &ResultCache {
+        &self.result_cache
+    }
+
+    /// Ask `authorizer` before every tool call, refusing the call with its error if it has one
+    ///
+    /// Calls are checked after aliases are resolved and before rate limits and timeouts apply,
+    /// so a refused call doesn't count against a [rate limit](Self::with_rate_limit). Tools run
+    /// from the command line aren't checked.
+    pub fn with_authorizer(mut self, authorizer: impl Authorizer + 'static) -> Self {
+        self.authorizer = Some(SharedAuthorizer(Arc::new(authorizer)));
+        self
+    }
+
+    pub fn authorizer(&self) -> Option<&dyn Authorizer> {
+        self.authorizer
+            .as_ref()
+            .map(|SharedAuthorizer(authorizer)| &**authorizer)
+    }
+
+    /// Write every message sent and received over a [`Transport`] like stdio to `path`, to be
+    /// replayed later
+    ///
+    /// Over TCP each connection is recorded to its own file, with its number added to the name,
+    /// like `session-1.jsonl`. See [`transport::record`](crate::transport::record) for the format.
+    pub fn with_recording(mut self, path: impl Into<PathBuf>) -> Self {
+        self.recording = Some(path.into());
+        self
+    }
+
+    pub fn recording(&self) -> Option<&Path> {
+        self.recording.as_deref()
+    }
+
+    /// Let pages from `origin`, like `https://app.example.com`, connect over HTTP+SSE
+    ///
+    /// Requests with an `Origin` header are refused unless it names this machine, like
+    /// `http://localhost:3000`, so that a web page can't reach a local server through DNS
+    /// rebinding. Requests without one, from clients that aren't browsers, are allowed.
+    pub fn with_allowed_origin(mut self, origin: impl Into<String>) -> Self {
+        self.allowed_origins.push(origin.into());
+        self
+    }
+
+    pub fn allowed_origins(&self) -> &[String] {
+        &self.allowed_origins
+    }
+
+    /// Serve `template` as a prompt, alongside the server's `Prompts` type
+    ///
+    /// # Panics
+    ///
+    /// If `template` uses a placeholder that isn't one of its arguments, or has an unmatched brace.
+    /// See [`PromptTemplate::validate`].
+    #[track_caller]
+    pub fn with_prompt_template(mut self, template: PromptTemplate) -> Self {
+        if let Err(e) = template.validate() {
+            panic!("invalid prompt template {}: {e}", template.name());
+        }
+        self.prompt_templates.push(template);
+        self
+    }
+
+    pub fn prompt_templates(&self) -> &[PromptTemplate] {
+        &self.prompt_templates
+    }
+
+    /// The template for the prompt named `name`, if there is one
+    pub(crate) fn prompt_template(&self, name: &str) -> Option<&PromptTemplate> {
+        self.prompt_templates
+            .iter()
+            .find(|template| template.name() == name)
+    }
+
+    /// Serve the files in `provider`'s directory as resources, alongside the server's `Resources`
+    /// type, which is checked first
+    pub fn with_fs_resources(mut self, provider: FsResourceProvider) -> Self {
+        self.fs_resources.push(provider);
+        self
+    }
+
+    pub fn fs_resources(&self) -> &[FsResourceProvider] {
+        &self.fs_resources
+    }
+
+    /// Guidance for the model, sent with the `initialize` response
+    ///
+    /// Accepts a `&'static str` or a `String`, so instructions can be read from a file or built
+    /// from configuration at startup.
+    pub fn with_instructions(mut self, instructions: impl Into<Cow<'static, str>>) -> Self {
+        self.instructions = Some(instructions.into());
+        self
+    }
+
+    pub fn instructions(&self) -> Option<&str> {
+        self.instructions.as_deref()
+    }
+
+    /// Make `extension` available to every tool call, through
+    /// [`ToolContext::extensions`](crate::context::ToolContext::extensions), replacing any earlier
+    /// extension of the same type
+    ///
+    /// This is for process-wide services like HTTP clients, database pools, or configuration,
+    /// which don't belong in the state of any one connection.
+    pub fn with_extension<T: Send + Sync + 'static>(mut self, extension: T) -> Self {
+        self.extensions.insert(extension);
+        self
+    }
+
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Run `hook` when the server stops, whether because the client disconnected or because the
+    /// process received SIGINT or SIGTERM
+    ///
+    /// Hooks run in the order they were added. An error is logged and doesn't stop later hooks.
+    pub fn with_shutdown_hook(
+        mut self,
+        hook: impl Fn() -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.shutdown_hooks.push(ShutdownHook(Arc::new(hook)));
+        self
+    }
+
+    /// Run the shutdown hooks and write out metrics
+    pub(crate) fn shut_down(&self) {
+        log::info!("shutting down");
+        for ShutdownHook(hook) in &self.shutdown_hooks {
+            if let Err(e) = hook() {
+                log::error!("shutdown hook failed: {e}");
+            }
+        }
+        if let Some(metrics) = &self.metrics
+            && let Err(e) = metrics.dump()
+        {
+            log::error!("could not write metrics: {e}");
+        }
+    }
+
+    /// Pick the protocol version to use with a client that asked for `requested`
+    pub fn negotiate(&self, requested: &str) -> ProtocolVersion {
+        self.protocol_versions
+            .iter()
+            .copied()
+            .find(|version| version.as_str() == requested)
+            .or_else(|| self.protocol_versions.iter().copied().max())
+            .unwrap_or_default()
+    }
+}
+
+type InstructionsFn<State> = Arc<dyn Fn(&State) -> String + Send + Sync>;
+
+/// What a server calls with its state as each connection proceeds, set with
+/// [`ServerBuilder::with_server_state`] and [`ServerBuilder::with_instructions_from`]
+///
+/// These belong to the server rather than its [`ToolRegistry`], which may be shared, so they are
+/// passed alongside it. The default calls nothing and uses the fixed instructions.
+pub struct Hooks<State> {
+    lifecycle: Option<Lifecycle<State>>,
+    instructions: Option<InstructionsFn<State>>,
+}
+
+/// The [`ServerState`] hooks of a state type
+struct Lifecycle<State> {
+    on_initialize: fn(&mut State, &Info) -> Result<()>,
+    health: fn(&State) -> Health,
+}
+
+impl<State> Clone for Lifecycle<State> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<State> Copy for Lifecycle<State> {}
+
+impl<State> Hooks<State> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call the state's [`ServerState`] hooks: `on_initialize` when a client connects, and
+    /// `health` when a client sends `mcplease/health`
+    pub fn with_server_state(mut self) -> Self
+    where
+        State: ServerState,
+    {
+        self.lifecycle = Some(Lifecycle {
+            on_initialize: State::on_initialize,
+            health: State::health,
+        });
+        self
+    }
+
+    /// Build the `initialize` response's instructions from the state with `instructions`
+    pub fn with_instructions_from(
+        mut self,
+        instructions: impl Fn(&State) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.instructions = Some(Arc::new(instructions));
+        self
+    }
+
+    pub(crate) fn on_initialize(&self, state: &mut State, client: &Info) -> Result<()> {
+        match self.lifecycle {
+            Some(lifecycle) => (lifecycle.on_initialize)(state, client),
+            None => Ok(()),
+        }
+    }
+
+    /// The instructions built from `state`, if there is an instructions function
+    pub(crate) fn instructions(&self, state: &State) -> Option<String> {
+        self.instructions
+            .as_ref()
+            .map(|instructions| instructions(state))
+    }
+
+    pub(crate) fn health(&self, state: &State) -> Health {
+        self.lifecycle
+            .map_or_else(Health::default, |lifecycle| (lifecycle.health)(state))
+    }
+}
+
+impl<State> Default for Hooks<State> {
+    fn default() -> Self {
+        Self {
+            lifecycle: None,
+            instructions: None,
+        }
+    }
+}
+
+impl<State> Clone for Hooks<State> {
+    fn clone(&self) -> Self {
+        Self {
+            lifecycle: self.lifecycle,
+            instructions: self.instructions.clone(),
+        }
+    }
+}
+
+impl<State> Debug for Hooks<State> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("server_state", &self.lifecycle.is_some())
+            .field("instructions_from", &self.instructions.is_some())
+            .finish()
+    }
+}
+
+/// Entry point for configuring and running a server with [`Server::builder`]
+#[derive(Debug, Clone, Copy)]
+pub struct Server;
+
+impl Server {
+    /// Start configuring a server
+    ///
+    /// ```ignore
+    /// Server::builder()
+    ///     .with_state(State::new()?)
+    ///     .with_tools::<tools::Tools>()
+    ///     .with_info(server_info!())
+    ///     .with_instructions(INSTRUCTIONS)
+    ///     .run()
+    /// ```
+    pub fn builder() -> ServerBuilder<(), (), (), ()> {
+        ServerBuilder {
+            state: Some(()),
+            config: ServerConfig::new(Info {
+                name: "".into(),
+                version: "".into(),
+            }),
+            registry: ToolRegistry::new(),
+            hooks: Hooks::default(),
+            serving: Serving::default(),
+            shutdown: vec![],
+            types: PhantomData,
+        }
+    }
+}
+
+type StateHook<State> = Box<dyn FnOnce(&mut State) -> Result<()>>;
+pub(crate) type Types<Tools, Resources, Prompts> = PhantomData<fn() -> (Tools, Resources, Prompts)>;
+
+/// A server being configured, created by [`Server::builder`]
+///
+/// `Tools`, `Resources`, and `Prompts` are the types generated by [`tools!`](crate::tools),
+/// [`resources!`](crate::resources), and [`prompts!`](crate::prompts). Resources and prompts
+/// default to `()`, meaning none.
+pub struct ServerBuilder<Tools, Resources, Prompts, State> {
+    /// `None` for [`run`](crate::run), which lends its state instead
+    state: Option<State>,
+    config: ServerConfig,
+    registry: ToolRegistry<State>,
+    hooks: Hooks<State>,
+    serving: Serving,
+    shutdown: Vec<StateHook<State>>,
+    types: Types<Tools, Resources, Prompts>,
+}
+
+impl<Tools, Resources, Prompts, State> ServerBuilder<Tools, Resources, Prompts, State> {
+    /// Use `state` as the server state
+    ///
+    /// This changes the state type, so it discards any tool registry,
+    /// [`with_server_state`](Self::with_server_state) or
+    /// [`with_instructions_from`](Self::with_instructions_from) hooks, or
+    /// [`on_shutdown`](Self::on_shutdown) hooks added before it. Call it first.
+    pub fn with_state<S>(self, state: S) -> ServerBuilder<Tools, Resources, Prompts, S> {
+        ServerBuilder {
+            state: Some(state),
+            ..self.with_state_type()
+        }
+    }
+
+    /// Like [`with_state`](Self::with_state), for a state that is lent to
+    /// [`run_on`](Self::run_on) instead
+    pub(crate) fn with_state_type<S>(self) -> ServerBuilder<Tools, Resources, Prompts, S> {
+        ServerBuilder {
+            state: None,
+            config: self.config,
+            registry: ToolRegistry::new(),
+            hooks: Hooks::default(),
+            serving: self.serving,
+            shutdown: vec![],
+            types: PhantomData,
+        }
+    }
+
+    /// Serve the tools in `T`, usually the `Tools` enum generated by [`tools!`](crate::tools)
+    pub fn with_tools<T>(self) -> ServerBuilder<T, Resources, Prompts, State> {
+        ServerBuilder {
+            state: self.state,
+            config: self.config,
+            registry: self.registry,
+            hooks: self.hooks,
+            serving: self.serving,
+            shutdown: self.shutdown,
+            types: PhantomData,
+        }
+    }
+
+    /// Serve the resources in `R`, generated by [`resources!`](crate::resources)
+    pub fn with_resources<R>(self) -> ServerBuilder<Tools, R, Prompts, State> {
+        ServerBuilder {
+            state: self.state,
+            config: self.config,
+            registry: self.registry,
+            hooks: self.hooks,
+            serving: self.serving,
+            shutdown: self.shutdown,
+            types: PhantomData,
+        }
+    }
+
+    /// Serve the prompts in `P`, generated by [`prompts!`](crate::prompts)
+    pub fn with_prompts<P>(self) -> ServerBuilder<Tools, Resources, P, State> {
+        ServerBuilder {
+            state: self.state,
+            config: self.config,
+            registry: self.registry,
+            hooks: self.hooks,
+            serving: self.serving,
+            shutdown: self.shutdown,
+            types: PhantomData,
+        }
+    }
+
+    /// The name and version reported to clients, usually from [`server_info!`](crate::server_info)
+    pub fn with_info(mut self, info: Info) -> Self {
+        self.config = self.config.with_info(info);
+        self
+    }
+
+    /// Guidance for the model, sent with the `initialize` response
+    pub fn with_instructions(mut self, instructions: impl Into<Cow<'static, str>>) -> Self {
+        self.config = self.config.with_instructions(instructions);
+        self
+    }
+
+    /// See [`ServerConfig::with_protocol_versions`]
+    pub fn with_protocol_versions(mut self, protocol_versions: Vec<ProtocolVersion>) -> Self {
+        self.config = self.config.with_protocol_versions(protocol_versions);
+        self
+    }
+
+    /// See [`ServerConfig::with_capabilities`]
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.config = self.config.with_capabilities(capabilities);
+        self
+    }
+
+    /// Record every tool call in `metrics`
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.config = self.config.with_metrics(metrics);
+        self
+    }
+
+    /// See [`ServerConfig::with_tool_timeout`]
+    pub fn with_tool_timeout(mut self, timeout: Duration) -> Self {
+        self.config = self.config.with_tool_timeout(timeout);
+        self
+    }
+
+    /// See [`ServerConfig::with_tool_timeout_for`]
+    pub fn with_tool_timeout_for(mut self, tool: impl Into<String>, timeout: Duration) -> Self {
+        self.config = self.config.with_tool_timeout_for(tool, timeout);
+        self
+    }
+
+    /// See [`ServerConfig::with_rate_limit`]
+    pub fn with_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.config = self.config.with_rate_limit(limit);
+        self
+    }
+
+    /// See [`ServerConfig::with_rate_limit_for`]
+    pub fn with_rate_limit_for(mut self, tool: impl Into<String>, limit: RateLimit) -> Self {
+        self.config = self.config.with_rate_limit_for(tool, limit);
+        self
+    }
+
+    /// See [`ServerConfig::with_cache_for`]
+    pub fn with_cache_for(mut self, tool: impl Into<String>, ttl: Duration) -> Self {
+        self.config = self.config.with_cache_for(tool, ttl);
+        self
+    }
+
+    /// See [`ServerConfig::with_authorizer`]
+    pub fn with_authorizer(mut self, authorizer: impl Authorizer + 'static) -> Self {
+        self.config = self.config.with_authorizer(authorizer);
+        self
+    }
+
+    /// See [`ServerConfig::with_recording`]
+    pub fn with_recording(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config = self.config.with_recording(path);
+        self
+    }
+
+    /// See [`ServerConfig::with_allowed_origin`]
+    pub fn with_allowed_origin(mut self, origin: impl Into<String>) -> Self {
+        self.config = self.config.with_allowed_origin(origin);
+        self
+    }
+
+    /// See [`ServerConfig::with_prompt_template`]
+    #[track_caller]
+    pub fn with_prompt_template(mut self, template: PromptTemplate) -> Self {
+        self.config = self.config.with_prompt_template(template);
+        self
+    }
+
+    /// See [`ServerConfig::with_fs_resources`]
+    pub fn with_fs_resources(mut self, provider: FsResourceProvider) -> Self {
+        self.config = self.config.with_fs_resources(provider);
+        self
+    }
+
+    /// See [`ServerConfig::with_extension`]
+    pub fn with_extension<T: Send + Sync + 'static>(mut self, extension: T) -> Self {
+        self.config = self.config.with_extension(extension);
+        self
+    }
+
+    /// Replace the configuration built so far, for settings the builder doesn't expose
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    /// Also serve the tools in `registry`, which can change while the server runs
+    pub fn with_tool_registry(mut self, registry: ToolRegistry<State>) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Call the state's [`ServerState`] hooks: `on_initialize` when a client connects, `health`
+    /// when a client asks, and `on_shutdown` along with the other [`on_shutdown`](Self::on_shutdown)
+    /// hooks
+    pub fn with_server_state(mut self) -> Self
+    where
+        State: ServerState,
+    {
+        self.hooks = self.hooks.with_server_state();
+        self.on_shutdown(|state| {
+            state.on_shutdown();
+            Ok(())
+        })
+    }
+
+    /// Build the instructions sent with each `initialize` response from the state, instead of
+    /// using the fixed [`with_instructions`](Self::with_instructions)
+    ///
+    /// `instructions` is called after the state's `on_initialize` hook, so it can mention what
+    /// that set up, like configured directories or enabled features.
+    ///
+    /// ```ignore
+    /// .with_instructions_from(|state: &State| {
+    ///     format!("Files are read relative to {}.", state.root().display())
+    /// })
+    /// ```
+    pub fn with_instructions_from(
+        mut self,
+        instructions: impl Fn(&State) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.hooks = self.hooks.with_instructions_from(instructions);
+        self
+    }
+
+    /// How [`serve`](Self::serve) talks to the client. Defaults to [`Stdio`](crate::transport::Stdio).
+    pub fn with_transport(mut self, transport: impl Transport) -> Self {
+        self.serving = Serving::Transport(Arc::new(transport));
+        self
+    }
+
+    /// Make [`serve`](Self::serve) listen for HTTP+SSE clients at `addr` instead of using a
+    /// [`Transport`]
+    pub fn with_sse(mut self, addr: impl Into<String>) -> Self {
+        self.serving = Serving::Sse(addr.into());
+        self
+    }
+
+    /// Make [`serve`](Self::serve) listen for TCP clients at `addr`, requiring each to send
+    /// `token` first if it is set. See [`transport::tcp`](crate::transport::tcp).
+    pub fn with_tcp(mut self, addr: impl Into<String>, token: Option<String>) -> Self {
+        self.serving = Serving::Tcp {
+            addr: addr.into(),
+            token,
+        };
+        self
+    }
+
+    /// Run `hook` with the state once the server stops, or once a command line tool invocation
+    /// finishes
+    ///
+    /// Hooks run in the order they were added, after the [`ServerConfig`] shutdown hooks. An
+    /// error is logged and doesn't stop later hooks.
+    pub fn on_shutdown(mut self, hook: impl FnOnce(&mut State) -> Result<()> + 'static) -> Self {
+        self.shutdown.push(Box::new(hook));
+        self
+    }
+}
+
+impl<Tools, Resources, Prompts, State> ServerBuilder<Tools, Resources, Prompts, State>
+where
+    Tools: Debug
+        + Subcommand
+        + AsToolsList
+        + Tool<State>
+        + CompleteArgument<State>
+        + EnabledTools<State>,
+    Resources: AsResourcesList + ReadResource<State>,
+    Prompts: AsPromptsList + GetPrompt<State> + CompleteArgument<State>,
+{
+    /// Behave like [`run`](crate::run): run the tool named on the command line, or serve MCP over
+    /// stdio with `serve`, or over HTTP+SSE with `serve --sse <addr>`
+    pub fn run(self) -> Result<()> {
+        self.run_from(std::env::args())
+    }
+
+    /// Like [`run`](Self::run), reading `args`, a command line starting with the binary's name,
+    /// instead of the process's
+    pub(crate) fn run_from(mut self, args: impl IntoIterator<Item = String>) -> Result<()> {
+        let mut state = self.take_state();
+        self.run_on_from(&mut state, args)
+    }
+
+    /// Like [`run`](Self::run), with a state lent by the caller, for [`run`](crate::run)
+    pub(crate) fn run_on(self, state: &mut State) -> Result<()> {
+        self.run_on_from(state, std::env::args())
+    }
+
+    fn run_on_from(
+        mut self,
+        state: &mut State,
+        args: impl IntoIterator<Item = String>,
+    ) -> Result<()> {
+        self.check()?;
+        crate::logging::init();
+        let result = crate::run_cli::<Tools, Resources, Prompts, State>(
+            state,
+            &self.config,
+            &self.registry,
+            &self.hooks,
+            args,
+        );
+        self.shut_down(state);
+        result
+    }
+
+    /// Serve MCP over the configured [`Transport`] or network transport, ignoring the command
+    /// line
+    pub fn serve(mut self) -> Result<()> {
+        let mut state = self.take_state();
+        self.check()?;
+        crate::logging::init();
+        let result = crate::serve_transport::<Tools, Resources, Prompts, State>(
+            &mut state,
+            &self.config,
+            &self.registry,
+            &self.hooks,
+            &self.serving,
+        );
+        self.shut_down(&mut state);
+        result
+    }
+
+    fn take_state(&mut self) -> State {
+        self.state
+            .take()
+            .expect("only run leaves the state out, and it uses run_on")
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.config.info().name.is_empty() {
+            bail!("the server needs a name and version: call with_info(server_info!())");
+        }
+        Ok(())
+    }
+
+    fn shut_down(&mut self, state: &mut State) {
+        for hook in self.shutdown.drain(..) {
+            if let Err(e) = hook(state) {
+                log::error!("shutdown hook failed: {e}");
+            }
+        }
+    }
+}
+
+impl<Tools, Resources, Prompts, State: Debug> Debug
+    for ServerBuilder<Tools, Resources, Prompts, State>
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerBuilder")
+            .field("state", &self.state)
+            .field("config", &self.config)
+            .field("registry", &self.registry)
+            .field("hooks", &self.hooks)
+            .field("serving", &self.serving)
+            .field("tools", &std::any::type_name::<Tools>())
+            .field("resources", &std::any::type_name::<Resources>())
+            .field("prompts", &std::any::type_name::<Prompts>())
+            .finish_non_exhaustive()
+    }
+}
@@ -1,26 +1,129 @@
-use anyhow::{Result, anyhow};
-use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+mod file;
+mod migrator;
+mod namespace;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use file::{FileBackend, SessionFormat};
+pub use migrator::Migrator;
+pub use namespace::Namespace;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteBackend;
+
+use crate::context::ToolContext;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
 use std::collections::hash_map::Entry;
-use std::fs::{self, OpenOptions};
-use std::path::PathBuf;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::time::SystemTime;
-
-/// Metadata tracked by the session store for each session
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct SessionMetadata {
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant, SystemTime};
+
+/// The session that [`SessionStore::scoped`] uses when there is only one client, as over stdio
+pub const DEFAULT_SESSION: &str = "default";
+
+/// Metadata tracked by the session store for each session, returned by
+/// [`SessionStore::metadata`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionMetadata {
     created_at: SystemTime,
     last_used: SystemTime,
 }
 
-/// Internal wrapper for session data with metadata
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-struct SessionEntry<T> {
+impl SessionMetadata {
+    /// When the session was created
+    pub fn created_at(&self) -> SystemTime {
+        self.created_at
+    }
+
+    /// When the session was last changed or [touched](SessionStore::touch), or read if the store
+    /// [touches on read](SessionStore::with_touch_on_read)
+    pub fn last_used(&self) -> SystemTime {
+        self.last_used
+    }
+
+    /// How long ago the session was last used, zero if that was in the future
+    pub fn idle_for(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.last_used)
+            .unwrap_or_default()
+    }
+}
+
+/// Session data along with its metadata, as handed to a [`StorageBackend`]
+#[derive(Debug, Clone, Default)]
+pub struct SessionEntry<T> {
     data: T,
     metadata: SessionMetadata,
+    /// The [`Migrator::version`] of `data`, zero if it was written without a migrator
+    version: u32,
+}
+
+/// How a [`SessionEntry`] is written in self-describing formats like JSON, where a missing
+/// version can be told apart from the next field
+#[derive(Serialize, Deserialize)]
+struct VersionedEntry<T, M> {
+    data: T,
+    metadata: M,
+    #[serde(default, skip_serializing_if = "is_unversioned")]
+    version: u32,
+}
+
+/// How a [`SessionEntry`] is written in compact formats like postcard, which have no version
+#[derive(Serialize, Deserialize)]
+struct CompactEntry<T, M> {
+    data: T,
+    metadata: M,
+}
+
+fn is_unversioned(version: &u32) -> bool {
+    *version == 0
+}
+
+impl<T: Serialize> Serialize for SessionEntry<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            VersionedEntry {
+                data: &self.data,
+                metadata: &self.metadata,
+                version: self.version,
+            }
+            .serialize(serializer)
+        } else {
+            CompactEntry {
+                data: &self.data,
+                metadata: &self.metadata,
+            }
+            .serialize(serializer)
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for SessionEntry<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let VersionedEntry {
+                data,
+                metadata,
+                version,
+            } = VersionedEntry::deserialize(deserializer)?;
+            Ok(Self {
+                data,
+                metadata,
+                version,
+            })
+        } else {
+            let CompactEntry { data, metadata } = CompactEntry::deserialize(deserializer)?;
+            Ok(Self {
+                data,
+                metadata,
+                version: 0,
+            })
+        }
+    }
 }
 
 impl<T> SessionEntry<T> {
@@ -39,6 +142,89 @@ impl Default for SessionMetadata {
     }
 }
 
+/// What a [`StorageBackend`] does when stored sessions can't be read, usually because the file was
+/// edited by hand or `T` changed without a [`Migrator`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LoadErrorPolicy {
+    /// Return the error, both from loading and from any save that would overwrite the sessions
+    /// that couldn't be read
+    Fail,
+
+    /// Copy the stored sessions to a `.bak` file alongside them, log where, and carry on without
+    /// the sessions that couldn't be read
+    #[default]
+    BackupAndReset,
+
+    /// Log the error and leave the stored sessions alone, carrying on with the sessions already
+    /// in memory
+    ///
+    /// Sessions that can't be read stay as they are, and a file that can't be read at all isn't
+    /// saved over until it can be, so changes are only kept in memory until then.
+    KeepInMemory,
+}
+
+/// Where to back up `path` before resetting it: alongside it, named for the time, and not
+/// overwriting an earlier backup
+fn backup_path(path: &Path) -> PathBuf {
+    let seconds = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    (0..)
+        .map(|attempt| {
+            let mut name = path.file_name().unwrap_or_default().to_os_string();
+            match attempt {
+                0 => name.push(format!(".{seconds}.bak")),
+                _ => name.push(format!(".{seconds}-{attempt}.bak")),
+            }
+            path.with_file_name(name)
+        })
+        .find(|backup| !backup.exists())
+        .unwrap()
+}
+
+/// Where a [`SessionStore`] persists its sessions
+///
+/// [`FileBackend`] is used by [`SessionStore::new`]. With the `sqlite` feature,
+/// `SqliteBackend` stores each session as its own row. Implement this trait to store sessions
+/// anywhere else, and pass it to [`SessionStore::with_backend`].
+pub trait StorageBackend<T>: Debug + Send {
+    /// Read every stored session
+    ///
+    /// Returns `None` if there is nothing usable to load, in which case the store keeps the
+    /// sessions it already has in memory. Sessions that can't be read should be handled according
+    /// to a [`LoadErrorPolicy`], rather than silently dropped.
+    fn load(&mut self) -> Result<Option<HashMap<String, SessionEntry<T>>>>;
+
+    /// Persist `sessions` after the sessions named in `changed` were created, updated, or
+    /// removed
+    ///
+    /// Backends that can write individual sessions only need to look at `changed`; a removed
+    /// session is one that is named in `changed` but missing from `sessions`.
+    fn save(&mut self, sessions: &HashMap<String, SessionEntry<T>>, changed: &[&str])
+    -> Result<()>;
+
+    /// Whether another process has changed the stored sessions since the last load
+    fn has_external_changes(&mut self) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Read every stored session with its data as JSON, for a [`Migrator`] to upgrade
+    ///
+    /// Backends that can't do this don't support migration, and fail by default.
+    fn load_raw(&mut self) -> Result<Option<HashMap<String, SessionEntry<Value>>>> {
+        anyhow::bail!("{self:?} does not support migrating sessions")
+    }
+}
+
+/// A session that changed when a [`SessionStore`] reloaded changes written by another process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionChange {
+    Created(String),
+    Updated(String),
+    Removed(String),
+}
+
 /// Generic session store that handles persistence and cross-process synchronization
 ///
 /// This store automatically watches for file changes from other processes and reloads
@@ -52,10 +238,19 @@ impl Default for SessionMetadata {
 #[derive(Debug)]
 pub struct SessionStore<T> {
     sessions: HashMap<String, SessionEntry<T>>,
-    storage_path: Option<PathBuf>,
-    needs_reload: Arc<AtomicBool>,
-    ignore_next_events: Arc<AtomicUsize>, // Counter for ignoring our own writes
-    _watcher: Option<RecommendedWatcher>, // Keeps the file watcher thread alive
+    backend: Option<Box<dyn StorageBackend<T>>>,
+    subscribers: Vec<Sender<SessionChange>>,
+    migrator: Option<Migrator>,
+    /// How long changes may wait to be saved, if they are saved behind
+    write_behind: Option<Duration>,
+    /// Sessions changed since the last save, in write-behind mode
+    unsaved: HashSet<String>,
+    /// When the oldest unsaved change was made
+    unsaved_since: Option<Instant>,
+    /// Whether reading a session counts as using it
+    touch_on_read: bool,
+    /// Whether the sessions in storage have been loaded yet
+    loaded: bool,
 }
 
 impl<T> SessionStore<T>
@@ -65,103 +260,159 @@ where
     /// Create a new session store with the given storage path
     ///
     /// If a storage path is provided, the store will:
-    /// - Load existing sessions from disk
+    /// - Load existing sessions from disk when it is first used
     /// - Set up file watching for cross-process synchronization
     /// - Automatically reload when other processes modify the file
+    ///
+    /// Without a storage path, sessions only live in memory.
     pub fn new(storage_path: Option<PathBuf>) -> Result<Self> {
-        let mut store = Self {
-            sessions: HashMap::new(),
-            storage_path: storage_path.clone(),
-            needs_reload: Arc::new(AtomicBool::new(false)),
-            ignore_next_events: Arc::new(AtomicUsize::new(0)),
-            _watcher: None,
-        };
+        match storage_path {
+            Some(storage_path) => Self::with_backend(FileBackend::new(storage_path)?),
+            None => Ok(Self {
+                sessions: HashMap::new(),
+                backend: None,
+                subscribers: vec![],
+                migrator: None,
+                write_behind: None,
+                unsaved: HashSet::new(),
+                unsaved_since: None,
+                touch_on_read: false,
+                loaded: true,
+            }),
+        }
+    }
 
-        // Ensure storage directory exists and file is accessible
-        if let Some(storage_path) = &storage_path {
-            if let Some(parent) = storage_path.parent() {
-                fs::create_dir_all(parent)?;
-            }
+    /// Create a new session store that persists to `backend`, loading any existing sessions when
+    /// it is first used
+    ///
+    /// Loading waits so that a [`with_migrator`](Self::with_migrator) can upgrade sessions
+    /// before they are read as `T`, rather than finding them unreadable.
+    pub fn with_backend(backend: impl StorageBackend<T> + 'static) -> Result<Self> {
+        Ok(Self {
+            sessions: HashMap::new(),
+            backend: Some(Box::new(backend)),
+            subscribers: vec![],
+            migrator: None,
+            write_behind: None,
+            unsaved: HashSet::new(),
+            unsaved_since: None,
+            touch_on_read: false,
+            loaded: false,
+        })
+    }
 
-            OpenOptions::new()
-                .append(true)
-                .create(true)
-                .open(storage_path)
-                .map_err(|_| anyhow!("could not open {}", storage_path.to_string_lossy()))?;
-        }
+    /// Upgrade sessions written with older versions of `T` using `migrator`, reloading them now
+    ///
+    /// Sessions are loaded as JSON and passed through the migrator's steps, and those that were
+    /// upgraded are saved with the new version. Sessions written later by other processes are
+    /// upgraded as they are reloaded. Fails if a session can't be upgraded, or was written by a
+    /// newer version, rather than dropping it.
+    pub fn with_migrator(mut self, migrator: Migrator) -> Result<Self> {
+        self.migrator = Some(migrator);
+        self.load()?;
+        self.loaded = true;
+        Ok(self)
+    }
 
-        // Load existing sessions from disk
-        store.load()?;
+    /// Save changes at most once every `window`, instead of on every change
+    ///
+    /// Changes made within the window are written together, the next time the store is used
+    /// after it has passed, or when [`flush`](Self::flush) is called or the store is dropped.
+    /// This makes tight loops of updates cheap, at the cost of other processes seeing changes
+    /// later, and of losing up to `window` of changes if the process is killed.
+    pub fn with_write_behind(mut self, window: Duration) -> Self {
+        self.write_behind = Some(window);
+        self
+    }
 
-        // Set up file watching for cross-process synchronization
-        if storage_path.is_some() {
-            store.setup_file_watching()?;
-        }
+    /// Update a session's [`last_used`](SessionMetadata::last_used) whenever it is read, not
+    /// only when it changes
+    ///
+    /// Each read is then saved, so this pairs well with
+    /// [`with_write_behind`](Self::with_write_behind).
+    pub fn with_touch_on_read(mut self, touch_on_read: bool) -> Self {
+        self.touch_on_read = touch_on_read;
+        self
+    }
 
-        Ok(store)
+    /// Save any changes still waiting in write-behind mode now
+    ///
+    /// Servers using [`with_write_behind`](Self::with_write_behind) can call this from
+    /// [`ServerState::on_shutdown`](crate::traits::ServerState::on_shutdown) to find out whether
+    /// saving failed, which dropping the store only logs.
+    pub fn flush(&mut self) -> Result<()> {
+        self.save_unsaved()
     }
 
-    /// Set up file watching to detect changes from other processes
-    fn setup_file_watching(&mut self) -> Result<()> {
-        let Some(storage_path) = &self.storage_path else {
+    /// Check if we need to reload from storage and do so if necessary
+    fn check_and_reload(&mut self) -> Result<()> {
+        if !self.loaded {
+            self.load()?;
+            self.loaded = true;
+            return Ok(());
+        }
+        let Some(backend) = &mut self.backend else {
             return Ok(());
         };
 
-        let needs_reload = Arc::clone(&self.needs_reload);
-        let ignore_next_events = Arc::clone(&self.ignore_next_events);
-        let watch_path = storage_path.clone();
-
-        let mut watcher = RecommendedWatcher::new(
-            move |res: Result<Event, notify::Error>| {
-                if let Ok(event) = res {
-                    log::trace!("received {event:?}");
-                    // Reload on content-changing events:
-                    // - Modify: direct writes, touch command
-                    // - Create: atomic rename completion
-                    match event.kind {
-                        EventKind::Modify(_) | EventKind::Create(_) => {
-                            // Check if we should ignore this event (from our own write)
-                            let current = ignore_next_events.load(Ordering::Relaxed);
-                            if current > 0 {
-                                // Saturating subtraction to prevent underflow
-                                let new_value = current.saturating_sub(1);
-                                ignore_next_events.store(new_value, Ordering::Relaxed);
-                                log::trace!(
-                                    "ignoring event from our own write (remaining: {new_value})"
-                                );
-                                return; // Skip this event - it's from our own write
-                            }
-
-                            log::trace!("marking needs_reload");
-                            needs_reload.store(true, Ordering::Relaxed);
-                        }
-                        _ => {} // Ignore access time, metadata changes, etc.
-                    }
-                }
-            },
-            notify::Config::default(),
-        )?;
-
-        // Watch the specific file for changes
-        watcher.watch(&watch_path, RecursiveMode::NonRecursive)?;
-
-        // Store the watcher to keep the background thread alive
-        self._watcher = Some(watcher);
+        if backend.has_external_changes()? {
+            log::trace!("needs reload detected");
+            // reloading replaces the sessions in memory, so changes waiting to be written
+            // behind would be lost
+            self.save_unsaved()?;
+            let before = (!self.subscribers.is_empty()).then(|| self.sessions.clone());
+            self.load()?;
+            if let Some(before) = before {
+                self.notify_subscribers(&before);
+            }
+        } else if self.write_behind.is_some_and(|window| {
+            self.unsaved_since
+                .is_some_and(|since| since.elapsed() >= window)
+        }) {
+            self.save_unsaved()?;
+        }
+        Ok(())
+    }
 
-        log::trace!("watching {}", watch_path.display());
+    /// Pick up changes written by other processes now, rather than on the next access
+    ///
+    /// Subscribers are notified of any sessions that changed.
+    pub fn refresh(&mut self) -> Result<()> {
+        self.check_and_reload()
+    }
 
-        Ok(())
+    /// Receive a [`SessionChange`] for every session that changes when the store reloads
+    /// changes written by another process
+    ///
+    /// Reloads happen lazily when the store is next accessed, or when [`SessionStore::refresh`]
+    /// is called. Changes made through this store are not reported.
+    pub fn subscribe(&mut self) -> Receiver<SessionChange> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
     }
 
-    /// Check if we need to reload from disk and do so if necessary
-    fn check_and_reload(&mut self) -> Result<()> {
-        if self.needs_reload.load(Ordering::Relaxed) {
-            log::trace!("needs reload detected");
+    fn notify_subscribers(&mut self, before: &HashMap<String, SessionEntry<T>>) {
+        let mut changes = vec![];
+        for (id, entry) in &self.sessions {
+            match before.get(id) {
+                None => changes.push(SessionChange::Created(id.clone())),
+                Some(previous) if previous.data != entry.data => {
+                    changes.push(SessionChange::Updated(id.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+        for id in before.keys() {
+            if !self.sessions.contains_key(id) {
+                changes.push(SessionChange::Removed(id.clone()));
+            }
+        }
 
-            self.load()?;
-            self.needs_reload.store(false, Ordering::Relaxed);
+        for change in changes {
+            self.subscribers
+                .retain(|subscriber| subscriber.send(change.clone()).is_ok());
         }
-        Ok(())
     }
 
     /// Get session data, creating a new session if it doesn't exist
@@ -174,11 +425,15 @@ where
 
         // Create or update the entry
         {
+            let touch_on_read = self.touch_on_read;
             self.sessions
                 .entry(session_id.to_string())
-                .and_modify(|_e| {
-                    // Just reading - no timestamp update, no changes
-                    changed = false;
+                .and_modify(|entry| {
+                    // Just reading - no changes, and no timestamp update unless reads count
+                    if touch_on_read {
+                        entry.update_last_used();
+                        changed = true;
+                    }
                 })
                 .or_insert_with(|| {
                     changed = true; // New entry is always a change
@@ -187,7 +442,7 @@ where
         }
 
         if changed {
-            self.save()?;
+            self.save(&[session_id])?;
         }
 
         Ok(&self.sessions.get(session_id).unwrap().data)
@@ -199,9 +454,27 @@ where
     /// file changes from other processes.
     pub fn get(&mut self, session_id: &str) -> Result<Option<&T>> {
         self.check_and_reload()?;
+        if self.touch_on_read {
+            self.touch_entry(session_id)?;
+        }
         Ok(self.sessions.get(session_id).map(|entry| &entry.data))
     }
 
+    /// When a session was created and last used, or None if it doesn't exist
+    ///
+    /// Looking at the metadata doesn't count as using the session, even with
+    /// [`with_touch_on_read`](Self::with_touch_on_read).
+    pub fn metadata(&mut self, session_id: &str) -> Result<Option<SessionMetadata>> {
+        self.check_and_reload()?;
+        Ok(self.sessions.get(session_id).map(|entry| entry.metadata))
+    }
+
+    /// Mark a session as used now without changing its data, returning whether it exists
+    pub fn touch(&mut self, session_id: &str) -> Result<bool> {
+        self.check_and_reload()?;
+        self.touch_entry(session_id)
+    }
+
     /// Update session data using a closure
     ///
     /// The closure receives a mutable reference to the session data and can modify it.
@@ -234,50 +507,646 @@ where
         }
 
         if changed {
-            self.save()?;
+            self.save(&[session_id])?;
         }
         Ok(())
     }
 
+    /// Get mutable access to session data, creating the session if it doesn't exist
+    ///
+    /// The returned guard dereferences to the session data and saves it when dropped, if it
+    /// changed. Use [`SessionGuard::save`] instead of dropping to find out whether saving failed.
+    pub fn get_mut(&mut self, session_id: &str) -> Result<SessionGuard<'_, T>> {
+        self.check_and_reload()?;
+        if self.touch_on_read {
+            self.touch_entry(session_id)?;
+        }
+
+        let before = self
+            .sessions
+            .get(session_id)
+            .map(|entry| entry.data.clone());
+        self.sessions.entry(session_id.to_string()).or_default();
+
+        Ok(SessionGuard {
+            store: self,
+            session_id: session_id.to_string(),
+            before,
+            saved: false,
+        })
+    }
+
+    /// The session of the client making a tool call, so that tools don't need to know which one
+    /// that is
+    ///
+    /// Each client of a transport that can serve more than one, like HTTP+SSE or TCP, gets its
+    /// own session, named by [`ToolContext::session_id`]. Over stdio and from the command line,
+    /// it is [`DEFAULT_SESSION`], which is shared with later runs of the server.
+    pub fn scoped(&mut self, context: &ToolContext) -> ScopedSessionStore<'_, T> {
+        self.scoped_to(context.session_id().unwrap_or(DEFAULT_SESSION))
+    }
+
+    /// The session named `session_id`, without passing the id to every call
+    pub fn scoped_to(&mut self, session_id: impl Into<String>) -> ScopedSessionStore<'_, T> {
+        ScopedSessionStore {
+            store: self,
+            session_id: session_id.into(),
+        }
+    }
+
     /// Set session data directly
     pub fn set(&mut self, session_id: &str, data: T) -> Result<()> {
         self.update(session_id, |existing| *existing = data)
     }
 
-    /// Load sessions from disk
+    /// Remove a session, returning its data if it existed
+    pub fn remove(&mut self, session_id: &str) -> Result<Option<T>> {
+        self.check_and_reload()?;
+
+        let removed = self.sessions.remove(session_id).map(|entry| entry.data);
+        if removed.is_some() {
+            self.save(&[session_id])?;
+        }
+        Ok(removed)
+    }
+
+    /// Remove all sessions
+    pub fn clear(&mut self) -> Result<()> {
+        self.check_and_reload()?;
+
+        if !self.sessions.is_empty() {
+            let removed: Vec<String> = self.sessions.drain().map(|(id, _)| id).collect();
+            self.save(&removed.iter().map(String::as_str).collect::<Vec<_>>())?;
+        }
+        Ok(())
+    }
+
+    /// List the ids of all sessions
+    ///
+    /// This automatically checks for file changes from other processes.
+    pub fn keys(&mut self) -> Result<Vec<String>> {
+        self.check_and_reload()?;
+        Ok(self.sessions.keys().cloned().collect())
+    }
+
+    /// Remove every session that has not been used within `max_age`, returning the ids of the
+    /// removed sessions
+    ///
+    /// Long-running servers can call this periodically to keep the store from growing forever.
+    /// Sessions count as used when they change or are [touched](Self::touch), and also when
+    /// they are read if the store [touches on read](Self::with_touch_on_read).
+    pub fn expire_older_than(&mut self, max_age: Duration) -> Result<Vec<String>> {
+        self.check_and_reload()?;
+
+        let now = SystemTime::now();
+        let mut expired = vec![];
+        self.sessions.retain(|session_id, entry| {
+            let age = now
+                .duration_since(entry.metadata.last_used)
+                .unwrap_or_default();
+            let keep = age <= max_age;
+            if !keep {
+                expired.push(session_id.clone());
+            }
+            keep
+        });
+
+        if !expired.is_empty() {
+            log::debug!("expired {} sessions", expired.len());
+            self.save(&expired.iter().map(String::as_str).collect::<Vec<_>>())?;
+        }
+        Ok(expired)
+    }
+
+    fn touch_entry(&mut self, session_id: &str) -> Result<bool> {
+        let Some(entry) = self.sessions.get_mut(session_id) else {
+            return Ok(false);
+        };
+        entry.update_last_used();
+        self.save(&[session_id])?;
+        Ok(true)
+    }
+
+    /// Load sessions from storage
     fn load(&mut self) -> Result<()> {
-        if let Some(storage_path) = &self.storage_path {
-            if storage_path.exists() {
-                log::trace!("reloading {}...", storage_path.display());
+        let Some(backend) = &mut self.backend else {
+            return Ok(());
+        };
+        let Some(migrator) = &self.migrator else {
+            if let Some(sessions) = backend.load()? {
+                self.sessions = sessions;
+            }
+            return Ok(());
+        };
+
+        let Some(raw) = backend.load_raw()? else {
+            return Ok(());
+        };
+        let mut sessions = HashMap::with_capacity(raw.len());
+        let mut migrated = vec![];
+        for (
+            id,
+            SessionEntry {
+                data,
+                metadata,
+                version,
+            },
+        ) in raw
+        {
+            let data = if version == migrator.version() {
+                data
+            } else {
+                migrated.push(id.clone());
+                migrator
+                    .migrate(version, data)
+                    .with_context(|| format!("could not migrate session {id}"))?
+            };
+            let data = serde_json::from_value(data)
+                .with_context(|| format!("session {id} is not valid after migration"))?;
+            sessions.insert(
+                id,
+                SessionEntry {
+                    data,
+                    metadata,
+                    version: migrator.version(),
+                },
+            );
+        }
+        self.sessions = sessions;
 
-                let contents = std::fs::read_to_string(storage_path)?;
-                if !contents.trim().is_empty() {
-                    if let Ok(sessions) = serde_json::from_str(&contents) {
-                        log::debug!("reloaded {}", storage_path.display());
+        if !migrated.is_empty() {
+            log::info!(
+                "migrated {} sessions to version {}",
+                migrated.len(),
+                migrator.version()
+            );
+            self.save(&migrated.iter().map(String::as_str).collect::<Vec<_>>())?;
+        }
+        Ok(())
+    }
 
-                        self.sessions = sessions;
-                    }
+    /// Persist the store after the sessions in `changed` were modified
+    fn save(&mut self, changed: &[&str]) -> Result<()> {
+        if let Some(migrator) = &self.migrator {
+            for id in changed {
+                if let Some(entry) = self.sessions.get_mut(*id) {
+                    entry.version = migrator.version();
                 }
             }
         }
+        let Some(window) = self.write_behind else {
+            if let Some(backend) = &mut self.backend {
+                backend.save(&self.sessions, changed)?;
+            }
+            return Ok(());
+        };
+
+        self.unsaved.extend(changed.iter().map(|id| id.to_string()));
+        let since = *self.unsaved_since.get_or_insert_with(Instant::now);
+        if since.elapsed() >= window {
+            self.save_unsaved()?;
+        }
         Ok(())
     }
+}
 
-    /// Save sessions to disk using atomic write (temp file + rename)
-    fn save(&self) -> Result<()> {
-        if let Some(storage_path) = &self.storage_path {
-            // TODO: Consider using notify-debouncer-mini for cleaner event handling
-            // Expect 2 events from atomic write (empirically observed on macOS)
-            self.ignore_next_events.store(2, Ordering::Relaxed);
+impl<T> SessionStore<T> {
+    /// Write the sessions changed since the last save in write-behind mode
+    fn save_unsaved(&mut self) -> Result<()> {
+        if self.unsaved.is_empty() {
+            return Ok(());
+        }
+        let Some(backend) = &mut self.backend else {
+            self.unsaved.clear();
+            return Ok(());
+        };
 
-            log::trace!("saving");
-            let temp_path = storage_path.with_extension("tmp");
+        let changed = self.unsaved.iter().map(String::as_str).collect::<Vec<_>>();
+        log::trace!("saving {} sessions written behind", changed.len());
+        backend.save(&self.sessions, &changed)?;
+        self.unsaved.clear();
+        self.unsaved_since = None;
+        Ok(())
+    }
+}
 
-            let contents = serde_json::to_string_pretty(&self.sessions)?;
-            std::fs::write(&temp_path, &contents)?;
-            std::fs::rename(temp_path, storage_path)?;
-            log::trace!("saved");
+impl<T> Drop for SessionStore<T> {
+    fn drop(&mut self) {
+        if let Err(e) = self.save_unsaved() {
+            log::error!("could not save sessions: {e}");
+        }
+    }
+}
+
+/// Mutable access to one session's data, returned by [`SessionStore::get_mut`]
+///
+/// Changes are persisted when the guard is dropped, or when [`SessionGuard::save`] is called.
+#[derive(Debug)]
+pub struct SessionGuard<'a, T>
+where
+    T: Serialize + for<'de> Deserialize<'de> + Clone + Default + PartialEq + Eq,
+{
+    store: &'a mut SessionStore<T>,
+    session_id: String,
+    before: Option<T>,
+    saved: bool,
+}
+
+impl<T> SessionGuard<'_, T>
+where
+    T: Serialize + for<'de> Deserialize<'de> + Clone + Default + PartialEq + Eq,
+{
+    /// Persist any changes now, returning an error if saving fails
+    pub fn save(mut self) -> Result<()> {
+        self.persist()
+    }
+
+    fn entry(&self) -> &SessionEntry<T> {
+        self.store.sessions.get(&self.session_id).unwrap()
+    }
+
+    fn entry_mut(&mut self) -> &mut SessionEntry<T> {
+        self.store.sessions.get_mut(&self.session_id).unwrap()
+    }
+
+    fn persist(&mut self) -> Result<()> {
+        if self.saved {
+            return Ok(());
+        }
+        self.saved = true;
+
+        if self.before.as_ref() != Some(&self.entry().data) {
+            self.entry_mut().update_last_used();
+            self.store.save(&[&self.session_id])?;
         }
         Ok(())
     }
 }
+
+impl<T> Deref for SessionGuard<'_, T>
+where
+    T: Serialize + for<'de> Deserialize<'de> + Clone + Default + PartialEq + Eq,
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.entry().data
+    }
+}
+
+impl<T> DerefMut for SessionGuard<'_, T>
+where
+    T: Serialize + for<'de> Deserialize<'de> + Clone + Default + PartialEq + Eq,
+{
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.entry_mut().data
+    }
+}
+
+impl<T> Drop for SessionGuard<'_, T>
+where
+    T: Serialize + for<'de> Deserialize<'de> + Clone + Default + PartialEq + Eq,
+{
+    fn drop(&mut self) {
+        if let Err(e) = self.persist() {
+            log::error!("could not save session {}: {e}", self.session_id);
+        }
+    }
+}
+
+/// One session of a [`SessionStore`], returned by [`SessionStore::scoped`]
+///
+/// Each method does the same as the [`SessionStore`] method of the same name, for this session.
+#[derive(Debug)]
+pub struct ScopedSessionStore<'a, T> {
+    store: &'a mut SessionStore<T>,
+    session_id: String,
+}
+
+impl<T> ScopedSessionStore<'_, T>
+where
+    T: Serialize + for<'de> Deserialize<'de> + Clone + Default + PartialEq + Eq,
+{
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    pub fn get_or_create(&mut self) -> Result<&T> {
+        self.store.get_or_create(&self.session_id)
+    }
+
+    pub fn get(&mut self) -> Result<Option<&T>> {
+        self.store.get(&self.session_id)
+    }
+
+    pub fn update(&mut self, fun: impl FnOnce(&mut T)) -> Result<()> {
+        self.store.update(&self.session_id, fun)
+    }
+
+    pub fn get_mut(&mut self) -> Result<SessionGuard<'_, T>> {
+        self.store.get_mut(&self.session_id)
+    }
+
+    pub fn set(&mut self, data: T) -> Result<()> {
+        self.store.set(&self.session_id, data)
+    }
+
+    pub fn remove(&mut self) -> Result<Option<T>> {
+        self.store.remove(&self.session_id)
+    }
+
+    pub fn metadata(&mut self) -> Result<Option<SessionMetadata>> {
+        self.store.metadata(&self.session_id)
+    }
+
+    pub fn touch(&mut self) -> Result<bool> {
+        self.store.touch(&self.session_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        registry::{NoTools, ToolRegistry},
+        testing::TestClient,
+        types::{ToolOutput, ToolSchema},
+    };
+    use serde_json::json;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+    struct Notes {
+        notes: Vec<String>,
+    }
+
+    fn schema(name: &str) -> ToolSchema {
+        ToolSchema {
+            name: name.into(),
+            description: None,
+            input_schema: serde_json::from_value(json!({ "type": "object" })).unwrap(),
+            output_schema: None,
+            aliases: vec![],
+            meta: None,
+        }
+    }
+
+    /// A client for a server whose state is `store`, with a `remember` tool that adds a note to
+    /// the session and a `recall` tool that lists them
+    fn client(store: SessionStore<Notes>) -> TestClient<SessionStore<Notes>, NoTools> {
+        let registry = ToolRegistry::new();
+        registry.register_fn(
+            schema("remember"),
+            |arguments, store: &mut SessionStore<Notes>, context| {
+                let note = arguments["note"].as_str().unwrap_or_default().to_string();
+                store
+                    .scoped(context)
+                    .update(|notes| notes.notes.push(note))?;
+                Ok(ToolOutput::text("remembered"))
+            },
+        );
+        registry.register_fn(schema("recall"), |_, store, context| {
+            let notes = store.scoped(context).get_or_create()?.notes.join(", ");
+            Ok(ToolOutput::text(notes))
+        });
+
+        let mut client = TestClient::new(store).with_tool_registry(registry);
+        client.initialize().unwrap();
+        client
+    }
+
+    fn remember(client: &mut TestClient<SessionStore<Notes>, NoTools>, note: &str) {
+        client
+            .call_tool("remember", json!({ "note": note }))
+            .unwrap();
+    }
+
+    fn recall(client: &mut TestClient<SessionStore<Notes>, NoTools>) -> Result<String> {
+        Ok(client.call_tool("recall", json!({}))?.to_string())
+    }
+
+    /// The session file at `path`, as JSON
+    fn stored(path: &Path) -> Value {
+        serde_json::from_slice(&fs::read(path).unwrap()).unwrap()
+    }
+
+    /// Store `data` as the default session, the way a server without a migrator would
+    fn store_unversioned(path: &Path, data: Value) {
+        let mut store = SessionStore::<Value>::new(Some(path.to_path_buf())).unwrap();
+        store.set(DEFAULT_SESSION, data).unwrap();
+    }
+
+    /// Version 1 made the single `note` a list of `notes`
+    fn migrator() -> Migrator {
+        Migrator::new(1).with_step(0, |data| Ok(json!({ "notes": [data["note"]] })))
+    }
+
+    #[test]
+    fn sessions_are_migrated_before_tools_read_them() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sessions.json");
+        store_unversioned(&path, json!({ "note": "old" }));
+
+        let store = SessionStore::new(Some(path.clone()))
+            .unwrap()
+            .with_migrator(migrator())
+            .unwrap();
+        // upgraded sessions are saved straight away
+        assert_eq!(stored(&path)[DEFAULT_SESSION]["version"], 1);
+
+        let mut client = client(store);
+        assert_eq!(recall(&mut client).unwrap(), "old");
+        remember(&mut client, "new");
+        drop(client);
+
+        let session = &stored(&path)[DEFAULT_SESSION];
+        assert_eq!(session["data"], json!({ "notes": ["old", "new"] }));
+        assert_eq!(session["version"], 1);
+    }
+
+    #[test]
+    fn sessions_that_cannot_be_migrated_are_not_dropped() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sessions.json");
+        store_unversioned(&path, json!({ "note": "old" }));
+        let unversioned = fs::read(&path).unwrap();
+
+        let failing = Migrator::new(1).with_step(0, |_| anyhow::bail!("unrecognized note"));
+        let error = SessionStore::<Notes>::new(Some(path.clone()))
+            .unwrap()
+            .with_migrator(failing)
+            .unwrap_err();
+        assert!(
+            format!("{error:#}").contains("unrecognized note"),
+            "{error:#}"
+        );
+        assert_eq!(fs::read(&path).unwrap(), unversioned);
+
+        // sessions written by a newer version of the server are refused too
+        drop(
+            SessionStore::<Notes>::new(Some(path.clone()))
+                .unwrap()
+                .with_migrator(migrator())
+                .unwrap(),
+        );
+        let migrated = fs::read(&path).unwrap();
+        assert!(
+            SessionStore::<Value>::new(Some(path.clone()))
+                .unwrap()
+                .with_migrator(Migrator::new(0))
+                .is_err()
+        );
+        assert_eq!(fs::read(&path).unwrap(), migrated);
+    }
+
+    /// A store for the session file at `path` that handles unreadable sessions with `policy`
+    fn store_with_policy(path: &Path, policy: LoadErrorPolicy) -> SessionStore<Notes> {
+        SessionStore::with_backend(
+            FileBackend::new(path.to_path_buf())
+                .unwrap()
+                .with_load_error_policy(policy),
+        )
+        .unwrap()
+    }
+
+    fn backups(dir: &TempDir) -> Vec<PathBuf> {
+        fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "bak"))
+            .collect()
+    }
+
+    const CORRUPT: &[u8] = b"{ \"default\": { \"data\": ";
+
+    #[test]
+    fn load_errors_fail_tool_calls() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sessions.json");
+        fs::write(&path, CORRUPT).unwrap();
+
+        let mut client = client(store_with_policy(&path, LoadErrorPolicy::Fail));
+        let error = recall(&mut client).unwrap_err();
+        assert!(error.to_string().contains("could not read"), "{error}");
+        assert!(
+            client
+                .call_tool("remember", json!({ "note": "new" }))
+                .is_err()
+        );
+        drop(client);
+
+        assert_eq!(fs::read(&path).unwrap(), CORRUPT);
+        assert!(backups(&dir).is_empty());
+    }
+
+    #[test]
+    fn load_errors_back_up_and_reset() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sessions.json");
+        fs::write(&path, CORRUPT).unwrap();
+
+        let mut client = client(store_with_policy(&path, LoadErrorPolicy::BackupAndReset));
+        assert_eq!(recall(&mut client).unwrap(), "");
+        remember(&mut client, "new");
+        drop(client);
+
+        let backups = backups(&dir);
+        assert_eq!(backups.len(), 1);
+        assert_eq!(fs::read(&backups[0]).unwrap(), CORRUPT);
+        assert_eq!(
+            stored(&path)[DEFAULT_SESSION]["data"]["notes"],
+            json!(["new"])
+        );
+    }
+
+    #[test]
+    fn load_errors_keep_sessions_in_memory() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sessions.json");
+        fs::write(&path, CORRUPT).unwrap();
+
+        let mut client = client(store_with_policy(&path, LoadErrorPolicy::KeepInMemory));
+        remember(&mut client, "new");
+        assert_eq!(recall(&mut client).unwrap(), "new");
+        drop(client);
+
+        assert_eq!(fs::read(&path).unwrap(), CORRUPT);
+        assert!(backups(&dir).is_empty());
+    }
+
+    #[test]
+    fn unreadable_sessions_are_kept_with_keep_in_memory() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sessions.json");
+        // `other` was written by a version of the server whose notes were a string
+        let mut store = SessionStore::<Value>::new(Some(path.clone())).unwrap();
+        store
+            .set("other", json!({ "notes": "not a list" }))
+            .unwrap();
+        drop(store);
+
+        let mut client = client(store_with_policy(&path, LoadErrorPolicy::KeepInMemory));
+        remember(&mut client, "new");
+        drop(client);
+
+        let stored = stored(&path);
+        assert_eq!(stored["other"]["data"], json!({ "notes": "not a list" }));
+        assert_eq!(stored[DEFAULT_SESSION]["data"]["notes"], json!(["new"]));
+    }
+
+    /// The notes in the default session at `path`, if it has been saved
+    fn stored_notes(path: &Path) -> Option<Value> {
+        let contents = fs::read(path).unwrap();
+        if contents.trim_ascii().is_empty() {
+            return None;
+        }
+        let stored: Value = serde_json::from_slice(&contents).unwrap();
+        stored
+            .get(DEFAULT_SESSION)
+            .map(|session| session["data"]["notes"].clone())
+    }
+
+    #[test]
+    fn write_behind_saves_on_flush_and_drop() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sessions.json");
+        let store = SessionStore::new(Some(path.clone()))
+            .unwrap()
+            .with_write_behind(Duration::from_secs(3600));
+
+        let mut client = client(store);
+        for note in ["a", "b", "c"] {
+            remember(&mut client, note);
+        }
+        assert_eq!(recall(&mut client).unwrap(), "a, b, c");
+        assert_eq!(stored_notes(&path), None);
+
+        client.state_mut().flush().unwrap();
+        assert_eq!(stored_notes(&path), Some(json!(["a", "b", "c"])));
+
+        remember(&mut client, "d");
+        assert_eq!(stored_notes(&path), Some(json!(["a", "b", "c"])));
+        drop(client);
+        assert_eq!(stored_notes(&path), Some(json!(["a", "b", "c", "d"])));
+    }
+
+    #[test]
+    fn write_behind_saves_once_the_window_has_passed() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sessions.json");
+        let window = Duration::from_millis(200);
+        let store = SessionStore::new(Some(path.clone()))
+            .unwrap()
+            .with_write_behind(window);
+
+        let mut client = client(store);
+        remember(&mut client, "a");
+        assert_eq!(stored_notes(&path), None);
+
+        std::thread::sleep(window);
+        // the next use of the store saves what was waiting
+        assert_eq!(recall(&mut client).unwrap(), "a");
+        assert_eq!(stored_notes(&path), Some(json!(["a"])));
+    }
+}
@@ -0,0 +1,102 @@
+use anyhow::{Result, anyhow, bail};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Debug, Formatter},
+    sync::Arc,
+};
+
+type Step = Arc<dyn Fn(Value) -> Result<Value> + Send + Sync>;
+
+/// Upgrades session data written by older versions of a server, registered with
+/// [`SessionStore::with_migrator`](super::SessionStore::with_migrator)
+///
+/// Each session is stored with the version of its data, which is zero for sessions written
+/// without a migrator. When a session older than [`version`](Self::version) is loaded, its data
+/// is passed as JSON through each step from its version up, and the upgraded session is saved.
+///
+/// ```ignore
+/// // version 1 renamed `dir` to `working_directory`, and version 2 made `tags` a list
+/// let migrator = Migrator::new(2)
+///     .with_step(0, |mut data| {
+///         if let Some(dir) = data.as_object_mut().and_then(|data| data.remove("dir")) {
+///             data["working_directory"] = dir;
+///         }
+///         Ok(data)
+///     })
+///     .with_step(1, |mut data| {
+///         let tags = data["tags"].take();
+///         data["tags"] = json!(tags.as_str().map(|tags| tags.split(',').collect::<Vec<_>>()));
+///         Ok(data)
+///     });
+/// ```
+#[derive(Clone)]
+pub struct Migrator {
+    version: u32,
+    steps: BTreeMap<u32, Step>,
+}
+
+impl Debug for Migrator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Migrator")
+            .field("version", &self.version)
+            .field("steps", &self.steps.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Migrator {
+    /// Stamp sessions with `version`, the current version of the session data type
+    pub fn new(version: u32) -> Self {
+        Self {
+            version,
+            steps: BTreeMap::new(),
+        }
+    }
+
+    /// Upgrade data from version `from` to version `from + 1` with `step`
+    ///
+    /// # Panics
+    ///
+    /// If `from` is not older than [`version`](Self::version)
+    #[track_caller]
+    pub fn with_step(
+        mut self,
+        from: u32,
+        step: impl Fn(Value) -> Result<Value> + Send + Sync + 'static,
+    ) -> Self {
+        assert!(
+            from < self.version,
+            "a migration step from version {from} is not older than version {}",
+            self.version
+        );
+        self.steps.insert(from, Arc::new(step));
+        self
+    }
+
+    /// The version sessions are upgraded to and saved with
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Upgrade `data` from version `from` to the current version
+    ///
+    /// Fails if `from` is newer than the current version, since the data was written by a newer
+    /// server, or if a step is missing or fails.
+    pub fn migrate(&self, from: u32, mut data: Value) -> Result<Value> {
+        if from > self.version {
+            bail!(
+                "written by version {from} of this server's session data, but this is version {}",
+                self.version
+            );
+        }
+        for version in from..self.version {
+            let step = self
+                .steps
+                .get(&version)
+                .ok_or_else(|| anyhow!("no migration from version {version}"))?;
+            data = step(data).map_err(|e| anyhow!("migrating from version {version}: {e}"))?;
+        }
+        Ok(data)
+    }
+}
@@ -0,0 +1,133 @@
+use super::SessionStore;
+use anyhow::{Context, Result};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::marker::PhantomData;
+
+/// Separates a namespace from the key within it in the ids of the underlying store
+const SEPARATOR: char = '/';
+
+impl SessionStore<Value> {
+    /// A logical map of `T`s named `name`, stored alongside other namespaces in this store
+    ///
+    /// Each key is stored as the session `"{name}/{key}"`, so one file (and one watcher) can hold
+    /// several unrelated maps, like contexts, preferences, and caches, each with its own type.
+    ///
+    /// ```ignore
+    /// let mut store = SessionStore::<Value>::new(Some(path))?;
+    /// store.namespace::<Preferences>("preferences").update("default", |p| p.verbose = true)?;
+    /// let context = store.namespace::<Context>("contexts").get_or_create("default")?;
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If `name` contains a `/`
+    #[track_caller]
+    pub fn namespace<T>(&mut self, name: &str) -> Namespace<'_, T>
+    where
+        T: Serialize + DeserializeOwned + Default,
+    {
+        assert!(
+            !name.contains(SEPARATOR),
+            "namespace {name:?} must not contain {SEPARATOR:?}"
+        );
+        Namespace {
+            store: self,
+            prefix: format!("{name}{SEPARATOR}"),
+            types: PhantomData,
+        }
+    }
+
+    /// The names of every namespace with at least one key
+    pub fn namespaces(&mut self) -> Result<Vec<String>> {
+        let mut namespaces = self
+            .keys()?
+            .into_iter()
+            .filter_map(|id| Some(id.split_once(SEPARATOR)?.0.to_string()))
+            .collect::<Vec<_>>();
+        namespaces.sort();
+        namespaces.dedup();
+        Ok(namespaces)
+    }
+}
+
+/// A typed map within a [`SessionStore<Value>`], returned by [`SessionStore::namespace`]
+///
+/// Each method does the same as the [`SessionStore`] method of the same name, for the key within
+/// this namespace. Values are converted to and from `T` on each access, so these return owned
+/// values rather than references, and fail if the stored value isn't a valid `T`.
+#[derive(Debug)]
+pub struct Namespace<'a, T> {
+    store: &'a mut SessionStore<Value>,
+    prefix: String,
+    types: PhantomData<fn() -> T>,
+}
+
+impl<T> Namespace<'_, T>
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    /// The name of this namespace
+    pub fn name(&self) -> &str {
+        self.prefix.trim_end_matches(SEPARATOR)
+    }
+
+    pub fn get(&mut self, key: &str) -> Result<Option<T>> {
+        let id = self.id(key);
+        self.store
+            .get(&id)?
+            .map(|value| T::deserialize(value).with_context(|| format!("{id} is not valid")))
+            .transpose()
+    }
+
+    pub fn get_or_create(&mut self, key: &str) -> Result<T> {
+        if let Some(data) = self.get(key)? {
+            return Ok(data);
+        }
+        let value = serde_json::to_value(T::default())?;
+        self.store.set(&self.id(key), value.clone())?;
+        Ok(T::deserialize(value)?)
+    }
+
+    /// Update the value for `key` using a closure, starting from `T::default()` if there is none
+    pub fn update(&mut self, key: &str, fun: impl FnOnce(&mut T)) -> Result<()> {
+        let mut data = self.get(key)?.unwrap_or_default();
+        fun(&mut data);
+        self.set(key, data)
+    }
+
+    pub fn set(&mut self, key: &str, data: T) -> Result<()> {
+        let value = serde_json::to_value(data)?;
+        self.store.set(&self.id(key), value)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Result<Option<T>> {
+        let id = self.id(key);
+        self.store
+            .remove(&id)?
+            .map(|value| Ok(T::deserialize(value)?))
+            .transpose()
+    }
+
+    /// The keys in this namespace, without the namespace prefix
+    pub fn keys(&mut self) -> Result<Vec<String>> {
+        Ok(self
+            .store
+            .keys()?
+            .into_iter()
+            .filter_map(|id| id.strip_prefix(&self.prefix).map(str::to_string))
+            .collect())
+    }
+
+    /// Remove every key in this namespace, leaving other namespaces alone
+    pub fn clear(&mut self) -> Result<()> {
+        for key in self.keys()? {
+            self.store.remove(&self.id(&key))?;
+        }
+        Ok(())
+    }
+
+    fn id(&self, key: &str) -> String {
+        format!("{}{key}", self.prefix)
+    }
+}
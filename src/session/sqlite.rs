@@ -0,0 +1,288 @@
+use super::{LoadErrorPolicy, SessionEntry, StorageBackend, backup_path};
+use anyhow::{Result, anyhow, bail};
+use rusqlite::{Connection, params};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Stores each session as its own row in an sqlite database
+///
+/// Unlike [`FileBackend`](super::FileBackend), saving only writes the sessions that
+/// changed, which keeps updates cheap when there are many or large sessions. Changes made by
+/// other processes are detected with `PRAGMA data_version`.
+///
+/// Rows that aren't a valid `T` are handled according to the [`LoadErrorPolicy`], which by
+/// default backs the database up and deletes them.
+#[derive(Debug)]
+pub struct SqliteBackend {
+    path: PathBuf,
+    connection: Connection,
+    data_version: Option<i64>,
+    policy: LoadErrorPolicy,
+}
+
+impl SqliteBackend {
+    /// Open (creating if needed) the sqlite database at `path`
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let connection = Connection::open(&path)?;
+        connection.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS sessions (
+                 id TEXT PRIMARY KEY NOT NULL,
+                 entry TEXT NOT NULL
+             );",
+        )?;
+
+        Ok(Self {
+            path,
+            connection,
+            data_version: None,
+            policy: LoadErrorPolicy::default(),
+        })
+    }
+
+    /// Handle rows that can't be read with `policy`, instead of backing them up
+    pub fn with_load_error_policy(mut self, policy: LoadErrorPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Apply the policy to the rows named in `unreadable`
+    fn recover(&mut self, unreadable: &[(String, serde_json::Error)]) -> Result<()> {
+        let path = self.path.display();
+        let problem = match unreadable {
+            [] => return Ok(()),
+            [(id, e)] => format!("could not read session {id} in {path}: {e}"),
+            [(id, e), rest @ ..] => format!(
+                "could not read session {id} and {} others in {path}: {e}",
+                rest.len()
+            ),
+        };
+
+        match self.policy {
+            LoadErrorPolicy::Fail => bail!(problem),
+
+            LoadErrorPolicy::KeepInMemory => {
+                log::error!("{problem}, leaving it alone");
+            }
+
+            LoadErrorPolicy::BackupAndReset => {
+                let backup = backup_path(&self.path);
+                self.connection
+                    .execute("VACUUM INTO ?1", params![backup.to_string_lossy()])?;
+                let transaction = self.connection.transaction()?;
+                for (id, _) in unreadable {
+                    transaction.execute("DELETE FROM sessions WHERE id = ?1", params![id])?;
+                }
+                transaction.commit()?;
+                log::error!("{problem}, backed it up to {}", backup.display());
+                // our own deletes don't change data_version for this connection
+            }
+        }
+        Ok(())
+    }
+
+    fn data_version(&self) -> Result<i64> {
+        Ok(self
+            .connection
+            .query_row("PRAGMA data_version", [], |row| row.get(0))?)
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> StorageBackend<T> for SqliteBackend {
+    fn load(&mut self) -> Result<Option<HashMap<String, SessionEntry<T>>>> {
+        log::trace!("reloading {}...", self.path.display());
+        self.data_version = Some(self.data_version()?);
+
+        let mut statement = self.connection.prepare("SELECT id, entry FROM sessions")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+
+        let mut sessions = HashMap::new();
+        let mut unreadable = vec![];
+        for row in rows {
+            let (id, entry) = row?;
+            match serde_json::from_str(&entry) {
+                Ok(entry) => {
+                    sessions.insert(id, entry);
+                }
+                Err(e) => unreadable.push((id, e)),
+            }
+        }
+        drop(statement);
+        self.recover(&unreadable)?;
+
+        log::debug!("reloaded {}", self.path.display());
+        Ok(Some(sessions))
+    }
+
+    fn save(
+        &mut self,
+        sessions: &HashMap<String, SessionEntry<T>>,
+        changed: &[&str],
+    ) -> Result<()> {
+        log::trace!("saving");
+        let transaction = self.connection.transaction()?;
+        for id in changed {
+            match sessions.get(*id) {
+                Some(entry) => {
+                    transaction.execute(
+                        "INSERT INTO sessions (id, entry) VALUES (?1, ?2)
+                         ON CONFLICT (id) DO UPDATE SET entry = excluded.entry",
+                        params![id, serde_json::to_string(entry)?],
+                    )?;
+                }
+                None => {
+                    transaction.execute("DELETE FROM sessions WHERE id = ?1", params![id])?;
+                }
+            }
+        }
+        transaction.commit()?;
+
+        // our own commits don't change data_version for this connection
+        log::trace!("saved");
+        Ok(())
+    }
+
+    fn has_external_changes(&mut self) -> Result<bool> {
+        let current = self.data_version()?;
+        Ok(self.data_version.is_some_and(|seen| seen != current))
+    }
+
+    fn load_raw(&mut self) -> Result<Option<HashMap<String, SessionEntry<Value>>>> {
+        log::trace!("reloading {} to migrate...", self.path.display());
+        self.data_version = Some(self.data_version()?);
+
+        let mut statement = self.connection.prepare("SELECT id, entry FROM sessions")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+
+        let mut sessions = HashMap::new();
+        for row in rows {
+            let (id, entry) = row?;
+            let entry = serde_json::from_str(&entry)
+                .map_err(|e| anyhow!("could not read session {id}: {e}"))?;
+            sessions.insert(id, entry);
+        }
+        Ok(Some(sessions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::SessionStore;
+    use tempfile::TempDir;
+
+    fn store(path: &Path) -> SessionStore<String> {
+        SessionStore::with_backend(SqliteBackend::new(path).unwrap()).unwrap()
+    }
+
+    fn stored(path: &Path) -> Vec<String> {
+        let connection = Connection::open(path).unwrap();
+        let mut statement = connection
+            .prepare("SELECT id FROM sessions ORDER BY id")
+            .unwrap();
+        statement
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn sessions_are_saved_and_loaded() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sessions.db");
+
+        let mut first = store(&path);
+        first.set("a", "one".into()).unwrap();
+        first.set("b", "two".into()).unwrap();
+        first.remove("b").unwrap();
+        assert_eq!(stored(&path), ["a"]);
+        drop(first);
+
+        let mut second = store(&path);
+        assert_eq!(second.get("a").unwrap().map(String::as_str), Some("one"));
+        assert!(second.get("b").unwrap().is_none());
+    }
+
+    #[test]
+    fn a_second_store_sees_the_first_stores_writes() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sessions.db");
+        let mut first = store(&path);
+        let mut second = store(&path);
+        assert!(second.get("a").unwrap().is_none());
+
+        first.set("a", "first".into()).unwrap();
+        assert_eq!(second.get("a").unwrap().map(String::as_str), Some("first"));
+
+        // and writing its own session keeps the first's
+        second.set("b", "second".into()).unwrap();
+        assert_eq!(stored(&path), ["a", "b"]);
+        assert_eq!(first.get("b").unwrap().map(String::as_str), Some("second"));
+
+        first.remove("a").unwrap();
+        assert!(second.get("a").unwrap().is_none());
+    }
+
+    #[test]
+    fn unreadable_rows_are_backed_up_and_deleted() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sessions.db");
+        store(&path).set("good", "fine".into()).unwrap();
+        Connection::open(&path)
+            .unwrap()
+            .execute(
+                "INSERT INTO sessions (id, entry) VALUES ('bad', 'not json')",
+                [],
+            )
+            .unwrap();
+
+        let mut store = store(&path);
+        assert_eq!(store.get("good").unwrap().map(String::as_str), Some("fine"));
+        assert_eq!(stored(&path), ["good"]);
+
+        let backups: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.ends_with(".bak"))
+            .collect();
+        let [backup] = &backups[..] else {
+            panic!("expected one backup, found {backups:?}");
+        };
+        assert_eq!(stored(&dir.path().join(backup)), ["bad", "good"]);
+    }
+
+    #[test]
+    fn unreadable_rows_can_fail_the_load() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sessions.db");
+        SqliteBackend::new(&path)
+            .unwrap()
+            .connection
+            .execute(
+                "INSERT INTO sessions (id, entry) VALUES ('bad', 'not json')",
+                [],
+            )
+            .unwrap();
+
+        let backend = SqliteBackend::new(&path)
+            .unwrap()
+            .with_load_error_policy(LoadErrorPolicy::Fail);
+        let error = SessionStore::<String>::with_backend(backend)
+            .and_then(|mut store| store.get("bad").map(|_| ()))
+            .unwrap_err();
+        assert!(error.to_string().contains("could not read session bad"));
+        assert_eq!(stored(&path), ["bad"]);
+    }
+}
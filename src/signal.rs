@@ -0,0 +1,54 @@
+//! Turning SIGINT/SIGTERM (or their windows equivalents) into a graceful shutdown
+
+use std::sync::{
+    Mutex, OnceLock,
+    atomic::{AtomicBool, Ordering},
+};
+
+type Callback = Box<dyn Fn() + Send>;
+
+static CALLBACK: Mutex<Option<Callback>> = Mutex::new(None);
+static RECEIVED: AtomicBool = AtomicBool::new(false);
+static INSTALLED: OnceLock<()> = OnceLock::new();
+
+/// Call `callback` when the process is asked to terminate, until the returned guard is dropped
+///
+/// A second signal, or a signal while no callback is registered, exits immediately, so a tool
+/// that never returns can't keep the process alive.
+pub(crate) fn on_terminate(callback: impl Fn() + Send + 'static) -> Guard {
+    INSTALLED.get_or_init(|| {
+        if let Err(e) = ctrlc::set_handler(handle) {
+            log::warn!("could not install signal handler: {e}");
+        }
+    });
+    RECEIVED.store(false, Ordering::Relaxed);
+    *CALLBACK.lock().unwrap() = Some(Box::new(callback));
+    Guard
+}
+
+/// Whether a signal has arrived since the most recent callback was registered
+pub(crate) fn received() -> bool {
+    RECEIVED.load(Ordering::Relaxed)
+}
+
+fn handle() {
+    let callback = CALLBACK.lock().unwrap();
+    match &*callback {
+        Some(callback) if !RECEIVED.swap(true, Ordering::Relaxed) => {
+            log::info!("received termination signal, shutting down");
+            callback();
+        }
+        _ => {
+            log::info!("received termination signal, exiting");
+            std::process::exit(130);
+        }
+    }
+}
+
+pub(crate) struct Guard;
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        CALLBACK.lock().unwrap().take();
+    }
+}
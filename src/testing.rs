@@ -0,0 +1,471 @@
+//! Driving a server in-process from tests, without spawning a binary
+
+use crate::{
+    auth::HttpHeaders,
+    context::{Connection, InFlight, Outbound},
+    registry::ToolRegistry,
+    server::{Hooks, ServerConfig, Types},
+    traits::{
+        AsPromptsList, AsResourcesList, AsToolsList, CompleteArgument, EnabledTools, GetPrompt,
+        ReadResource, ServerState, Tool,
+    },
+    transport::record::{self, Difference},
+    types::{
+        Health, Info, McpNotification, McpRequest, McpResponse, ProtocolVersion, ToolOutput,
+        ToolSchema, ToolsListResponse,
+    },
+};
+use anyhow::{Result, bail};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use std::{
+    fmt::Debug,
+    fs, io,
+    marker::PhantomData,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+pub use crate::{assert_schemas_snapshot, types::InitializeResult};
+
+/// Set this environment variable to rewrite snapshots that don't match instead of failing
+pub const UPDATE_SNAPSHOTS: &str = "MCPLEASE_UPDATE_SNAPSHOTS";
+
+/// How many unchanged lines are shown around the lines that differ from a snapshot
+const DIFF_CONTEXT: usize = 3;
+
+/// An MCP client connected directly to a server's `Tools` and state
+///
+/// Requests are handled synchronously on the calling thread, exactly as the stdio transport
+/// would handle them, so tests can check both tool behavior and protocol details like argument
+/// validation.
+///
+/// ```ignore
+/// use mcplease::testing::TestClient;
+///
+/// let mut client = TestClient::<_, tools::Tools>::new(State::default());
+/// client.initialize()?;
+/// let output = client.call_tool("hello", json!({ "name": "world" }))?;
+/// assert_eq!(output.to_string(), "hello world");
+/// ```
+///
+/// The client advertises no capabilities, so tools that ask it for sampling or roots get an error
+/// rather than waiting for an answer.
+pub struct TestClient<State, Tools, Resources = (), Prompts = ()> {
+    state: State,
+    config: ServerConfig,
+    registry: ToolRegistry<State>,
+    hooks: Hooks<State>,
+    connection: Connection,
+    http_headers: Option<HttpHeaders>,
+    sent: Arc<Mutex<Vec<Value>>>,
+    next_id: u64,
+    types: Types<Tools, Resources, Prompts>,
+}
+
+impl<State, Tools, Resources, Prompts> TestClient<State, Tools, Resources, Prompts>
+where
+    Tools: Debug + AsToolsList + Tool<State> + CompleteArgument<State> + EnabledTools<State>,
+    Resources: AsResourcesList + ReadResource<State>,
+    Prompts: AsPromptsList + GetPrompt<State> + CompleteArgument<State>,
+{
+    pub fn new(state: State) -> Self {
+        let sent = Arc::new(Mutex::new(vec![]));
+        let outbound = {
+            let sent = Arc::clone(&sent);
+            Outbound::new(move |message| {
+                sent.lock()
+                    .unwrap()
+                    .push(serde_json::from_str(message).unwrap_or(Value::Null));
+                Ok(())
+            })
+        };
+        let registry = ToolRegistry::new();
+        registry.subscribe(outbound.clone());
+
+        Self {
+            state,
+            config: ServerConfig::new(Info {
+                name: "test".into(),
+                version: "0.0.0".into(),
+            }),
+            registry,
+            hooks: Hooks::default(),
+            connection: Connection::new("", InFlight::default(), outbound),
+            http_headers: None,
+            sent,
+            next_id: 1,
+            types: PhantomData,
+        }
+    }
+
+    /// Serve with `config` instead of a default configuration named "test"
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Make `extension` available to tools, like
+    /// [`ServerConfig::with_extension`](crate::server::ServerConfig::with_extension), for testing
+    /// tools with a fake service
+    pub fn with_extension<T: Send + Sync + 'static>(mut self, extension: T) -> Self {
+        self.config = self.config.with_extension(extension);
+        self
+    }
+
+    /// Also serve the tools in `registry`
+    pub fn with_tool_registry(mut self, registry: ToolRegistry<State>) -> Self {
+        registry.subscribe(self.connection.outbound().clone());
+        self.registry = registry;
+        self
+    }
+
+    /// Call the state's [`ServerState`] hooks, like
+    /// [`ServerBuilder::with_server_state`](crate::server::ServerBuilder::with_server_state).
+    /// `on_shutdown` is never called, since the client doesn't know when a test is done with it.
+    pub fn with_server_state(mut self) -> Self
+    where
+        State: ServerState,
+    {
+        self.hooks = self.hooks.with_server_state();
+        self
+    }
+
+    /// Build the `initialize` instructions from the state, like
+    /// [`ServerBuilder::with_instructions_from`](crate::server::ServerBuilder::with_instructions_from)
+    pub fn with_instructions_from(
+        mut self,
+        instructions: impl Fn(&State) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.hooks = self.hooks.with_instructions_from(instructions);
+        self
+    }
+
+    /// Serve as if over a transport that streams [`OutputStream`](crate::context::OutputStream)
+    /// chunks to the client while tools run, like HTTP+SSE
+    pub fn with_output_streaming(mut self) -> Self {
+        self.connection = self.connection.with_output_streaming(true);
+        self
+    }
+
+    /// Send every request as if it came over HTTP with these headers, for testing an
+    /// [`Authorizer`](crate::auth::Authorizer) or tools that read
+    /// [`ToolContext::http_headers`](crate::context::ToolContext::http_headers)
+    pub fn with_http_headers<'a>(
+        mut self,
+        headers: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Self {
+        self.http_headers = Some(HttpHeaders::new(
+            headers
+                .into_iter()
+                .map(|(name, value)| (name.to_ascii_lowercase(), value.to_string()))
+                .collect(),
+        ));
+        self
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut State {
+        &mut self.state
+    }
+
+    pub fn into_state(self) -> State {
+        self.state
+    }
+
+    /// Send a request and return the response exactly as it would be sent over the wire
+    ///
+    /// Requests are refused before [`initialize`](Self::initialize), as they are over a
+    /// transport. A request cancelled while it ran, like a tool call past its timeout, returns the
+    /// error the server sent instead of the tool's late response, which a client never sees.
+    pub fn request(&mut self, method: &str, params: impl Serialize) -> McpResponse {
+        let id = Value::from(self.next_id);
+        self.next_id += 1;
+        let request = McpRequest::new(id.clone(), method, params);
+        crate::handle::<Tools, Resources, Prompts, State>(
+            &mut self.state,
+            &self.config,
+            &self.registry,
+            &self.hooks,
+            &mut self.connection,
+            request,
+            self.http_headers.clone(),
+        )
+        .unwrap_or_else(|| self.cancelled(id))
+    }
+
+    /// The response the server sent to the cancelled request `id`, or an error saying it was
+    /// cancelled if it sent none
+    fn cancelled(&mut self, id: Value) -> McpResponse {
+        let mut sent = self.sent.lock().unwrap();
+        let index = sent
+            .iter()
+            .position(|message| message["id"] == id && message.get("method").is_none());
+        let Some(mut response) = index.map(|index| sent.remove(index)) else {
+            return McpResponse::error_with_code(id, -32800, "the request was cancelled".into());
+        };
+        McpResponse {
+            jsonrpc: "2.0",
+            id,
+            result: response.get_mut("result").map(Value::take),
+            error: response
+                .get_mut("error")
+                .and_then(|error| serde_json::from_value(error.take()).ok()),
+        }
+    }
+
+    /// Send a notification, like `notifications/roots/list_changed`
+    pub fn notify(&mut self, method: &str, params: impl Serialize) {
+        self.connection
+            .notify(&McpNotification::new(method, params));
+    }
+
+    /// Send a request and deserialize its result, or return its error as an [`McpError`](crate::types::McpError)
+    pub fn request_as<T: DeserializeOwned>(
+        &mut self,
+        method: &str,
+        params: impl Serialize,
+    ) -> Result<T> {
+        let response = self.request(method, params);
+        match response.error {
+            Some(error) => Err(error.into()),
+            None => Ok(serde_json::from_value(
+                response.result.unwrap_or(Value::Null),
+            )?),
+        }
+    }
+
+    /// Perform the `initialize` handshake with the newest protocol version
+    pub fn initialize(&mut self) -> Result<InitializeResult> {
+        let result = self.request_as(
+            "initialize",
+            json!({
+                "protocolVersion": ProtocolVersion::ALL.iter().max().copied().unwrap_or_default(),
+                "capabilities": {},
+                "clientInfo": { "name": "mcplease-test", "version": env!("CARGO_PKG_VERSION") },
+            }),
+        )?;
+        self.notify("notifications/initialized", json!({}));
+        Ok(result)
+    }
+
+    pub fn list_tools(&mut self) -> Result<Vec<ToolSchema>> {
+        let ToolsListResponse { tools } = self.request_as("tools/list", json!({}))?;
+        Ok(tools)
+    }
+
+    /// Send `mcplease/health`, answered by the state's [`ServerState::health`] hook
+    pub fn health(&mut self) -> Result<Health> {
+        self.request_as("mcplease/health", json!({}))
+    }
+
+    /// Call the tool `name`, returning its output, or an error if the call failed, whether with
+    /// `isError` or as an [`McpError`](crate::types::McpError)
+    pub fn call_tool(&mut self, name: &str, arguments: Value) -> Result<ToolOutput> {
+        let result: Value = self.request_as(
+            "tools/call",
+            json!({ "name": name, "arguments": arguments }),
+        )?;
+        let failed = result["isError"] == true;
+        let output: ToolOutput = serde_json::from_value(result)?;
+        if failed {
+            bail!("{output}");
+        }
+        Ok(output)
+    }
+
+    /// Replay a session recorded with `serve --record` on a new connection to this client's
+    /// server, returning every message the server sent that differs from the recording
+    ///
+    /// See [`transport::record`](crate::transport::record) for how the session is replayed.
+    pub fn replay(&mut self, path: impl AsRef<Path>) -> Result<Vec<Difference>> {
+        let recording = record::read(path)?;
+        record::replay::<Tools, Resources, Prompts, State>(
+            &mut self.state,
+            &self.config,
+            &self.registry,
+            &self.hooks,
+            &recording,
+        )
+    }
+
+    /// Everything the server has sent since the last call, other than the responses returned by
+    /// [`request`](Self::request), like progress and log notifications, and timeout errors sent
+    /// while a tool was still running
+    pub fn take_sent(&mut self) -> Vec<Value> {
+        std::mem::take(&mut *self.sent.lock().unwrap())
+    }
+}
+
+impl<State: Debug, Tools, Resources, Prompts> Debug
+    for TestClient<State, Tools, Resources, Prompts>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TestClient")
+            .field("state", &self.state)
+            .field("config", &self.config)
+            .field("registry", &self.registry)
+            .field("hooks", &self.hooks)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The schemas of `Tools` as canonical JSON, for snapshot tests that catch changes to the shape
+/// of tool arguments in review
+///
+/// The schemas are those clients see in `tools/list`, with the tools sorted by name and every
+/// object by key, so the snapshot only changes when a schema does. Use it with
+/// [`assert_schemas_snapshot!`], or with a snapshot testing library:
+///
+/// ```ignore
+/// insta::assert_snapshot!(mcplease::testing::schemas_snapshot::<tools::Tools>());
+/// ```
+pub fn schemas_snapshot<Tools: AsToolsList>() -> String {
+    let mut tools = Tools::tools_list();
+    tools.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut value = serde_json::to_value(ToolsListResponse { tools }).unwrap_or_default();
+    value.sort_all_objects();
+    let mut snapshot = serde_json::to_string_pretty(&value).unwrap_or_default();
+    snapshot.push('\n');
+    snapshot
+}
+
+/// Compare `actual` with the snapshot at `path`, panicking with the lines that differ
+///
+/// The snapshot is written instead if the [`UPDATE_SNAPSHOTS`] environment variable is set, or
+/// if there isn't one yet and the `CI` environment variable isn't set. New snapshots are then
+/// created by running the tests locally and checked in, and a missing snapshot fails in CI rather
+/// than passing without checking anything.
+#[track_caller]
+pub fn assert_snapshot(path: impl AsRef<Path>, actual: &str) {
+    let path = path.as_ref();
+    let expected = match fs::read_to_string(path) {
+        Ok(expected) => Some(expected.replace("\r\n", "\n")),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => panic!("could not read snapshot {}: {e}", path.display()),
+    };
+    if expected.as_deref() == Some(actual) {
+        return;
+    }
+
+    let update = std::env::var_os(UPDATE_SNAPSHOTS).is_some();
+    if update || (expected.is_none() && std::env::var_os("CI").is_none()) {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .unwrap_or_else(|e| panic!("could not create {}: {e}", parent.display()));
+        }
+        fs::write(path, actual)
+            .unwrap_or_else(|e| panic!("could not write snapshot {}: {e}", path.display()));
+        eprintln!("wrote snapshot {}", path.display());
+        return;
+    }
+
+    match expected {
+        None => panic!(
+            "there is no snapshot at {}; run the tests with {UPDATE_SNAPSHOTS}=1 to write it",
+            path.display()
+        ),
+        Some(expected) => panic!(
+            "the snapshot at {} does not match:\n{}\nrun the tests with {UPDATE_SNAPSHOTS}=1 to \
+             accept the change",
+            path.display(),
+            snapshot_diff(&expected, actual)
+        ),
+    }
+}
+
+/// The lines that differ between `expected` and `actual`, with a few unchanged lines around them
+fn snapshot_diff(expected: &str, actual: &str) -> String {
+    let expected = expected.lines().collect::<Vec<_>>();
+    let actual = actual.lines().collect::<Vec<_>>();
+    let prefix = expected
+        .iter()
+        .zip(&actual)
+        .take_while(|(expected, actual)| expected == actual)
+        .count();
+    let suffix = expected[prefix..]
+        .iter()
+        .rev()
+        .zip(actual[prefix..].iter().rev())
+        .take_while(|(expected, actual)| expected == actual)
+        .count();
+
+    let mut diff = format!("@@ line {} @@\n", prefix + 1);
+    for line in &expected[prefix.saturating_sub(DIFF_CONTEXT)..prefix] {
+        diff.push_str(&format!("  {line}\n"));
+    }
+    for line in &expected[prefix..expected.len() - suffix] {
+        diff.push_str(&format!("- {line}\n"));
+    }
+    for line in &actual[prefix..actual.len() - suffix] {
+        diff.push_str(&format!("+ {line}\n"));
+    }
+    for line in expected[expected.len() - suffix..]
+        .iter()
+        .take(DIFF_CONTEXT)
+    {
+        diff.push_str(&format!("  {line}\n"));
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::NoTools;
+
+    #[test]
+    fn requests_before_initialize_are_refused() {
+        let mut client = TestClient::<(), NoTools>::new(());
+        let response = client.request("tools/call", json!({ "name": "hello", "arguments": {} }));
+        let error = response.error.expect("tools/call to be refused");
+        assert_eq!(error.code, -32600);
+        assert_eq!(error.message, "received tools/call before initialize");
+
+        // pings are answered at any point
+        assert!(client.request("ping", json!({})).error.is_none());
+
+        client.initialize().unwrap();
+        assert!(client.list_tools().unwrap().is_empty());
+    }
+
+    #[derive(Default)]
+    struct Greeted {
+        client: Option<String>,
+    }
+
+    impl ServerState for Greeted {
+        fn on_initialize(&mut self, client: &Info) -> Result<()> {
+            self.client = Some(client.name.to_string());
+            Ok(())
+        }
+
+        fn health(&self) -> Health {
+            Health::Degraded("tired".into())
+        }
+    }
+
+    #[test]
+    fn hooks_belong_to_the_server_not_its_registry() {
+        let registry = ToolRegistry::new();
+        let mut client = TestClient::<Greeted, NoTools>::new(Greeted::default())
+            .with_server_state()
+            .with_instructions_from(|state: &Greeted| {
+                format!("hello {}", state.client.as_deref().unwrap_or_default())
+            })
+            .with_tool_registry(registry.clone());
+        let initialized = client.initialize().unwrap();
+        assert_eq!(initialized.instructions.unwrap(), "hello mcplease-test");
+        assert_eq!(client.health().unwrap(), Health::Degraded("tired".into()));
+
+        // another server with the same registry has none of them
+        let mut other =
+            TestClient::<Greeted, NoTools>::new(Greeted::default()).with_tool_registry(registry);
+        assert_eq!(other.initialize().unwrap().instructions, None);
+        assert!(other.state().client.is_none());
+        assert_eq!(other.health().unwrap(), Health::Ok);
+    }
+}
@@ -1,4 +1,8 @@
-use crate::types::{Example, ToolSchema};
+use crate::context::ToolContext;
+use crate::types::{
+    Example, GetPromptResponse, Health, Info, PromptArgument, PromptDescriptor, ResourceContents,
+    ResourceDescriptor, ResourceTemplateDescriptor, ToolOutput, ToolSchema,
+};
 use anyhow::Result;
 use schemars::{
     JsonSchema, Schema,
@@ -7,6 +11,7 @@ use schemars::{
 };
 use serde::{Serialize, de::DeserializeOwned};
 use serde_json::Value;
+use std::collections::HashMap;
 
 pub trait WithExamples: Sized + Serialize {
     fn examples() -> Vec<Example<Self>> {
@@ -14,6 +19,27 @@ pub trait WithExamples: Sized + Serialize {
     }
 }
 
+/// Server state that reacts to the connection lifecycle
+///
+/// Every method has a default that does nothing, so implement only the hooks you need, then
+/// enable them with [`ServerBuilder::with_server_state`](crate::server::ServerBuilder::with_server_state).
+pub trait ServerState {
+    /// Called when a client sends `initialize`, before the server responds. An error is sent to
+    /// the client instead of the `initialize` response.
+    fn on_initialize(&mut self, client: &Info) -> Result<()> {
+        let _ = client;
+        Ok(())
+    }
+
+    /// Called once the server stops, or once a command line tool invocation finishes
+    fn on_shutdown(&mut self) {}
+
+    /// How the server is doing, reported to clients that send a `mcplease/health` request
+    fn health(&self) -> Health {
+        Health::Ok
+    }
+}
+
 fn remove_null(schema: &mut Schema) {
     if let Some(a @ Value::Array(_)) = schema.get_mut("type") {
         let arr = a.as_array_mut().unwrap();
@@ -27,34 +53,378 @@ fn remove_null(schema: &mut Schema) {
         let arr = a.as_array_mut().unwrap();
         arr.retain(|v| matches!(v, Value::String(s) if s != "null"));
     }
+
+    // `Option<T>` is `anyOf` T and null when T isn't a single type, like an enum with documented
+    // variants, and is described as T like other optional fields
+    let null_variant = match schema.get("anyOf") {
+        Some(Value::Array(any_of)) if any_of.len() == 2 => any_of
+            .iter()
+            .position(|variant| variant.get("type").and_then(Value::as_str) == Some("null"))
+            .filter(|index| any_of[1 - index].is_object()),
+        _ => None,
+    };
+    if let Some(index) = null_variant
+        && let Some(Value::Array(mut any_of)) = schema.remove("anyOf")
+        && let Value::Object(variant) = any_of.swap_remove(1 - index)
+        && let Some(object) = schema.as_object_mut()
+    {
+        for (key, value) in variant {
+            object.entry(key).or_insert(value);
+        }
+    }
+}
+
+/// Turn the `oneOf` that schemars emits for an enum with documented unit variants into a string
+/// `enum`, which clients understand far better, keeping each variant's documentation in
+/// `enumDescriptions` and listing it in the description for the model to read
+fn merge_string_variants(schema: &mut Schema) {
+    let Some(object) = schema.as_object_mut() else {
+        return;
+    };
+    let Some(Value::Array(variants)) = object.get("oneOf") else {
+        return;
+    };
+
+    let mut values = vec![];
+    let mut descriptions = vec![];
+    for variant in variants {
+        let Some(variant) = variant.as_object() else {
+            return;
+        };
+        if variant.get("type").and_then(Value::as_str) != Some("string")
+            || variant.keys().any(|key| {
+                !matches!(
+                    key.as_str(),
+                    "type" | "const" | "enum" | "description" | "title"
+                )
+            })
+        {
+            return;
+        }
+        let description = variant
+            .get("description")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let variant_values = match (variant.get("const"), variant.get("enum")) {
+            (Some(value), None) => std::slice::from_ref(value),
+            (None, Some(Value::Array(values))) => values.as_slice(),
+            _ => return,
+        };
+        for value in variant_values {
+            let Value::String(value) = value else {
+                return;
+            };
+            values.push(value.clone());
+            descriptions.push(description.to_string());
+        }
+    }
+
+    object.remove("oneOf");
+    object.insert("type".into(), "string".into());
+    if descriptions
+        .iter()
+        .any(|description| !description.is_empty())
+    {
+        let listed = values
+            .iter()
+            .zip(&descriptions)
+            .filter(|(_, description)| !description.is_empty())
+            .map(|(value, description)| format!("- `{value}`: {description}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let description = match object.get("description").and_then(Value::as_str) {
+            Some(description) => format!("{description}\n\n{listed}"),
+            None => listed,
+        };
+        object.insert("description".into(), description.into());
+        object.insert("enumDescriptions".into(), descriptions.into());
+    }
+    object.insert("enum".into(), values.into());
 }
 
+/// A tool that can be called over MCP or from the command line
+///
+/// Implement either [`execute`](Self::execute) or
+/// [`execute_with_context`](Self::execute_with_context); each defaults to calling the other.
 pub trait Tool<State>: Serialize + DeserializeOwned {
-    fn execute(self, state: &mut State) -> Result<String>;
+    /// Run the tool. Plain text converts into a [`ToolOutput`] with `.into()`; build one
+    /// explicitly to return images or embedded resources.
+    fn execute(self, state: &mut State) -> Result<ToolOutput> {
+        self.execute_with_context(state, &ToolContext::default())
+    }
+
+    /// Run the tool with access to the [`ToolContext`] for this call, which allows observing
+    /// cancellation. This is what the server calls.
+    fn execute_with_context(self, state: &mut State, context: &ToolContext) -> Result<ToolOutput> {
+        let _ = context;
+        self.execute(state)
+    }
+
+    /// The JSON schema of the `structuredContent` this tool returns, advertised as its
+    /// `outputSchema`. Implement [`StructuredTool`] rather than overriding this.
+    fn output_schema() -> Option<Value> {
+        None
+    }
+
+    /// Suggest values for `argument` given the partial `value` typed so far, for
+    /// `completion/complete`. Suggests nothing unless overridden.
+    fn complete(argument: &str, value: &str, state: &mut State) -> Result<Vec<String>> {
+        let _ = (argument, value, state);
+        Ok(vec![])
+    }
+
+    /// Whether this tool is available, checked whenever tools are listed or called. A disabled
+    /// tool is left out of `tools/list` and calls to it are refused.
+    ///
+    /// Always enabled unless overridden. Override it to offer a tool only when the state is
+    /// configured for it, or when an environment variable or cargo feature
+    /// (`cfg!(feature = "...")`) is set.
+    fn enabled(state: &State) -> bool {
+        let _ = state;
+        true
+    }
+
+    /// Other names this tool can be called by, like names it had before it was renamed
+    ///
+    /// Aliases aren't listed in `tools/list`, so models only learn the current name, but calls to
+    /// them are dispatched to this tool with a note in the result telling the model to use the
+    /// current name.
+    fn aliases() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Why this tool is deprecated and what to use instead, if it is
+    ///
+    /// The notice starts the tool's description and is sent as `_meta.deprecated` in
+    /// `tools/list`, and it is added to the result of each call.
+    fn deprecated() -> Option<&'static str> {
+        None
+    }
+}
+
+/// The part of a server's `State` that some of its tools work with
+///
+/// A tool can implement `Tool<GitState>` rather than `Tool<State>`, so it only sees, and can be
+/// tested with, the state it needs. Name the sub-state after the tool's entry in the
+/// [`tools!`](crate::tools) macro, or with `#[state(GitState)]` on its
+/// [`tool_router`](crate::tool_router) variant, and the server's state is projected for it:
+///
+/// ```ignore
+/// impl FromState<State> for GitState {
+///     fn from_state(state: &State) -> &Self {
+///         &state.git
+///     }
+///
+///     fn from_state_mut(state: &mut State) -> &mut Self {
+///         &mut state.git
+///     }
+/// }
+///
+/// mcplease::tools!(State, (Hello, hello, "hello"), (Status, status, "status", GitState));
+/// ```
+///
+/// Every state is a projection of itself, which is how tools without a sub-state are served.
+pub trait FromState<State> {
+    fn from_state(state: &State) -> &Self;
+
+    fn from_state_mut(state: &mut State) -> &mut Self;
+}
+
+impl<State> FromState<State> for State {
+    fn from_state(state: &State) -> &Self {
+        state
+    }
+
+    fn from_state_mut(state: &mut State) -> &mut Self {
+        state
+    }
+}
+
+/// Reports which of a server's tools are enabled, for the `Tools` enums generated by
+/// [`tools!`](crate::tools) and [`tool_router`](crate::tool_router)
+pub trait EnabledTools<State> {
+    /// Whether the tool called `name` is [enabled](Tool::enabled), or `None` if there is no such
+    /// tool
+    fn tool_enabled(name: &str, state: &State) -> Option<bool>;
+}
+
+/// A tool that returns typed, structured output
+///
+/// The output is sent to the client as `structuredContent` along with a JSON text fallback, and
+/// its schema is advertised as the tool's `outputSchema`. MCP requires structured content to be
+/// an object, so `Output` should be a struct. Every `StructuredTool` is a [`Tool`].
+pub trait StructuredTool<State>: Serialize + DeserializeOwned {
+    type Output: JsonSchema + Serialize;
+
+    fn call(self, state: &mut State, context: &ToolContext) -> Result<Self::Output>;
+
+    /// See [`Tool::complete`]
+    fn complete(argument: &str, value: &str, state: &mut State) -> Result<Vec<String>> {
+        let _ = (argument, value, state);
+        Ok(vec![])
+    }
+
+    /// See [`Tool::enabled`]
+    fn enabled(state: &State) -> bool {
+        let _ = state;
+        true
+    }
+
+    /// See [`Tool::aliases`]
+    fn aliases() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// See [`Tool::deprecated`]
+    fn deprecated() -> Option<&'static str> {
+        None
+    }
+}
+
+impl<State, T: StructuredTool<State>> Tool<State> for T {
+    fn execute_with_context(self, state: &mut State, context: &ToolContext) -> Result<ToolOutput> {
+        Ok(ToolOutput::structured(&self.call(state, context)?)?)
+    }
+
+    fn output_schema() -> Option<Value> {
+        let mut schema = root_schema_for::<T::Output>();
+        schema.remove("$schema");
+        Some(schema.into())
+    }
+
+    fn complete(argument: &str, value: &str, state: &mut State) -> Result<Vec<String>> {
+        <T as StructuredTool<State>>::complete(argument, value, state)
+    }
+
+    fn enabled(state: &State) -> bool {
+        <T as StructuredTool<State>>::enabled(state)
+    }
+
+    fn aliases() -> &'static [&'static str] {
+        <T as StructuredTool<State>>::aliases()
+    }
+
+    fn deprecated() -> Option<&'static str> {
+        <T as StructuredTool<State>>::deprecated()
+    }
 }
 
 pub trait AsToolSchema {
     fn schema() -> ToolSchema;
 }
 
+/// A tool with a fixed wire name, implemented with `#[derive(McpTool)]` and collected into a
+/// `Tools` enum by [`tool_router`](crate::tool_router)
+pub trait McpTool {
+    const NAME: &'static str;
+}
+
 pub trait AsToolsList {
     fn tools_list() -> Vec<ToolSchema>;
 }
 
+/// Completes the arguments of a server's tools or prompts, for `completion/complete`
+pub trait CompleteArgument<State> {
+    /// Suggest values for `argument` of the tool or prompt called `name`, or `None` if there is
+    /// no such tool or prompt
+    fn complete_argument(
+        name: &str,
+        argument: &str,
+        value: &str,
+        state: &mut State,
+    ) -> Option<Result<Vec<String>>>;
+}
+
+/// A single readable resource, registered with the [`resources!`](crate::resources) macro
+pub trait Resource<State> {
+    /// The uri, name, and optional description and mime type advertised in `resources/list`
+    fn descriptor() -> ResourceDescriptor;
+
+    /// Produce the current contents of this resource
+    fn read(state: &mut State) -> Result<ResourceContents>;
+}
+
+/// Readable resources whose uris match a [`UriTemplate`](crate::types::UriTemplate), like
+/// `file:///{+path}`, registered after `templates:` in the [`resources!`](crate::resources) macro
+pub trait ResourceTemplate<State> {
+    /// The uri template, name, and optional description and mime type advertised in
+    /// `resources/templates/list`
+    fn descriptor() -> ResourceTemplateDescriptor;
+
+    /// Produce the contents of the resource at `uri`, given the value of each of the template's
+    /// variables. Return [`Error::NotFound`](crate::Error::NotFound) if there is no such resource.
+    fn read(
+        uri: &str,
+        parameters: &HashMap<String, String>,
+        state: &mut State,
+    ) -> Result<ResourceContents>;
+
+    /// Suggest values for the variable `argument` given the partial `value` typed so far, for
+    /// `completion/complete`. Suggests nothing unless overridden.
+    fn complete(argument: &str, value: &str, state: &mut State) -> Result<Vec<String>> {
+        let _ = (argument, value, state);
+        Ok(vec![])
+    }
+}
+
+pub trait AsResourcesList {
+    fn resources_list() -> Vec<ResourceDescriptor>;
+
+    fn resource_templates_list() -> Vec<ResourceTemplateDescriptor> {
+        vec![]
+    }
+}
+
+pub trait ReadResource<State> {
+    /// Read the resource identified by `uri`, or `None` if there is no such resource
+    fn read_resource(uri: &str, state: &mut State) -> Option<Result<ResourceContents>>;
+
+    /// Suggest values for `argument` of the resource template `uri_template`, or `None` if there
+    /// is no such template
+    fn complete_template_argument(
+        uri_template: &str,
+        argument: &str,
+        value: &str,
+        state: &mut State,
+    ) -> Option<Result<Vec<String>>> {
+        let _ = (uri_template, argument, value, state);
+        None
+    }
+}
+
+/// `()` represents a server with no resources
+impl AsResourcesList for () {
+    fn resources_list() -> Vec<ResourceDescriptor> {
+        vec![]
+    }
+}
+
+impl<State> ReadResource<State> for () {
+    fn read_resource(_uri: &str, _state: &mut State) -> Option<Result<ResourceContents>> {
+        None
+    }
+}
+
+pub(crate) fn root_schema_for<T: JsonSchema>() -> Schema {
+    let settings = SchemaSettings::draft2020_12().with(|s| {
+        s.meta_schema = None;
+        s.inline_subschemas = true;
+    });
+
+    let generator = settings.into_generator();
+    let mut schema = generator.into_root_schema_for::<T>();
+
+    RecursiveTransform(remove_null).transform(&mut schema);
+    RecursiveTransform(merge_string_variants).transform(&mut schema);
+    schema
+}
+
 impl<T> AsToolSchema for T
 where
     T: JsonSchema + WithExamples,
 {
     fn schema() -> ToolSchema {
-        let settings = SchemaSettings::draft2020_12().with(|s| {
-            s.meta_schema = None;
-            s.inline_subschemas = true;
-        });
-
-        let generator = settings.into_generator();
-        let mut schema = generator.into_root_schema_for::<Self>();
-
-        RecursiveTransform(remove_null).transform(&mut schema);
+        let mut schema = root_schema_for::<Self>();
 
         let name = schema
             .remove("title")
@@ -71,7 +441,7 @@ where
         schema.remove("$schema");
 
         let examples = Self::examples();
-        if examples.is_empty() {
+        if !examples.is_empty() {
             schema.insert(
                 "examples".to_string(),
                 serde_json::to_value(examples).unwrap(),
@@ -79,6 +449,9 @@ where
         }
 
         let value: Value = schema.into();
+        #[cfg(feature = "raw-schema")]
+        let input_schema = crate::types::InputSchema::Raw(value);
+        #[cfg(not(feature = "raw-schema"))]
         let input_schema = match serde_json::from_value(value.clone()) {
             Ok(input_schema) => input_schema,
             Err(e) => {
@@ -93,6 +466,117 @@ where
             name,
             description: Some(description),
             input_schema,
+            output_schema: None,
+            aliases: vec![],
+            meta: None,
+        }
+    }
+}
+
+/// A prompt template, registered with the [`prompts!`](crate::prompts) macro
+///
+/// The implementing type's fields are the prompt's arguments, which clients always send as
+/// strings. Use `Option<String>` for optional arguments. A prompt that only fills its arguments
+/// into text can be a [`PromptTemplate`](crate::types::PromptTemplate) instead.
+pub trait Prompt<State>: DeserializeOwned {
+    fn render(self, state: &mut State) -> Result<GetPromptResponse>;
+
+    /// Suggest values for `argument` given the partial `value` typed so far, for
+    /// `completion/complete`. Suggests nothing unless overridden.
+    fn complete(argument: &str, value: &str, state: &mut State) -> Result<Vec<String>> {
+        let _ = (argument, value, state);
+        Ok(vec![])
+    }
+}
+
+pub trait AsPromptSchema {
+    fn prompt_schema() -> PromptDescriptor;
+}
+
+pub trait AsPromptsList {
+    fn prompts_list() -> Vec<PromptDescriptor>;
+}
+
+pub trait GetPrompt<State> {
+    /// Render the prompt named `name`, or `None` if there is no such prompt
+    fn get_prompt(
+        name: &str,
+        arguments: Value,
+        state: &mut State,
+    ) -> Option<Result<GetPromptResponse>>;
+}
+
+impl<T: JsonSchema> AsPromptSchema for T {
+    fn prompt_schema() -> PromptDescriptor {
+        let schema = root_schema_for::<Self>();
+
+        let name = schema
+            .get("title")
+            .and_then(Value::as_str)
+            .expect("prompt schema must have a title")
+            .to_string();
+
+        let description = schema
+            .get("description")
+            .and_then(Value::as_str)
+            .map(String::from);
+
+        let required = schema
+            .get("required")
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default();
+
+        let arguments = schema
+            .get("properties")
+            .and_then(Value::as_object)
+            .map(|properties| {
+                properties
+                    .iter()
+                    .map(|(name, property)| PromptArgument {
+                        name: name.clone(),
+                        description: property
+                            .get("description")
+                            .and_then(Value::as_str)
+                            .map(String::from),
+                        required: Some(required.iter().any(|r| r == name)),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        PromptDescriptor {
+            name,
+            description,
+            arguments,
         }
     }
 }
+
+/// `()` represents a server with no prompts
+impl AsPromptsList for () {
+    fn prompts_list() -> Vec<PromptDescriptor> {
+        vec![]
+    }
+}
+
+impl<State> GetPrompt<State> for () {
+    fn get_prompt(
+        _name: &str,
+        _arguments: Value,
+        _state: &mut State,
+    ) -> Option<Result<GetPromptResponse>> {
+        None
+    }
+}
+
+impl<State> CompleteArgument<State> for () {
+    fn complete_argument(
+        _name: &str,
+        _argument: &str,
+        _value: &str,
+        _state: &mut State,
+    ) -> Option<Result<Vec<String>>> {
+        None
+    }
+}
@@ -0,0 +1,652 @@
+//! Transports for serving MCP
+//!
+//! Most transports carry one client's messages both ways, and implement [`Transport`]. The
+//! network transports accept clients themselves, so they have their own [`sse::serve`] and
+//! [`tcp::serve`].
+
+mod http;
+pub mod record;
+pub mod sse;
+pub mod tcp;
+
+use std::{
+    error::Error as StdError,
+    fmt::{self, Debug, Display, Formatter},
+    io::{self, BufRead, BufReader, Read, Write},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+/// The JSON-RPC error code of the answer to a message over a transport's size limit
+pub const MESSAGE_TOO_LARGE: i32 = -32004;
+
+/// How large a message [`Stdio`] and [`Stream`] accept unless configured otherwise, 16 MiB
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// A connection to one client, carrying JSON-RPC messages in both directions
+///
+/// Messages are JSON text. The server parses, logs, and [records](record) them, and answers
+/// anything that isn't valid JSON-RPC, so implementations only move text. `recv` is called on a
+/// reader thread while `send` is called from whichever thread is responding, so both take `&self`.
+pub trait Transport: Send + Sync + 'static {
+    /// Block until the client sends a message, returning `None` once it has disconnected
+    fn recv(&self) -> io::Result<Option<String>>;
+
+    /// Send one message to the client
+    fn send(&self, message: &str) -> io::Result<()>;
+}
+
+/// The error for a message that is larger than a transport accepts
+///
+/// A transport that skips such a message returns this from [`Transport::recv`], converted into
+/// an [`io::Error`], and the server answers with a [`MESSAGE_TOO_LARGE`] error and goes on
+/// reading. Any other error from `recv` ends the session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageTooLarge {
+    /// The size of the message in bytes, as far as it was read
+    pub size: usize,
+    pub max_size: usize,
+}
+
+impl Display for MessageTooLarge {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "message of {} bytes is over the limit of {} bytes",
+            self.size, self.max_size
+        )
+    }
+}
+
+impl StdError for MessageTooLarge {}
+
+impl From<MessageTooLarge> for io::Error {
+    fn from(too_large: MessageTooLarge) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, too_large)
+    }
+}
+
+/// How messages are separated from each other on a [`Stdio`] or [`Stream`] transport
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Framing {
+    /// Whichever of the others the client's first message uses, answering the same way
+    #[default]
+    Detect,
+
+    /// One JSON message per line, as the MCP specification describes
+    ///
+    /// Messages from clients that pretty-print them across several lines are read whole too.
+    Lines,
+
+    /// Each message after a `Content-Length` header and a blank line, like the Language Server
+    /// Protocol, which some client libraries also use for MCP
+    ContentLength,
+}
+
+/// JSON-RPC over stdin and stdout, the default
+///
+/// Messages are newline-delimited unless the client frames them with `Content-Length` headers,
+/// in which case responses are framed the same way. Use [`with_framing`](Self::with_framing) to
+/// insist on one or the other.
+///
+/// Messages over 16 MiB are skipped and answered with a [`MESSAGE_TOO_LARGE`] error, and bytes
+/// that aren't UTF-8 are replaced with `U+FFFD`, so neither ends the session.
+#[derive(Debug, Default)]
+pub struct Stdio {
+    framer: Framer,
+}
+
+impl Stdio {
+    /// Frame messages with `framing` instead of detecting it
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framer = self.framer.with_framing(framing);
+        self
+    }
+
+    /// Skip messages larger than `max_message_size` bytes, instead of 16 MiB
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.framer.max_message_size = max_message_size;
+        self
+    }
+}
+
+impl Transport for Stdio {
+    fn recv(&self) -> io::Result<Option<String>> {
+        self.framer.read(&mut io::stdin().lock())
+    }
+
+    fn send(&self, message: &str) -> io::Result<()> {
+        self.framer.write(&mut io::stdout().lock(), message)
+    }
+}
+
+/// JSON-RPC over any reader and writer, like the two halves of a
+/// [`UnixStream`](std::os::unix::net::UnixStream) from `try_clone`, framed like [`Stdio`]
+pub struct Stream<R, W> {
+    reader: Mutex<BufReader<R>>,
+    writer: Mutex<W>,
+    framer: Framer,
+}
+
+impl<R: Read, W: Write> Stream<R, W> {
+    pub fn new(reader: R, writer: W) -> Self {
+        Self {
+            reader: Mutex::new(BufReader::new(reader)),
+            writer: Mutex::new(writer),
+            framer: Framer::default(),
+        }
+    }
+
+    /// Frame messages with `framing` instead of detecting it
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framer = self.framer.with_framing(framing);
+        self
+    }
+
+    /// Skip messages larger than `max_message_size` bytes, instead of 16 MiB
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.framer.max_message_size = max_message_size;
+        self
+    }
+}
+
+impl<R, W> Transport for Stream<R, W>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    fn recv(&self) -> io::Result<Option<String>> {
+        self.framer.read(&mut *self.reader.lock().unwrap())
+    }
+
+    fn send(&self, message: &str) -> io::Result<()> {
+        self.framer
+            .write(&mut *self.writer.lock().unwrap(), message)
+    }
+}
+
+impl<R, W> Debug for Stream<R, W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Stream")
+            .field("framing", &self.framer.framing())
+            .field("max_message_size", &self.framer.max_message_size)
+            .finish()
+    }
+}
+
+/// Reads and writes messages with a [`Framing`], remembering the one detected
+#[derive(Debug)]
+struct Framer {
+    framing: OnceLock<Framing>,
+    max_message_size: usize,
+}
+
+impl Default for Framer {
+    fn default() -> Self {
+        Self {
+            framing: OnceLock::new(),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+}
+
+impl Framer {
+    fn with_framing(self, framing: Framing) -> Self {
+        let framing = match framing {
+            Framing::Detect => OnceLock::new(),
+            framing => OnceLock::from(framing),
+        };
+        Self { framing, ..self }
+    }
+
+    /// The framing in use, which is [`Framing::Detect`] until the first message arrives
+    fn framing(&self) -> Framing {
+        self.framing.get().copied().unwrap_or_default()
+    }
+
+    fn read(&self, reader: &mut impl BufRead) -> io::Result<Option<String>> {
+        let framing = match self.framing.get() {
+            Some(framing) => *framing,
+            None => match detect(reader)? {
+                Some(framing) => *self.framing.get_or_init(|| framing),
+                None => return Ok(None),
+            },
+        };
+
+        match framing {
+            Framing::ContentLength => read_content_length(reader, self.max_message_size),
+            _ => read_json(reader, self.max_message_size),
+        }
+    }
+
+    fn write(&self, writer: &mut impl Write, message: &str) -> io::Result<()> {
+        match self.framing() {
+            Framing::ContentLength => write_content_length(writer, message),
+            // a server only speaks first if it is told to, so this is rare, and lines are the
+            // standard
+            _ => write_line(writer, message),
+        }
+    }
+}
+
+/// Decide from the first byte after any whitespace, or `None` at the end of the input
+///
+/// JSON-RPC messages are objects or batches, so anything else starts a header.
+fn detect(reader: &mut impl BufRead) -> io::Result<Option<Framing>> {
+    Ok(skip_whitespace(reader)?.map(|first| match first {
+        b'{' | b'[' => Framing::Lines,
+        _ => Framing::ContentLength,
+    }))
+}
+
+/// Consume whitespace, returning the byte after it without consuming that, or `None` at the end
+/// of the input
+fn skip_whitespace(reader: &mut impl BufRead) -> io::Result<Option<u8>> {
+    loop {
+        let buf = reader.fill_buf()?;
+        let Some(&first) = buf.first() else {
+            return Ok(None);
+        };
+        if !first.is_ascii_whitespace() {
+            return Ok(Some(first));
+        }
+        reader.consume(1);
+    }
+}
+
+/// Read one JSON object or array, however many lines it spans, or a line of anything else
+///
+/// This only tracks nesting and strings to find where the value ends, and leaves parsing it to
+/// the server, which answers malformed messages. A `{` at the start of a line always begins a new
+/// message, since pretty-printed messages indent what they contain, so a message that is cut off
+/// before it closes ends there, and is answered as malformed without swallowing the next one.
+fn read_json(reader: &mut impl BufRead, max_size: usize) -> io::Result<Option<String>> {
+    match skip_whitespace(reader)? {
+        None => return Ok(None),
+        Some(b'{' | b'[') => {}
+        Some(_) => return read_line(reader, max_size),
+    }
+
+    let mut message = vec![];
+    let mut size = 0;
+    let mut depth = 0_usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut line_start = false;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+
+        let mut end = None;
+        for (index, &byte) in buf.iter().enumerate() {
+            if byte == b'{' && line_start {
+                end = Some(index);
+                break;
+            }
+            line_start = byte == b'\n';
+            if in_string {
+                match byte {
+                    _ if escaped => escaped = false,
+                    b'\\' => escaped = true,
+                    b'"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match byte {
+                b'"' => in_string = true,
+                b'{' | b'[' => depth += 1,
+                b'}' | b']' => {
+                    depth = depth.saturating_sub(1);
+                    if depth == 0 {
+                        end = Some(index + 1);
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let consumed = end.unwrap_or(buf.len());
+        size += consumed;
+        keep(&mut message, &buf[..consumed], size, max_size);
+        reader.consume(consumed);
+        if end.is_some() {
+            break;
+        }
+    }
+
+    into_message(message, size, max_size).map(Some)
+}
+
+/// Read up to and including the next newline, or `None` at the end of the input
+fn read_line(reader: &mut impl BufRead, max_size: usize) -> io::Result<Option<String>> {
+    let mut line = vec![];
+    let mut size = 0;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            if size == 0 {
+                return Ok(None);
+            }
+            break;
+        }
+
+        let end = buf
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map(|index| index + 1);
+        let consumed = end.unwrap_or(buf.len());
+        size += consumed;
+        keep(&mut line, &buf[..consumed], size, max_size);
+        reader.consume(consumed);
+        if end.is_some() {
+            break;
+        }
+    }
+
+    into_message(line, size, max_size).map(Some)
+}
+
+/// Add `bytes` to `message` while it is no more than `max_size` bytes, which `size` counts
+/// including `bytes`, and otherwise drop what there is, since it will be skipped
+fn keep(message: &mut Vec<u8>, bytes: &[u8], size: usize, max_size: usize) {
+    if size <= max_size {
+        message.extend_from_slice(bytes);
+    } else {
+        *message = vec![];
+    }
+}
+
+/// `message` as text, replacing anything that isn't UTF-8, or [`MessageTooLarge`] if its `size`
+/// is over `max_size`
+fn into_message(message: Vec<u8>, size: usize, max_size: usize) -> io::Result<String> {
+    if size > max_size {
+        return Err(MessageTooLarge { size, max_size }.into());
+    }
+    Ok(match String::from_utf8(message) {
+        Ok(message) => message,
+        Err(e) => {
+            log::warn!("replacing bytes that aren't UTF-8 in a message");
+            String::from_utf8_lossy(e.as_bytes()).into_owned()
+        }
+    })
+}
+
+/// Read headers up to a blank line, then as many bytes as the `Content-Length` header says
+fn read_content_length(reader: &mut impl BufRead, max_size: usize) -> io::Result<Option<String>> {
+    let mut length = None;
+    let mut any_headers = false;
+    loop {
+        let Some(line) = read_line(reader, max_size)? else {
+            return if any_headers {
+                Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "input ended in the middle of message headers",
+                ))
+            } else {
+                Ok(None)
+            };
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            if any_headers {
+                break;
+            }
+            continue;
+        }
+        any_headers = true;
+
+        let (name, value) = line.split_once(':').ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected a message header, found {line:?}"),
+            )
+        })?;
+        if name.trim().eq_ignore_ascii_case("content-length") {
+            length = Some(value.trim().parse::<usize>().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid Content-Length {:?}", value.trim()),
+                )
+            })?);
+        }
+    }
+
+    let length = length.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message headers have no Content-Length",
+        )
+    })?;
+    if length > max_size {
+        io::copy(&mut reader.take(length as u64), &mut io::sink())?;
+        return Err(MessageTooLarge {
+            size: length,
+            max_size,
+        }
+        .into());
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    into_message(body, length, max_size).map(Some)
+}
+
+fn write_line(writer: &mut impl Write, message: &str) -> io::Result<()> {
+    writer.write_all(message.as_bytes())?;
+    writer.write_all(b"\n")?;
+    writer.flush()
+}
+
+fn write_content_length(writer: &mut impl Write, message: &str) -> io::Result<()> {
+    write!(writer, "Content-Length: {}\r\n\r\n", message.len())?;
+    writer.write_all(message.as_bytes())?;
+    writer.flush()
+}
+
+/// What a server serves over: one duplex [`Transport`], or a network transport at an address
+#[derive(Clone)]
+pub(crate) enum Serving {
+    Transport(Arc<dyn Transport>),
+    Sse(String),
+    Tcp { addr: String, token: Option<String> },
+}
+
+impl Default for Serving {
+    fn default() -> Self {
+        Self::Transport(Arc::new(Stdio::default()))
+    }
+}
+
+impl Debug for Serving {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transport(_) => f.write_str("Transport"),
+            Self::Sse(addr) => f.debug_tuple("Sse").field(addr).finish(),
+            Self::Tcp { addr, token } => f
+                .debug_struct("Tcp")
+                .field("addr", addr)
+                .field("token", &token.as_ref().map(|_| "..."))
+                .finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Every message `framer` reads from `input`, with errors as their text
+    fn read_all(framer: &Framer, input: &str) -> Vec<Result<String, String>> {
+        let mut reader = Cursor::new(input.as_bytes());
+        let mut messages = vec![];
+        loop {
+            match framer.read(&mut reader) {
+                Ok(Some(message)) => messages.push(Ok(message.trim().to_string())),
+                Ok(None) => return messages,
+                Err(e) => messages.push(Err(e.to_string())),
+            }
+        }
+    }
+
+    #[test]
+    fn pretty_printed_message_is_read_whole() {
+        let input = "{\n  \"id\": 1,\n  \"params\": {\n    \"a\": \"}\"\n  }\n}\n{\"id\": 2}\n";
+        assert_eq!(
+            read_all(&Framer::default(), input),
+            [
+                Ok("{\n  \"id\": 1,\n  \"params\": {\n    \"a\": \"}\"\n  }\n}".to_string()),
+                Ok("{\"id\": 2}".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_message_does_not_swallow_the_next() {
+        let input = "{\"id\": 1, \"method\": \"a\"\n{\"id\": 2}\n";
+        assert_eq!(
+            read_all(&Framer::default(), input),
+            [
+                Ok("{\"id\": 1, \"method\": \"a\"".to_string()),
+                Ok("{\"id\": 2}".to_string()),
+            ]
+        );
+
+        // an unterminated string too, which would otherwise hide every brace after it
+        let input = "{\"id\": 1, \"method\": \"a\n{\"id\": 2}\n";
+        assert_eq!(
+            read_all(&Framer::default(), input)[1],
+            Ok("{\"id\": 2}".to_string())
+        );
+    }
+
+    #[test]
+    fn message_over_the_limit_is_skipped() {
+        let framer = Framer {
+            max_message_size: 16,
+            ..Framer::default()
+        };
+        let input = "{\"id\": 1, \"params\": \"long enough\"}\n{\"id\": 2}\n";
+        assert_eq!(
+            read_all(&framer, input),
+            [
+                Err(MessageTooLarge {
+                    size: 34,
+                    max_size: 16
+                }
+                .to_string()),
+                Ok("{\"id\": 2}".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_framing() {
+        let framer = Framer::default().with_framing(Framing::Lines);
+        assert_eq!(
+            read_all(&framer, "{\"id\": 1}\n\n{\"id\": 2}\n"),
+            [Ok("{\"id\": 1}".to_string()), Ok("{\"id\": 2}".to_string())]
+        );
+
+        let mut output = vec![];
+        framer.write(&mut output, "{\"id\": 1}").unwrap();
+        assert_eq!(output, b"{\"id\": 1}\n");
+    }
+
+    #[test]
+    fn content_length_framing() {
+        let framer = Framer::default().with_framing(Framing::ContentLength);
+        let input = "Content-Length: 9\r\nContent-Type: application/json\r\n\r\n{\"id\": 1}\
+                     content-length: 9\r\n\r\n{\"id\": 2}";
+        assert_eq!(
+            read_all(&framer, input),
+            [Ok("{\"id\": 1}".to_string()), Ok("{\"id\": 2}".to_string())]
+        );
+
+        let mut output = vec![];
+        framer.write(&mut output, "{\"id\": 1}").unwrap();
+        assert_eq!(output, b"Content-Length: 9\r\n\r\n{\"id\": 1}");
+    }
+
+    #[test]
+    fn detect_framing() {
+        let framer = Framer::default();
+        assert_eq!(framer.framing(), Framing::Detect);
+        read_all(&framer, "  {\"id\": 1}\n");
+        assert_eq!(framer.framing(), Framing::Lines);
+
+        let framer = Framer::default();
+        assert_eq!(
+            read_all(&framer, "Content-Length: 9\r\n\r\n{\"id\": 1}"),
+            [Ok("{\"id\": 1}".to_string())]
+        );
+        assert_eq!(framer.framing(), Framing::ContentLength);
+
+        // responses are framed the way the client framed its messages
+        let mut output = vec![];
+        framer.write(&mut output, "{}").unwrap();
+        assert_eq!(output, b"Content-Length: 2\r\n\r\n{}");
+    }
+
+    #[test]
+    fn missing_or_invalid_content_length() {
+        let framer = Framer::default().with_framing(Framing::ContentLength);
+        let mut reader = Cursor::new(&b"Content-Type: application/json\r\n\r\n{}"[..]);
+        let error = framer.read(&mut reader).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "message headers have no Content-Length");
+
+        let mut reader = Cursor::new(&b"Content-Length: lots\r\n\r\n{}"[..]);
+        let error = framer.read(&mut reader).unwrap_err();
+        assert_eq!(error.to_string(), "invalid Content-Length \"lots\"");
+
+        let mut reader = Cursor::new(&b"Content-Length: 2\r\n"[..]);
+        let error = framer.read(&mut reader).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn oversized_content_length_is_skipped() {
+        let framer = Framer {
+            max_message_size: 24,
+            ..Framer::default().with_framing(Framing::ContentLength)
+        };
+        let input = "Content-Length: 30\r\n\r\n{\"id\": \"12345678901234567890\"}Content-Length: 9\r\n\r\n{\"id\": 2}";
+        assert_eq!(
+            read_all(&framer, input),
+            [
+                Err(MessageTooLarge {
+                    size: 30,
+                    max_size: 24
+                }
+                .to_string()),
+                Ok("{\"id\": 2}".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn stream_goes_on_after_an_oversized_or_invalid_message() {
+        let input = [
+            &b"{\"id\": 1, \"params\": \"long enough to skip\"}\n"[..],
+            b"{\"id\": 2, \"name\": \"\xff\"}\n",
+        ]
+        .concat();
+        let stream = Stream::new(Cursor::new(input), io::sink()).with_max_message_size(32);
+
+        let error = stream.recv().unwrap_err();
+        let too_large = error
+            .get_ref()
+            .and_then(|error| error.downcast_ref::<MessageTooLarge>())
+            .expect("a MessageTooLarge");
+        assert_eq!(too_large.max_size, 32);
+
+        assert_eq!(
+            stream.recv().unwrap().unwrap().trim(),
+            "{\"id\": 2, \"name\": \"\u{fffd}\"}"
+        );
+        assert_eq!(stream.recv().unwrap(), None);
+    }
+}
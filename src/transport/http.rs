@@ -0,0 +1,337 @@
+//! Just enough HTTP/1.1 to serve the network transports without pulling in a web framework
+
+use anyhow::{Result, anyhow};
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io::{BufRead, Read, Write},
+};
+
+/// Requests with bodies larger than this are rejected
+const MAX_BODY_LENGTH: usize = 16 * 1024 * 1024;
+
+/// Requests whose request line and headers together are longer than this are rejected
+const MAX_HEAD_LENGTH: usize = 16 * 1024;
+
+/// Requests with more headers than this are rejected
+const MAX_HEADERS: usize = 64;
+
+#[derive(Debug)]
+pub(crate) struct Request {
+    pub(crate) method: String,
+    pub(crate) path: String,
+    pub(crate) query: Vec<(String, String)>,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+}
+
+impl Request {
+    /// Read a single request, returning `None` if the connection closed before a request line
+    pub(crate) fn read(reader: &mut impl BufRead) -> Result<Option<Self>> {
+        // what's left of MAX_HEAD_LENGTH
+        let mut remaining = MAX_HEAD_LENGTH;
+        let mut line = String::new();
+        if read_line(reader, &mut line, &mut remaining)? == 0 {
+            return Ok(None);
+        }
+
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return Err(anyhow!("malformed request line: {line:?}"));
+        };
+        let method = method.to_string();
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path.to_string(), parse_query(query)),
+            None => (target.to_string(), vec![]),
+        };
+
+        let mut headers = vec![];
+        loop {
+            line.clear();
+            if read_line(reader, &mut line, &mut remaining)? == 0 {
+                break;
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if headers.len() == MAX_HEADERS {
+                return Err(HeadersTooLarge.into());
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+            }
+        }
+
+        let mut request = Self {
+            method,
+            path,
+            query,
+            headers,
+            body: vec![],
+        };
+
+        if let Some(length) = request.header("content-length") {
+            let length: usize = length.parse()?;
+            if length > MAX_BODY_LENGTH {
+                return Err(BodyTooLarge(length).into());
+            }
+            request.body = vec![0; length];
+            reader.read_exact(&mut request.body)?;
+        }
+
+        Ok(Some(request))
+    }
+
+    /// Case-insensitive header lookup
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub(crate) fn query(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Read a line of at most `remaining` bytes into `line`, counting it against `remaining`
+fn read_line(reader: &mut impl BufRead, line: &mut String, remaining: &mut usize) -> Result<usize> {
+    let read = reader.by_ref().take(*remaining as u64).read_line(line)?;
+    *remaining -= read;
+    if *remaining == 0 && !line.ends_with('\n') {
+        return Err(HeadersTooLarge.into());
+    }
+    Ok(read)
+}
+
+/// The error for a request whose `Content-Length` is over the limit, to answer with a 413
+#[derive(Debug)]
+pub(crate) struct BodyTooLarge(usize);
+
+impl Display for BodyTooLarge {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "request body of {} bytes is too large", self.0)
+    }
+}
+
+impl Error for BodyTooLarge {}
+
+/// The error for a request with too many headers or too long a head, to answer with a 431
+#[derive(Debug)]
+pub(crate) struct HeadersTooLarge;
+
+impl Display for HeadersTooLarge {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("request headers are too large")
+    }
+}
+
+impl Error for HeadersTooLarge {}
+
+/// The status to answer a request that [`Request::read`] failed to read with
+pub(crate) fn error_status(error: &anyhow::Error) -> u16 {
+    if error.is::<BodyTooLarge>() {
+        413
+    } else if error.is::<HeadersTooLarge>() {
+        431
+    } else {
+        400
+    }
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None => (pair.to_string(), String::new()),
+        })
+        .collect()
+}
+
+/// Write a complete response with a body and close-delimited connection semantics
+pub(crate) fn respond(
+    stream: &mut impl Write,
+    status: u16,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> std::io::Result<()> {
+    write!(stream, "HTTP/1.1 {status} {}\r\n", reason(status))?;
+    for (name, value) in headers {
+        write!(stream, "{name}: {value}\r\n")?;
+    }
+    write!(
+        stream,
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+/// Write the head of a `text/event-stream` response. Events are written afterwards with
+/// [`write_event`].
+pub(crate) fn start_event_stream(
+    stream: &mut impl Write,
+    headers: &[(&str, &str)],
+) -> std::io::Result<()> {
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\n\
+          Content-Type: text/event-stream\r\n\
+          Cache-Control: no-cache\r\n\
+          Connection: keep-alive\r\n",
+    )?;
+    for (name, value) in headers {
+        write!(stream, "{name}: {value}\r\n")?;
+    }
+    stream.write_all(b"\r\n")?;
+    stream.flush()
+}
+
+pub(crate) fn write_event(stream: &mut impl Write, event: &str, data: &str) -> std::io::Result<()> {
+    writeln!(stream, "event: {event}")?;
+    for line in data.lines() {
+        writeln!(stream, "data: {line}")?;
+    }
+    stream.write_all(b"\n")?;
+    stream.flush()
+}
+
+/// A random identifier suitable for naming a session, from the operating system's secure random
+/// number generator, since knowing a session's id is enough to send messages as its client
+pub(crate) fn random_id() -> Result<String> {
+    let mut bytes = [0; 16];
+    getrandom::fill(&mut bytes).map_err(|e| anyhow!("could not generate a session id: {e}"))?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(request: &str) -> Result<Option<Request>> {
+        Request::read(&mut request.as_bytes())
+    }
+
+    #[test]
+    fn request() {
+        let request = read(
+            "POST /messages?sessionId=abc&flag HTTP/1.1\r\n\
+             Host: localhost\r\n\
+             Content-Type: application/json\r\n\
+             Content-Length: 2\r\n\
+             \r\n\
+             {}trailing",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/messages");
+        assert_eq!(request.query("sessionId"), Some("abc"));
+        assert_eq!(request.query("flag"), Some(""));
+        assert_eq!(request.query("missing"), None);
+        assert_eq!(request.header("content-type"), Some("application/json"));
+        assert_eq!(request.header("HOST"), Some("localhost"));
+        assert_eq!(request.body, b"{}");
+    }
+
+    #[test]
+    fn closed_connection() {
+        assert!(read("").unwrap().is_none());
+    }
+
+    #[test]
+    fn malformed_request_line() {
+        assert_eq!(error_status(&read("GET\r\n\r\n").unwrap_err()), 400);
+        assert!(read("\r\n").is_err());
+    }
+
+    #[test]
+    fn invalid_content_length() {
+        assert!(read("POST /messages HTTP/1.1\r\nContent-Length: many\r\n\r\n").is_err());
+        // shorter than it says
+        assert!(read("POST /messages HTTP/1.1\r\nContent-Length: 10\r\n\r\n{}").is_err());
+    }
+
+    #[test]
+    fn body_over_the_limit() {
+        let error = read(&format!(
+            "POST /messages HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_LENGTH + 1
+        ))
+        .unwrap_err();
+        assert!(error.is::<BodyTooLarge>(), "{error}");
+        assert_eq!(error_status(&error), 413);
+    }
+
+    #[test]
+    fn head_over_the_limit() {
+        let error = read(&format!(
+            "GET /{} HTTP/1.1\r\n\r\n",
+            "a".repeat(MAX_HEAD_LENGTH)
+        ))
+        .unwrap_err();
+        assert!(error.is::<HeadersTooLarge>(), "{error}");
+        assert_eq!(error_status(&error), 431);
+
+        // a header that never ends
+        let mut endless = "GET / HTTP/1.1\r\nX-Endless: "
+            .as_bytes()
+            .chain(std::io::repeat(b'a'));
+        let error = Request::read(&mut std::io::BufReader::new(&mut endless)).unwrap_err();
+        assert!(error.is::<HeadersTooLarge>(), "{error}");
+
+        // just under the limit is fine
+        let path = "a".repeat(MAX_HEAD_LENGTH - "GET / HTTP/1.1\r\n\r\n".len());
+        assert!(
+            read(&format!("GET /{path} HTTP/1.1\r\n\r\n"))
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn too_many_headers() {
+        let headers = "X-Header: value\r\n".repeat(MAX_HEADERS);
+        assert!(
+            read(&format!("GET / HTTP/1.1\r\n{headers}\r\n"))
+                .unwrap()
+                .is_some()
+        );
+
+        let headers = "X-Header: value\r\n".repeat(MAX_HEADERS + 1);
+        let error = read(&format!("GET / HTTP/1.1\r\n{headers}\r\n")).unwrap_err();
+        assert!(error.is::<HeadersTooLarge>(), "{error}");
+    }
+
+    #[test]
+    fn random_ids() {
+        let id = random_id().unwrap();
+        assert_eq!(id.len(), 32);
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(id, random_id().unwrap());
+    }
+}
@@ -0,0 +1,256 @@
+//! Recording sessions to a file, and replaying them to find changes in behavior
+//!
+//! A recording has one JSON object per line, holding a message and the direction it went:
+//!
+//! ```json
+//! {"direction":"in","message":{"jsonrpc":"2.0","id":1,"method":"tools/list"}}
+//! {"direction":"out","message":{"jsonrpc":"2.0","id":1,"result":{"tools":[]}}}
+//! ```
+//!
+//! Replaying sends the client's messages to the server again and compares everything the server
+//! sends back with what it sent when the session was recorded. Each client message is delivered
+//! once the server has sent as many messages as it had when that message arrived the first time,
+//! so responses to sampling requests and cancellations reach the server at the same point in the
+//! conversation as they did originally.
+
+use crate::{
+    context::{Connection, InFlight, Outbound},
+    registry::ToolRegistry,
+    server::{Hooks, ServerConfig},
+    traits::{
+        AsPromptsList, AsResourcesList, AsToolsList, CompleteArgument, EnabledTools, GetPrompt,
+        ReadResource, Tool,
+    },
+    types::McpMessage,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, mpsc},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long replay waits for the server to catch up with the recording before delivering the
+/// client's next message anyway
+const WAIT: Duration = Duration::from_secs(5);
+
+/// Which way a recorded message went
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// From the client to the server
+    In,
+
+    /// From the server to the client
+    Out,
+}
+
+/// One line of a recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    pub direction: Direction,
+
+    /// The message, or the line as a string if the client sent something other than JSON
+    pub message: Value,
+}
+
+/// Read the recording at `path`
+pub fn read(path: impl AsRef<Path>) -> Result<Vec<RecordedMessage>> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("could not open {}", path.display()))?;
+    let mut messages = vec![];
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        messages.push(
+            serde_json::from_str(&line)
+                .with_context(|| format!("{}:{}: invalid recording", path.display(), index + 1))?,
+        );
+    }
+    Ok(messages)
+}
+
+/// Appends every message on a connection to a recording
+#[derive(Clone)]
+pub(crate) struct Recorder(Arc<Mutex<File>>);
+
+impl Recorder {
+    /// Start a new recording at `path`, replacing any file that is already there
+    pub(crate) fn create(path: &Path) -> Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("could not create {}", path.display()))?;
+        Ok(Self(Arc::new(Mutex::new(file))))
+    }
+
+    pub(crate) fn record(&self, direction: Direction, message: &str) {
+        let message = serde_json::from_str(message)
+            .unwrap_or_else(|_| Value::String(message.trim_end().to_string()));
+        let result = serde_json::to_string(&RecordedMessage { direction, message })
+            .map_err(anyhow::Error::from)
+            .and_then(|line| Ok(writeln!(self.0.lock().unwrap(), "{line}")?));
+        if let Err(e) = result {
+            log::error!("could not record message: {e}");
+        }
+    }
+}
+
+/// `path` with `-{number}` added to its name, before any extension
+pub(crate) fn numbered(path: &Path, number: usize) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!("-{number}"));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+impl Debug for Recorder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("Recorder")
+    }
+}
+
+/// A message the server sent during replay that doesn't match the recording
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    /// The position of the message among those the server sent, starting at zero
+    pub index: usize,
+
+    /// What the server sent when the session was recorded, if it sent this many messages
+    pub expected: Option<Value>,
+
+    /// What the server sent during replay, if it sent this many messages
+    pub actual: Option<Value>,
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let show = |message: &Option<Value>| match message {
+            Some(message) => message.to_string(),
+            None => "nothing".to_string(),
+        };
+        write!(
+            f,
+            "message {}:\n  expected: {}\n    actual: {}",
+            self.index,
+            show(&self.expected),
+            show(&self.actual)
+        )
+    }
+}
+
+/// Send the client's messages from `recording` to the server and return every way its replies
+/// differ from the recorded ones
+pub(crate) fn replay<Tools, Resources, Prompts, State>(
+    state: &mut State,
+    config: &ServerConfig,
+    registry: &ToolRegistry<State>,
+    hooks: &Hooks<State>,
+    recording: &[RecordedMessage],
+) -> Result<Vec<Difference>>
+where
+    Tools: Debug + AsToolsList + Tool<State> + CompleteArgument<State> + EnabledTools<State>,
+    Resources: AsResourcesList + ReadResource<State>,
+    Prompts: AsPromptsList + GetPrompt<State> + CompleteArgument<State>,
+{
+    let sent = Arc::new(Mutex::new(vec![]));
+    let outbound = {
+        let sent = Arc::clone(&sent);
+        Outbound::new(move |message| {
+            sent.lock()
+                .unwrap()
+                .push(serde_json::from_str(message).unwrap_or(Value::Null));
+            Ok(())
+        })
+    };
+    let in_flight = InFlight::default();
+    let mut connection = Connection::new("", in_flight.clone(), outbound.clone());
+    registry.subscribe(outbound.clone());
+
+    // each of the client's messages, with how many messages the server had sent before it
+    let mut inbound = vec![];
+    let mut expected = vec![];
+    for recorded in recording {
+        match recorded.direction {
+            Direction::In => inbound.push((expected.len(), recorded.message.clone())),
+            Direction::Out => expected.push(recorded.message.clone()),
+        }
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    {
+        let sent = Arc::clone(&sent);
+        thread::spawn(move || {
+            for (sent_before, message) in inbound {
+                let deadline = Instant::now() + WAIT;
+                while sent.lock().unwrap().len() < sent_before && Instant::now() < deadline {
+                    thread::sleep(Duration::from_millis(5));
+                }
+
+                let bytes = match message {
+                    Value::String(line) => line.into_bytes(),
+                    message => message.to_string().into_bytes(),
+                };
+                match McpMessage::parse(&bytes) {
+                    Ok(message) => {
+                        if let Some(message) = in_flight.receive("", message)
+                            && sender.send(Some(message)).is_err()
+                        {
+                            return;
+                        }
+                    }
+                    Err(response) => {
+                        let _ = outbound.send(&response);
+                    }
+                }
+            }
+            in_flight.disconnect("");
+            let _ = sender.send(None);
+        });
+    }
+
+    crate::dispatch::<Tools, Resources, Prompts, State>(
+        state,
+        config,
+        registry,
+        hooks,
+        &mut connection,
+        &receiver,
+    )?;
+
+    let actual = std::mem::take(&mut *sent.lock().unwrap());
+    Ok((0..expected.len().max(actual.len()))
+        .filter_map(|index| {
+            let expected = expected.get(index);
+            let actual = actual.get(index);
+            (expected != actual).then(|| Difference {
+                index,
+                expected: expected.cloned(),
+                actual: actual.cloned(),
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbered_paths() {
+        assert_eq!(
+            numbered(Path::new("sessions/basic.jsonl"), 2),
+            Path::new("sessions/basic-2.jsonl")
+        );
+        assert_eq!(numbered(Path::new("session"), 1), Path::new("session-1"));
+    }
+}
@@ -0,0 +1,579 @@
+//! The HTTP+SSE transport from the 2024-11-05 MCP specification
+//!
+//! Clients open a long-lived `GET /sse` event stream. The first event is `endpoint`, whose data
+//! is the url the client should `POST` its JSON-RPC messages to. Responses to those messages are
+//! delivered as `message` events on the stream rather than in the POST response body.
+
+use super::http::{self, Request};
+use crate::{
+    auth::HttpHeaders,
+    context::{Connection, InFlight, Outbound},
+    registry::ToolRegistry,
+    server::{Hooks, ServerConfig},
+    signal,
+    traits::{
+        AsPromptsList, AsResourcesList, AsToolsList, CompleteArgument, EnabledTools, GetPrompt,
+        ReadResource, Tool,
+    },
+    types::McpMessage,
+};
+use anyhow::Result;
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    io::{BufReader, Read},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Sender},
+    },
+    thread,
+    time::Duration,
+};
+
+/// Each session's event stream, locked on its own so that a slow client only holds up itself
+type Sessions = Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>;
+
+/// Connections past this many are answered with a 503 and closed
+const MAX_CONNECTIONS: usize = 256;
+
+/// How long a client has to send each part of a request, or to take each event sent to it,
+/// before it is disconnected
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What the connection threads pass to the dispatch loop
+#[derive(Debug)]
+enum Event {
+    /// A message from a session, with the headers of the POST that carried it
+    Message(String, McpMessage, HttpHeaders),
+    /// A session's event stream closed
+    Disconnected(String),
+}
+
+/// Serve MCP over HTTP+SSE at `addr`, blocking until the process receives SIGINT or SIGTERM
+///
+/// Connection handling happens on background threads, but every message is dispatched on the
+/// calling thread, so `State` does not need to be `Send`.
+pub fn serve<Tools, Resources, Prompts, State>(
+    state: &mut State,
+    config: &ServerConfig,
+    registry: &ToolRegistry<State>,
+    hooks: &Hooks<State>,
+    addr: impl ToSocketAddrs,
+) -> Result<()>
+where
+    Tools: Debug + AsToolsList + Tool<State> + CompleteArgument<State> + EnabledTools<State>,
+    Resources: AsResourcesList + ReadResource<State>,
+    Prompts: AsPromptsList + GetPrompt<State> + CompleteArgument<State>,
+{
+    let listener = TcpListener::bind(addr)?;
+    log::info!("serving sse on http://{}/sse", listener.local_addr()?);
+
+    let sessions = Sessions::default();
+    let in_flight = InFlight::default();
+    let mut connections = HashMap::new();
+    // `None` means stop serving because of a signal
+    let (sender, receiver) = mpsc::channel();
+    let _signal = {
+        let in_flight = in_flight.clone();
+        let sender = sender.clone();
+        signal::on_terminate(move || {
+            in_flight.cancel_all();
+            let _ = sender.send(None);
+        })
+    };
+    {
+        let sessions = Arc::clone(&sessions);
+        let in_flight = in_flight.clone();
+        let origins = config.allowed_origins().into();
+        thread::spawn(move || accept(listener, sessions, in_flight, origins, sender));
+    }
+
+    while let Ok(Some(event)) = receiver.recv() {
+        let (session_id, message, headers) = match event {
+            Event::Message(session_id, message, headers) => (session_id, message, headers),
+            Event::Disconnected(session_id) => {
+                connections.remove(&session_id);
+                // nothing is running for the session between messages, so it can be forgotten
+                in_flight.forget(&session_id);
+                continue;
+            }
+        };
+        // a message can arrive just after its session closed, with nobody left to answer
+        if !sessions.lock().unwrap().contains_key(&session_id) {
+            log::debug!("session {session_id} disconnected, ignoring {message:?}");
+            connections.remove(&session_id);
+            in_flight.forget(&session_id);
+            continue;
+        }
+        match message {
+            McpMessage::Request(request) => {
+                let connection = connections.entry(session_id.clone()).or_insert_with(|| {
+                    let outbound = outbound(&sessions, &session_id);
+                    registry.subscribe(outbound.clone());
+                    Connection::new(&session_id, in_flight.clone(), outbound)
+                        .with_session_id(Some(session_id.clone()))
+                        .with_output_streaming(true)
+                });
+                let Some(response) = crate::handle::<Tools, Resources, Prompts, State>(
+                    state,
+                    config,
+                    registry,
+                    hooks,
+                    connection,
+                    request,
+                    Some(headers),
+                ) else {
+                    continue;
+                };
+                if let Err(e) = connection.outbound().send(&response) {
+                    log::error!("could not respond to session {session_id}: {e}");
+                    connections.remove(&session_id);
+                }
+            }
+            McpMessage::Notification(notification) => {
+                if let Some(connection) = connections.get_mut(&session_id) {
+                    connection.notify(&notification);
+                }
+            }
+            other => {
+                log::trace!("received {other:?}, ignoring");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Sends `message` events on the event stream for `session_id`
+fn outbound(sessions: &Sessions, session_id: &str) -> Outbound {
+    let sessions = Arc::clone(sessions);
+    let session_id = session_id.to_string();
+    Outbound::new(move |message| {
+        let Some(stream) = sessions.lock().unwrap().get(&session_id).cloned() else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                format!("session {session_id} disconnected"),
+            ));
+        };
+
+        let result = http::write_event(&mut *stream.lock().unwrap(), "message", message);
+        if result.is_err() {
+            let mut sessions = sessions.lock().unwrap();
+            if sessions
+                .get(&session_id)
+                .is_some_and(|current| Arc::ptr_eq(current, &stream))
+            {
+                sessions.remove(&session_id);
+            }
+        }
+        result
+    })
+}
+
+/// Hand each connection to a thread of its own, up to [`MAX_CONNECTIONS`] at once. `origins` are
+/// the allowed origins besides local ones.
+fn accept(
+    listener: TcpListener,
+    sessions: Sessions,
+    in_flight: InFlight,
+    origins: Arc<[String]>,
+    sender: Sender<Option<Event>>,
+) {
+    let connections = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        match stream {
+            Ok(mut stream) => {
+                if let Err(e) = stream
+                    .set_read_timeout(Some(TIMEOUT))
+                    .and_then(|()| stream.set_write_timeout(Some(TIMEOUT)))
+                {
+                    log::error!("could not set a timeout: {e}");
+                    continue;
+                }
+                if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                    connections.fetch_sub(1, Ordering::SeqCst);
+                    log::warn!("refusing a connection, {MAX_CONNECTIONS} are already open");
+                    let _ = http::respond(&mut stream, 503, &[], b"Too many connections");
+                    continue;
+                }
+                let connections = Arc::clone(&connections);
+                let sessions = Arc::clone(&sessions);
+                let in_flight = in_flight.clone();
+                let origins = Arc::clone(&origins);
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    if let Err(e) = handle_connection(stream, sessions, in_flight, &origins, sender)
+                    {
+                        log::error!("{e}");
+                    }
+                    connections.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+            Err(e) => log::error!("could not accept connection: {e}"),
+        }
+    }
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    sessions: Sessions,
+    in_flight: InFlight,
+    origins: &[String],
+    sender: Sender<Option<Event>>,
+) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let request = match Request::read(&mut reader) {
+        Ok(Some(request)) => request,
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            http::respond(
+                &mut stream,
+                http::error_status(&e),
+                &[],
+                e.to_string().as_bytes(),
+            )?;
+            return Err(e);
+        }
+    };
+
+    // browsers send an origin, so a page can't reach a local server by rebinding its own name
+    if let Some(origin) = request.header("origin")
+        && !origin_allowed(origin, origins)
+    {
+        log::warn!("refusing a request from {origin}");
+        http::respond(&mut stream, 403, &[], b"Origin not allowed")?;
+        return Ok(());
+    }
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/sse") => {
+            let session_id = http::random_id()?;
+            // the event stream stays open, and quiet, for as long as the client is connected
+            stream.set_read_timeout(None)?;
+            let events = Arc::new(Mutex::new(stream.try_clone()?));
+            // known before the client learns the endpoint, so that it can post right away
+            sessions
+                .lock()
+                .unwrap()
+                .insert(session_id.clone(), Arc::clone(&events));
+            {
+                let mut stream = events.lock().unwrap();
+                http::start_event_stream(&mut *stream, &[])?;
+                http::write_event(
+                    &mut *stream,
+                    "endpoint",
+                    &format!("/messages?sessionId={session_id}"),
+                )?;
+            }
+            log::debug!("sse session {session_id} connected");
+
+            // the client never sends anything else on this connection, so reading only
+            // returns once it disconnects
+            let mut buf = [0; 64];
+            while matches!(reader.read(&mut buf), Ok(n) if n > 0) {}
+
+            log::debug!("sse session {session_id} disconnected");
+            sessions.lock().unwrap().remove(&session_id);
+            in_flight.disconnect(&session_id);
+            let _ = sender.send(Some(Event::Disconnected(session_id)));
+        }
+
+        ("POST", "/messages") => {
+            let session_id = request.query("sessionId").unwrap_or_default();
+            if !sessions.lock().unwrap().contains_key(session_id) {
+                http::respond(&mut stream, 404, &[], b"Unknown session")?;
+                return Ok(());
+            }
+
+            log::trace!(
+                "<- [{session_id}] {}",
+                String::from_utf8_lossy(&request.body)
+            );
+            let message = match McpMessage::parse(&request.body) {
+                Ok(message) => message,
+                Err(response) => {
+                    http::respond(
+                        &mut stream,
+                        400,
+                        &[("Content-Type", "application/json")],
+                        &serde_json::to_vec(&response)?,
+                    )?;
+                    return Ok(());
+                }
+            };
+
+            if let Some(message) = in_flight.receive(session_id, message) {
+                sender.send(Some(Event::Message(
+                    session_id.to_string(),
+                    message,
+                    HttpHeaders::new(request.headers.clone()),
+                )))?;
+            }
+            http::respond(&mut stream, 202, &[], b"Accepted")?;
+        }
+
+        (_, "/sse" | "/messages") => {
+            http::respond(&mut stream, 405, &[], b"Method Not Allowed")?;
+        }
+
+        _ => {
+            http::respond(&mut stream, 404, &[], b"Not Found")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether `origin` is on this machine, like `http://localhost:3000`, or one of `allowed`
+fn origin_allowed(origin: &str, allowed: &[String]) -> bool {
+    let origin = origin.trim_end_matches('/');
+    if allowed
+        .iter()
+        .any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(origin))
+    {
+        return true;
+    }
+
+    let Some((_, authority)) = origin.split_once("://") else {
+        // including `null`, from sandboxed pages and local files
+        return false;
+    };
+    let host = match authority.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next().unwrap_or_default(),
+        None => authority.split(':').next().unwrap_or_default(),
+    };
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, Write},
+        net::SocketAddr,
+        sync::mpsc::Receiver,
+    };
+
+    fn server() -> (SocketAddr, Receiver<Option<Event>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let origins = Arc::from(["https://app.example.com".to_string()]);
+        thread::spawn(move || {
+            accept(
+                listener,
+                Sessions::default(),
+                InFlight::default(),
+                origins,
+                sender,
+            )
+        });
+        (addr, receiver)
+    }
+
+    /// Send `request` on a connection of its own, returning the status and body of the response
+    fn send(addr: SocketAddr, request: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").unwrap().1.to_string();
+        (status, body)
+    }
+
+    fn post(addr: SocketAddr, path: &str, body: &str) -> (u16, String) {
+        send(
+            addr,
+            &format!(
+                "POST {path} HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            ),
+        )
+    }
+
+    /// Open an event stream, returning it with the endpoint it names
+    fn open(addr: SocketAddr) -> (BufReader<TcpStream>, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /sse HTTP/1.1\r\n\r\n").unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        while !line.starts_with("data: ") {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+        }
+        let endpoint = line["data: ".len()..].trim_end().to_string();
+        (reader, endpoint)
+    }
+
+    const PING: &str = r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#;
+
+    #[test]
+    fn messages_are_received_for_open_sessions() {
+        let (addr, receiver) = server();
+        let (_stream, endpoint) = open(addr);
+        assert!(endpoint.starts_with("/messages?sessionId="));
+
+        assert_eq!(post(addr, &endpoint, PING).0, 202);
+        let Some(Event::Message(session_id, message, _)) = receiver.recv().unwrap() else {
+            panic!("expected a message");
+        };
+        assert_eq!(endpoint, format!("/messages?sessionId={session_id}"));
+        assert!(matches!(message, McpMessage::Request(request) if request.method == "ping"));
+    }
+
+    #[test]
+    fn disconnections_are_reported() {
+        let (addr, receiver) = server();
+        let (stream, endpoint) = open(addr);
+        drop(stream);
+        let Some(Event::Disconnected(session_id)) = receiver.recv().unwrap() else {
+            panic!("expected a disconnection");
+        };
+        assert_eq!(endpoint, format!("/messages?sessionId={session_id}"));
+    }
+
+    #[test]
+    fn missing_or_unknown_session() {
+        let (addr, _receiver) = server();
+        assert_eq!(post(addr, "/messages", PING).0, 404);
+        assert_eq!(post(addr, "/messages?sessionId=", PING).0, 404);
+        assert_eq!(post(addr, "/messages?sessionId=unknown", PING).0, 404);
+
+        // a session that has closed is unknown too
+        let (stream, endpoint) = open(addr);
+        drop(stream);
+        let mut status = 202;
+        for _ in 0..100 {
+            status = post(addr, &endpoint, PING).0;
+            if status == 404 {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(status, 404);
+    }
+
+    #[test]
+    fn malformed_requests() {
+        let (addr, receiver) = server();
+        let (_stream, endpoint) = open(addr);
+        assert_eq!(send(addr, "GET\r\n\r\n").0, 400);
+        assert_eq!(post(addr, &endpoint, "{not json").0, 400);
+        assert_eq!(send(addr, "PUT /sse HTTP/1.1\r\n\r\n").0, 405);
+        assert_eq!(send(addr, "GET /messages HTTP/1.1\r\n\r\n").0, 405);
+        assert_eq!(send(addr, "GET / HTTP/1.1\r\n\r\n").0, 404);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn body_over_the_limit() {
+        let (addr, receiver) = server();
+        let (_stream, endpoint) = open(addr);
+        let (status, body) = send(
+            addr,
+            &format!("POST {endpoint} HTTP/1.1\r\nContent-Length: 1000000000\r\n\r\n"),
+        );
+        assert_eq!(status, 413);
+        assert!(body.contains("too large"), "{body}");
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn headers_over_the_limit() {
+        let (addr, receiver) = server();
+        // a few more than the limit, all sent before the server stops reading
+        let headers = "X-Header: value\r\n".repeat(65);
+        let (status, body) = send(addr, &format!("GET /sse HTTP/1.1\r\n{headers}\r\n"));
+        assert_eq!(status, 431);
+        assert!(body.contains("too large"), "{body}");
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn a_busy_session_does_not_hold_up_others() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let sessions = Sessions::default();
+        let busy = TcpStream::connect(addr).unwrap();
+        let idle = TcpStream::connect(addr).unwrap();
+        let (_, _) = listener.accept().unwrap();
+        let (mut idle_client, _) = listener.accept().unwrap();
+        let busy = Arc::new(Mutex::new(busy));
+        sessions
+            .lock()
+            .unwrap()
+            .insert("busy".into(), Arc::clone(&busy));
+        sessions
+            .lock()
+            .unwrap()
+            .insert("idle".into(), Arc::new(Mutex::new(idle)));
+
+        // as if an event were being written to a client that isn't reading
+        let _writing = busy.lock().unwrap();
+        outbound(&sessions, "idle")
+            .send(&serde_json::json!({}))
+            .unwrap();
+        let mut event = [0; 24];
+        idle_client.read_exact(&mut event).unwrap();
+        assert_eq!(&event, b"event: message\ndata: {}\n");
+    }
+
+    #[test]
+    fn origins() {
+        let allowed = ["https://app.example.com".to_string()];
+        for origin in [
+            "http://localhost",
+            "http://localhost:3000",
+            "https://LOCALHOST:3000/",
+            "http://127.0.0.1:8080",
+            "http://[::1]:8080",
+            "https://app.example.com",
+        ] {
+            assert!(origin_allowed(origin, &allowed), "{origin}");
+        }
+        for origin in [
+            "http://example.com",
+            "http://localhost.example.com",
+            "http://192.168.1.10",
+            "https://app.example.com.evil.com",
+            "null",
+        ] {
+            assert!(!origin_allowed(origin, &allowed), "{origin}");
+        }
+    }
+
+    #[test]
+    fn requests_from_other_origins_are_refused() {
+        let (addr, receiver) = server();
+        let (_stream, endpoint) = open(addr);
+        let post_from = |origin: &str| {
+            send(
+                addr,
+                &format!(
+                    "POST {endpoint} HTTP/1.1\r\nOrigin: {origin}\r\nContent-Length: {}\r\n\r\n{PING}",
+                    PING.len()
+                ),
+            )
+            .0
+        };
+        assert_eq!(post_from("http://evil.example.com"), 403);
+        assert_eq!(
+            send(
+                addr,
+                "GET /sse HTTP/1.1\r\nOrigin: http://evil.example.com\r\n\r\n"
+            )
+            .0,
+            403
+        );
+        assert!(receiver.try_recv().is_err());
+
+        assert_eq!(post_from("http://localhost:3000"), 202);
+        assert_eq!(post_from("https://app.example.com"), 202);
+    }
+}
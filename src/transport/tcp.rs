@@ -0,0 +1,255 @@
+//! Newline-delimited JSON-RPC over TCP, for running a server on one machine and connecting to it
+//! from another
+//!
+//! TCP has none of the ambient trust of a child process's stdio, so a server can require a token.
+//! When it does, the first line a client sends must be exactly that token, before any JSON-RPC.
+//! A client that sends anything else is sent an error and disconnected. Clients that only speak
+//! stdio can connect through a bridge like `sh -c '(echo "$TOKEN"; cat) | nc host port'`.
+//!
+//! This transport serves a single client at a time. The others wait until it disconnects, which
+//! it is made to do if it stays silent for too long.
+
+use super::{Stream, http, record};
+use crate::{
+    Error,
+    registry::ToolRegistry,
+    server::{Hooks, ServerConfig},
+    signal,
+    traits::{
+        AsPromptsList, AsResourcesList, AsToolsList, CompleteArgument, EnabledTools, GetPrompt,
+        ReadResource, Tool,
+    },
+    types::McpResponse,
+};
+use anyhow::Result;
+use serde_json::Value;
+use std::{
+    fmt::Debug,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{Arc, mpsc},
+    thread,
+    time::Duration,
+};
+
+/// How long a client has to send its token, or its first message if there's no token, before it
+/// is disconnected
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a client can go without sending anything before it is disconnected, to let the next
+/// client be served
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Serve MCP over TCP at `addr`, blocking until the process receives SIGINT or SIGTERM
+///
+/// Clients are served one at a time, in the order they connect, since they share the state. If
+/// `token` is set, each client must send it as its first line. A client must send its token, or
+/// its first message, within ten seconds, and is disconnected once it has sent nothing for ten
+/// minutes, even if one of its calls is still running. A client that disconnects or fails only
+/// ends its own session. With a [recording](ServerConfig::with_recording), each connection
+/// is recorded to its own file, numbered in the order they connect, like `session-1.jsonl`.
+pub fn serve<Tools, Resources, Prompts, State>(
+    state: &mut State,
+    config: &ServerConfig,
+    registry: &ToolRegistry<State>,
+    hooks: &Hooks<State>,
+    addr: impl ToSocketAddrs,
+    token: Option<&str>,
+) -> Result<()>
+where
+    Tools: Debug + AsToolsList + Tool<State> + CompleteArgument<State> + EnabledTools<State>,
+    Resources: AsResourcesList + ReadResource<State>,
+    Prompts: AsPromptsList + GetPrompt<State> + CompleteArgument<State>,
+{
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    log::info!("serving tcp on {local_addr}");
+    if token.is_none() && !local_addr.ip().is_loopback() {
+        log::warn!(
+            "serving tcp on {local_addr} without a token, so anyone who can reach it can connect"
+        );
+    }
+
+    // `None` means stop serving because of a signal
+    let (sender, receiver) = mpsc::channel();
+    {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if sender.send(Some(stream)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => log::error!("could not accept connection: {e}"),
+                }
+            }
+        });
+    }
+
+    let mut connections = 0;
+    loop {
+        // each connection installs its own handler while it is served, so ours is only
+        // installed while waiting for the next one
+        let stream = {
+            let sender = sender.clone();
+            let _signal = signal::on_terminate(move || {
+                let _ = sender.send(None);
+            });
+            receiver.recv()
+        };
+        let Ok(Some(stream)) = stream else {
+            break;
+        };
+
+        let peer = stream
+            .peer_addr()
+            .map_or_else(|_| "unknown".into(), |addr| addr.to_string());
+        let reader = match accept(&stream, token) {
+            Ok(Some(reader)) => reader,
+            Ok(None) => {
+                log::warn!("{peer} sent the wrong token, disconnecting");
+                continue;
+            }
+            Err(e) => {
+                log::error!("{peer}: {e}");
+                continue;
+            }
+        };
+
+        let session_id = match http::random_id() {
+            Ok(session_id) => session_id,
+            Err(e) => {
+                log::error!("{peer}: {e}");
+                continue;
+            }
+        };
+        log::info!("{peer} connected, session {session_id}");
+        connections += 1;
+        let recording;
+        let config = match config.recording() {
+            Some(path) => {
+                recording = config
+                    .clone()
+                    .with_recording(record::numbered(path, connections));
+                &recording
+            }
+            None => config,
+        };
+        let transport = Arc::new(Stream::new(reader, stream));
+        match crate::serve::<Tools, Resources, Prompts, State>(
+            state,
+            config,
+            registry,
+            hooks,
+            transport,
+            Some(session_id),
+        ) {
+            Ok(()) => log::info!("{peer} disconnected"),
+            Err(e) => log::error!("{peer}: {e}"),
+        }
+
+        if signal::received() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Check the client's token if one is required, returning the reader to serve the connection
+/// from, or `None` if the client should be disconnected
+fn accept(stream: &TcpStream, token: Option<&str>) -> Result<Option<BufReader<TcpStream>>> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let Some(token) = token else {
+        // wait for the first message without consuming it
+        reader.fill_buf()?;
+        stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+        return Ok(Some(reader));
+    };
+
+    let mut line = String::new();
+    // no longer than the token and its line ending, so a client can't send an endless line
+    let read = reader
+        .by_ref()
+        .take(token.len() as u64 + 2)
+        .read_line(&mut line);
+    stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+
+    if read.is_ok() && constant_time_eq(line.trim_end_matches(['\r', '\n']), token) {
+        return Ok(Some(reader));
+    }
+
+    let response = McpResponse::from_error(Value::Null, Error::Unauthorized("Unauthorized".into()));
+    let mut stream = stream;
+    writeln!(stream, "{}", serde_json::to_string(&response)?)?;
+    Ok(None)
+}
+
+/// Compare without returning early, so the time taken doesn't reveal how much of a guess was right
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    /// Connect a client that sends `sent`, returning the server's end and the client's
+    fn connect(sent: &[u8]) -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.write_all(sent).unwrap();
+        (listener.accept().unwrap().0, client)
+    }
+
+    #[test]
+    fn the_token_comes_first() {
+        let (server, _client) = connect(b"secret\r\n{\"jsonrpc\":\"2.0\"}\n");
+        let mut reader = accept(&server, Some("secret")).unwrap().unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "{\"jsonrpc\":\"2.0\"}\n");
+        assert_eq!(server.read_timeout().unwrap(), Some(IDLE_TIMEOUT));
+    }
+
+    #[test]
+    fn a_wrong_token_is_refused() {
+        let (server, client) = connect(b"guess\n");
+        assert!(accept(&server, Some("secret")).unwrap().is_none());
+        let mut response = String::new();
+        BufReader::new(client).read_line(&mut response).unwrap();
+        assert!(response.contains("Unauthorized"), "{response}");
+    }
+
+    #[test]
+    fn a_token_line_is_read_no_further_than_the_token() {
+        let (server, _client) = connect(format!("secret{}\n", "a".repeat(64 * 1024)).as_bytes());
+        assert!(accept(&server, Some("secret")).unwrap().is_none());
+    }
+
+    #[test]
+    fn without_a_token_the_first_message_is_left_to_read() {
+        let (server, _client) = connect(b"{}\n");
+        let mut reader = accept(&server, None).unwrap().unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "{}\n");
+        assert_eq!(server.read_timeout().unwrap(), Some(IDLE_TIMEOUT));
+    }
+
+    #[test]
+    fn tokens() {
+        assert!(constant_time_eq("secret", "secret"));
+        assert!(!constant_time_eq("secret", "secreT"));
+        assert!(!constant_time_eq("secret", "secret2"));
+    }
+}
@@ -1,8 +1,26 @@
 use serde::{Deserialize, Deserializer, Serialize};
-use serde_json::Value;
-use std::{borrow::Cow, collections::HashMap, fmt::Debug};
+use serde_json::{Number, Value};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Debug, Display, Formatter},
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
-use crate::traits::{AsToolsList, Tool};
+use crate::{
+    Error,
+    auth::AuthorizationRequest,
+    context::ToolContext,
+    fs::{self, FsResourceProvider},
+    metrics::METRICS_TOOL,
+    registry::ToolRegistry,
+    server::{Hooks, ServerConfig},
+    traits::{
+        AsPromptsList, AsResourcesList, AsToolSchema, AsToolsList, CompleteArgument, EnabledTools,
+        GetPrompt, ReadResource, Tool,
+    },
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
@@ -10,6 +28,34 @@ pub enum McpMessage {
     #[serde(deserialize_with = "deserialize_request")]
     Request(McpRequest),
     Notification(McpNotification),
+    #[serde(deserialize_with = "deserialize_response")]
+    Response(IncomingResponse),
+}
+
+impl McpMessage {
+    /// Parse one inbound JSON-RPC message
+    ///
+    /// On failure, returns the error response the client should receive: `-32700` with a null id
+    /// for malformed JSON, or `-32600` for JSON that isn't a valid request or notification.
+    pub fn parse(input: &[u8]) -> Result<Self, Box<McpResponse>> {
+        let value: Value = serde_json::from_slice(input).map_err(|e| {
+            log::error!("{e:?}");
+            Box::new(McpResponse::error_with_code(
+                Value::Null,
+                -32700,
+                format!("Parse error: {e}"),
+            ))
+        })?;
+        let id = value.get("id").cloned().unwrap_or_default();
+        serde_json::from_value(value).map_err(|e| {
+            log::error!("{e:?}");
+            Box::new(McpResponse::error_with_code(
+                id,
+                -32600,
+                format!("Invalid request: {e}"),
+            ))
+        })
+    }
 }
 
 fn deserialize_request<'de, D>(deserializer: D) -> Result<McpRequest, D::Error>
@@ -25,6 +71,27 @@ where
     }
 }
 
+fn deserialize_response<'de, D>(deserializer: D) -> Result<IncomingResponse, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: Value = Deserialize::deserialize(deserializer)?;
+    if value.get("result").is_some() || value.get("error").is_some() {
+        serde_json::from_value(value).map_err(serde::de::Error::custom)
+    } else {
+        Err(serde::de::Error::custom("Not a response"))
+    }
+}
+
+/// A response from the client to a request the server sent it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IncomingResponse {
+    pub jsonrpc: String,
+    pub id: Value,
+    pub result: Option<Value>,
+    pub error: Option<McpError>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct McpRequest {
     pub jsonrpc: String,
@@ -34,77 +101,832 @@ pub struct McpRequest {
 }
 
 impl McpRequest {
-    pub fn execute<State, Tools: Debug + AsToolsList + Tool<State>>(
+    pub fn new(id: Value, method: impl Into<String>, params: impl Serialize) -> Self {
+        Self {
+            jsonrpc: "2.0".into(),
+            id,
+            method: method.into(),
+            params: Some(serde_json::to_value(params).unwrap()),
+        }
+    }
+    /// The params of an `initialize` request
+    pub fn initialize_request(&self) -> Option<InitializeRequest> {
+        if self.method != "initialize" {
+            return None;
+        }
+        serde_json::from_value(self.params.clone()?).ok()
+    }
+
+    /// The `_meta.progressToken` the client sent, if it wants progress notifications
+    pub fn progress_token(&self) -> Option<Value> {
+        self.params
+            .as_ref()?
+            .get("_meta")?
+            .get("progressToken")
+            .cloned()
+    }
+
+    pub fn execute<State, Tools, Resources, Prompts>(
         self,
         state: &mut State,
-        instructions: Option<&'static str>,
-        server_info: &Info,
-    ) -> McpResponse {
+        config: &ServerConfig,
+        registry: &ToolRegistry<State>,
+        hooks: &Hooks<State>,
+        context: &ToolContext,
+    ) -> McpResponse
+    where
+        Tools: Debug + AsToolsList + Tool<State> + CompleteArgument<State> + EnabledTools<State>,
+        Resources: AsResourcesList + ReadResource<State>,
+        Prompts: AsPromptsList + GetPrompt<State> + CompleteArgument<State>,
+    {
         let Self {
             id, method, params, ..
         } = self;
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("request", method = %method, id = %id).entered();
         match method.as_str() {
-            "initialize" => McpResponse::success(
-                id,
-                InitializeResponse::new(server_info.to_owned()).with_instructions(instructions),
-            ),
-            "tools/list" => {
-                let tools = Tools::tools_list();
-                McpResponse::success(id, ToolsListResponse { tools })
+            "initialize" => {
+                let capabilities = server_capabilities::<Resources, Prompts>(config);
+                let client = params
+                    .and_then(|params| serde_json::from_value::<InitializeRequest>(params).ok())
+                    .map(|request| request.client_info);
+                if let Some(client) = client
+                    && let Err(e) = hooks.on_initialize(state, &client)
+                {
+                    log::error!("could not initialize for {}: {e}", client.name);
+                    return McpResponse::from_error(id, e);
+                }
+                // built after `on_initialize`, so they can describe what it set up
+                let instructions = server_instructions(state, config, hooks);
+                McpResponse::success(
+                    id,
+                    InitializeResponse::new(config.info().to_owned())
+                        .with_protocol_version(*context.protocol_version())
+                        .with_capabilities(capabilities)
+                        .with_instructions(instructions.map(Into::into)),
+                )
+            }
+            "resources/list" => {
+                let resources = list_resources::<Resources>(config);
+                McpResponse::success(id, ResourcesListResponse { resources })
             }
-            "tools/call" => match serde_json::from_value::<Tools>(params.unwrap_or(Value::Null)) {
-                Ok(tool) => {
-                    log::info!("{tool:?}");
-                    match tool.execute(state) {
-                        Ok(string) => {
-                            log::debug!("{string}");
-                            McpResponse::success(id, ContentResponse::text(string))
+            "resources/templates/list" => {
+                let resource_templates = list_resource_templates::<Resources>(config);
+                McpResponse::success(id, ResourceTemplatesListResponse { resource_templates })
+            }
+            "resources/read" => {
+                match serde_json::from_value::<ReadResourceRequest>(params.unwrap_or(Value::Null)) {
+                    Ok(ReadResourceRequest { uri }) => {
+                        let contents = Resources::read_resource(&uri, state).or_else(|| {
+                            config
+                                .fs_resources()
+                                .iter()
+                                .find_map(|provider| provider.read(&uri))
+                        });
+                        match contents {
+                            Some(Ok(contents)) => McpResponse::success(
+                                id,
+                                ReadResourceResponse {
+                                    contents: vec![contents],
+                                },
+                            ),
+                            Some(Err(e)) => {
+                                log::error!("{e}");
+                                McpResponse::from_error(id, e)
+                            }
+                            None => McpResponse::error_with_code(
+                                id,
+                                -32002,
+                                format!("Resource not found: {uri}"),
+                            ),
                         }
-                        Err(e) => {
-                            log::error!("{e}");
-                            McpResponse::error(id, e.to_string())
+                    }
+                    Err(e) => {
+                        log::error!("{e}");
+                        McpResponse::error_with_code(id, -32602, e.to_string())
+                    }
+                }
+            }
+            "prompts/list" => {
+                let prompts = list_prompts::<Prompts>(config);
+                McpResponse::success(id, PromptsListResponse { prompts })
+            }
+            "prompts/get" => {
+                match serde_json::from_value::<GetPromptRequest>(params.unwrap_or(Value::Null)) {
+                    Ok(GetPromptRequest { name, arguments }) => {
+                        let arguments =
+                            arguments.unwrap_or_else(|| Value::Object(Default::default()));
+                        let response = match config.prompt_template(&name) {
+                            Some(template) => Some(template.render(&arguments)),
+                            None => Prompts::get_prompt(&name, arguments, state),
+                        };
+                        match response {
+                            Some(Ok(response)) => McpResponse::success(id, response),
+                            Some(Err(e)) => {
+                                log::error!("{e}");
+                                McpResponse::from_error(id, e)
+                            }
+                            None => McpResponse::error_with_code(
+                                id,
+                                -32602,
+                                format!("Unknown prompt: {name}"),
+                            ),
                         }
                     }
+                    Err(e) => {
+                        log::error!("{e}");
+                        McpResponse::error_with_code(id, -32602, e.to_string())
+                    }
                 }
-                Err(e) => {
-                    log::error!("{e}");
-                    McpResponse::error(id, e.to_string())
+            }
+            "completion/complete" => {
+                match serde_json::from_value::<CompleteRequest>(params.unwrap_or(Value::Null)) {
+                    Ok(CompleteRequest {
+                        reference,
+                        argument,
+                    }) => {
+                        let (name, value) = (&argument.name, &argument.value);
+                        let values = match &reference {
+                            CompletionReference::Prompt { name: prompt } => config
+                                .prompt_template(prompt)
+                                .map(|_| Ok(vec![]))
+                                .or_else(|| Prompts::complete_argument(prompt, name, value, state)),
+                            CompletionReference::Tool { name: tool } => {
+                                let tool = tool_schema::<State, Tools>(tool, registry)
+                                    .map_or_else(|| tool.clone(), |schema| schema.name);
+                                let tool = &tool;
+                                if tool_enabled::<State, Tools>(tool, state, registry) {
+                                    registry.complete(tool, name, value, state).or_else(|| {
+                                        Tools::complete_argument(tool, name, value, state)
+                                    })
+                                } else {
+                                    None
+                                }
+                            }
+                            CompletionReference::Resource { uri } => {
+                                Resources::complete_template_argument(uri, name, value, state)
+                                    .or_else(|| {
+                                        config
+                                            .fs_resources()
+                                            .iter()
+                                            .find(|provider| {
+                                                provider.template().uri_template.as_str() == uri
+                                            })
+                                            .map(|provider| Ok(provider.complete(value)))
+                                    })
+                            }
+                        };
+                        match values {
+                            Some(Ok(values)) => McpResponse::success(
+                                id,
+                                CompleteResponse {
+                                    completion: values.into(),
+                                },
+                            ),
+                            Some(Err(e)) => {
+                                log::error!("{e}");
+                                McpResponse::from_error(id, e)
+                            }
+                            None => McpResponse::error_with_code(
+                                id,
+                                -32602,
+                                format!("Unknown {reference}"),
+                            ),
+                        }
+                    }
+                    Err(e) => {
+                        log::error!("{e}");
+                        McpResponse::error_with_code(id, -32602, e.to_string())
+                    }
                 }
-            },
+            }
+            "logging/setLevel" => {
+                match serde_json::from_value::<SetLevelRequest>(params.unwrap_or(Value::Null)) {
+                    Ok(SetLevelRequest { level }) => {
+                        log::debug!("client log level set to {level:?}");
+                        context.logger().set_level(level);
+                        McpResponse::success(id, serde_json::json!({}))
+                    }
+                    Err(e) => {
+                        log::error!("{e}");
+                        McpResponse::error_with_code(id, -32602, e.to_string())
+                    }
+                }
+            }
+            "tools/list" => {
+                let mut tools = list_tools::<State, Tools>(state, config, registry);
+                if *context.protocol_version() < ProtocolVersion::V2025_06_18 {
+                    for tool in &mut tools {
+                        tool.output_schema = None;
+                    }
+                }
+                McpResponse::success(id, ToolsListResponse { tools })
+            }
+            "tools/call" => {
+                let mut params = params.unwrap_or(Value::Null);
+                let requested = params
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string();
+                let schema = tool_schema::<State, Tools>(&requested, registry);
+                let name = schema
+                    .as_ref()
+                    .map_or_else(|| requested.clone(), |schema| schema.name.clone());
+                if name != requested {
+                    log::debug!("{requested} is an alias of {name}");
+                    params["name"] = Value::from(name.as_str());
+                }
+                #[cfg(feature = "tracing")]
+                let _span = tracing::info_span!("tool_call", tool = %name).entered();
+                if let Some(authorizer) = config.authorizer() {
+                    let arguments = params.get("arguments").unwrap_or(&Value::Null);
+                    let request = AuthorizationRequest::new(&name, arguments, context);
+                    if let Err(e) = authorizer.authorize(&request) {
+                        log::warn!("{name} was not authorized: {e}");
+                        return McpResponse::from_error(id, e);
+                    }
+                }
+                let start = Instant::now();
+                let arguments = params.get("arguments").cloned().unwrap_or_default();
+                let cached = config
+                    .result_cache()
+                    .get(&name, context.session_id(), &arguments)
+                    .filter(|_| tool_enabled::<State, Tools>(&name, state, registry));
+                let mut response = if let Some(result) = cached {
+                    log::debug!("{name} was answered from the cache");
+                    McpResponse::success(id, result)
+                } else {
+                    if name != METRICS_TOOL
+                        && let Err(e) = config.rate_limiter().acquire(&name)
+                    {
+                        log::warn!("{e}");
+                        return McpResponse::from_error(id, e);
+                    }
+                    let watchdog = config.tool_timeout(&name).map(|timeout| {
+                        let response = McpResponse::error_with_code(
+                            id.clone(),
+                            -32001,
+                            format!("{name} timed out after {timeout:?}"),
+                        );
+                        (context.watchdog(&name, timeout, response.clone()), response)
+                    });
+                    let mut response = dispatch_tool_call::<State, Tools>(
+                        id,
+                        params,
+                        schema.as_ref(),
+                        state,
+                        config,
+                        registry,
+                        context,
+                    );
+                    if let Some((watchdog, timeout_response)) = watchdog
+                        && watchdog.finish()
+                    {
+                        log::warn!(
+                            "{name} finished after {:?}, past its timeout",
+                            start.elapsed()
+                        );
+                        response = timeout_response;
+                    }
+                    if let Some(result) = &response.result {
+                        config.result_cache().insert(
+                            &name,
+                            context.session_id(),
+                            &arguments,
+                            result,
+                        );
+                    }
+                    response
+                };
+                let elapsed = start.elapsed();
+                log::debug!("{name} took {elapsed:?}");
+                if let Some(metrics) = config.metrics()
+                    && name != METRICS_TOOL
+                {
+                    let failed = response.error.is_some()
+                        || response
+                            .result
+                            .as_ref()
+                            .is_some_and(|result| result["isError"] == true);
+                    metrics.record(&name, elapsed, !failed);
+                }
+                if let Some(notice) = schema.and_then(|schema| schema.call_notice(&requested))
+                    && let Some(content) = response
+                        .result
+                        .as_mut()
+                        .and_then(|result| result["content"].as_array_mut())
+                {
+                    content.push(serde_json::to_value(Content::text(notice)).unwrap_or_default());
+                }
+                response
+            }
+            "ping" => McpResponse::success(id, serde_json::json!({})),
+            "mcplease/health" => McpResponse::success(id, hooks.health(state)),
             _ => McpResponse::error(id, format!("Unknown method: {method}")),
         }
     }
 }
 
+/// The capabilities sent with the `initialize` response: those configured, or those detected
+/// from the server's resources and prompts
+pub(crate) fn server_capabilities<Resources, Prompts>(config: &ServerConfig) -> Capabilities
+where
+    Resources: AsResourcesList,
+    Prompts: AsPromptsList,
+{
+    config.capabilities().cloned().unwrap_or_else(|| {
+        let mut capabilities = Capabilities::detect::<Resources, Prompts>();
+        if !config.fs_resources().is_empty() {
+            capabilities.resources.get_or_insert_default();
+        }
+        if !config.prompt_templates().is_empty() {
+            capabilities.prompts.get_or_insert_default();
+        }
+        capabilities
+    })
+}
+
+/// The instructions sent with the `initialize` response, built from `state` if there is an
+/// instructions function
+pub(crate) fn server_instructions<State>(
+    state: &State,
+    config: &ServerConfig,
+    hooks: &Hooks<State>,
+) -> Option<String> {
+    hooks.instructions(state).or_else(|| {
+        config
+            .instructions()
+            .map(|instructions| instructions.to_owned())
+    })
+}
+
+/// The tools listed in `tools/list`: the registered tools, the enabled tools of `Tools`, and the
+/// metrics tool if it's turned on
+pub(crate) fn list_tools<State, Tools>(
+    state: &State,
+    config: &ServerConfig,
+    registry: &ToolRegistry<State>,
+) -> Vec<ToolSchema>
+where
+    Tools: AsToolsList + EnabledTools<State>,
+{
+    let mut tools = registry.tools_list(state);
+    tools.extend(Tools::tools_list().into_iter().filter(|tool| {
+        !registry.contains(&tool.name) && Tools::tool_enabled(&tool.name, state) != Some(false)
+    }));
+    if let Some(metrics) = config.metrics()
+        && metrics.debug_tool()
+    {
+        tools.push(metrics_tool_schema());
+    }
+    tools
+}
+
+/// The resources listed in `resources/list`, including files served from the filesystem
+pub(crate) fn list_resources<Resources>(config: &ServerConfig) -> Vec<ResourceDescriptor>
+where
+    Resources: AsResourcesList,
+{
+    let mut resources = Resources::resources_list();
+    for provider in config.fs_resources() {
+        resources.extend(provider.list());
+    }
+    resources
+}
+
+/// The resource templates listed in `resources/templates/list`
+pub(crate) fn list_resource_templates<Resources>(
+    config: &ServerConfig,
+) -> Vec<ResourceTemplateDescriptor>
+where
+    Resources: AsResourcesList,
+{
+    let mut resource_templates = Resources::resource_templates_list();
+    resource_templates.extend(
+        config
+            .fs_resources()
+            .iter()
+            .map(FsResourceProvider::template),
+    );
+    resource_templates
+}
+
+/// The prompts listed in `prompts/list`, including prompt templates
+pub(crate) fn list_prompts<Prompts>(config: &ServerConfig) -> Vec<PromptDescriptor>
+where
+    Prompts: AsPromptsList,
+{
+    let mut prompts = Prompts::prompts_list();
+    prompts.extend(
+        config
+            .prompt_templates()
+            .iter()
+            .map(PromptTemplate::descriptor),
+    );
+    prompts
+}
+
+/// Call the tool named in `params`, whose `schema` is looked up with [`tool_schema`]
+fn dispatch_tool_call<State, Tools>(
+    id: Value,
+    mut params: Value,
+    schema: Option<&ToolSchema>,
+    state: &mut State,
+    config: &ServerConfig,
+    registry: &ToolRegistry<State>,
+    context: &ToolContext,
+) -> McpResponse
+where
+    Tools: Debug + AsToolsList + Tool<State> + EnabledTools<State>,
+{
+    let name = params
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    if let Some(metrics) = config.metrics()
+        && metrics.debug_tool()
+        && name == METRICS_TOOL
+    {
+        return tool_response(
+            id,
+            ToolOutput::structured(&metrics.to_json()).map_err(Into::into),
+            context,
+        );
+    }
+
+    if !tool_enabled::<State, Tools>(&name, state, registry) {
+        return McpResponse::from_error(id, Error::InvalidParams(format!("{name} is not enabled")));
+    }
+
+    if let Some(schema) = schema {
+        apply_argument_defaults(&mut params, schema);
+        if let Some(response) = validate_tool_arguments(&id, &params, schema) {
+            return response;
+        }
+    }
+
+    let arguments = params.get("arguments").cloned().unwrap_or_default();
+    match registry.call(&name, arguments, state, context) {
+        Some(result) => tool_response(id, result, context),
+        None => call_tool::<State, Tools>(id, params, state, context),
+    }
+}
+
+/// The schema of the tool named `name`, or that has `name` as an [alias](Tool::aliases), checking
+/// registered tools first
+fn tool_schema<State, Tools>(name: &str, registry: &ToolRegistry<State>) -> Option<ToolSchema>
+where
+    Tools: AsToolsList,
+{
+    registry.schema(name).or_else(|| {
+        Tools::tools_list()
+            .into_iter()
+            .find(|tool| tool.answers_to(name))
+    })
+}
+
+/// Whether the tool named `name` is [enabled](crate::traits::Tool::enabled), checking registered
+/// tools first. Unknown tools count as enabled, so that calls to them get the usual error.
+fn tool_enabled<State, Tools>(name: &str, state: &State, registry: &ToolRegistry<State>) -> bool
+where
+    Tools: EnabledTools<State>,
+{
+    registry
+        .enabled(name, state)
+        .or_else(|| Tools::tool_enabled(name, state))
+        .unwrap_or(true)
+}
+
+fn metrics_tool_schema() -> ToolSchema {
+    ToolSchema {
+        name: METRICS_TOOL.into(),
+        description: Some(
+            "Call counts, durations in milliseconds, and error rates for each tool on this server"
+                .into(),
+        ),
+        input_schema: serde_json::from_value(serde_json::json!({
+            "type": "object",
+            "properties": {}
+        }))
+        .unwrap(),
+        output_schema: None,
+        aliases: vec![],
+        meta: None,
+    }
+}
+
+/// Fill in the defaults from the tool's input schema for any `tools/call` arguments the client
+/// left out
+fn apply_argument_defaults(params: &mut Value, schema: &ToolSchema) {
+    let Some(params) = params.as_object_mut() else {
+        return;
+    };
+    let mut arguments = params.remove("arguments").unwrap_or_default();
+    schema.input_schema.apply_defaults(&mut arguments);
+    if !arguments.is_null() {
+        params.insert("arguments".into(), arguments);
+    }
+}
+
+/// Check `tools/call` arguments against the tool's input schema, returning an invalid params
+/// response that lists every failing field
+fn validate_tool_arguments(id: &Value, params: &Value, schema: &ToolSchema) -> Option<McpResponse> {
+    let name = &schema.name;
+    let arguments = params.get("arguments").unwrap_or(&Value::Null);
+    let errors = schema.input_schema.validate(arguments);
+    if errors.is_empty() {
+        return None;
+    }
+
+    let message = format!(
+        "Invalid arguments for {name}: {}",
+        errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("; ")
+    );
+    log::error!("{message}");
+    Some(McpResponse::error_with_data(
+        id.clone(),
+        -32602,
+        message,
+        serde_json::json!({ "errors": errors }),
+    ))
+}
+
+fn call_tool<State, Tools>(
+    id: Value,
+    params: Value,
+    state: &mut State,
+    context: &ToolContext,
+) -> McpResponse
+where
+    Tools: Debug + Tool<State>,
+{
+    match serde_json::from_value::<Tools>(params) {
+        Ok(tool) => {
+            log::info!("{tool:?}");
+            tool_response(id, tool.execute_with_context(state, context), context)
+        }
+        Err(e) => {
+            log::error!("{e}");
+            McpResponse::from_error(id, Error::InvalidParams(e.to_string()))
+        }
+    }
+}
+
+fn tool_response(
+    id: Value,
+    result: anyhow::Result<ToolOutput>,
+    context: &ToolContext,
+) -> McpResponse {
+    match result {
+        Ok(output) => {
+            log::debug!("{output}");
+            McpResponse::success(
+                id,
+                ContentResponse::from(output).for_protocol_version(*context.protocol_version()),
+            )
+        }
+        Err(e) => {
+            log::error!("{e}");
+            tool_error_response(id, &e)
+        }
+    }
+}
+
+/// Respond to a tool call that failed with `error`
+///
+/// Failures are results with `isError` set, so the model can see what went wrong and try again,
+/// unless the tool returned an [`Error`] with a specific code, which is sent as a JSON-RPC error.
+pub(crate) fn tool_error_response(id: Value, error: &anyhow::Error) -> McpResponse {
+    match Error::from(error) {
+        Error::Internal(message) => McpResponse::success(id, ContentResponse::error(message)),
+        error => McpResponse::from_error(id, error),
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct InitializeRequest {
-    capabilities: Value,
-    client_info: Info,
-    protocol_version: String,
+    pub capabilities: Value,
+    pub client_info: Info,
+    pub protocol_version: String,
+}
+
+/// What a client said it supports when it sent `initialize`
+///
+/// Each capability is kept as the client sent it, since their options vary between protocol
+/// versions. Anything the server doesn't recognize is ignored.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClientCapabilities {
+    /// Present if the client answers `roots/list`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roots: Option<Value>,
+
+    /// Present if the client answers `sampling/createMessage`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampling: Option<Value>,
+
+    /// Present if the client answers `elicitation/create`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub elicitation: Option<Value>,
+
+    /// Non-standard capabilities, by name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub experimental: Option<Value>,
+}
+
+impl ClientCapabilities {
+    /// The capabilities in the `capabilities` of an `initialize` request, ignoring any that are
+    /// malformed
+    pub fn from_value(capabilities: &Value) -> Self {
+        let field = |name: &str| capabilities.get(name).filter(|v| !v.is_null()).cloned();
+        Self {
+            roots: field("roots"),
+            sampling: field("sampling"),
+            elicitation: field("elicitation"),
+            experimental: field("experimental"),
+        }
+    }
+
+    pub fn supports_roots(&self) -> bool {
+        self.roots.is_some()
+    }
+
+    pub fn supports_sampling(&self) -> bool {
+        self.sampling.is_some()
+    }
+
+    pub fn supports_elicitation(&self) -> bool {
+        self.elicitation.is_some()
+    }
+
+    /// Whether the client declared the non-standard capability `name` under `experimental`
+    pub fn supports_experimental(&self, name: &str) -> bool {
+        self.experimental
+            .as_ref()
+            .is_some_and(|experimental| experimental.get(name).is_some())
+    }
+
+    /// Whether the client sends `notifications/roots/list_changed`
+    pub fn roots_list_changed(&self) -> bool {
+        self.roots
+            .as_ref()
+            .and_then(|roots| roots.get("listChanged"))
+            .and_then(Value::as_bool)
+            .unwrap_or(false)
+    }
+}
+
+/// A revision of the MCP specification
+///
+/// Variants are ordered from oldest to newest.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+pub enum ProtocolVersion {
+    #[serde(rename = "2024-11-05")]
+    #[default]
+    V2024_11_05,
+    #[serde(rename = "2025-03-26")]
+    V2025_03_26,
+    #[serde(rename = "2025-06-18")]
+    V2025_06_18,
+}
+
+impl ProtocolVersion {
+    /// Every version mcplease supports, from oldest to newest
+    pub const ALL: &[ProtocolVersion] = &[
+        ProtocolVersion::V2024_11_05,
+        ProtocolVersion::V2025_03_26,
+        ProtocolVersion::V2025_06_18,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ProtocolVersion::V2024_11_05 => "2024-11-05",
+            ProtocolVersion::V2025_03_26 => "2025-03-26",
+            ProtocolVersion::V2025_06_18 => "2025-06-18",
+        }
+    }
+}
+
+impl Display for ProtocolVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Everything a client learns about a server from `initialize` and the list requests, printed by
+/// `<server> --manifest` so that changes to it can be reviewed
+///
+/// Output schemas are included as clients of the newest protocol version see them, and resources
+/// and prompts are left out unless the server has any.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub server_info: Info,
+    /// The protocol versions the server agrees to, from oldest to newest
+    pub protocol_versions: Vec<ProtocolVersion>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+    pub capabilities: Capabilities,
+    pub tools: Vec<ToolSchema>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resources: Vec<ResourceDescriptor>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resource_templates: Vec<ResourceTemplateDescriptor>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prompts: Vec<PromptDescriptor>,
+}
+
+impl Manifest {
+    /// The manifest of a server with these tools, resources, and prompts, as it would be served
+    /// with `state`
+    pub fn for_server<Tools, Resources, Prompts, State>(
+        state: &State,
+        config: &ServerConfig,
+        registry: &ToolRegistry<State>,
+        hooks: &Hooks<State>,
+    ) -> Self
+    where
+        Tools: AsToolsList + EnabledTools<State>,
+        Resources: AsResourcesList,
+        Prompts: AsPromptsList,
+    {
+        Self {
+            server_info: config.info().clone(),
+            protocol_versions: config.protocol_versions().to_vec(),
+            instructions: server_instructions(state, config, hooks),
+            capabilities: server_capabilities::<Resources, Prompts>(config),
+            tools: list_tools::<State, Tools>(state, config, registry),
+            resources: list_resources::<Resources>(config),
+            resource_templates: list_resource_templates::<Resources>(config),
+            prompts: list_prompts::<Prompts>(config),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, fieldwork::Fieldwork)]
 #[serde(rename_all = "camelCase")]
 pub struct InitializeResponse {
-    protocol_version: &'static str,
+    #[fieldwork(with)]
+    protocol_version: ProtocolVersion,
+    #[fieldwork(with)]
     capabilities: Capabilities,
     server_info: Info,
     #[fieldwork(with)]
-    instructions: Option<&'static str>,
+    instructions: Option<Cow<'static, str>>,
 }
 
+/// An example call to a tool, listed in the `examples` of its input schema and its `--help`
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct Example<T> {
     pub description: &'static str,
     #[serde(flatten)]
     pub item: T,
+
+    /// What the tool returns for these arguments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_output: Option<&'static str>,
+
+    /// Whether this is a call the tool rejects or that shouldn't be made, shown so the model
+    /// knows what to avoid
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub negative: bool,
+}
+
+impl<T> Example<T> {
+    pub fn new(description: &'static str, item: T) -> Self {
+        Self {
+            description,
+            item,
+            expected_output: None,
+            negative: false,
+        }
+    }
+
+    pub fn with_expected_output(mut self, expected_output: &'static str) -> Self {
+        self.expected_output = Some(expected_output);
+        self
+    }
+
+    /// Mark this as a call the tool rejects or that shouldn't be made
+    pub fn negative(mut self) -> Self {
+        self.negative = true;
+        self
+    }
 }
 
 impl InitializeResponse {
     pub fn new(server_info: Info) -> Self {
         Self {
-            protocol_version: "2024-11-05",
+            protocol_version: ProtocolVersion::default(),
             capabilities: Capabilities::default(),
             server_info,
             instructions: None,
@@ -118,50 +940,1312 @@ pub struct Info {
     pub version: Cow<'static, str>,
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+/// The `initialize` result, as a client receives it
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeResult {
+    pub protocol_version: ProtocolVersion,
+    pub capabilities: Capabilities,
+    pub server_info: Info,
+    pub instructions: Option<String>,
+}
+
+/// How a server is doing, from [`ServerState::health`](crate::traits::ServerState::health)
+///
+/// Serialized as `{"status": "ok"}`, or with a `message` for the other statuses.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "status", content = "message", rename_all = "lowercase")]
+pub enum Health {
+    #[default]
+    Ok,
+
+    /// Working, but not fully, like when a cache is unavailable
+    Degraded(String),
+
+    /// Unable to do its job, like when a database it needs is unreachable
+    Unhealthy(String),
+}
+
+/// What the server supports, sent with the `initialize` response
+///
+/// Each capability that is `None` is left out of the handshake, telling the client not to use it.
+/// By default servers advertise tools, logging, and completions, plus resources and prompts if
+/// there are any. Replace that with
+/// [`ServerBuilder::with_capabilities`](crate::server::ServerBuilder::with_capabilities).
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Capabilities {
-    pub tools: HashMap<(), ()>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<ToolsCapability>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourcesCapability>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompts: Option<PromptsCapability>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logging: Option<LoggingCapability>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completions: Option<CompletionsCapability>,
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
-pub struct ToolsListResponse {
-    pub tools: Vec<ToolSchema>,
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolsCapability {
+    /// Whether the server sends `notifications/tools/list_changed`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub list_changed: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct ToolSchema {
+pub struct ResourcesCapability {
+    /// Whether clients can subscribe to changes to individual resources
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub subscribe: bool,
+    /// Whether the server sends `notifications/resources/list_changed`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub list_changed: bool,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptsCapability {
+    /// Whether the server sends `notifications/prompts/list_changed`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub list_changed: bool,
+}
+
+/// The server sends `notifications/message` and accepts `logging/setLevel`
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoggingCapability {}
+
+/// The server answers `completion/complete`
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompletionsCapability {}
+
+impl Capabilities {
+    /// What a server with these resources and prompts supports, unless configured otherwise
+    pub fn detect<Resources: AsResourcesList, Prompts: AsPromptsList>() -> Self {
+        Self {
+            tools: Some(ToolsCapability { list_changed: true }),
+            resources: (!Resources::resources_list().is_empty()
+                || !Resources::resource_templates_list().is_empty())
+            .then(ResourcesCapability::default),
+            prompts: (!Prompts::prompts_list().is_empty()).then(PromptsCapability::default),
+            logging: Some(LoggingCapability {}),
+            completions: Some(CompletionsCapability {}),
+        }
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct PromptsListResponse {
+    pub prompts: Vec<PromptDescriptor>,
+}
+
+/// An entry in the `prompts/list` response
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PromptDescriptor {
     pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
-    pub input_schema: InputSchema,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arguments: Vec<PromptArgument>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PromptArgument {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required: Option<bool>,
 }
 
+/// The params of `completion/complete`
 #[derive(Debug, Serialize, Deserialize)]
-#[serde(untagged)]
-pub enum InputSchema {
-    // Union types (check these first)
-    AnyOf {
-        #[serde(rename = "anyOf")]
-        any_of: Vec<InputSchema>,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        title: Option<String>,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        description: Option<String>,
-    },
-    OneOf {
-        #[serde(rename = "oneOf")]
-        one_of: Vec<InputSchema>,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        title: Option<String>,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        description: Option<String>,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        examples: Option<Vec<Value>>,
-    },
-    Tagged(Tagged),
+pub struct CompleteRequest {
+    #[serde(rename = "ref")]
+    pub reference: CompletionReference,
+    pub argument: CompletionArgument,
+}
+
+/// What a `completion/complete` request is completing an argument of
+///
+/// `ref/tool` is not part of the MCP specification; mcplease accepts it so that clients can
+/// complete tool arguments the same way as prompt arguments.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum CompletionReference {
+    #[serde(rename = "ref/prompt")]
+    Prompt { name: String },
+    #[serde(rename = "ref/resource")]
+    Resource { uri: String },
+    #[serde(rename = "ref/tool")]
+    Tool { name: String },
+}
+
+impl Display for CompletionReference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CompletionReference::Prompt { name } => write!(f, "prompt: {name}"),
+            CompletionReference::Resource { uri } => write!(f, "resource: {uri}"),
+            CompletionReference::Tool { name } => write!(f, "tool: {name}"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompletionArgument {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompleteResponse {
+    pub completion: Completion,
+}
+
+/// Suggested values for an argument, at most 100 of them
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Completion {
+    pub values: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub has_more: Option<bool>,
+}
+
+impl From<Vec<String>> for Completion {
+    fn from(mut values: Vec<String>) -> Self {
+        let total = values.len();
+        values.truncate(100);
+        Self {
+            has_more: Some(total > values.len()),
+            total: Some(total),
+            values,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetPromptRequest {
+    pub name: String,
+    pub arguments: Option<Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GetPromptResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub messages: Vec<PromptMessage>,
+}
+
+impl GetPromptResponse {
+    pub fn new(messages: Vec<PromptMessage>) -> Self {
+        Self {
+            description: None,
+            messages,
+        }
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+/// The params of a `sampling/createMessage` request, asking the client's model for a completion
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateMessageRequest {
+    pub messages: Vec<SamplingMessage>,
+    pub max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_preferences: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_context: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Value>,
+}
+
+impl CreateMessageRequest {
+    pub fn new(messages: Vec<SamplingMessage>, max_tokens: u32) -> Self {
+        Self {
+            messages,
+            max_tokens,
+            system_prompt: None,
+            temperature: None,
+            stop_sequences: None,
+            model_preferences: None,
+            include_context: None,
+            metadata: None,
+        }
+    }
+
+    pub fn with_system_prompt(mut self, system_prompt: impl Into<String>) -> Self {
+        self.system_prompt = Some(system_prompt.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SamplingMessage {
+    pub role: Role,
+    pub content: Content,
+}
+
+impl SamplingMessage {
+    pub fn user(text: impl Into<String>) -> Self {
+        Self {
+            role: Role::User,
+            content: Content::text(text),
+        }
+    }
+
+    pub fn assistant(text: impl Into<String>) -> Self {
+        Self {
+            role: Role::Assistant,
+            content: Content::text(text),
+        }
+    }
+}
+
+/// The client's response to `sampling/createMessage`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateMessageResult {
+    pub role: Role,
+    pub content: Content,
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+}
+
+/// A directory or file the client has exposed to the server, from `roots/list`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Root {
+    pub uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl Root {
+    /// The local path this root refers to, if its uri is a `file://` uri
+    pub fn path(&self) -> Option<PathBuf> {
+        let path = self.uri.strip_prefix("file://")?;
+        // a uri may include a host, as in file://localhost/path
+        let path = &path[path.find('/')?..];
+        Some(PathBuf::from(percent_decode(path)?))
+    }
+}
+
+/// Standard base64 with padding, for binary content
+fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &byte)| n | u32::from(byte) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decode `%XX` escapes, or `None` if one is malformed or the result isn't utf-8
+pub(crate) fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1..i + 3)) {
+            (b'%', Some(hex)) => {
+                let hex = std::str::from_utf8(hex).ok()?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// The client's response to `roots/list`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListRootsResult {
+    pub roots: Vec<Root>,
+}
+
+/// A message of a prompt, which can hold any [`Content`], such as an image to discuss
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PromptMessage {
+    pub role: Role,
+    pub content: Content,
+}
+
+impl PromptMessage {
+    pub fn user(text: impl Into<String>) -> Self {
+        Self::new(Role::User, text)
+    }
+
+    pub fn assistant(text: impl Into<String>) -> Self {
+        Self::new(Role::Assistant, text)
+    }
+
+    pub fn new(role: Role, text: impl Into<String>) -> Self {
+        Self::with_content(role, Content::text(text))
+    }
+
+    pub fn with_content(role: Role, content: Content) -> Self {
+        Self { role, content }
+    }
+}
+
+/// A prompt whose messages are text with `{placeholder}`s for its arguments, so that serving it
+/// takes no code. Register it with [`ServerConfig::with_prompt_template`].
+///
+/// Rendering checks the client's arguments first: each must be declared and a string, and every
+/// required argument must be present. Optional arguments the client leaves out render as nothing.
+/// Write `{{` and `}}` for literal braces.
+///
+/// ```ignore
+/// let review = PromptTemplate::new("code_review")
+///     .with_description("Review a piece of code")
+///     .with_argument("code", "The code to review")
+///     .with_optional_argument("focus", "What to pay most attention to")
+///     .with_user_message("Please review this code, paying attention to {focus}:\n\n{code}");
+/// ```
+#[derive(Debug, Clone)]
+pub struct PromptTemplate {
+    name: String,
+    description: Option<String>,
+    arguments: Vec<PromptArgument>,
+    messages: Vec<(Role, String)>,
+}
+
+impl PromptTemplate {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: None,
+            arguments: vec![],
+            messages: vec![],
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Add an argument that clients must send
+    pub fn with_argument(self, name: impl Into<String>, description: impl Into<String>) -> Self {
+        self.with_prompt_argument(name.into(), description.into(), true)
+    }
+
+    /// Add an argument that clients may leave out
+    pub fn with_optional_argument(
+        self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Self {
+        self.with_prompt_argument(name.into(), description.into(), false)
+    }
+
+    fn with_prompt_argument(mut self, name: String, description: String, required: bool) -> Self {
+        self.arguments.push(PromptArgument {
+            name,
+            description: Some(description),
+            required: Some(required),
+        });
+        self
+    }
+
+    /// Add a message from `role`, with `{placeholder}`s where arguments go
+    pub fn with_message(mut self, role: Role, template: impl Into<String>) -> Self {
+        self.messages.push((role, template.into()));
+        self
+    }
+
+    pub fn with_user_message(self, template: impl Into<String>) -> Self {
+        self.with_message(Role::User, template)
+    }
+
+    pub fn with_assistant_message(self, template: impl Into<String>) -> Self {
+        self.with_message(Role::Assistant, template)
+    }
+
+    /// This prompt's entry in the `prompts/list` response
+    pub fn descriptor(&self) -> PromptDescriptor {
+        PromptDescriptor {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            arguments: self.arguments.clone(),
+        }
+    }
+
+    /// Check that every brace is matched and every placeholder names a declared argument
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (_, template) in &self.messages {
+            interpolate(template, |name| {
+                if self.argument(name).is_none() {
+                    anyhow::bail!("{{{name}}} is not an argument of prompt {}", self.name);
+                }
+                Ok("")
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Fill in the messages with `arguments`, the object a client sent with `prompts/get`
+    ///
+    /// Arguments that fail the checks described [above](Self) are [`Error::InvalidParams`].
+    pub fn render(&self, arguments: &Value) -> anyhow::Result<GetPromptResponse> {
+        let no_arguments = serde_json::Map::new();
+        let arguments = match arguments {
+            Value::Object(arguments) => arguments,
+            Value::Null => &no_arguments,
+            _ => anyhow::bail!(Error::InvalidParams(
+                "prompt arguments must be an object".into()
+            )),
+        };
+
+        for (name, value) in arguments {
+            if self.argument(name).is_none() {
+                anyhow::bail!(Error::InvalidParams(format!(
+                    "{name} is not an argument of prompt {}",
+                    self.name
+                )));
+            }
+            if !value.is_string() {
+                anyhow::bail!(Error::InvalidParams(format!(
+                    "argument {name} must be a string"
+                )));
+            }
+        }
+
+        if let Some(missing) = self.arguments.iter().find(|argument| {
+            argument.required == Some(true) && !arguments.contains_key(&argument.name)
+        }) {
+            anyhow::bail!(Error::InvalidParams(format!(
+                "missing required argument {}",
+                missing.name
+            )));
+        }
+
+        let messages = self
+            .messages
+            .iter()
+            .map(|(role, template)| {
+                let text = interpolate(template, |name| match arguments.get(name) {
+                    Some(value) => Ok(value.as_str().unwrap_or_default()),
+                    None if self.argument(name).is_some() => Ok(""),
+                    None => anyhow::bail!("{{{name}}} is not an argument of prompt {}", self.name),
+                })?;
+                Ok(PromptMessage::new(*role, text))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let response = GetPromptResponse::new(messages);
+        Ok(match &self.description {
+            Some(description) => response.with_description(description),
+            None => response,
+        })
+    }
+
+    fn argument(&self, name: &str) -> Option<&PromptArgument> {
+        self.arguments.iter().find(|argument| argument.name == name)
+    }
+}
+
+/// Replace each `{name}` in `template` with `value(name)`, and `{{` and `}}` with single braces
+fn interpolate<'a>(
+    template: &str,
+    mut value: impl FnMut(&str) -> anyhow::Result<&'a str>,
+) -> anyhow::Result<String> {
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(index) = rest.find(['{', '}']) {
+        text.push_str(&rest[..index]);
+        rest = &rest[index..];
+        if let Some(after) = rest.strip_prefix("{{") {
+            text.push('{');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("}}") {
+            text.push('}');
+            rest = after;
+        } else if rest.starts_with('}') {
+            anyhow::bail!("unmatched }} in prompt template {template:?}");
+        } else {
+            let Some(end) = rest.find('}') else {
+                anyhow::bail!("unclosed {{ in prompt template {template:?}");
+            };
+            text.push_str(value(&rest[1..end])?);
+            rest = &rest[end + 1..];
+        }
+    }
+    text.push_str(rest);
+    Ok(text)
+}
+
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct ResourcesListResponse {
+    pub resources: Vec<ResourceDescriptor>,
+}
+
+/// An entry in the `resources/list` response
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceDescriptor {
+    pub uri: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+impl ResourceDescriptor {
+    pub fn new(uri: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            uri: uri.into(),
+            name: name.into(),
+            description: None,
+            mime_type: None,
+        }
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn with_mime_type(mut self, mime_type: impl Into<String>) -> Self {
+        self.mime_type = Some(mime_type.into());
+        self
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceTemplatesListResponse {
+    pub resource_templates: Vec<ResourceTemplateDescriptor>,
+}
+
+/// An entry in the `resources/templates/list` response
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceTemplateDescriptor {
+    pub uri_template: UriTemplate,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+impl ResourceTemplateDescriptor {
+    /// # Panics
+    ///
+    /// If `uri_template` isn't a [`UriTemplate`] that mcplease supports
+    #[track_caller]
+    pub fn new(uri_template: &str, name: impl Into<String>) -> Self {
+        Self {
+            uri_template: UriTemplate::parse(uri_template)
+                .unwrap_or_else(|e| panic!("invalid uri template {uri_template}: {e}")),
+            name: name.into(),
+            description: None,
+            mime_type: None,
+        }
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn with_mime_type(mut self, mime_type: impl Into<String>) -> Self {
+        self.mime_type = Some(mime_type.into());
+        self
+    }
+}
+
+/// An [RFC 6570](https://www.rfc-editor.org/rfc/rfc6570) uri template like `file:///{+path}`,
+/// which [resource templates](crate::traits::ResourceTemplate) match uris against
+///
+/// `{name}` matches one or more characters up to the next `/`, and `{+name}` matches one or more
+/// of any character, so `file:///{+path}` matches `file:///src/main.rs` with `path` set to
+/// `src/main.rs`. Matched values are percent-decoded. The other RFC 6570 operators, lists, and
+/// modifiers aren't supported, and variables must be separated by some literal text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UriTemplate {
+    template: String,
+    parts: Vec<UriTemplatePart>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum UriTemplatePart {
+    Literal(String),
+    Variable { name: String, reserved: bool },
+}
+
+impl UriTemplate {
+    pub fn parse(template: &str) -> anyhow::Result<Self> {
+        let mut parts = vec![];
+        let mut rest = template;
+        while let Some(start) = rest.find(['{', '}']) {
+            if rest[start..].starts_with('}') {
+                anyhow::bail!("unmatched }}");
+            }
+            if start > 0 {
+                parts.push(UriTemplatePart::Literal(rest[..start].to_string()));
+            }
+            let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+                anyhow::bail!("unclosed {{");
+            };
+            let expression = &rest[start + 1..end];
+            let (name, reserved) = match expression.strip_prefix('+') {
+                Some(name) => (name, true),
+                None => (expression, false),
+            };
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+            {
+                anyhow::bail!("{{{expression}}} is not a supported expression");
+            }
+            if let Some(UriTemplatePart::Variable { .. }) = parts.last() {
+                anyhow::bail!("{{{expression}}} must be separated from the variable before it");
+            }
+            parts.push(UriTemplatePart::Variable {
+                name: name.to_string(),
+                reserved,
+            });
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(UriTemplatePart::Literal(rest.to_string()));
+        }
+
+        Ok(Self {
+            template: template.to_string(),
+            parts,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    /// The names of the variables in this template, in order
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            UriTemplatePart::Variable { name, .. } => Some(name.as_str()),
+            UriTemplatePart::Literal(_) => None,
+        })
+    }
+
+    /// The value of each variable, if `uri` matches this template
+    ///
+    /// When a variable could match more than one way, it takes as much of the uri as it can.
+    pub fn matches(&self, uri: &str) -> Option<HashMap<String, String>> {
+        let mut parameters = HashMap::new();
+        match_parts(&self.parts, uri, &mut parameters).then_some(parameters)
+    }
+}
+
+fn match_parts(
+    parts: &[UriTemplatePart],
+    uri: &str,
+    parameters: &mut HashMap<String, String>,
+) -> bool {
+    match parts.split_first() {
+        None => uri.is_empty(),
+        Some((UriTemplatePart::Literal(literal), rest)) => uri
+            .strip_prefix(literal.as_str())
+            .is_some_and(|uri| match_parts(rest, uri, parameters)),
+        Some((UriTemplatePart::Variable { name, reserved }, rest)) => {
+            let limit = match reserved {
+                true => uri.len(),
+                false => uri.find('/').unwrap_or(uri.len()),
+            };
+            (1..=limit)
+                .rev()
+                .filter(|&end| uri.is_char_boundary(end))
+                .any(|end| {
+                    let Some(value) = percent_decode(&uri[..end]) else {
+                        return false;
+                    };
+                    let matched = match_parts(rest, &uri[end..], parameters);
+                    if matched {
+                        parameters.insert(name.clone(), value);
+                    }
+                    matched
+                })
+        }
+    }
+}
+
+impl Display for UriTemplate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.template)
+    }
+}
+
+impl Serialize for UriTemplate {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.template)
+    }
+}
+
+impl<'de> Deserialize<'de> for UriTemplate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let template = String::deserialize(deserializer)?;
+        Self::parse(&template).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadResourceRequest {
+    pub uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadResourceResponse {
+    pub contents: Vec<ResourceContents>,
+}
+
+/// The contents of a resource, either utf-8 text or a base64-encoded blob
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceContents {
+    pub uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blob: Option<String>,
+}
+
+impl ResourceContents {
+    pub fn text(uri: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            uri: uri.into(),
+            mime_type: None,
+            text: Some(text.into()),
+            blob: None,
+        }
+    }
+
+    /// Build a binary resource from already-base64-encoded data
+    pub fn blob(uri: impl Into<String>, base64: impl Into<String>) -> Self {
+        Self {
+            uri: uri.into(),
+            mime_type: None,
+            text: None,
+            blob: Some(base64.into()),
+        }
+    }
+
+    /// Build a binary resource from raw bytes, encoding them as base64
+    pub fn blob_bytes(uri: impl Into<String>, bytes: impl AsRef<[u8]>) -> Self {
+        Self::blob(uri, base64_encode(bytes.as_ref()))
+    }
+
+    pub fn with_mime_type(mut self, mime_type: impl Into<String>) -> Self {
+        self.mime_type = Some(mime_type.into());
+        self
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct ToolsListResponse {
+    pub tools: Vec<ToolSchema>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolSchema {
+    pub name: String,
+    pub description: Option<String>,
+    pub input_schema: InputSchema,
+    /// The schema of the tool's `structuredContent`, for tools that return it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<Value>,
+
+    /// Other names the tool can be called by, which aren't listed. See [`Tool::aliases`].
+    #[serde(skip)]
+    pub aliases: Vec<String>,
+
+    /// Extra information about the tool, like `deprecated` for a tool with a deprecation notice
+    #[serde(rename = "_meta", default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<serde_json::Map<String, Value>>,
+}
+
+impl ToolSchema {
+    /// The schema of `T`, with its output schema, aliases, and deprecation notice
+    pub fn for_tool<T, State>() -> Self
+    where
+        T: AsToolSchema + Tool<State>,
+    {
+        let mut schema = T::schema();
+        schema.output_schema = T::output_schema();
+        schema.aliases = T::aliases().iter().map(|alias| alias.to_string()).collect();
+        match T::deprecated() {
+            Some(notice) => schema.with_deprecation(notice),
+            None => schema,
+        }
+    }
+
+    /// Mark the tool as deprecated, starting its description with `notice` and sending it as
+    /// `_meta.deprecated`
+    pub fn with_deprecation(mut self, notice: &str) -> Self {
+        self.description = Some(match self.description {
+            Some(description) => format!("Deprecated: {notice}\n\n{description}"),
+            None => format!("Deprecated: {notice}"),
+        });
+        self.meta
+            .get_or_insert_default()
+            .insert("deprecated".into(), notice.into());
+        self
+    }
+
+    /// Why the tool is deprecated, if it is
+    pub fn deprecation(&self) -> Option<&str> {
+        self.meta.as_ref()?.get("deprecated")?.as_str()
+    }
+
+    /// Whether `name` is this tool's name or one of its aliases
+    pub fn answers_to(&self, name: &str) -> bool {
+        self.name == name || self.aliases.iter().any(|alias| alias == name)
+    }
+
+    /// What to tell the model after a successful call to this tool as `requested`: that it was
+    /// called by an old name, or that it is deprecated
+    fn call_notice(&self, requested: &str) -> Option<String> {
+        let renamed = (requested != self.name).then(|| {
+            format!(
+                "`{requested}` has been renamed to `{}`, call it by that name from now on.",
+                self.name
+            )
+        });
+        let deprecated = self
+            .deprecation()
+            .map(|notice| format!("`{}` is deprecated: {notice}", self.name));
+        match (renamed, deprecated) {
+            (Some(renamed), Some(deprecated)) => Some(format!("{renamed} {deprecated}")),
+            (renamed, deprecated) => renamed.or(deprecated),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum InputSchema {
+    // Union types (check these first)
+    AnyOf {
+        #[serde(rename = "anyOf")]
+        any_of: Vec<InputSchema>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        default: Option<Value>,
+    },
+    OneOf {
+        #[serde(rename = "oneOf")]
+        one_of: Vec<InputSchema>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        examples: Option<Vec<Value>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        default: Option<Value>,
+    },
+    /// A reference to the root schema, `#`, or to one of its definitions, `#/$defs/Name`, which
+    /// schemars emits for recursive types
+    Ref {
+        #[serde(rename = "$ref")]
+        reference: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        default: Option<Value>,
+    },
+    Tagged(Tagged),
+    /// schemars output passed through verbatim, which tool schemas are with the `raw-schema`
+    /// feature. Arguments aren't checked against it before the tool deserializes them.
+    #[serde(skip_deserializing)]
+    Raw(Value),
+}
+
+/// One reason that tool arguments don't match the tool's [`InputSchema`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArgumentError {
+    /// Where in the arguments the problem is, like `files[2].path`, or empty for the arguments
+    /// themselves
+    pub field: String,
+    pub message: String,
+}
+
+impl Display for ArgumentError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.field.is_empty() {
+            f.write_str(&self.message)
+        } else {
+            write!(f, "{}: {}", self.field, self.message)
+        }
+    }
+}
+
+impl InputSchema {
+    /// Check `value` against this schema, returning every mismatch found
+    pub fn validate(&self, value: &Value) -> Vec<ArgumentError> {
+        let mut errors = vec![];
+        self.validate_at(self, "", value, &mut errors);
+        errors
+    }
+
+    /// The schema that `reference` refers to, when this is the root schema
+    ///
+    /// References that can't be resolved are `None`, and anything is accepted for them.
+    pub fn resolve(&self, reference: &str) -> Option<&InputSchema> {
+        if reference == "#" {
+            return Some(self);
+        }
+        let name = reference.strip_prefix("#/$defs/")?;
+        let name = name.replace("~1", "/").replace("~0", "~");
+        match self {
+            InputSchema::Tagged(Tagged::Object {
+                defs: Some(defs), ..
+            }) => defs.get(&name),
+            _ => None,
+        }
+    }
+
+    /// The value used when this schema's field is left out, from `#[serde(default)]` or
+    /// `#[schemars(default = ...)]`
+    pub fn default_value(&self) -> Option<&Value> {
+        match self {
+            InputSchema::AnyOf { default, .. }
+            | InputSchema::OneOf { default, .. }
+            | InputSchema::Ref { default, .. }
+            | InputSchema::Tagged(
+                Tagged::Object { default, .. }
+                | Tagged::String { default, .. }
+                | Tagged::Boolean { default, .. }
+                | Tagged::Array { default, .. }
+                | Tagged::Integer {
+                    range: NumberRange { default, .. },
+                    ..
+                }
+                | Tagged::Number {
+                    range: NumberRange { default, .. },
+                    ..
+                },
+            ) => default.as_ref(),
+            InputSchema::Tagged(Tagged::Null) | InputSchema::Raw(_) => None,
+        }
+    }
+
+    /// Fill in the advertised default of each field that `arguments` leaves out or sets to null,
+    /// including fields of nested objects
+    ///
+    /// This makes a tool see the default its schema promises even when the default is only
+    /// declared to schemars, and treats an explicit null like an omitted field.
+    pub fn apply_defaults(&self, arguments: &mut Value) {
+        if arguments.is_null()
+            && let InputSchema::Tagged(Tagged::Object { properties, .. }) = self
+            && properties
+                .values()
+                .any(|schema| schema.default_value().is_some())
+        {
+            *arguments = Value::Object(Default::default());
+        }
+        self.apply_defaults_at(self, arguments);
+    }
+
+    fn apply_defaults_at(&self, root: &InputSchema, value: &mut Value) {
+        match (self, value) {
+            (InputSchema::Ref { reference, .. }, value) => {
+                // a recursive type refers to itself, but only as deep as the value goes
+                if let Some(schema) = root.resolve(reference) {
+                    schema.apply_defaults_at(root, value);
+                }
+            }
+            (InputSchema::Tagged(Tagged::Object { properties, .. }), Value::Object(map)) => {
+                for (name, schema) in properties {
+                    if let Some(default) = schema.default_value()
+                        && map.get(name).is_none_or(Value::is_null)
+                    {
+                        map.insert(name.clone(), default.clone());
+                    }
+                    if let Some(value) = map.get_mut(name) {
+                        schema.apply_defaults_at(root, value);
+                    }
+                }
+            }
+            (InputSchema::Tagged(Tagged::Array { items, .. }), Value::Array(values)) => {
+                for value in values {
+                    items.apply_defaults_at(root, value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn validate_at(
+        &self,
+        root: &InputSchema,
+        field: &str,
+        value: &Value,
+        errors: &mut Vec<ArgumentError>,
+    ) {
+        let variants = match self {
+            InputSchema::Tagged(tagged) => return tagged.validate_at(root, field, value, errors),
+            InputSchema::Ref { reference, .. } => {
+                if let Some(schema) = root.resolve(reference) {
+                    schema.validate_at(root, field, value, errors);
+                }
+                return;
+            }
+            InputSchema::Raw(_) => return,
+            InputSchema::AnyOf { any_of, .. } => any_of,
+            InputSchema::OneOf { one_of, .. } => one_of,
+        };
+
+        // the schema types don't capture everything that distinguishes variants (such as
+        // `const`), so accept a value that matches any of them rather than exactly one
+        if !variants.iter().any(|variant| {
+            let mut variant_errors = vec![];
+            variant.validate_at(root, field, value, &mut variant_errors);
+            variant_errors.is_empty()
+        }) {
+            errors.push(ArgumentError {
+                field: field.to_string(),
+                message: "does not match any of the allowed values".into(),
+            });
+        }
+    }
+}
+
+impl Tagged {
+    fn validate_at(
+        &self,
+        root: &InputSchema,
+        field: &str,
+        value: &Value,
+        errors: &mut Vec<ArgumentError>,
+    ) {
+        let mut error = |message: String| {
+            errors.push(ArgumentError {
+                field: field.to_string(),
+                message,
+            })
+        };
+
+        match (self, value) {
+            (
+                Tagged::Object {
+                    properties,
+                    required,
+                    additional_properties,
+                    ..
+                },
+                Value::Object(map),
+            ) => {
+                for name in required.iter().flatten() {
+                    if !map.contains_key(name) {
+                        errors.push(ArgumentError {
+                            field: join_field(field, name),
+                            message: "is required".into(),
+                        });
+                    }
+                }
+
+                for (name, value) in map {
+                    let schema = match properties.get(name) {
+                        Some(schema) => schema,
+                        None => match additional_properties {
+                            Some(schema) => schema,
+                            None => continue,
+                        },
+                    };
+
+                    // optional fields are described without `null`, but clients may send it
+                    let is_required = required.iter().flatten().any(|r| r == name);
+                    if value.is_null() && !is_required {
+                        continue;
+                    }
+
+                    schema.validate_at(root, &join_field(field, name), value, errors);
+                }
+            }
+
+            (
+                Tagged::String {
+                    r#enum,
+                    min_length,
+                    max_length,
+                    ..
+                },
+                Value::String(string),
+            ) => {
+                let length = string.chars().count() as u64;
+                if let Some(allowed) = r#enum
+                    && !allowed.contains(string)
+                {
+                    error(format!(
+                        "must be one of {}, but was \"{string}\"",
+                        allowed
+                            .iter()
+                            .map(|allowed| format!("\"{allowed}\""))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ));
+                } else if let Some(min_length) = min_length
+                    && length < *min_length
+                {
+                    error(format!(
+                        "must be at least {min_length} characters, but was {length}"
+                    ));
+                } else if let Some(max_length) = max_length
+                    && length > *max_length
+                {
+                    error(format!(
+                        "must be at most {max_length} characters, but was {length}"
+                    ));
+                }
+            }
+
+            (
+                Tagged::Array {
+                    items,
+                    min_items,
+                    max_items,
+                    unique_items,
+                    ..
+                },
+                Value::Array(values),
+            ) => {
+                let count = values.len() as u64;
+                if let Some(min_items) = min_items
+                    && count < *min_items
+                {
+                    error(format!(
+                        "must have at least {min_items} items, but had {count}"
+                    ));
+                } else if let Some(max_items) = max_items
+                    && count > *max_items
+                {
+                    error(format!(
+                        "must have at most {max_items} items, but had {count}"
+                    ));
+                } else if *unique_items == Some(true)
+                    && values
+                        .iter()
+                        .enumerate()
+                        .any(|(index, value)| values[..index].contains(value))
+                {
+                    error("must not have duplicate items".into());
+                }
+
+                for (index, value) in values.iter().enumerate() {
+                    items.validate_at(root, &format!("{field}[{index}]"), value, errors);
+                }
+            }
+
+            (Tagged::Integer { range, .. }, Value::Number(number))
+                if number.is_i64() || number.is_u64() =>
+            {
+                if let Some(message) = number.as_f64().and_then(|number| range.check(number)) {
+                    error(message);
+                }
+            }
+
+            (Tagged::Number { range, .. }, Value::Number(number)) => {
+                if let Some(message) = number.as_f64().and_then(|number| range.check(number)) {
+                    error(message);
+                }
+            }
+
+            (Tagged::Boolean { .. }, Value::Bool(_)) | (Tagged::Null, Value::Null) => {}
+
+            (expected, actual) => error(format!(
+                "expected {}, but found {}",
+                expected.type_name(),
+                json_type_name(actual)
+            )),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Tagged::Object { .. } => "an object",
+            Tagged::String { .. } => "a string",
+            Tagged::Boolean { .. } => "a boolean",
+            Tagged::Integer { .. } => "an integer",
+            Tagged::Number { .. } => "a number",
+            Tagged::Array { .. } => "an array",
+            Tagged::Null => "null",
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+fn join_field(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{parent}.{name}")
+    }
+}
+
+fn json_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "camelCase")]
 pub enum Tagged {
     #[serde(rename = "object")]
@@ -171,13 +2255,18 @@ pub enum Tagged {
         #[serde(skip_serializing_if = "Option::is_none")]
         title: Option<String>,
         #[serde(default)]
-        properties: HashMap<String, Box<InputSchema>>,
+        properties: BTreeMap<String, Box<InputSchema>>,
         #[serde(skip_serializing_if = "Option::is_none")]
         required: Option<Vec<String>>,
         #[serde(skip_serializing_if = "Option::is_none")]
         additional_properties: Option<Box<InputSchema>>,
         #[serde(skip_serializing_if = "Option::is_none")]
         examples: Option<Vec<Value>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        default: Option<Value>,
+        /// Definitions for [`InputSchema::Ref`]s to refer to, on the root schema
+        #[serde(rename = "$defs", skip_serializing_if = "Option::is_none")]
+        defs: Option<BTreeMap<String, InputSchema>>,
     },
     #[serde(rename = "string")]
     String {
@@ -187,8 +2276,24 @@ pub enum Tagged {
         description: Option<String>,
         #[serde(skip_serializing_if = "Option::is_none")]
         r#enum: Option<Vec<String>>,
+        /// What each value of `enum` means, in the same order, from the doc comments of an enum's
+        /// variants. Empty for undocumented variants.
+        #[serde(rename = "enumDescriptions", skip_serializing_if = "Option::is_none")]
+        enum_descriptions: Option<Vec<String>>,
         #[serde(skip_serializing_if = "Option::is_none")]
         examples: Option<Vec<String>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        default: Option<Value>,
+        /// A hint like `email` or `date-time`, which isn't checked
+        #[serde(skip_serializing_if = "Option::is_none")]
+        format: Option<String>,
+        /// A regular expression for clients, which isn't checked
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pattern: Option<String>,
+        #[serde(rename = "minLength", skip_serializing_if = "Option::is_none")]
+        min_length: Option<u64>,
+        #[serde(rename = "maxLength", skip_serializing_if = "Option::is_none")]
+        max_length: Option<u64>,
     },
 
     #[serde(rename = "boolean")]
@@ -197,6 +2302,8 @@ pub enum Tagged {
         title: Option<String>,
         #[serde(skip_serializing_if = "Option::is_none")]
         description: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        default: Option<Value>,
     },
 
     #[serde(rename = "integer")]
@@ -205,6 +2312,18 @@ pub enum Tagged {
         title: Option<String>,
         #[serde(skip_serializing_if = "Option::is_none")]
         description: Option<String>,
+        #[serde(flatten)]
+        range: NumberRange,
+    },
+
+    #[serde(rename = "number")]
+    Number {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+        #[serde(flatten)]
+        range: NumberRange,
     },
 
     #[serde(rename = "array")]
@@ -214,12 +2333,143 @@ pub enum Tagged {
         #[serde(skip_serializing_if = "Option::is_none")]
         description: Option<String>,
         items: Box<InputSchema>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        default: Option<Value>,
+        #[serde(rename = "minItems", skip_serializing_if = "Option::is_none")]
+        min_items: Option<u64>,
+        #[serde(rename = "maxItems", skip_serializing_if = "Option::is_none")]
+        max_items: Option<u64>,
+        #[serde(rename = "uniqueItems", skip_serializing_if = "Option::is_none")]
+        unique_items: Option<bool>,
     },
 
     #[serde(rename = "null")]
     Null,
 }
 
+/// The bounds, format, and default of an integer or number, like those schemars emits for `u8`
+/// or `#[schemars(range(min = 1, max = 10))]`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NumberRange {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minimum: Option<Number>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maximum: Option<Number>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclusive_minimum: Option<Number>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclusive_maximum: Option<Number>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multiple_of: Option<Number>,
+    /// A hint like `uint8` or `double`, which isn't checked beyond the bounds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+}
+
+impl NumberRange {
+    /// Why `number` is out of range, if it is
+    fn check(&self, number: f64) -> Option<String> {
+        let bound = |bound: &Option<Number>| bound.as_ref().and_then(Number::as_f64);
+        if let Some(minimum) = bound(&self.minimum)
+            && number < minimum
+        {
+            Some(format!("must be at least {minimum}, but was {number}"))
+        } else if let Some(maximum) = bound(&self.maximum)
+            && number > maximum
+        {
+            Some(format!("must be at most {maximum}, but was {number}"))
+        } else if let Some(minimum) = bound(&self.exclusive_minimum)
+            && number <= minimum
+        {
+            Some(format!("must be greater than {minimum}, but was {number}"))
+        } else if let Some(maximum) = bound(&self.exclusive_maximum)
+            && number >= maximum
+        {
+            Some(format!("must be less than {maximum}, but was {number}"))
+        } else if let Some(multiple_of) = bound(&self.multiple_of)
+            && multiple_of > 0.0
+            && ((number / multiple_of).round() - number / multiple_of).abs() > 1e-9
+        {
+            Some(format!(
+                "must be a multiple of {multiple_of}, but was {number}"
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelledNotification {
+    pub request_id: Value,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressNotification {
+    pub progress_token: Value,
+    pub progress: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Log severities, as defined by RFC 5424 and used by `logging/setLevel`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoggingLevel {
+    Debug,
+    #[default]
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Critical,
+    Alert,
+    Emergency,
+}
+
+impl From<LoggingLevel> for log::Level {
+    fn from(level: LoggingLevel) -> Self {
+        match level {
+            LoggingLevel::Debug => log::Level::Debug,
+            LoggingLevel::Info | LoggingLevel::Notice => log::Level::Info,
+            LoggingLevel::Warning => log::Level::Warn,
+            _ => log::Level::Error,
+        }
+    }
+}
+
+impl From<log::Level> for LoggingLevel {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => LoggingLevel::Error,
+            log::Level::Warn => LoggingLevel::Warning,
+            log::Level::Info => LoggingLevel::Info,
+            log::Level::Debug | log::Level::Trace => LoggingLevel::Debug,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetLevelRequest {
+    pub level: LoggingLevel,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoggingMessageNotification {
+    pub level: LoggingLevel,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logger: Option<String>,
+    pub data: Value,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct McpNotification {
@@ -227,7 +2477,17 @@ pub struct McpNotification {
     pub method: String,
     pub params: Option<Value>,
 }
-#[derive(Debug, Serialize, Deserialize)]
+
+impl McpNotification {
+    pub fn new(method: impl Into<String>, params: impl Serialize) -> Self {
+        Self {
+            jsonrpc: "2.0".into(),
+            method: method.into(),
+            params: Some(serde_json::to_value(params).unwrap()),
+        }
+    }
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct McpResponse {
     pub jsonrpc: &'static str,
     pub id: Value,
@@ -237,7 +2497,7 @@ pub struct McpResponse {
     pub error: Option<McpError>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct McpError {
     pub code: i32,
     pub message: String,
@@ -245,24 +2505,296 @@ pub struct McpError {
     pub data: Option<Value>,
 }
 
+impl Display for McpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for McpError {}
+
 #[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ContentResponse {
-    content: Vec<TextContent>,
+    content: Vec<Content>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    structured_content: Option<Value>,
+    /// Whether the tool failed, in which case `content` describes the failure to the model
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    is_error: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct TextContent {
-    pub r#type: &'static str,
-    pub text: String,
+/// A single block of tool output
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Content {
+    Text {
+        text: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Image {
+        /// base64-encoded image data
+        data: String,
+        mime_type: String,
+    },
+    /// Audio, which clients on protocol versions before `2025-03-26` receive as a text placeholder
+    #[serde(rename_all = "camelCase")]
+    Audio {
+        /// base64-encoded audio data
+        data: String,
+        mime_type: String,
+    },
+    /// A resource embedded directly in the output
+    Resource {
+        resource: ResourceContents,
+    },
+    /// A reference to a resource the client can read if it needs to, such as a large file the tool
+    /// wrote. Clients on protocol versions before `2025-06-18` receive it as text.
+    #[serde(rename = "resource_link")]
+    ResourceLink(ResourceDescriptor),
+}
+
+impl Content {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::Text { text: text.into() }
+    }
+
+    /// Build image content from already-base64-encoded data
+    pub fn image(data: impl Into<String>, mime_type: impl Into<String>) -> Self {
+        Self::Image {
+            data: data.into(),
+            mime_type: mime_type.into(),
+        }
+    }
+
+    /// Build image content from raw bytes, encoding them as base64
+    pub fn image_bytes(bytes: impl AsRef<[u8]>, mime_type: impl Into<String>) -> Self {
+        Self::image(base64_encode(bytes.as_ref()), mime_type)
+    }
+
+    pub fn image_png(bytes: impl AsRef<[u8]>) -> Self {
+        Self::image_bytes(bytes, "image/png")
+    }
+
+    pub fn image_jpeg(bytes: impl AsRef<[u8]>) -> Self {
+        Self::image_bytes(bytes, "image/jpeg")
+    }
+
+    /// Build audio content from already-base64-encoded data
+    pub fn audio(data: impl Into<String>, mime_type: impl Into<String>) -> Self {
+        Self::Audio {
+            data: data.into(),
+            mime_type: mime_type.into(),
+        }
+    }
+
+    /// Build audio content from raw bytes, encoding them as base64
+    pub fn audio_bytes(bytes: impl AsRef<[u8]>, mime_type: impl Into<String>) -> Self {
+        Self::audio(base64_encode(bytes.as_ref()), mime_type)
+    }
+
+    pub fn audio_wav(bytes: impl AsRef<[u8]>) -> Self {
+        Self::audio_bytes(bytes, "audio/wav")
+    }
+
+    pub fn audio_mp3(bytes: impl AsRef<[u8]>) -> Self {
+        Self::audio_bytes(bytes, "audio/mpeg")
+    }
+
+    pub fn resource(resource: ResourceContents) -> Self {
+        Self::Resource { resource }
+    }
+
+    /// Link to the resource at `uri` instead of including its contents
+    pub fn resource_link(
+        uri: impl Into<String>,
+        name: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Self {
+        Self::ResourceLink(ResourceDescriptor::new(uri, name).with_description(description))
+    }
+
+    /// Link to the file at `path`, named after the file, with a mime type guessed from its
+    /// extension. A relative `path` is relative to the current directory.
+    pub fn file_link(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let name = path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy();
+        let uri = format!("file://{}", fs::percent_encode(&path.to_string_lossy()));
+        let descriptor = ResourceDescriptor::new(uri, name);
+        Self::ResourceLink(match fs::mime_type(&path) {
+            Some(mime_type) => descriptor.with_mime_type(mime_type),
+            None => descriptor,
+        })
+    }
+}
+
+impl Display for Content {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Content::Text { text } => f.write_str(text),
+            Content::Image { data, mime_type } => {
+                write!(f, "[{mime_type} image, {} base64 bytes]", data.len())
+            }
+            Content::Audio { data, mime_type } => {
+                write!(f, "[{mime_type} audio, {} base64 bytes]", data.len())
+            }
+            Content::Resource { resource } => match &resource.text {
+                Some(text) => f.write_str(text),
+                None => write!(f, "[resource {}]", resource.uri),
+            },
+            Content::ResourceLink(link) => match &link.description {
+                Some(description) => write!(f, "[{}: {description}]({})", link.name, link.uri),
+                None => write!(f, "[{}]({})", link.name, link.uri),
+            },
+        }
+    }
+}
+
+/// The result of a successful tool call
+///
+/// Returned from [`Tool::execute`](crate::traits::Tool::execute). `String` and `&str` convert
+/// into a single text block.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolOutput {
+    content: Vec<Content>,
+    #[serde(default)]
+    structured_content: Option<Value>,
+}
+
+impl ToolOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Output for a [`StructuredTool`](crate::traits::StructuredTool): `value` is sent as
+    /// `structuredContent`, with its pretty-printed JSON as a text block for clients that don't
+    /// read structured content
+    pub fn structured(value: &impl Serialize) -> serde_json::Result<Self> {
+        let value = serde_json::to_value(value)?;
+        let text = serde_json::to_string_pretty(&value)?;
+        Ok(Self::text(text).with_structured_content(value))
+    }
+
+    /// Set the `structuredContent` of this output, returning self
+    pub fn with_structured_content(mut self, structured_content: Value) -> Self {
+        self.structured_content = Some(structured_content);
+        self
+    }
+
+    pub fn structured_content(&self) -> Option<&Value> {
+        self.structured_content.as_ref()
+    }
+
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::new().with_content(Content::text(text))
+    }
+
+    /// Append a content block, returning self
+    pub fn with_content(mut self, content: Content) -> Self {
+        self.content.push(content);
+        self
+    }
+
+    /// Append a content block
+    pub fn push(&mut self, content: Content) {
+        self.content.push(content);
+    }
+
+    pub fn content(&self) -> &[Content] {
+        &self.content
+    }
+
+    pub fn into_content(self) -> Vec<Content> {
+        self.content
+    }
+}
+
+impl From<String> for ToolOutput {
+    fn from(text: String) -> Self {
+        Self::text(text)
+    }
+}
+
+impl From<&str> for ToolOutput {
+    fn from(text: &str) -> Self {
+        Self::text(text)
+    }
+}
+
+impl From<Content> for ToolOutput {
+    fn from(content: Content) -> Self {
+        Self::new().with_content(content)
+    }
+}
+
+impl From<Vec<Content>> for ToolOutput {
+    fn from(content: Vec<Content>) -> Self {
+        Self {
+            content,
+            ..Self::default()
+        }
+    }
+}
+
+impl Display for ToolOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (i, content) in self.content.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n")?;
+            }
+            Display::fmt(content, f)?;
+        }
+        Ok(())
+    }
 }
 
 impl ContentResponse {
     pub fn text(text: String) -> Self {
         Self {
-            content: vec![TextContent {
-                r#type: "text",
-                text,
-            }],
+            content: vec![Content::text(text)],
+            structured_content: None,
+            is_error: false,
+        }
+    }
+
+    /// A failed tool call, reported to the model as text rather than as a JSON-RPC error
+    pub fn error(message: String) -> Self {
+        Self {
+            is_error: true,
+            ..Self::text(message)
+        }
+    }
+
+    /// Drop anything that `protocol_version` doesn't have a place for
+    pub fn for_protocol_version(mut self, protocol_version: ProtocolVersion) -> Self {
+        if protocol_version < ProtocolVersion::V2025_06_18 {
+            self.structured_content = None;
+        }
+        for content in &mut self.content {
+            let unsupported = match content {
+                Content::Audio { .. } => protocol_version < ProtocolVersion::V2025_03_26,
+                Content::ResourceLink(_) => protocol_version < ProtocolVersion::V2025_06_18,
+                _ => false,
+            };
+            if unsupported {
+                *content = Content::text(content.to_string());
+            }
+        }
+        self
+    }
+}
+
+impl From<ToolOutput> for ContentResponse {
+    fn from(output: ToolOutput) -> Self {
+        Self {
+            content: output.content,
+            structured_content: output.structured_content,
+            is_error: false,
         }
     }
 }
@@ -278,15 +2810,496 @@ impl McpResponse {
     }
 
     pub fn error(id: Value, message: String) -> Self {
+        Self::error_with_code(id, -32601, message)
+    }
+
+    /// Respond with the code and data of `error`, which can be an `anyhow::Error` that wraps an
+    /// [`Error`]
+    pub fn from_error(id: Value, error: impl Into<Error>) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result: None,
+            error: Some(error.into().into()),
+        }
+    }
+
+    pub fn error_with_code(id: Value, code: i32, message: String) -> Self {
+        Self::error_with_data(id, code, message, None)
+    }
+
+    pub fn error_with_data(
+        id: Value,
+        code: i32,
+        message: String,
+        data: impl Into<Option<Value>>,
+    ) -> Self {
         Self {
             jsonrpc: "2.0",
             id,
             result: None,
             error: Some(McpError {
-                code: -32601,
+                code,
                 message,
-                data: None,
+                data: data.into(),
             }),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        registry::{NoTools, ToolRegistry},
+        server::ServerConfig,
+        testing::TestClient,
+    };
+    use serde_json::json;
+    use std::{thread, time::Duration};
+
+    fn input_schema(schema: Value) -> InputSchema {
+        serde_json::from_value(schema).unwrap()
+    }
+
+    fn search() -> InputSchema {
+        input_schema(json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string", "minLength": 1, "maxLength": 10 },
+                "mode": { "type": "string", "enum": ["exact", "fuzzy"] },
+                "limit": { "type": "integer", "minimum": 1, "maximum": 100 },
+                "paths": {
+                    "type": "array",
+                    "items": { "$ref": "#/$defs/Path" },
+                    "maxItems": 2,
+                    "uniqueItems": true
+                },
+                "verbose": { "type": "boolean" }
+            },
+            "required": ["query"],
+            "$defs": {
+                "Path": {
+                    "type": "object",
+                    "properties": { "path": { "type": "string" } },
+                    "required": ["path"]
+                }
+            }
+        }))
+    }
+
+    fn errors(schema: &InputSchema, value: Value) -> Vec<String> {
+        schema
+            .validate(&value)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn valid_arguments() {
+        let schema = search();
+        assert!(errors(&schema, json!({ "query": "mcp" })).is_empty());
+        assert!(
+            errors(
+                &schema,
+                json!({
+                    "query": "mcp",
+                    "mode": "exact",
+                    "limit": 10,
+                    "paths": [{ "path": "src" }, { "path": "docs" }],
+                    "verbose": null,
+                    "unknown": "is ignored",
+                })
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn every_failing_field_is_reported() {
+        let schema = search();
+        assert_eq!(
+            errors(
+                &schema,
+                json!({
+                    "mode": "regex",
+                    "limit": 1.5,
+                    "paths": [{ "path": "src" }, {}],
+                    "verbose": "yes",
+                })
+            ),
+            [
+                "query: is required",
+                "limit: expected an integer, but found a number",
+                "mode: must be one of \"exact\", \"fuzzy\", but was \"regex\"",
+                "paths[1].path: is required",
+                "verbose: expected a boolean, but found a string",
+            ]
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let schema = search();
+        assert_eq!(
+            errors(&schema, json!({ "query": "" })),
+            ["query: must be at least 1 characters, but was 0"]
+        );
+        assert_eq!(
+            errors(&schema, json!({ "query": "ö".repeat(11) })),
+            ["query: must be at most 10 characters, but was 11"]
+        );
+        assert_eq!(errors(&schema, json!({ "query": "ö".repeat(10) })).len(), 0);
+        assert_eq!(
+            errors(&schema, json!({ "query": "q", "limit": 0 })).len(),
+            1
+        );
+        assert_eq!(
+            errors(&schema, json!({ "query": "q", "limit": 101 })).len(),
+            1
+        );
+        assert_eq!(
+            errors(
+                &schema,
+                json!({ "query": "q", "paths": [{ "path": "a" }, { "path": "a" }] })
+            ),
+            ["paths: must not have duplicate items"]
+        );
+        assert_eq!(
+            errors(
+                &schema,
+                json!({ "query": "q", "paths": [{ "path": "a" }, { "path": "b" }, { "path": "c" }] })
+            ),
+            ["paths: must have at most 2 items, but had 3"]
+        );
+    }
+
+    #[test]
+    fn arguments_of_the_wrong_type() {
+        let schema = search();
+        assert_eq!(
+            errors(&schema, json!(["mcp"])),
+            ["expected an object, but found an array"]
+        );
+        assert_eq!(errors(&schema, json!({ "query": null })).len(), 1);
+    }
+
+    #[test]
+    fn variants() {
+        let schema = input_schema(json!({
+            "anyOf": [{ "type": "string" }, { "type": "integer" }]
+        }));
+        assert!(errors(&schema, json!("a")).is_empty());
+        assert!(errors(&schema, json!(1)).is_empty());
+        assert_eq!(
+            errors(&schema, json!(true)),
+            ["does not match any of the allowed values"]
+        );
+
+        // anything is accepted for raw schemas and references that can't be resolved
+        assert!(errors(&InputSchema::Raw(json!({ "type": "object" })), json!(1)).is_empty());
+        let unresolved = input_schema(json!({ "$ref": "#/$defs/Missing" }));
+        assert!(errors(&unresolved, json!(1)).is_empty());
+    }
+
+    #[test]
+    fn invalid_calls_are_refused_before_the_tool_runs() {
+        let registry = ToolRegistry::new();
+        registry.register_fn(
+            ToolSchema {
+                name: "search".into(),
+                description: None,
+                input_schema: search(),
+                output_schema: None,
+                aliases: vec![],
+                meta: None,
+            },
+            |_, _, _| panic!("search should not run"),
+        );
+        let mut client = TestClient::<(), NoTools>::new(()).with_tool_registry(registry);
+        client.initialize().unwrap();
+
+        let response = client.request(
+            "tools/call",
+            json!({ "name": "search", "arguments": { "limit": "ten" } }),
+        );
+        let error = response.error.expect("the call to be refused");
+        assert_eq!(error.code, -32602);
+        assert_eq!(
+            error.message,
+            "Invalid arguments for search: query: is required; \
+             limit: expected an integer, but found a string"
+        );
+        assert_eq!(error.data.unwrap()["errors"][0]["field"], "query");
+    }
+
+    #[test]
+    fn calls_past_their_timeout_are_cancelled_and_reported() {
+        let registry = ToolRegistry::new();
+        registry.register_fn(
+            ToolSchema {
+                name: "slow".into(),
+                description: None,
+                input_schema: input_schema(json!({ "type": "object" })),
+                output_schema: None,
+                aliases: vec![],
+                meta: None,
+            },
+            |_, cancelled: &mut bool, context| {
+                while !context.is_cancelled() {
+                    thread::sleep(Duration::from_millis(5));
+                }
+                *cancelled = true;
+                Ok(ToolOutput::text("done"))
+            },
+        );
+        let config = ServerConfig::new(Info {
+            name: "test".into(),
+            version: "0.0.0".into(),
+        })
+        .with_tool_timeout(Duration::from_secs(60))
+        .with_tool_timeout_for("slow", Duration::from_millis(50));
+        let mut client = TestClient::<bool, NoTools>::new(false)
+            .with_config(config)
+            .with_tool_registry(registry);
+        client.initialize().unwrap();
+
+        let response = client.request("tools/call", json!({ "name": "slow", "arguments": {} }));
+        let error = response.error.expect("the call to time out");
+        assert_eq!(error.code, -32001);
+        assert_eq!(error.message, "slow timed out after 50ms");
+        // the tool saw the cancellation, and its result was discarded
+        assert!(client.state());
+    }
+
+    fn review() -> PromptTemplate {
+        PromptTemplate::new("code_review")
+            .with_description("Review a piece of code")
+            .with_argument("code", "The code to review")
+            .with_optional_argument("focus", "What to pay most attention to")
+            .with_user_message("Review this {{code}}, paying attention to {focus}:\n\n{code}")
+            .with_assistant_message("Looking at {code} now.")
+    }
+
+    fn rendered(template: &PromptTemplate, arguments: Value) -> Vec<Value> {
+        let response = serde_json::to_value(template.render(&arguments).unwrap()).unwrap();
+        response["messages"].as_array().unwrap().clone()
+    }
+
+    fn render_error(template: &PromptTemplate, arguments: Value) -> String {
+        let error = template.render(&arguments).unwrap_err();
+        assert!(
+            matches!(error.downcast_ref(), Some(Error::InvalidParams(_))),
+            "{error}"
+        );
+        error.to_string()
+    }
+
+    #[test]
+    fn prompt_template_rendering() {
+        let template = review();
+        template.validate().unwrap();
+
+        let messages = rendered(&template, json!({ "code": "x + 1", "focus": "overflow" }));
+        assert_eq!(messages[0]["role"], "user");
+        assert_eq!(
+            messages[0]["content"]["text"],
+            "Review this {code}, paying attention to overflow:\n\nx + 1"
+        );
+        assert_eq!(messages[1]["role"], "assistant");
+        assert_eq!(messages[1]["content"]["text"], "Looking at x + 1 now.");
+
+        // optional arguments that are left out render as nothing
+        let messages = rendered(&template, json!({ "code": "x" }));
+        assert_eq!(
+            messages[0]["content"]["text"],
+            "Review this {code}, paying attention to :\n\nx"
+        );
+    }
+
+    #[test]
+    fn prompt_template_arguments_are_checked() {
+        let template = review();
+        assert_eq!(
+            render_error(&template, json!({})),
+            "missing required argument code"
+        );
+        assert_eq!(
+            render_error(&template, Value::Null),
+            "missing required argument code"
+        );
+        assert_eq!(
+            render_error(&template, json!({ "code": "x", "style": "terse" })),
+            "style is not an argument of prompt code_review"
+        );
+        assert_eq!(
+            render_error(&template, json!({ "code": 1 })),
+            "argument code must be a string"
+        );
+        assert_eq!(
+            render_error(&template, json!(["x"])),
+            "prompt arguments must be an object"
+        );
+    }
+
+    #[test]
+    fn prompt_template_validation() {
+        assert!(review().validate().is_ok());
+        for message in ["{missing}", "{code", "code}", "{{code}"] {
+            let template = PromptTemplate::new("broken")
+                .with_argument("code", "The code")
+                .with_user_message(message);
+            assert!(template.validate().is_err(), "{message}");
+        }
+    }
+
+    #[test]
+    fn prompt_templates_are_served() {
+        let config = ServerConfig::new(Info {
+            name: "test".into(),
+            version: "0.0.0".into(),
+        })
+        .with_prompt_template(review());
+        let mut client = TestClient::<(), NoTools>::new(()).with_config(config);
+        let initialized = client.initialize().unwrap();
+        assert!(initialized.capabilities.prompts.is_some());
+
+        let prompts: Value = client.request_as("prompts/list", json!({})).unwrap();
+        assert_eq!(prompts["prompts"][0]["name"], "code_review");
+        assert_eq!(prompts["prompts"][0]["arguments"][1]["required"], false);
+
+        let prompt: Value = client
+            .request_as(
+                "prompts/get",
+                json!({ "name": "code_review", "arguments": { "code": "x" } }),
+            )
+            .unwrap();
+        assert_eq!(prompt["description"], "Review a piece of code");
+        assert_eq!(
+            prompt["messages"][1]["content"]["text"],
+            "Looking at x now."
+        );
+
+        let response = client.request("prompts/get", json!({ "name": "code_review" }));
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid prompt template broken")]
+    fn invalid_prompt_templates_are_not_served() {
+        let _ = ServerConfig::new(Info {
+            name: "test".into(),
+            version: "0.0.0".into(),
+        })
+        .with_prompt_template(PromptTemplate::new("broken").with_user_message("{missing}"));
+    }
+
+    fn matches(template: &str, uri: &str) -> Option<Vec<(String, String)>> {
+        let mut parameters = UriTemplate::parse(template)
+            .unwrap()
+            .matches(uri)?
+            .into_iter()
+            .collect::<Vec<_>>();
+        parameters.sort();
+        Some(parameters)
+    }
+
+    fn parameters(pairs: &[(&str, &str)]) -> Option<Vec<(String, String)>> {
+        Some(
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn uri_template_parsing() {
+        let template = UriTemplate::parse("repo://{owner}/{name}/blob/{+path}").unwrap();
+        assert_eq!(
+            template.variables().collect::<Vec<_>>(),
+            ["owner", "name", "path"]
+        );
+        assert_eq!(template.to_string(), "repo://{owner}/{name}/blob/{+path}");
+        assert_eq!(
+            serde_json::to_value(&template).unwrap(),
+            "repo://{owner}/{name}/blob/{+path}"
+        );
+        assert_eq!(
+            UriTemplate::parse("static://readme")
+                .unwrap()
+                .variables()
+                .count(),
+            0
+        );
+
+        for invalid in [
+            "file:///{path",
+            "file:///path}",
+            "file:///{}",
+            "file:///{#path}",
+            "file:///{a,b}",
+            "file:///{a}{b}",
+        ] {
+            assert!(UriTemplate::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn uri_template_matching() {
+        assert_eq!(
+            matches("repo://{owner}/{name}", "repo://jbr/mcplease"),
+            parameters(&[("name", "mcplease"), ("owner", "jbr")])
+        );
+        assert_eq!(
+            matches("file:///{+path}", "file:///src/main.rs"),
+            parameters(&[("path", "src/main.rs")])
+        );
+        assert_eq!(
+            matches(
+                "repo://{owner}/{name}/blob/{+path}",
+                "repo://jbr/mcplease/blob/src/lib.rs"
+            ),
+            parameters(&[
+                ("name", "mcplease"),
+                ("owner", "jbr"),
+                ("path", "src/lib.rs")
+            ])
+        );
+        // values are percent-decoded
+        assert_eq!(
+            matches("file:///{+path}", "file:///my%20notes/%C3%A9t%C3%A9.md"),
+            parameters(&[("path", "my notes/été.md")])
+        );
+        // a reserved variable takes as much as it can
+        assert_eq!(
+            matches("file:///{+path}.md", "file:///a.md/b.md"),
+            parameters(&[("path", "a.md/b")])
+        );
+
+        // simple variables stop at `/`, and every variable matches something
+        assert_eq!(
+            matches("repo://{owner}/{name}", "repo://jbr/mcplease/extra"),
+            None
+        );
+        assert_eq!(matches("repo://{owner}/{name}", "repo://jbr/"), None);
+        assert_eq!(matches("file:///{+path}", "file:///"), None);
+        assert_eq!(matches("file:///{+path}", "http://example.com/"), None);
+        assert_eq!(matches("file:///{+path}", "file:///bad%ZZ"), None);
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("a%20b%2Fc").as_deref(), Some("a b/c"));
+        assert_eq!(percent_decode("100%").as_deref(), Some("100%"));
+        assert_eq!(percent_decode("%zz"), None);
+        assert_eq!(percent_decode("%FF"), None, "not utf-8");
+    }
+
+    #[test]
+    #[should_panic]
+    fn resource_template_descriptors_need_valid_templates() {
+        ResourceTemplateDescriptor::new("file:///{path", "files");
+    }
+}